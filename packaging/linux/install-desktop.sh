@@ -0,0 +1,24 @@
+#!/bin/sh
+# Register the messenger as the handler for chat-p2p:// links (current user).
+# Usage: packaging/linux/install-desktop.sh [path/to/encodeur_rsa_rust]
+set -eu
+
+here=$(dirname "$0")
+exe=${1:-$(command -v encodeur_rsa_rust || true)}
+if [ -z "$exe" ] || [ ! -x "$exe" ]; then
+    echo "encodeur_rsa_rust not found; pass the path to the binary" >&2
+    exit 1
+fi
+exe=$(cd "$(dirname "$exe")" && pwd)/$(basename "$exe")
+
+apps=${XDG_DATA_HOME:-$HOME/.local/share}/applications
+mkdir -p "$apps"
+sed "s|^Exec=.*|Exec=\"$exe\" %u|" "$here/chat-p2p.desktop" > "$apps/chat-p2p.desktop"
+
+update-desktop-database "$apps" 2>/dev/null || true
+if ! command -v xdg-mime >/dev/null; then
+    echo "xdg-mime not found (install xdg-utils), the handler was not registered" >&2
+    exit 1
+fi
+xdg-mime default chat-p2p.desktop x-scheme-handler/chat-p2p
+echo "chat-p2p:// links now open with $exe"
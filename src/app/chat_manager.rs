@@ -6,16 +6,24 @@
 //! - Message routing and typing indicators
 //! - File transfer state and toasts/notifications
 //! - Invite link generation and parsing (including QR codes)
+//! - Full-text message search (`search`)
+//! - Identity key rotation announcements (`KeyTransition`)
+//! - Nearby peers found by LAN discovery
 
 use anyhow::Result;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use tokio::sync::mpsc;
+use tokio::sync::{broadcast, mpsc};
 use uuid::Uuid;
 
-use crate::core::{generate_rsa_keypair_async, ProtocolMessage};
-use crate::network::{run_client_session, run_host_session};
+use crate::app::search::{SearchFilters, SearchHit, SearchIndex};
+use crate::identity::{Identity, Invite, InviteOptions, IssuedInvite, KeyTransition};
+use crate::identity::backup::TrustedPeer;
+use crate::core::{PrivateKey, ProtocolMessage};
+use crate::core::safety::SafetyNumber;
+use crate::network::discovery::{DiscoveredPeer, DiscoveryEvent, LanDiscovery, LocalPeer};
+use crate::network::{run_client_session, run_host_session, HandshakeIdentity, SessionOptions};
 use crate::transfer::IncomingFileSync;
 use crate::types::*;
 
@@ -42,6 +50,28 @@ pub struct ChatManager {
     pub toasts: Vec<Toast>,
     pub config: Config,
     pub fingerprint_verification_request: Option<(String, String, Uuid)>,
+    /// Invites we handed out, for expiry and single-use checks
+    pub issued_invites: Vec<IssuedInvite>,
+    /// Safety number of each session, once both identity keys are known
+    pub safety_numbers: HashMap<Uuid, SafetyNumber>,
+    /// Inverted index over message text and file names
+    pub(crate) search_index: SearchIndex,
+    /// Copies of session events for external observers (e.g. daemon subscribers)
+    event_tx: Option<broadcast::Sender<(Uuid, SessionEvent)>>,
+    /// Our own key rotations, sent to every peer once the session is ready
+    key_transitions: Vec<KeyTransition>,
+    /// Chats whose current session key came from the hybrid post-quantum handshake
+    post_quantum_sessions: HashSet<Uuid>,
+    /// Chats whose peer proved one of our invites during the handshake
+    invited_sessions: HashSet<Uuid>,
+    /// Peers seen on the local network, keyed by fingerprint
+    pub discovered_peers: HashMap<String, DiscoveredPeer>,
+    /// LAN discovery, while turned on
+    discovery: Option<Arc<LanDiscovery>>,
+    /// Port of our latest host session, advertised while discovery runs
+    hosting_port: Option<u16>,
+    /// Identity key pair that signs our handshakes, while the identity is unlocked
+    handshake_identity: Option<HandshakeIdentity>,
 }
 
 impl ChatManager {
@@ -75,10 +105,92 @@ impl ChatManager {
             toasts: Vec::new(),
             config,
             fingerprint_verification_request: None,
+            issued_invites: Vec::new(),
+            safety_numbers: HashMap::new(),
             fingerprint_confirm_senders: HashMap::new(),
+            search_index: SearchIndex::new(),
+            event_tx: None,
+            key_transitions: Vec::new(),
+            post_quantum_sessions: HashSet::new(),
+            invited_sessions: HashSet::new(),
+            discovered_peers: HashMap::new(),
+            discovery: None,
+            hosting_port: None,
+            handshake_identity: None,
         }
     }
 
+    /// Sign our handshakes with `identity`. Sessions can only start while it
+    /// is unlocked; cheap enough to call on every frame. Its key transitions
+    /// are announced to peers (see [`ChatManager::announce_key_transition`]).
+    pub fn sync_identity(&mut self, identity: &Identity) {
+        for transition in &identity.key_transitions {
+            if !self.key_transitions.contains(transition) {
+                self.announce_key_transition(transition.clone());
+            }
+        }
+
+        let unchanged = match &self.handshake_identity {
+            Some(current) => {
+                identity.is_unlocked() && current.public_key_pem == identity.public_key_pem
+            }
+            None => !identity.is_unlocked(),
+        };
+        if !unchanged {
+            self.handshake_identity = HandshakeIdentity::from_identity(identity).ok();
+        }
+    }
+
+    /// Whether sessions can be started (see [`ChatManager::sync_identity`])
+    pub fn has_identity(&self) -> bool {
+        self.handshake_identity.is_some()
+    }
+
+    /// Options for a new session, signed with our identity
+    fn session_options(&self) -> Result<SessionOptions> {
+        let identity = self
+            .handshake_identity
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("Unlock your identity before connecting"))?;
+        Ok(SessionOptions::from_config(&self.config, identity))
+    }
+
+    /// Receive a copy of every session event handled by this manager, tagged with its chat ID
+    pub fn subscribe_session_events(&mut self) -> broadcast::Receiver<(Uuid, SessionEvent)> {
+        match &self.event_tx {
+            Some(tx) => tx.subscribe(),
+            None => {
+                let (tx, rx) = broadcast::channel(SESSION_EVENT_BUFFER);
+                self.event_tx = Some(tx);
+                rx
+            }
+        }
+    }
+
+    /// Append a message to a chat and index it for search.
+    /// Returns false if the chat does not exist.
+    pub fn push_message(&mut self, chat_id: Uuid, message: Message) -> bool {
+        match self.chats.get_mut(&chat_id) {
+            Some(chat) => {
+                self.search_index.add_message(chat_id, &message);
+                chat.messages.push(message);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Full-text search over message text and file names across all chats
+    pub fn search(&self, query: &str, filters: &SearchFilters) -> Vec<SearchHit> {
+        self.search_index.search(query, filters)
+    }
+
+    /// Rebuild the search index from the current chat history
+    pub fn rebuild_search_index(&mut self) {
+        self.search_index = SearchIndex::from_chats(self.chats.values());
+        tracing::debug!(indexed = %self.search_index.len(), "Rebuilt search index");
+    }
+
     /// Add a contact
     pub fn add_contact(
         &mut self,
@@ -96,6 +208,8 @@ impl ChatManager {
             fingerprint,
             public_key,
             created_at: chrono::Utc::now(),
+            verified_at: None,
+            invite_token: None,
         };
         self.contacts.insert(id, contact);
         // no chat association by default
@@ -103,6 +217,83 @@ impl ChatManager {
         id
     }
 
+    /// Keep the secret of the invite a contact was added from, so the host
+    /// recognizes us when we connect
+    pub fn set_invite_token(&mut self, contact_id: Uuid, invite_token: Option<String>) {
+        if let Some(contact) = self.contacts.get_mut(&contact_id) {
+            contact.invite_token = invite_token;
+        }
+    }
+
+    /// Record that the safety number of a chat was compared and matched. The
+    /// contact linked to the chat, or pinned to the peer's fingerprint, is
+    /// marked verified; without one, a contact is created from the chat.
+    /// The peer's identity fingerprint is pinned to the contact, and a linked
+    /// contact pinned to another key is left alone. Returns the contact's ID.
+    pub fn mark_verified(&mut self, chat_id: Uuid) -> Option<Uuid> {
+        let chat = self.chats.get(&chat_id)?;
+        let (title, fingerprint) = (chat.title.clone(), chat.peer_fingerprint.clone());
+        let linked = self
+            .contact_to_chat
+            .iter()
+            .find(|(_, chat)| **chat == chat_id)
+            .map(|(contact_id, _)| *contact_id)
+            .filter(|contact_id| {
+                let pinned = self.contacts.get(contact_id).and_then(|c| c.fingerprint.as_deref());
+                match (pinned, fingerprint.as_deref()) {
+                    (Some(pinned), Some(fingerprint)) => pinned == fingerprint,
+                    _ => true,
+                }
+            });
+        let contact_id = linked
+            .or_else(|| {
+                let fingerprint = fingerprint.as_deref()?;
+                self.contacts
+                    .values()
+                    .find(|c| c.fingerprint.as_deref() == Some(fingerprint))
+                    .map(|c| c.id)
+            })
+            .unwrap_or_else(|| {
+                let id = self.add_contact(title, None, fingerprint.clone(), None);
+                self.contact_to_chat.insert(id, chat_id);
+                id
+            });
+
+        let contact = self.contacts.get_mut(&contact_id)?;
+        if fingerprint.is_some() {
+            contact.fingerprint = fingerprint;
+        }
+        contact.verified_at = Some(chrono::Utc::now());
+        tracing::info!(contact_id = %contact_id, chat_id = %chat_id, "Contact verified");
+        Some(contact_id)
+    }
+
+    /// Verify a session from the peer's safety-number QR code. If it holds
+    /// the session's safety number, the session is accepted (if it is still
+    /// waiting for the user) and its contact marked verified.
+    pub fn verify_safety_qr(&mut self, chat_id: Uuid, payload: &str) -> Result<()> {
+        let safety_number = self
+            .safety_numbers
+            .get(&chat_id)
+            .ok_or_else(|| anyhow::anyhow!("This session has no safety number"))?;
+        if !safety_number.matches_qr_payload(payload) {
+            return Err(anyhow::anyhow!(
+                "The QR code does not match the safety number of this session"
+            ));
+        }
+        // Only a client session waits for the answer
+        let _ = self.confirm_fingerprint(chat_id, true);
+        self.mark_verified(chat_id);
+        if self
+            .fingerprint_verification_request
+            .as_ref()
+            .is_some_and(|(_, _, pending)| *pending == chat_id)
+        {
+            self.fingerprint_verification_request = None;
+        }
+        Ok(())
+    }
+
     /// Remove a contact
     pub fn remove_contact(&mut self, contact_id: Uuid) {
         tracing::info!(contact_id = %contact_id, "Removing contact");
@@ -111,6 +302,95 @@ impl ChatManager {
         tracing::debug!(remaining_contacts = %self.contacts.len(), "Contact removed");
     }
 
+    /// Fingerprints accepted in conversations that no contact records yet
+    pub fn trusted_peers(&self) -> Vec<TrustedPeer> {
+        let mut peers: Vec<TrustedPeer> = Vec::new();
+        for chat in self.chats.values() {
+            if let Some(fingerprint) = &chat.peer_fingerprint
+                && !self.is_known_fingerprint(fingerprint)
+                && !peers.iter().any(|p| &p.fingerprint == fingerprint)
+            {
+                peers.push(TrustedPeer {
+                    name: chat.title.clone(),
+                    fingerprint: fingerprint.clone(),
+                });
+            }
+        }
+        peers
+    }
+
+    fn is_known_fingerprint(&self, fingerprint: &str) -> bool {
+        self.contacts
+            .values()
+            .any(|c| c.fingerprint.as_deref() == Some(fingerprint))
+    }
+
+    /// Tell peers about a rotation of our identity key. The statement is sent to
+    /// every connected session now and to each session that becomes ready later.
+    /// Returns the number of sessions reached now.
+    pub fn announce_key_transition(&mut self, transition: KeyTransition) -> usize {
+        if self.key_transitions.contains(&transition) {
+            return 0;
+        }
+        let msg = ProtocolMessage::KeyTransition {
+            transition: transition.clone(),
+        };
+        self.key_transitions.push(transition);
+        self.sessions
+            .values()
+            .filter(|session| session.from_app_tx.send(msg.clone()).is_ok())
+            .count()
+    }
+
+    /// Move contacts pinned to the old key of a verified transition to the new key.
+    /// Returns the names of the contacts updated.
+    pub fn apply_key_transition(&mut self, transition: &KeyTransition) -> Result<Vec<String>> {
+        transition.verify()?;
+        let old_fingerprint = transition.old_fingerprint();
+        let new_fingerprint = transition.new_fingerprint();
+
+        let mut updated = Vec::new();
+        for contact in self.contacts.values_mut() {
+            if contact.fingerprint.as_deref() == Some(old_fingerprint.as_str()) {
+                contact.fingerprint = Some(new_fingerprint.clone());
+                contact.public_key = Some(transition.new_public_key_pem.clone());
+                updated.push(contact.name.clone());
+            }
+        }
+        tracing::info!(
+            old = %old_fingerprint,
+            new = %new_fingerprint,
+            contacts = %updated.len(),
+            "Applied key transition"
+        );
+        Ok(updated)
+    }
+
+    /// Merge contacts and trusted fingerprints from a backup. Contacts already
+    /// present (same ID or fingerprint) are kept as they are; trusted peers
+    /// become contacts without an address. Returns the number of contacts added.
+    pub fn import_contacts(&mut self, contacts: Vec<Contact>, trusted_peers: Vec<TrustedPeer>) -> usize {
+        let mut added = 0;
+        for contact in contacts {
+            let duplicate = self.contacts.contains_key(&contact.id)
+                || contact
+                    .fingerprint
+                    .as_deref()
+                    .is_some_and(|fp| self.is_known_fingerprint(fp));
+            if !duplicate {
+                self.contacts.insert(contact.id, contact);
+                added += 1;
+            }
+        }
+        for peer in trusted_peers {
+            if !self.is_known_fingerprint(&peer.fingerprint) {
+                self.add_contact(peer.name, None, Some(peer.fingerprint), None);
+                added += 1;
+            }
+        }
+        added
+    }
+
     /// Get a contact
     pub fn get_contact(&self, contact_id: Uuid) -> Option<&Contact> {
         self.contacts.get(&contact_id)
@@ -120,10 +400,10 @@ impl ChatManager {
     pub fn associate_contact_with_chat(&mut self, contact_id: Uuid, chat_id: Uuid) {
         tracing::debug!("associate_contact_with_chat: contact_id={}, chat_id={}", contact_id, chat_id);
         self.contact_to_chat.insert(contact_id, chat_id);
-        if let Some(chat) = self.chats.get_mut(&chat_id) {
-            if !chat.participants.contains(&contact_id) {
-                chat.participants.push(contact_id);
-            }
+        if let Some(chat) = self.chats.get_mut(&chat_id)
+            && !chat.participants.contains(&contact_id)
+        {
+            chat.participants.push(contact_id);
         }
         tracing::info!("Associated contact {} -> chat {}", contact_id, chat_id);
     }
@@ -148,6 +428,7 @@ impl ChatManager {
             created_at: chrono::Utc::now(),
             peer_typing: false,
             typing_since: None,
+            disappearing_after_secs: None,
         };
 
         self.chats.insert(chat_id, chat);
@@ -159,14 +440,24 @@ impl ChatManager {
     /// via the existing session channels. Contacts without an active session are skipped.
     ///
     /// Returns the number of participants the message was successfully sent to.
-    pub fn send_group_message(&mut self, group_chat_id: Uuid, text: String) -> Result<usize> {
+    pub fn send_group_message(
+        &mut self,
+        group_chat_id: Uuid,
+        text: String,
+        reply_to: Option<Uuid>,
+    ) -> Result<usize> {
         let chat = self
             .chats
             .get(&group_chat_id)
             .ok_or_else(|| anyhow::anyhow!("Group chat not found"))?;
 
+        // Every recipient gets the same message ID so later edits/deletes can refer to it
+        let local = Message::new(true, MessageContent::Text { text: text.clone() })
+            .replying_to(reply_to);
         let msg = ProtocolMessage::Text {
-            text: text.clone(),
+            id: local.id,
+            text,
+            reply_to,
             timestamp: crate::util::current_timestamp_millis(),
         };
 
@@ -174,14 +465,7 @@ impl ChatManager {
         let participants = chat.participants.clone();
 
         // Add message to group chat history ONCE (not per recipient)
-        if let Some(gchat) = self.chats.get_mut(&group_chat_id) {
-            gchat.messages.push(Message {
-                id: Uuid::new_v4(),
-                from_me: true,
-                content: MessageContent::Text { text: text.clone() },
-                timestamp: chrono::Utc::now(),
-            });
-        }
+        self.push_message(group_chat_id, local);
 
         // Try to send to all participants with active sessions
         let mut sent_count = 0;
@@ -246,9 +530,9 @@ impl ChatManager {
             self.add_toast(ToastLevel::Info, format!("Already listening on port {}", port));
             return Err(anyhow::anyhow!("Already listening on port {}", port));
         }
+        let options = self.session_options()?;
         let chat_id = Uuid::new_v4();
         tracing::info!(chat_id = %chat_id, port = %port, "start_host called");
-        let privkey = generate_rsa_keypair_async(2048).await?;
 
         // Create channels
         let (to_app_tx, to_app_rx) = mpsc::unbounded_channel();
@@ -259,7 +543,9 @@ impl ChatManager {
 
         // Spawn session task
         tokio::spawn(async move {
-            if let Err(e) = run_host_session(port, privkey, to_app_tx, from_app_rx, confirm_rx, chat_id).await {
+            if let Err(e) =
+                run_host_session(port, to_app_tx, from_app_rx, confirm_rx, chat_id, options).await
+            {
                 tracing::error!("Host session error: {}", e);
             }
         });
@@ -274,6 +560,7 @@ impl ChatManager {
             created_at: chrono::Utc::now(),
             peer_typing: false,
             typing_since: None,
+            disappearing_after_secs: None,
         };
 
         self.chats.insert(chat_id, chat);
@@ -282,6 +569,12 @@ impl ChatManager {
         self.fingerprint_confirm_senders.insert(chat_id, confirm_tx);
 
         self.add_toast(ToastLevel::Info, format!("Listening on port {}", port));
+        self.hosting_port = Some(port);
+        if let Some(discovery) = &self.discovery
+            && let Err(e) = discovery.announce(port)
+        {
+            tracing::warn!("Failed to advertise host on the local network: {}", e);
+        }
         tracing::debug!(chat_count = %self.chats.len(), session_count = %self.sessions.len(), "Host session initialized");
 
         Ok(chat_id)
@@ -293,10 +586,29 @@ impl ChatManager {
         host: &str,
         port: u16,
         existing_chat_id: Option<Uuid>,
+    ) -> Result<Uuid> {
+        self.connect(host, port, existing_chat_id, None, None).await
+    }
+
+    /// Identity fingerprint the host must have when we connect with a
+    /// contact's invite: the key that signed the invite, or its signed successor
+    fn invite_pin(contact: &Contact) -> Option<String> {
+        contact.invite_token.as_ref().and(contact.fingerprint.clone())
+    }
+
+    /// Start a client session, proving `invite_token` to the host if we were
+    /// invited. A host whose identity fingerprint is not
+    /// `expected_fingerprint` is refused during the handshake.
+    async fn connect(
+        &mut self,
+        host: &str,
+        port: u16,
+        existing_chat_id: Option<Uuid>,
+        invite_token: Option<String>,
+        expected_fingerprint: Option<String>,
     ) -> Result<Uuid> {
         let chat_id = existing_chat_id.unwrap_or_else(Uuid::new_v4);
         tracing::info!(chat_id = %chat_id, host = %host, port = %port, "connect_to_host called");
-        let privkey = generate_rsa_keypair_async(2048).await?;
 
         let (to_app_tx, to_app_rx) = mpsc::unbounded_channel();
         let (from_app_tx, from_app_rx) = mpsc::unbounded_channel();
@@ -304,16 +616,28 @@ impl ChatManager {
         let host_copy = host.to_string();
         let (confirm_tx, confirm_rx) = mpsc::unbounded_channel();
 
+        let options = SessionOptions {
+            invite_token,
+            expected_fingerprint,
+            ..self.session_options()?
+        };
         tokio::spawn(async move {
-            if let Err(e) =
-                run_client_session(&host_copy, port, privkey, to_app_tx, from_app_rx, confirm_rx, chat_id)
-                    .await
+            if let Err(e) = run_client_session(
+                &host_copy,
+                port,
+                to_app_tx,
+                from_app_rx,
+                confirm_rx,
+                chat_id,
+                options,
+            )
+            .await
             {
                 tracing::error!("Client session error: {}", e);
             }
         });
 
-        if self.chats.get(&chat_id).is_none() {
+        if let std::collections::hash_map::Entry::Vacant(entry) = self.chats.entry(chat_id) {
             let chat = Chat {
                 id: chat_id,
                 title: format!("{}:{}", host, port),
@@ -323,8 +647,9 @@ impl ChatManager {
                 created_at: chrono::Utc::now(),
                 peer_typing: false,
                 typing_since: None,
+                disappearing_after_secs: None,
             };
-            self.chats.insert(chat_id, chat);
+            entry.insert(chat);
             tracing::debug!(chat_id = %chat_id, "Created local chat entry for client session");
         }
 
@@ -361,25 +686,32 @@ impl ChatManager {
             }
 
             // Try to re-associate to an existing active session by fingerprint first
-            if let Some(fp) = contact.fingerprint.clone() {
-                if let Some((&active_chat_id, _)) = self
+            if let Some(fp) = contact.fingerprint.clone()
+                && let Some((&active_chat_id, _)) = self
                     .chats
                     .iter()
                     .find(|(_, chat)| chat.peer_fingerprint.as_deref() == Some(fp.as_str()) && self.sessions.contains_key(&chat.id))
-                {
-                    tracing::info!("Re-associating mapped contact {} to active chat {} by fingerprint", contact_id, active_chat_id);
-                    self.associate_contact_with_chat(contact_id, active_chat_id);
-                    return Ok(active_chat_id);
-                }
+            {
+                tracing::info!("Re-associating mapped contact {} to active chat {} by fingerprint", contact_id, active_chat_id);
+                self.associate_contact_with_chat(contact_id, active_chat_id);
+                return Ok(active_chat_id);
             }
             // Otherwise, if the contact has an address, start a connection using the mapped chat id
-            if let Some(address) = contact.address.clone() {
-                if let Ok((host, port)) = Self::parse_address(&address) {
-                    tracing::info!("Connecting mapped chat {} to {}:{}", mapped, host, port);
-                    let chat_id = self.connect_to_host(&host, port, Some(mapped)).await?;
-                    self.associate_contact_with_chat(contact_id, chat_id);
-                    return Ok(chat_id);
-                }
+            if let Some(address) = contact.address.clone()
+                && let Ok((host, port)) = Self::parse_address(&address)
+            {
+                tracing::info!("Connecting mapped chat {} to {}:{}", mapped, host, port);
+                let chat_id = self
+                    .connect(
+                        &host,
+                        port,
+                        Some(mapped),
+                        contact.invite_token.clone(),
+                        Self::invite_pin(&contact),
+                    )
+                    .await?;
+                self.associate_contact_with_chat(contact_id, chat_id);
+                return Ok(chat_id);
             }
             // No way to create a session yet; fall through to fingerprint/address logic below
         }
@@ -388,7 +720,15 @@ impl ChatManager {
         if let Some(address) = contact.address.clone() {
             let (host, port) = Self::parse_address(&address)?;
             tracing::info!("Connecting to contact {} via {}:{}", contact_id, host, port);
-            let chat_id = self.connect_to_host(&host, port, existing_chat_id).await?;
+            let chat_id = self
+                .connect(
+                    &host,
+                    port,
+                    existing_chat_id,
+                    contact.invite_token.clone(),
+                    Self::invite_pin(&contact),
+                )
+                .await?;
             self.associate_contact_with_chat(contact_id, chat_id);
             Ok(chat_id)
         } else {
@@ -411,8 +751,141 @@ impl ChatManager {
         }
     }
 
-    /// Send a text message (handles both 1-on-1 chats and group chats)
-    pub fn send_message(&mut self, chat_id: Uuid, text: String) -> Result<()> {
+    /// Start looking for peers on the local network, advertising `local`
+    /// whenever we host. Beacons are signed with `signer` when there is one.
+    pub fn start_discovery(&mut self, local: LocalPeer, signer: Option<PrivateKey>) -> Result<()> {
+        self.stop_discovery();
+        let discovery = LanDiscovery::start(local, signer, self.config.discovery_beacon_port)?;
+        if let Some(port) = self.hosting_port {
+            discovery.announce(port)?;
+        }
+        self.discovery = Some(Arc::new(discovery));
+        tracing::info!("LAN discovery started");
+        Ok(())
+    }
+
+    /// Stop LAN discovery and forget the peers it found
+    pub fn stop_discovery(&mut self) {
+        if self.discovery.take().is_some() {
+            tracing::info!("LAN discovery stopped");
+        }
+        self.discovered_peers.clear();
+    }
+
+    /// Start, restart or stop LAN discovery to follow the settings and the
+    /// identity, which may be unlocked, renamed or replaced while running
+    pub fn sync_discovery(&mut self, identity: &Identity) {
+        if !self.config.enable_lan_discovery {
+            if self.discovery.is_some() {
+                self.stop_discovery();
+            }
+            return;
+        }
+        let local = LocalPeer::from_identity(identity);
+        let signing = identity.is_unlocked();
+        if self.discovery.as_ref().is_some_and(|discovery| {
+            discovery.matches(&local, signing, self.config.discovery_beacon_port)
+        }) {
+            return;
+        }
+        let signer = identity.private_key().ok();
+        if let Err(e) = self.start_discovery(local, signer) {
+            tracing::warn!("Failed to start LAN discovery: {}", e);
+            // Turn it off rather than retrying on every frame
+            self.config.enable_lan_discovery = false;
+            self.add_toast(ToastLevel::Error, format!("Failed to start LAN discovery: {}", e));
+        }
+    }
+
+    pub fn is_discovering(&self) -> bool {
+        self.discovery.is_some()
+    }
+
+    /// Contact pinned to an identity fingerprint
+    pub fn contact_by_fingerprint(&self, fingerprint: &str) -> Option<&Contact> {
+        self.contacts.values().find(|c| {
+            c.fingerprint
+                .as_deref()
+                .is_some_and(|fp| fp.eq_ignore_ascii_case(fingerprint))
+        })
+    }
+
+    /// Nearby peers sorted by name, each with the contact it advertises to be
+    pub fn nearby_peers(&self) -> Vec<(&DiscoveredPeer, Option<&Contact>)> {
+        let mut peers: Vec<_> = self
+            .discovered_peers
+            .values()
+            .map(|peer| (peer, self.contact_by_fingerprint(&peer.fingerprint)))
+            .collect();
+        peers.sort_by(|(a, ca), (b, cb)| {
+            let a = ca.map_or(a.name.as_str(), |c| c.name.as_str());
+            let b = cb.map_or(b.name.as_str(), |c| c.name.as_str());
+            a.to_lowercase().cmp(&b.to_lowercase())
+        });
+        peers
+    }
+
+    /// Connect to a nearby peer. A known contact keeps its chat and invite.
+    /// The handshake refuses a peer whose identity key doesn't have the
+    /// advertised fingerprint; the usual verification follows, since anyone
+    /// can advertise anyone's fingerprint.
+    pub async fn connect_to_discovered(&mut self, fingerprint: &str) -> Result<Uuid> {
+        let peer = self
+            .discovered_peers
+            .get(fingerprint)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Peer is no longer on the network"))?;
+        let host = peer.address.ip().to_string();
+        let port = peer.address.port();
+
+        let expected_fingerprint = Some(peer.fingerprint.clone());
+        let Some((contact_id, invite_token)) = self
+            .contact_by_fingerprint(&peer.fingerprint)
+            .map(|c| (c.id, c.invite_token.clone()))
+        else {
+            return self.connect(&host, port, None, None, expected_fingerprint).await;
+        };
+        let mapped = self.contact_to_chat.get(&contact_id).copied();
+        if let Some(chat_id) = mapped
+            && self.sessions.contains_key(&chat_id)
+        {
+            return Ok(chat_id);
+        }
+        tracing::info!("Connecting to nearby contact {} via {}", contact_id, peer.address);
+        let chat_id = self
+            .connect(&host, port, mapped, invite_token, expected_fingerprint)
+            .await?;
+        self.associate_contact_with_chat(contact_id, chat_id);
+        Ok(chat_id)
+    }
+
+    fn handle_discovery_event(&mut self, event: DiscoveryEvent) {
+        match event {
+            DiscoveryEvent::Found(peer) => {
+                tracing::debug!(name = %peer.name, address = %peer.address, "Peer found on the local network");
+                self.discovered_peers.insert(peer.fingerprint.clone(), peer);
+            }
+            DiscoveryEvent::Lost { fingerprint, source } => {
+                // Another method may still see the peer
+                if self
+                    .discovered_peers
+                    .get(&fingerprint)
+                    .is_some_and(|peer| peer.source == source)
+                {
+                    self.discovered_peers.remove(&fingerprint);
+                }
+            }
+        }
+    }
+
+    /// Send a text message (handles both 1-on-1 chats and group chats),
+    /// optionally as a reply to an earlier message
+    pub fn send_message(
+        &mut self,
+        chat_id: Uuid,
+        text: String,
+        reply_to: Option<Uuid>,
+    ) -> Result<()> {
         tracing::debug!("send_message called for chat_id={}, len(text)={} chars", chat_id, text.len());
         // Determine if this is a true group chat
         let (participants_len, has_session) = if let Some(chat) = self.chats.get(&chat_id) {
@@ -429,7 +902,7 @@ impl ChatManager {
 
         if is_group_chat {
             tracing::info!("Sending as group message to chat {}", chat_id);
-            self.send_group_message(chat_id, text)?;
+            self.send_group_message(chat_id, text, reply_to)?;
             return Ok(());
         }
 
@@ -445,26 +918,243 @@ impl ChatManager {
             .get(&chat_id)
             .ok_or_else(|| anyhow::anyhow!("Session should exist but was not found"))?;
 
+        let local = Message::new(true, MessageContent::Text { text: text.clone() })
+            .replying_to(reply_to);
         let msg = ProtocolMessage::Text {
-            text: text.clone(),
+            id: local.id,
+            text,
+            reply_to,
             timestamp: crate::util::current_timestamp_millis(),
         };
 
         session.from_app_tx.send(msg)?;
 
         // Add to local history
-        if let Some(chat) = self.chats.get_mut(&chat_id) {
-            chat.messages.push(Message {
-                id: Uuid::new_v4(),
-                from_me: true,
-                content: MessageContent::Text { text },
-                timestamp: chrono::Utc::now(),
-            });
+        self.push_message(chat_id, local);
+
+        Ok(())
+    }
+
+    /// Edit one of our own text messages and propagate the edit to the peer(s)
+    pub fn edit_message(&mut self, chat_id: Uuid, message_id: Uuid, new_text: String) -> Result<()> {
+        let chat = self
+            .chats
+            .get_mut(&chat_id)
+            .ok_or_else(|| anyhow::anyhow!("Chat not found"))?;
+        let message = chat
+            .messages
+            .iter_mut()
+            .find(|m| m.id == message_id)
+            .ok_or_else(|| anyhow::anyhow!("Message not found"))?;
+
+        if !message.from_me {
+            return Err(anyhow::anyhow!("Only your own messages can be edited"));
+        }
+        if message.text().is_none() {
+            return Err(anyhow::anyhow!("Only text messages can be edited"));
         }
 
+        apply_edit(message, new_text.clone(), chrono::Utc::now());
+        let updated = message.clone();
+        self.search_index.add_message(chat_id, &updated);
+
+        self.send_to_chat_peers(
+            chat_id,
+            ProtocolMessage::EditMessage {
+                message_id,
+                new_text,
+                edited_at: crate::util::current_timestamp_millis(),
+            },
+        );
         Ok(())
     }
 
+    /// Delete one of our own messages for everyone in the chat
+    pub fn delete_message(&mut self, chat_id: Uuid, message_id: Uuid) -> Result<()> {
+        let chat = self
+            .chats
+            .get_mut(&chat_id)
+            .ok_or_else(|| anyhow::anyhow!("Chat not found"))?;
+        let message = chat
+            .messages
+            .iter_mut()
+            .find(|m| m.id == message_id)
+            .ok_or_else(|| anyhow::anyhow!("Message not found"))?;
+
+        if !message.from_me {
+            return Err(anyhow::anyhow!("Only your own messages can be deleted"));
+        }
+
+        apply_delete(message);
+        self.search_index.remove_message(message_id);
+
+        self.send_to_chat_peers(chat_id, ProtocolMessage::DeleteMessage { message_id });
+        Ok(())
+    }
+
+    /// Toggle our own emoji reaction on a message and tell the peer(s)
+    pub fn toggle_reaction(&mut self, chat_id: Uuid, message_id: Uuid, emoji: &str) -> Result<()> {
+        if !is_valid_reaction(emoji) {
+            return Err(anyhow::anyhow!("Invalid reaction"));
+        }
+        let message = self
+            .chats
+            .get_mut(&chat_id)
+            .ok_or_else(|| anyhow::anyhow!("Chat not found"))?
+            .messages
+            .iter_mut()
+            .find(|m| m.id == message_id)
+            .ok_or_else(|| anyhow::anyhow!("Message not found"))?;
+
+        let add = !message.has_my_reaction(emoji);
+        message.set_reaction(emoji, Reactor::Me, add);
+
+        self.send_to_chat_peers(
+            chat_id,
+            ProtocolMessage::Reaction {
+                message_id,
+                emoji: emoji.to_string(),
+                add,
+            },
+        );
+        Ok(())
+    }
+
+    /// Apply a reaction received on session `chat_id`. The message is looked up
+    /// in the session's own chat first (reactor: the peer), then in group chats
+    /// the peer is a member of (reactor: that member).
+    fn apply_remote_reaction(&mut self, chat_id: Uuid, message_id: Uuid, emoji: &str, add: bool) -> bool {
+        if let Some(message) = self
+            .chats
+            .get_mut(&chat_id)
+            .and_then(|c| c.messages.iter_mut().find(|m| m.id == message_id))
+        {
+            message.set_reaction(emoji, Reactor::Peer, add);
+            return true;
+        }
+
+        let Some(contact_id) = self
+            .contact_to_chat
+            .iter()
+            .find(|(_, one_chat)| **one_chat == chat_id)
+            .map(|(contact_id, _)| *contact_id)
+        else {
+            return false;
+        };
+
+        for chat in self.chats.values_mut() {
+            if chat.participants.len() >= 2
+                && chat.participants.contains(&contact_id)
+                && let Some(message) = chat.messages.iter_mut().find(|m| m.id == message_id)
+            {
+                message.set_reaction(emoji, Reactor::Member(contact_id), add);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Set (or turn off with `None`) the disappearing-messages timer of a chat and
+    /// tell the peer(s), who enforce the same timer on their side
+    pub fn set_disappearing_timer(&mut self, chat_id: Uuid, secs: Option<u64>) -> Result<()> {
+        let secs = secs.filter(|s| *s > 0);
+        let chat = self
+            .chats
+            .get_mut(&chat_id)
+            .ok_or_else(|| anyhow::anyhow!("Chat not found"))?;
+        chat.disappearing_after_secs = secs;
+
+        self.send_to_chat_peers(
+            chat_id,
+            ProtocolMessage::SetExpiry {
+                chat_id,
+                seconds: secs.unwrap_or(0),
+            },
+        );
+        self.purge_expired_messages(chrono::Utc::now());
+        Ok(())
+    }
+
+    /// Chat a disappearing timer received on session `chat_id` may change:
+    /// the session's own chat, or a group the peer is a member of
+    fn expiry_target(&mut self, chat_id: Uuid, target: Uuid) -> Option<&mut Chat> {
+        if target != chat_id {
+            let contact_id = self
+                .contact_to_chat
+                .iter()
+                .find(|(_, one_chat)| **one_chat == chat_id)
+                .map(|(contact_id, _)| *contact_id)?;
+            let group = self.chats.get(&target)?;
+            if group.participants.len() < 2 || !group.participants.contains(&contact_id) {
+                return None;
+            }
+        }
+        self.chats.get_mut(&target)
+    }
+
+    /// Remove messages older than their chat's disappearing timer, along with
+    /// any attachment files we received for them. Returns the number of messages purged.
+    pub fn purge_expired_messages(&mut self, now: chrono::DateTime<chrono::Utc>) -> usize {
+        let mut purged = 0;
+        for chat in self.chats.values_mut() {
+            let Some(secs) = chat.disappearing_after_secs else {
+                continue;
+            };
+            let cutoff = now - chrono::Duration::seconds(secs.min(i64::MAX as u64) as i64);
+
+            let (expired, kept): (Vec<Message>, Vec<Message>) = std::mem::take(&mut chat.messages)
+                .into_iter()
+                .partition(|m| m.timestamp <= cutoff);
+            chat.messages = kept;
+
+            for message in expired {
+                self.search_index.remove_message(message.id);
+                if !message.from_me
+                    && let MessageContent::File { path: Some(path), .. } = &message.content
+                    && let Err(e) = std::fs::remove_file(path)
+                    && e.kind() != std::io::ErrorKind::NotFound
+                {
+                    tracing::warn!("Failed to delete expired attachment {}: {}", path.display(), e);
+                }
+                purged += 1;
+            }
+        }
+        if purged > 0 {
+            tracing::debug!(purged = %purged, "Purged expired messages");
+        }
+        purged
+    }
+
+    /// Send a control message to the peer of a 1:1 chat, or to every connected
+    /// participant of a group chat. Returns the number of sessions reached.
+    fn send_to_chat_peers(&self, chat_id: Uuid, msg: ProtocolMessage) -> usize {
+        let Some(chat) = self.chats.get(&chat_id) else {
+            return 0;
+        };
+
+        let session_chats: Vec<Uuid> = if chat.participants.len() >= 2 {
+            chat.participants
+                .iter()
+                .filter_map(|p| self.contact_to_chat.get(p).copied())
+                .collect()
+        } else {
+            vec![chat_id]
+        };
+
+        let mut sent = 0;
+        for session_chat in session_chats {
+            if let Some(session) = self.sessions.get(&session_chat)
+                && session.from_app_tx.send(msg.clone()).is_ok()
+            {
+                sent += 1;
+            }
+        }
+        if sent == 0 {
+            tracing::warn!("No connected peer for chat {}; change applied locally only", chat_id);
+        }
+        sent
+    }
+
     /// Start receiving a file
     pub fn start_receiving_file(
         &mut self,
@@ -508,6 +1198,11 @@ impl ChatManager {
         }
     }
 
+    /// File transfers seen this session (incoming), in no particular order
+    pub fn transfers(&self) -> Vec<&FileTransferState> {
+        self.active_transfers.values().collect()
+    }
+
     /// Add a toast notification
     pub fn add_toast(&mut self, level: ToastLevel, message: String) {
         self.toasts.push(Toast {
@@ -596,6 +1291,22 @@ impl ChatManager {
     }
 
     /// Get all chat IDs
+    /// Whether a chat has a running session
+    pub fn is_connected(&self, chat_id: Uuid) -> bool {
+        self.sessions.contains_key(&chat_id)
+    }
+
+    /// Whether the chat's current session key is post-quantum hybrid (X25519 + ML-KEM)
+    pub fn is_post_quantum(&self, chat_id: Uuid) -> bool {
+        self.post_quantum_sessions.contains(&chat_id)
+    }
+
+    /// Whether the chat's peer was trusted through one of our invites, so
+    /// there is no safety number left to compare
+    pub fn joined_with_invite(&self, chat_id: Uuid) -> bool {
+        self.invited_sessions.contains(&chat_id)
+    }
+
     pub fn chat_ids(&self) -> Vec<Uuid> {
         self.chats.keys().copied().collect()
     }
@@ -604,15 +1315,18 @@ impl ChatManager {
     pub fn delete_chat(&mut self, chat_id: Uuid) {
         tracing::info!(chat_id = %chat_id, "Deleting chat");
         self.chats.remove(&chat_id);
+        self.search_index.remove_chat(chat_id);
         self.sessions.remove(&chat_id);
         self.session_events.remove(&chat_id);
         self.fingerprint_confirm_senders.remove(&chat_id);
+        self.safety_numbers.remove(&chat_id);
+        self.invited_sessions.remove(&chat_id);
         self.add_toast(ToastLevel::Info, "Chat deleted".to_string());
         tracing::debug!(remaining_chats = %self.chats.len(), remaining_sessions = %self.sessions.len(), "Chat deleted");
     }
 
     /// Clear all chat history and contacts
-    pub fn clear_history(&mut self, history_path: &std::path::PathBuf) {
+    pub fn clear_history(&mut self, history_path: &std::path::Path) {
         tracing::warn!(
             chats = %self.chats.len(),
             contacts = %self.contacts.len(),
@@ -620,11 +1334,14 @@ impl ChatManager {
             "Clearing all history and state"
         );
         self.chats.clear();
+        self.search_index.clear();
         self.contacts.clear();
         self.contact_to_chat.clear();
         self.sessions.clear();
         self.session_events.clear();
         self.fingerprint_confirm_senders.clear();
+        self.safety_numbers.clear();
+        self.invited_sessions.clear();
         self.active_transfers.clear();
         self.incoming_files.clear();
         self.toasts.clear();
@@ -702,7 +1419,7 @@ impl ChatManager {
             };
             session.from_app_tx.send(chunk_msg)?;
             seq += 1;
-            if seq % 64 == 0 { tracing::trace!(sent_chunks = %seq, "File sending progress"); }
+            if seq.is_multiple_of(64) { tracing::trace!(sent_chunks = %seq, "File sending progress"); }
         }
 
         // Send end marker
@@ -710,18 +1427,17 @@ impl ChatManager {
         tracing::info!(file = %filename, total_bytes = %file_size, "File send complete");
 
         // Add to local history
-        if let Some(chat) = self.chats.get_mut(&chat_id) {
-            chat.messages.push(Message {
-                id: Uuid::new_v4(),
-                from_me: true,
-                content: MessageContent::File {
+        self.push_message(
+            chat_id,
+            Message::new(
+                true,
+                MessageContent::File {
                     filename: filename.clone(),
                     size: file_size,
                     path: Some(path),
                 },
-                timestamp: chrono::Utc::now(),
-            });
-        }
+            ),
+        );
 
         self.add_toast(ToastLevel::Success, format!("File sent: {}", filename));
 
@@ -730,17 +1446,26 @@ impl ChatManager {
 
     /// Poll and process all pending session events
     pub fn poll_session_events(&mut self) {
+        let discovered = self
+            .discovery
+            .as_ref()
+            .map(|discovery| discovery.drain_events())
+            .unwrap_or_default();
+        for event in discovered {
+            self.handle_discovery_event(event);
+        }
+
         let chat_ids: Vec<Uuid> = self.session_events.keys().copied().collect();
         tracing::trace!(tracked_sessions = %chat_ids.len(), "Polling session events");
 
         for chat_id in chat_ids {
             // Collect all pending events for this session
             let mut events = Vec::new();
-            if let Some(rx_mutex) = self.session_events.get(&chat_id) {
-                if let Ok(mut rx) = rx_mutex.try_lock() {
-                    while let Ok(event) = rx.try_recv() {
-                        events.push(event);
-                    }
+            if let Some(rx_mutex) = self.session_events.get(&chat_id)
+                && let Ok(mut rx) = rx_mutex.try_lock()
+            {
+                while let Ok(event) = rx.try_recv() {
+                    events.push(event);
                 }
             }
 
@@ -756,6 +1481,12 @@ impl ChatManager {
     fn handle_session_event(&mut self, chat_id: Uuid, event: SessionEvent) {
         tracing::debug!("Handling session event for {}: {:?}", chat_id, event);
 
+        if let Some(tx) = &self.event_tx
+            && tx.receiver_count() > 0
+        {
+            let _ = tx.send((chat_id, event.clone()));
+        }
+
         match event {
             SessionEvent::Listening { port } => {
                 tracing::info!("Session {} listening on port {}", chat_id, port);
@@ -774,6 +1505,7 @@ impl ChatManager {
             SessionEvent::NewConnection {
                 peer_addr,
                 fingerprint,
+                local_fingerprint,
                 chat_id: incoming_chat_id,
             } => {
                 tracing::info!(
@@ -781,8 +1513,11 @@ impl ChatManager {
                     peer_addr,
                     incoming_chat_id
                 );
-                // Create a chat for this new connection
-                if self.chats.get(&incoming_chat_id).is_none() {
+                // Create a chat for this new connection, or record the peer's
+                // fingerprint on the host's existing chat
+                if let Some(chat) = self.chats.get_mut(&incoming_chat_id) {
+                    chat.peer_fingerprint = Some(fingerprint.clone());
+                } else {
                     let chat = Chat {
                         id: incoming_chat_id,
                         title: peer_addr.clone(),
@@ -792,6 +1527,7 @@ impl ChatManager {
                         created_at: chrono::Utc::now(),
                         peer_typing: false,
                         typing_since: None,
+                        disappearing_after_secs: None,
                     };
                     self.chats.insert(incoming_chat_id, chat);
                 }
@@ -799,13 +1535,22 @@ impl ChatManager {
                     ToastLevel::Info,
                     format!("New connection from {}", peer_addr),
                 );
+                // The host compares the safety number too, without holding up the handshake
+                self.safety_numbers.insert(
+                    incoming_chat_id,
+                    SafetyNumber::new(&local_fingerprint, &fingerprint),
+                );
+                self.fingerprint_verification_request = Some((fingerprint, peer_addr, incoming_chat_id));
             }
 
             SessionEvent::ShowFingerprintVerification {
                 fingerprint,
+                local_fingerprint,
                 peer_name,
                 chat_id,
             } => {
+                self.safety_numbers
+                    .insert(chat_id, SafetyNumber::new(&local_fingerprint, &fingerprint));
                 // Store peer fingerprint early so UI and mapping-by-fingerprint can work immediately
                 if let Some(chat) = self.chats.get_mut(&chat_id) {
                     chat.peer_fingerprint = Some(fingerprint.clone());
@@ -814,27 +1559,53 @@ impl ChatManager {
                 self.fingerprint_verification_request = Some((fingerprint, peer_name, chat_id));
             }
 
-            SessionEvent::Ready => {
-                tracing::info!("Session {} is ready", chat_id);
-                self.add_toast(ToastLevel::Success, "Connection established!".to_string());
+            SessionEvent::Ready { post_quantum } => {
+                tracing::info!("Session {} is ready (post-quantum: {})", chat_id, post_quantum);
+                let toast = if post_quantum {
+                    self.post_quantum_sessions.insert(chat_id);
+                    "Connection established (post-quantum protected)"
+                } else {
+                    self.post_quantum_sessions.remove(&chat_id);
+                    "Connection established!"
+                };
+                self.add_toast(ToastLevel::Success, toast.to_string());
+                if let Some(session) = self.sessions.get(&chat_id) {
+                    for transition in &self.key_transitions {
+                        let _ = session.from_app_tx.send(ProtocolMessage::KeyTransition {
+                            transition: transition.clone(),
+                        });
+                    }
+                }
+            }
+
+            SessionEvent::InviteProof { proof, binding } => {
+                self.accept_invite_proof(chat_id, &proof, &binding, chrono::Utc::now());
             }
 
             SessionEvent::MessageReceived(proto_msg) => {
                 tracing::debug!("Session {} received message: {:?}", chat_id, proto_msg);
 
                 match proto_msg {
-                    ProtocolMessage::Text { text, .. } => {
-                        if let Some(chat) = self.chats.get_mut(&chat_id) {
-                            chat.messages.push(Message {
-                                id: Uuid::new_v4(),
-                                from_me: false,
-                                content: MessageContent::Text { text: text.clone() },
-                                timestamp: chrono::Utc::now(),
-                            });
-
+                    ProtocolMessage::Text {
+                        id, text, reply_to, ..
+                    } => {
+                        if self
+                            .chats
+                            .get(&chat_id)
+                            .is_some_and(|c| c.messages.iter().any(|m| m.id == id))
+                        {
+                            tracing::warn!("Ignoring duplicate message {} in chat {}", id, chat_id);
+                            return;
+                        }
+                        let received =
+                            Message::with_id(id, false, MessageContent::Text { text: text.clone() })
+                                .replying_to(reply_to);
+                        if self.push_message(chat_id, received) {
                             // Clear typing indicator
-                            chat.peer_typing = false;
-                            chat.typing_since = None;
+                            if let Some(chat) = self.chats.get_mut(&chat_id) {
+                                chat.peer_typing = false;
+                                chat.typing_since = None;
+                            }
 
                             // Show desktop notification
                             let preview = if text.len() > 50 {
@@ -918,18 +1689,15 @@ impl ChatManager {
                                             self.active_transfers.get(&transfer_id)
                                         {
                                             // Add to chat history
-                                            if let Some(chat) = self.chats.get_mut(&chat_id) {
-                                                chat.messages.push(Message {
-                                                    id: Uuid::new_v4(),
-                                                    from_me: false,
-                                                    content: MessageContent::File {
-                                                        filename: transfer.filename.clone(),
-                                                        size: transfer.size,
-                                                        path: Some(final_path),
-                                                    },
-                                                    timestamp: chrono::Utc::now(),
-                                                });
-                                            }
+                                            let received = Message::new(
+                                                false,
+                                                MessageContent::File {
+                                                    filename: transfer.filename.clone(),
+                                                    size: transfer.size,
+                                                    path: Some(final_path),
+                                                },
+                                            );
+                                            self.push_message(chat_id, received);
                                         }
                                         self.update_transfer_progress(transfer_id, bytes_received);
                                     }
@@ -963,7 +1731,125 @@ impl ChatManager {
                         }
                     }
 
-                    ProtocolMessage::Version { .. } | ProtocolMessage::EphemeralKey { .. } => {
+                    ProtocolMessage::EditMessage {
+                        message_id,
+                        new_text,
+                        edited_at,
+                    } => {
+                        let edited_at = chrono::DateTime::from_timestamp_millis(edited_at as i64)
+                            .unwrap_or_else(chrono::Utc::now);
+                        let updated = self.chats.get_mut(&chat_id).and_then(|chat| {
+                            let message = chat.messages.iter_mut().find(|m| m.id == message_id)?;
+                            // Only the author of a message may edit it
+                            if message.from_me || message.text().is_none() {
+                                return None;
+                            }
+                            apply_edit(message, new_text, edited_at);
+                            Some(message.clone())
+                        });
+                        match updated {
+                            Some(message) => self.search_index.add_message(chat_id, &message),
+                            None => tracing::warn!(
+                                "Ignoring edit for unknown or foreign message {} in chat {}",
+                                message_id,
+                                chat_id
+                            ),
+                        }
+                    }
+
+                    ProtocolMessage::DeleteMessage { message_id } => {
+                        let deleted = self.chats.get_mut(&chat_id).is_some_and(|chat| {
+                            match chat.messages.iter_mut().find(|m| m.id == message_id) {
+                                Some(message) if !message.from_me => {
+                                    apply_delete(message);
+                                    true
+                                }
+                                _ => false,
+                            }
+                        });
+                        if deleted {
+                            self.search_index.remove_message(message_id);
+                        } else {
+                            tracing::warn!(
+                                "Ignoring delete for unknown or foreign message {} in chat {}",
+                                message_id,
+                                chat_id
+                            );
+                        }
+                    }
+
+                    ProtocolMessage::Reaction {
+                        message_id,
+                        emoji,
+                        add,
+                    } => {
+                        if !is_valid_reaction(&emoji) {
+                            tracing::warn!("Ignoring invalid reaction from chat {}", chat_id);
+                        } else if !self.apply_remote_reaction(chat_id, message_id, &emoji, add) {
+                            tracing::warn!(
+                                "Ignoring reaction for unknown message {} in chat {}",
+                                message_id,
+                                chat_id
+                            );
+                        }
+                    }
+
+                    ProtocolMessage::SetExpiry {
+                        chat_id: target,
+                        seconds,
+                    } => {
+                        let secs = (seconds > 0).then_some(seconds);
+                        if let Some(chat) = self.expiry_target(chat_id, target) {
+                            chat.disappearing_after_secs = secs;
+                            let text = match secs {
+                                Some(secs) => format!(
+                                    "Peer turned on disappearing messages in {} ({})",
+                                    chat.title,
+                                    crate::util::format_duration_short(secs)
+                                ),
+                                None => format!("Peer turned off disappearing messages in {}", chat.title),
+                            };
+                            self.add_toast(ToastLevel::Info, text);
+                            self.purge_expired_messages(chrono::Utc::now());
+                        } else {
+                            tracing::warn!(
+                                "Ignoring disappearing timer for chat {} received in chat {}",
+                                target,
+                                chat_id
+                            );
+                        }
+                    }
+
+                    ProtocolMessage::KeyTransition { transition } => {
+                        match self.apply_key_transition(&transition) {
+                            Ok(updated) => {
+                                for name in updated {
+                                    self.add_toast(
+                                        ToastLevel::Info,
+                                        format!("{} rotated their identity key; fingerprint updated", name),
+                                    );
+                                }
+                            }
+                            Err(e) => {
+                                tracing::warn!("Rejected key transition on chat {}: {}", chat_id, e);
+                                self.add_toast(
+                                    ToastLevel::Warning,
+                                    "Ignored a key change from peer with an invalid signature".to_string(),
+                                );
+                            }
+                        }
+                    }
+
+                    ProtocolMessage::Version { .. }
+                    | ProtocolMessage::Capabilities { .. }
+                    | ProtocolMessage::EphemeralKey { .. }
+                    | ProtocolMessage::KemPublicKey { .. }
+                    | ProtocolMessage::KemCiphertext { .. }
+                    | ProtocolMessage::HandshakeSignature { .. }
+                    | ProtocolMessage::RatchetKey { .. }
+                    | ProtocolMessage::Rekey { .. }
+                    | ProtocolMessage::RekeyAck
+                    | ProtocolMessage::InviteProof { .. } => {
                         // These are handshake messages, should not appear in message loop
                         tracing::warn!(
                             "Received handshake message in message loop: {:?}",
@@ -980,6 +1866,8 @@ impl ChatManager {
                 // Clean up session
                 self.sessions.remove(&chat_id);
                 self.session_events.remove(&chat_id);
+                self.post_quantum_sessions.remove(&chat_id);
+                self.invited_sessions.remove(&chat_id);
             }
 
             SessionEvent::Error(err) => {
@@ -991,74 +1879,106 @@ impl ChatManager {
                 tracing::warn!("Session {} warning: {}", chat_id, msg);
                 self.add_toast(ToastLevel::Warning, msg);
             }
+
+            SessionEvent::SecurityViolation(event) => {
+                tracing::warn!("Session {} security event: {}", chat_id, event);
+                self.add_toast(ToastLevel::Warning, format!("Security warning: {}", event));
+            }
         }
     }
 
-    /// Generate an invite link for sharing contact information
-    /// Format: chat-p2p://invite/<base64_json>
-    pub fn generate_invite_link(
-        &self,
-        name: &str,
-        address: Option<String>,
-        fingerprint: &str,
-        public_key_pem: &str,
-    ) -> Result<String> {
-        use base64::Engine;
-        use serde::{Deserialize, Serialize};
-
-        #[derive(Serialize, Deserialize)]
-        struct InvitePayload {
-            name: String,
-            address: Option<String>,
-            fingerprint: String,
-            public_key: String,
-        }
-
-        let payload = InvitePayload {
-            name: name.to_string(),
-            address,
-            fingerprint: fingerprint.to_string(),
-            public_key: public_key_pem.to_string(),
-        };
-
-        let json = serde_json::to_string(&payload)?;
-        let encoded = base64::engine::general_purpose::STANDARD.encode(json);
-        Ok(format!("chat-p2p://invite/{}", encoded))
+    /// Create a signed invite link for `identity` and remember its token, so
+    /// the link's expiry and single use can be enforced when it is used
+    pub fn issue_invite(&mut self, identity: &Identity, options: &InviteOptions) -> Result<String> {
+        let (link, issued) = identity.generate_invite_link(options)?;
+        tracing::info!(
+            expires_at = ?issued.expires_at,
+            single_use = %issued.single_use,
+            "Issued invite link"
+        );
+        self.issued_invites.push(issued);
+        Ok(link)
     }
 
-    /// Parse an invite link and create a Contact
-    pub fn parse_invite_link(&self, link: &str) -> Result<Contact> {
-        use serde::{Deserialize, Serialize};
+    /// Record a use of one of our invites. Fails for unknown, expired and
+    /// already used single-use invites.
+    pub fn redeem_invite(&mut self, token: &str, now: chrono::DateTime<chrono::Utc>) -> Result<()> {
+        let issued = self
+            .issued_invites
+            .iter_mut()
+            .find(|issued| issued.token == token)
+            .ok_or_else(|| anyhow::anyhow!("Unknown invite"))?;
+        issued.check(now)?;
+        issued.redeemed_at.get_or_insert(now);
+        Ok(())
+    }
 
-        #[derive(Serialize, Deserialize)]
-        struct InvitePayload {
-            name: String,
-            address: Option<String>,
-            fingerprint: String,
-            public_key: String,
+    /// Trust a client that proved it holds one of our invites: the invite is
+    /// redeemed, the chat named after it and the client's fingerprint pinned to
+    /// a verified contact. Returns the contact's ID; without a usable invite
+    /// the user still has to compare the safety number.
+    pub fn accept_invite_proof(
+        &mut self,
+        chat_id: Uuid,
+        proof: &[u8],
+        binding: &[u8],
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Option<Uuid> {
+        let Some(issued) = self
+            .issued_invites
+            .iter()
+            .find(|issued| issued.matches_proof(binding, proof))
+            .cloned()
+        else {
+            tracing::warn!(chat_id = %chat_id, "Peer presented an invite proof we don't recognize");
+            self.add_toast(
+                ToastLevel::Warning,
+                "The peer's invite is not one of ours; compare the safety number".to_string(),
+            );
+            return None;
+        };
+        if let Err(e) = self.redeem_invite(&issued.token, now) {
+            tracing::warn!(chat_id = %chat_id, "Invite not accepted: {}", e);
+            self.add_toast(
+                ToastLevel::Warning,
+                format!("{}; compare the safety number", e),
+            );
+            return None;
         }
 
-        // Remove prefix if present
-        let encoded = link.strip_prefix("chat-p2p://invite/").unwrap_or(link);
-
-        // Decode base64
-        use base64::Engine;
-        let json = base64::engine::general_purpose::STANDARD
-            .decode(encoded)
-            .map_err(|e| anyhow::anyhow!("Invalid invite link: {}", e))?;
-        let json_str = String::from_utf8(json)
-            .map_err(|e| anyhow::anyhow!("Invalid UTF-8 in invite link: {}", e))?;
+        if let Some(label) = &issued.label
+            && let Some(chat) = self.chats.get_mut(&chat_id)
+        {
+            chat.title = label.clone();
+        }
+        let contact_id = self.mark_verified(chat_id)?;
+        self.invited_sessions.insert(chat_id);
+        if self
+            .fingerprint_verification_request
+            .as_ref()
+            .is_some_and(|(_, _, pending)| *pending == chat_id)
+        {
+            self.fingerprint_verification_request = None;
+        }
+        let name = self.contacts[&contact_id].name.clone();
+        tracing::info!(chat_id = %chat_id, contact_id = %contact_id, "Peer joined with our invite");
+        self.add_toast(
+            ToastLevel::Success,
+            format!("{} joined with your invite (fingerprint pinned)", name),
+        );
+        Some(contact_id)
+    }
 
-        // Parse JSON
-        let payload: InvitePayload = serde_json::from_str(&json_str)
-            .map_err(|e| anyhow::anyhow!("Invalid invite data: {}", e))?;
+    /// Parse an invite link and create a Contact. The signature, the
+    /// fingerprint and the expiry are checked.
+    pub fn parse_invite_link(&self, link: &str) -> Result<Contact> {
+        let payload = Invite::from_link(link)?;
+        payload.verify(chrono::Utc::now())?;
 
         // Sanitize address: ignore placeholder or clearly invalid addresses like "YOUR_IP:PORT"
         let address = payload.address.and_then(|addr| {
             let trimmed = addr.trim();
-            if trimmed.is_empty() {
-                None
-            } else if trimmed.eq_ignore_ascii_case("YOUR_IP:PORT") {
+            if trimmed.is_empty() || trimmed.eq_ignore_ascii_case("YOUR_IP:PORT") {
                 None
             } else {
                 // Basic validation: should contain a colon and a numeric port
@@ -1085,6 +2005,8 @@ impl ChatManager {
             fingerprint: Some(payload.fingerprint),
             public_key: Some(payload.public_key),
             created_at: chrono::Utc::now(),
+            verified_at: None,
+            invite_token: (!payload.token.is_empty()).then_some(payload.token),
         };
 
         Ok(contact)
@@ -1092,23 +2014,7 @@ impl ChatManager {
 
     /// Generate a QR code for an invite link (as PNG bytes)
     pub fn generate_invite_qr(&self, invite_link: &str) -> Result<Vec<u8>> {
-        use qrcode::QrCode;
-
-        let code = QrCode::new(invite_link.as_bytes())
-            .map_err(|e| anyhow::anyhow!("Failed to generate QR code: {}", e))?;
-
-        let qr_image = code
-            .render::<image::Luma<u8>>()
-            .min_dimensions(200, 200)
-            .build();
-
-        let mut bytes = Vec::new();
-        let mut cursor = std::io::Cursor::new(&mut bytes);
-        image::DynamicImage::ImageLuma8(qr_image)
-            .write_to(&mut cursor, image::ImageFormat::Png)
-            .map_err(|e| anyhow::anyhow!("Failed to encode QR code: {}", e))?;
-
-        Ok(bytes)
+        crate::util::render_qr_png(invite_link)
     }
 }
 
@@ -1118,89 +2024,175 @@ impl Default for ChatManager {
     }
 }
 
+/// Number of session events buffered per subscriber before the oldest are dropped
+const SESSION_EVENT_BUFFER: usize = 256;
+
+/// Maximum length (in characters) of a reaction; reactions are meant to be a single emoji
+const MAX_REACTION_CHARS: usize = 8;
+
+/// A reaction must be a short, non-empty string without whitespace
+fn is_valid_reaction(emoji: &str) -> bool {
+    !emoji.is_empty()
+        && emoji.chars().count() <= MAX_REACTION_CHARS
+        && !emoji.chars().any(|c| c.is_whitespace() || c.is_control())
+}
+
+/// Replace a text message's content, keeping the previous text in its edit history
+fn apply_edit(message: &mut Message, new_text: String, edited_at: chrono::DateTime<chrono::Utc>) {
+    if let Some(old) = message.text() {
+        let old = old.to_string();
+        message.edit_history.push(MessageEdit {
+            text: old,
+            replaced_at: edited_at,
+        });
+    }
+    message.content = MessageContent::Edited { new_text };
+}
+
+/// Turn a message into a tombstone, dropping its content, edit history and reactions
+fn apply_delete(message: &mut Message) {
+    message.content = MessageContent::Deleted;
+    message.edit_history.clear();
+    message.reactions.clear();
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::identity::invite_proof;
     use base64::Engine;
 
+    fn signed_invite_link(address: &str) -> String {
+        let identity = Identity::new("Alice".to_string()).unwrap();
+        let options = InviteOptions {
+            address: Some(address.to_string()),
+            ..Default::default()
+        };
+        identity.generate_invite_link(&options).unwrap().0
+    }
+
     #[test]
     fn parse_invite_placeholder_is_ignored() {
         let mgr = ChatManager::default();
-
-        let payload = serde_json::json!({
-            "name": "Alice",
-            "address": "YOUR_IP:PORT",
-            "fingerprint": "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef",
-            "public_key": "-----BEGIN PUBLIC KEY-----\nMIIBIjANBgkq...\n-----END PUBLIC KEY-----",
-        });
-
-        let json = serde_json::to_string(&payload).unwrap();
-        use base64::engine::general_purpose;
-        let encoded = general_purpose::STANDARD.encode(json);
-        let link = format!("chat-p2p://invite/{}", encoded);
-
-        let contact = mgr.parse_invite_link(&link).expect("should parse invite");
+        let contact = mgr
+            .parse_invite_link(&signed_invite_link("YOUR_IP:PORT"))
+            .expect("should parse invite");
+        assert_eq!(contact.name, "Alice");
         assert!(contact.address.is_none(), "placeholder address must be ignored");
     }
 
     #[test]
     fn parse_invite_with_valid_address_keeps_it() {
         let mgr = ChatManager::default();
-
-        let payload = serde_json::json!({
-            "name": "Bob",
-            "address": "127.0.0.1:54321",
-            "fingerprint": "fedcba9876543210fedcba9876543210fedcba9876543210fedcba9876543210",
-            "public_key": "-----BEGIN PUBLIC KEY-----\nMIIBIjANBgkq...\n-----END PUBLIC KEY-----",
-        });
-
-        let json = serde_json::to_string(&payload).unwrap();
-        use base64::engine::general_purpose;
-        let encoded = general_purpose::STANDARD.encode(json);
-        let link = format!("chat-p2p://invite/{}", encoded);
-
-        let contact = mgr.parse_invite_link(&link).expect("should parse invite");
+        let contact = mgr
+            .parse_invite_link(&signed_invite_link("127.0.0.1:54321"))
+            .expect("should parse invite");
         assert_eq!(contact.address, Some("127.0.0.1:54321".to_string()));
     }
 
     #[test]
     fn parse_invite_invalid_address_no_port() {
         let mgr = ChatManager::default();
-
-        let payload = serde_json::json!({
-            "name": "Charlie",
-            "address": "127.0.0.1",
-            "fingerprint": "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
-            "public_key": "-----BEGIN PUBLIC KEY-----\nMIIBIjANBgkq...\n-----END PUBLIC KEY-----",
-        });
-
-        let json = serde_json::to_string(&payload).unwrap();
-        use base64::engine::general_purpose;
-        let encoded = general_purpose::STANDARD.encode(json);
-        let link = format!("chat-p2p://invite/{}", encoded);
-
-        let contact = mgr.parse_invite_link(&link).expect("should parse invite");
+        let contact = mgr
+            .parse_invite_link(&signed_invite_link("127.0.0.1"))
+            .expect("should parse invite");
         assert!(contact.address.is_none(), "address without port should be None");
     }
 
     #[test]
     fn parse_invite_invalid_address_bad_port() {
         let mgr = ChatManager::default();
+        let contact = mgr
+            .parse_invite_link(&signed_invite_link("127.0.0.1:notaport"))
+            .expect("should parse invite");
+        assert!(contact.address.is_none(), "address with non-numeric port should be None");
+    }
 
+    #[test]
+    fn parse_invite_rejects_unsigned_links() {
+        let mgr = ChatManager::default();
         let payload = serde_json::json!({
-            "name": "Dana",
-            "address": "127.0.0.1:notaport",
-            "fingerprint": "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb",
+            "name": "Alice",
+            "address": "127.0.0.1:54321",
+            "fingerprint": "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef",
             "public_key": "-----BEGIN PUBLIC KEY-----\nMIIBIjANBgkq...\n-----END PUBLIC KEY-----",
         });
-
-        let json = serde_json::to_string(&payload).unwrap();
-        use base64::engine::general_purpose;
-        let encoded = general_purpose::STANDARD.encode(json);
+        let encoded = base64::engine::general_purpose::STANDARD.encode(payload.to_string());
         let link = format!("chat-p2p://invite/{}", encoded);
+        assert!(mgr.parse_invite_link(&link).is_err());
+    }
 
-        let contact = mgr.parse_invite_link(&link).expect("should parse invite");
-        assert!(contact.address.is_none(), "address with non-numeric port should be None");
+    #[test]
+    fn single_use_invite_is_redeemed_once() {
+        let identity = Identity::new("Alice".to_string()).unwrap();
+        let mut mgr = ChatManager::default();
+        let options = InviteOptions {
+            valid_for: Some(chrono::Duration::days(1)),
+            single_use: true,
+            ..Default::default()
+        };
+        let link = mgr.issue_invite(&identity, &options).unwrap();
+        let token = Invite::from_link(&link).unwrap().token;
+        let reusable = mgr.issue_invite(&identity, &InviteOptions::default()).unwrap();
+        let reusable = Invite::from_link(&reusable).unwrap().token;
+
+        let now = chrono::Utc::now();
+        assert!(mgr.redeem_invite(&token, now + chrono::Duration::days(2)).is_err(), "expired");
+        mgr.redeem_invite(&token, now).unwrap();
+        assert!(mgr.redeem_invite(&token, now).is_err(), "leaked link stops working");
+        assert!(mgr.redeem_invite("feed", now).is_err());
+
+        for _ in 0..2 {
+            mgr.redeem_invite(&reusable, now).unwrap();
+        }
+    }
+
+    #[test]
+    fn invited_peer_is_pinned_without_a_prompt() {
+        let identity = Identity::new("Alice".to_string()).unwrap();
+        let (mut mgr, chat_id) = manager_with_chat();
+        let options = InviteOptions {
+            single_use: true,
+            label: Some("Bob".to_string()),
+            ..Default::default()
+        };
+        let link = mgr.issue_invite(&identity, &options).unwrap();
+        // The client keeps the secret with the contact it creates
+        let token = mgr.parse_invite_link(&link).unwrap().invite_token.unwrap();
+
+        let connect = |mgr: &mut ChatManager, chat_id: Uuid, binding: &[u8], proof: Vec<u8>| {
+            mgr.handle_session_event(chat_id, SessionEvent::NewConnection {
+                peer_addr: "10.0.0.2:5000".to_string(),
+                fingerprint: "cd".repeat(16),
+                local_fingerprint: "ab".repeat(16),
+                chat_id,
+            });
+            mgr.handle_session_event(chat_id, SessionEvent::InviteProof {
+                proof,
+                binding: binding.to_vec(),
+            });
+        };
+
+        // A proof made for another session is not accepted
+        connect(&mut mgr, chat_id, &[1; 32], invite_proof(&token, &[2; 32]));
+        assert!(!mgr.joined_with_invite(chat_id));
+        assert!(mgr.fingerprint_verification_request.is_some());
+        assert!(mgr.issued_invites[0].redeemed_at.is_none());
+
+        connect(&mut mgr, chat_id, &[1; 32], invite_proof(&token, &[1; 32]));
+        assert!(mgr.joined_with_invite(chat_id));
+        assert!(mgr.fingerprint_verification_request.is_none());
+        assert_eq!(mgr.chats[&chat_id].title, "Bob");
+        let contact = mgr.contacts.values().find(|c| c.name == "Bob").unwrap();
+        assert_eq!(contact.fingerprint.as_deref(), Some("cd".repeat(16).as_str()));
+        assert!(contact.verified_at.is_some());
+        assert_eq!(mgr.contact_to_chat[&contact.id], chat_id);
+
+        // Single use: the next peer with the same link is verified by hand
+        let other_chat = Uuid::new_v4();
+        connect(&mut mgr, other_chat, &[3; 32], invite_proof(&token, &[3; 32]));
+        assert!(!mgr.joined_with_invite(other_chat));
+        assert!(mgr.fingerprint_verification_request.is_some());
     }
 
     #[test]
@@ -1217,9 +2209,585 @@ mod tests {
             created_at: chrono::Utc::now(),
             peer_typing: false,
             typing_since: None,
+            disappearing_after_secs: None,
         };
         let id = chat.id;
         mgr.chats.insert(id, chat);
         assert!(mgr.has_placeholder_host(port));
     }
-}
\ No newline at end of file
+
+    fn manager_with_chat() -> (ChatManager, Uuid) {
+        let mut mgr = ChatManager::new(Config::default());
+        let chat = Chat {
+            id: Uuid::new_v4(),
+            title: "Peer".to_string(),
+            peer_fingerprint: None,
+            participants: Vec::new(),
+            messages: Vec::new(),
+            created_at: chrono::Utc::now(),
+            peer_typing: false,
+            typing_since: None,
+            disappearing_after_secs: None,
+        };
+        let id = chat.id;
+        mgr.chats.insert(id, chat);
+        (mgr, id)
+    }
+
+    fn receive(mgr: &mut ChatManager, chat_id: Uuid, msg: ProtocolMessage) {
+        mgr.handle_session_event(chat_id, SessionEvent::MessageReceived(msg));
+    }
+
+    #[test]
+    fn remote_edit_and_delete_update_message_in_place() {
+        let (mut mgr, chat_id) = manager_with_chat();
+        let message_id = Uuid::new_v4();
+        receive(
+            &mut mgr,
+            chat_id,
+            ProtocolMessage::Text {
+                id: message_id,
+                text: "helo".to_string(),
+                reply_to: None,
+                timestamp: 0,
+            },
+        );
+
+        receive(
+            &mut mgr,
+            chat_id,
+            ProtocolMessage::EditMessage {
+                message_id,
+                new_text: "hello".to_string(),
+                edited_at: 1_700_000_000_000,
+            },
+        );
+        let chat = &mgr.chats[&chat_id];
+        assert_eq!(chat.messages.len(), 1);
+        assert_eq!(chat.messages[0].text(), Some("hello"));
+        assert_eq!(chat.messages[0].edit_history.len(), 1);
+        assert_eq!(chat.messages[0].edit_history[0].text, "helo");
+        assert_eq!(mgr.search("hello", &SearchFilters::default()).len(), 1);
+
+        receive(&mut mgr, chat_id, ProtocolMessage::DeleteMessage { message_id });
+        let chat = &mgr.chats[&chat_id];
+        assert!(matches!(chat.messages[0].content, MessageContent::Deleted));
+        assert!(mgr.search("hello", &SearchFilters::default()).is_empty());
+    }
+
+    #[test]
+    fn received_reply_keeps_reference_to_original() {
+        let (mut mgr, chat_id) = manager_with_chat();
+        mgr.push_message(
+            chat_id,
+            Message::new(true, MessageContent::Text { text: "lunch?".to_string() }),
+        );
+        let original = mgr.chats[&chat_id].messages[0].id;
+
+        receive(
+            &mut mgr,
+            chat_id,
+            ProtocolMessage::Text {
+                id: Uuid::new_v4(),
+                text: "sure".to_string(),
+                reply_to: Some(original),
+                timestamp: 0,
+            },
+        );
+
+        let reply = &mgr.chats[&chat_id].messages[1];
+        assert_eq!(reply.reply_to, Some(original));
+
+        let json = serde_json::to_string(reply).unwrap();
+        let restored: Message = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.reply_to, Some(original));
+    }
+
+    #[test]
+    fn remote_edit_of_own_message_is_rejected() {
+        let (mut mgr, chat_id) = manager_with_chat();
+        mgr.push_message(
+            chat_id,
+            Message::new(true, MessageContent::Text { text: "mine".to_string() }),
+        );
+        let message_id = mgr.chats[&chat_id].messages[0].id;
+
+        receive(
+            &mut mgr,
+            chat_id,
+            ProtocolMessage::EditMessage {
+                message_id,
+                new_text: "forged".to_string(),
+                edited_at: 0,
+            },
+        );
+        receive(&mut mgr, chat_id, ProtocolMessage::DeleteMessage { message_id });
+
+        let message = &mgr.chats[&chat_id].messages[0];
+        assert_eq!(message.text(), Some("mine"));
+        assert!(message.edit_history.is_empty());
+    }
+
+    #[test]
+    fn local_edit_requires_own_message() {
+        let (mut mgr, chat_id) = manager_with_chat();
+        mgr.push_message(
+            chat_id,
+            Message::new(false, MessageContent::Text { text: "theirs".to_string() }),
+        );
+        let message_id = mgr.chats[&chat_id].messages[0].id;
+        assert!(mgr.edit_message(chat_id, message_id, "nope".to_string()).is_err());
+        assert!(mgr.delete_message(chat_id, message_id).is_err());
+
+        mgr.push_message(
+            chat_id,
+            Message::new(true, MessageContent::Text { text: "mine".to_string() }),
+        );
+        let own_id = mgr.chats[&chat_id].messages[1].id;
+        mgr.edit_message(chat_id, own_id, "mine, edited".to_string()).unwrap();
+        assert_eq!(mgr.chats[&chat_id].messages[1].text(), Some("mine, edited"));
+    }
+
+    #[test]
+    fn reactions_are_attributed_per_member_in_groups() {
+        let mut mgr = ChatManager::new(Config::default());
+        let alice = Uuid::new_v4();
+        let bob = Uuid::new_v4();
+        let alice_chat = Uuid::new_v4();
+        let bob_chat = Uuid::new_v4();
+        mgr.contact_to_chat.insert(alice, alice_chat);
+        mgr.contact_to_chat.insert(bob, bob_chat);
+
+        let group = Chat {
+            id: Uuid::new_v4(),
+            title: "Team".to_string(),
+            peer_fingerprint: None,
+            participants: vec![alice, bob],
+            messages: Vec::new(),
+            created_at: chrono::Utc::now(),
+            peer_typing: false,
+            typing_since: None,
+            disappearing_after_secs: None,
+        };
+        let group_id = group.id;
+        mgr.chats.insert(group_id, group);
+        for (id, title) in [(alice_chat, "Alice"), (bob_chat, "Bob")] {
+            mgr.chats.insert(
+                id,
+                Chat {
+                    id,
+                    title: title.to_string(),
+                    peer_fingerprint: None,
+                    participants: Vec::new(),
+                    messages: Vec::new(),
+                    created_at: chrono::Utc::now(),
+                    peer_typing: false,
+                    typing_since: None,
+                    disappearing_after_secs: None,
+                },
+            );
+        }
+        mgr.push_message(
+            group_id,
+            Message::new(true, MessageContent::Text { text: "ship it?".to_string() }),
+        );
+        let message_id = mgr.chats[&group_id].messages[0].id;
+
+        let thumbs_up = |add| ProtocolMessage::Reaction {
+            message_id,
+            emoji: "👍".to_string(),
+            add,
+        };
+        receive(&mut mgr, alice_chat, thumbs_up(true));
+        receive(&mut mgr, bob_chat, thumbs_up(true));
+        receive(&mut mgr, bob_chat, thumbs_up(true));
+        mgr.toggle_reaction(group_id, message_id, "👍").unwrap();
+
+        let reactors = &mgr.chats[&group_id].messages[0].reactions["👍"];
+        assert_eq!(reactors.len(), 3);
+        assert!(reactors.contains(&Reactor::Member(alice)));
+        assert!(reactors.contains(&Reactor::Member(bob)));
+        assert!(reactors.contains(&Reactor::Me));
+
+        receive(&mut mgr, alice_chat, thumbs_up(false));
+        mgr.toggle_reaction(group_id, message_id, "👍").unwrap();
+        let reactors = &mgr.chats[&group_id].messages[0].reactions["👍"];
+        assert_eq!(reactors.iter().collect::<Vec<_>>(), vec![&Reactor::Member(bob)]);
+    }
+
+    #[test]
+    fn expired_messages_and_received_files_are_purged() {
+        let (mut mgr, chat_id) = manager_with_chat();
+        let dir = tempfile::tempdir().unwrap();
+        let received = dir.path().join("photo.jpg");
+        let sent = dir.path().join("notes.txt");
+        std::fs::write(&received, b"jpeg").unwrap();
+        std::fs::write(&sent, b"notes").unwrap();
+
+        let now = chrono::Utc::now();
+        let old = now - chrono::Duration::hours(2);
+        let file_message = |from_me, path: &std::path::Path| {
+            let mut message = Message::new(
+                from_me,
+                MessageContent::File {
+                    filename: path.file_name().unwrap().to_string_lossy().to_string(),
+                    size: 4,
+                    path: Some(path.to_path_buf()),
+                },
+            );
+            message.timestamp = old;
+            message
+        };
+        mgr.push_message(chat_id, file_message(false, &received));
+        mgr.push_message(chat_id, file_message(true, &sent));
+        mgr.push_message(
+            chat_id,
+            Message::new(false, MessageContent::Text { text: "fresh".to_string() }),
+        );
+
+        // No timer: nothing is purged
+        assert_eq!(mgr.purge_expired_messages(now), 0);
+
+        mgr.set_disappearing_timer(chat_id, Some(3600)).unwrap();
+        let chat = &mgr.chats[&chat_id];
+        assert_eq!(chat.disappearing_after_secs, Some(3600));
+        assert_eq!(chat.messages.len(), 1);
+        assert_eq!(chat.messages[0].text(), Some("fresh"));
+        assert!(!received.exists(), "received attachment must be deleted");
+        assert!(sent.exists(), "our own files are left alone");
+        assert!(mgr.search("photo", &SearchFilters::default()).is_empty());
+    }
+
+    #[test]
+    fn peer_sets_disappearing_timer() {
+        let (mut mgr, chat_id) = manager_with_chat();
+        let set_expiry = |chat_id, seconds| ProtocolMessage::SetExpiry { chat_id, seconds };
+        receive(&mut mgr, chat_id, set_expiry(chat_id, 300));
+        assert_eq!(mgr.chats[&chat_id].disappearing_after_secs, Some(300));
+
+        receive(&mut mgr, chat_id, set_expiry(chat_id, 0));
+        assert_eq!(mgr.chats[&chat_id].disappearing_after_secs, None);
+
+        // A timer for a chat the peer isn't in is ignored
+        let other_id = Uuid::new_v4();
+        let other = Chat { id: other_id, ..mgr.chats[&chat_id].clone() };
+        mgr.chats.insert(other_id, other);
+        receive(&mut mgr, chat_id, set_expiry(other_id, 60));
+        assert_eq!(mgr.chats[&other_id].disappearing_after_secs, None);
+        assert_eq!(mgr.chats[&chat_id].disappearing_after_secs, None);
+    }
+
+    #[test]
+    fn group_disappearing_timer_stays_in_the_group() {
+        let mut mgr = ChatManager::new(Config::default());
+        let alice = mgr.add_contact("Alice".to_string(), None, None, None);
+        let bob = mgr.add_contact("Bob".to_string(), None, None, None);
+        let carol = mgr.add_contact("Carol".to_string(), None, None, None);
+        let mut outboxes = Vec::new();
+        let mut one_to_one = Vec::new();
+        for (contact_id, title) in [(alice, "Alice"), (bob, "Bob"), (carol, "Carol")] {
+            let id = Uuid::new_v4();
+            mgr.contact_to_chat.insert(contact_id, id);
+            mgr.chats.insert(
+                id,
+                Chat {
+                    id,
+                    title: title.to_string(),
+                    peer_fingerprint: None,
+                    participants: Vec::new(),
+                    messages: Vec::new(),
+                    created_at: chrono::Utc::now(),
+                    peer_typing: false,
+                    typing_since: None,
+                    disappearing_after_secs: None,
+                },
+            );
+            let (from_app_tx, from_app_rx) = mpsc::unbounded_channel();
+            mgr.sessions.insert(id, SessionHandle { from_app_tx });
+            outboxes.push(from_app_rx);
+            one_to_one.push(id);
+        }
+        let group_id = mgr.create_group_chat(vec![alice, bob], Some("Team".to_string()));
+
+        // Our timer goes to each member, naming the group
+        mgr.set_disappearing_timer(group_id, Some(3600)).unwrap();
+        for outbox in &mut outboxes[..2] {
+            assert_eq!(
+                outbox.try_recv().unwrap(),
+                ProtocolMessage::SetExpiry { chat_id: group_id, seconds: 3600 }
+            );
+        }
+        assert!(outboxes[2].try_recv().is_err(), "Carol is not in the group");
+        assert!(one_to_one.iter().all(|id| mgr.chats[id].disappearing_after_secs.is_none()));
+
+        // A member's timer for the group leaves their one-to-one chat alone
+        let set_expiry = |seconds| ProtocolMessage::SetExpiry { chat_id: group_id, seconds };
+        receive(&mut mgr, one_to_one[1], set_expiry(300));
+        assert_eq!(mgr.chats[&group_id].disappearing_after_secs, Some(300));
+        assert_eq!(mgr.chats[&one_to_one[1]].disappearing_after_secs, None);
+
+        // Someone outside the group can't change it
+        receive(&mut mgr, one_to_one[2], set_expiry(0));
+        assert_eq!(mgr.chats[&group_id].disappearing_after_secs, Some(300));
+    }
+
+    #[test]
+    fn verified_key_transition_repins_contact() {
+        let (mut mgr, chat_id) = manager_with_chat();
+        let mut alice = crate::identity::Identity::new("Alice".to_string()).unwrap();
+        let contact_id = mgr.add_contact(
+            "Alice".to_string(),
+            None,
+            Some(alice.fingerprint.clone()),
+            Some(alice.public_key_pem.clone()),
+        );
+        let old_fingerprint = alice.fingerprint.clone();
+        let transition = alice.rotate(None).unwrap();
+
+        let mut forged = transition.clone();
+        forged.new_public_key_pem = crate::identity::Identity::new("Mallory".to_string())
+            .unwrap()
+            .public_key_pem;
+        receive(&mut mgr, chat_id, ProtocolMessage::KeyTransition { transition: forged });
+        assert_eq!(mgr.contacts[&contact_id].fingerprint, Some(old_fingerprint));
+
+        receive(&mut mgr, chat_id, ProtocolMessage::KeyTransition { transition });
+        let contact = &mgr.contacts[&contact_id];
+        assert_eq!(contact.fingerprint.as_deref(), Some(alice.fingerprint.as_str()));
+        assert_eq!(contact.public_key.as_deref(), Some(alice.public_key_pem.as_str()));
+    }
+
+    #[tokio::test]
+    async fn contact_still_matches_after_peer_rotates_key() {
+        let port = 47661;
+        let mut alice = Identity::new("Alice".to_string()).unwrap();
+        let bob = Identity::new("Bob".to_string()).unwrap();
+
+        // Bob pinned and verified Alice's first key
+        let mut bob_mgr = ChatManager::new(Config::default());
+        bob_mgr.sync_identity(&bob);
+        let contact_id = bob_mgr.add_contact(
+            "Alice".to_string(),
+            Some(format!("127.0.0.1:{}", port)),
+            Some(alice.fingerprint.clone()),
+            Some(alice.public_key_pem.clone()),
+        );
+        bob_mgr.contacts.get_mut(&contact_id).unwrap().verified_at = Some(chrono::Utc::now());
+
+        // Alice rotates while they are apart, then hosts with the new key.
+        // Loading the identity is enough to queue the transition.
+        alice.rotate(None).unwrap();
+        let mut alice_mgr = ChatManager::new(Config::default());
+        alice_mgr.sync_identity(&alice);
+        alice_mgr.start_host(port).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let chat_id = bob_mgr.connect_to_contact(contact_id, None).await.unwrap();
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+        while bob_mgr.contacts[&contact_id].fingerprint.as_deref() != Some(alice.fingerprint.as_str()) {
+            assert!(tokio::time::Instant::now() < deadline, "key transition never arrived");
+            alice_mgr.poll_session_events();
+            bob_mgr.poll_session_events();
+            if bob_mgr.fingerprint_verification_request.take().is_some() {
+                bob_mgr.confirm_fingerprint(chat_id, true).unwrap();
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        // The session's peer is the same contact, still verified
+        let peer = bob_mgr.chats[&chat_id].peer_fingerprint.clone().unwrap();
+        assert_eq!(peer, alice.fingerprint);
+        let contact = bob_mgr.contact_by_fingerprint(&peer).unwrap();
+        assert_eq!(contact.id, contact_id);
+        assert!(contact.verified_at.is_some());
+        assert_eq!(bob_mgr.contact_to_chat[&contact_id], chat_id);
+    }
+
+    #[test]
+    fn trusted_peers_roundtrip_through_import() {
+        let (mut mgr, chat_id) = manager_with_chat();
+        let fp = "ab".repeat(32);
+        mgr.chats.get_mut(&chat_id).unwrap().peer_fingerprint = Some(fp.clone());
+        let known = mgr.add_contact("Carol".to_string(), None, Some("cd".repeat(32)), None);
+
+        let trusted = mgr.trusted_peers();
+        assert_eq!(trusted.len(), 1);
+        assert_eq!(trusted[0].fingerprint, fp);
+
+        let mut restored = ChatManager::default();
+        restored.add_contact("Carol again".to_string(), None, Some("cd".repeat(32)), None);
+        let contacts: Vec<Contact> = mgr.contacts.values().cloned().collect();
+        assert_eq!(restored.import_contacts(contacts, trusted), 1);
+        assert_eq!(restored.contacts.len(), 2);
+        assert!(!restored.contacts.contains_key(&known), "duplicate fingerprint is skipped");
+        assert!(restored.contacts.values().any(|c| c.name == "Peer" && c.address.is_none()));
+    }
+
+    #[test]
+    fn accepted_safety_number_marks_contact_verified() {
+        let (mut mgr, chat_id) = manager_with_chat();
+        let (ours, theirs) = ("ab".repeat(16), "cd".repeat(16));
+        mgr.handle_session_event(chat_id, SessionEvent::ShowFingerprintVerification {
+            fingerprint: theirs.clone(),
+            local_fingerprint: ours.clone(),
+            peer_name: "Peer".to_string(),
+            chat_id,
+        });
+        assert_eq!(mgr.safety_numbers[&chat_id], SafetyNumber::new(&theirs, &ours));
+        assert!(mgr.fingerprint_verification_request.is_some());
+
+        // The contact pinned to the peer's key is the one marked
+        let pinned = mgr.add_contact("Bob".to_string(), None, Some(theirs), None);
+        let other = mgr.add_contact("Carol".to_string(), None, Some("ef".repeat(16)), None);
+        assert_eq!(mgr.mark_verified(chat_id), Some(pinned));
+        assert!(mgr.contacts[&pinned].verified_at.is_some());
+        assert!(mgr.contacts[&other].verified_at.is_none());
+
+        // Without a matching contact, one is created from the chat
+        let (mut mgr, chat_id) = manager_with_chat();
+        let created = mgr.mark_verified(chat_id).unwrap();
+        assert_eq!(mgr.contacts[&created].name, "Peer");
+        assert_eq!(mgr.contact_to_chat[&created], chat_id);
+        assert_eq!(mgr.mark_verified(chat_id), Some(created), "the same contact next time");
+        assert_eq!(mgr.mark_verified(Uuid::new_v4()), None);
+    }
+
+    #[test]
+    fn verification_pins_the_identity_fingerprint() {
+        let (mut mgr, chat_id) = manager_with_chat();
+        let theirs = "cd".repeat(16);
+        mgr.chats.get_mut(&chat_id).unwrap().peer_fingerprint = Some(theirs.clone());
+
+        // A linked contact without a key gets the peer's
+        let linked = mgr.add_contact("Bob".to_string(), Some("10.0.0.2:5000".to_string()), None, None);
+        mgr.contact_to_chat.insert(linked, chat_id);
+        assert_eq!(mgr.mark_verified(chat_id), Some(linked));
+        assert_eq!(mgr.contacts[&linked].fingerprint.as_deref(), Some(theirs.as_str()));
+
+        // A linked contact pinned to another key is not vouched for
+        let (mut mgr, chat_id) = manager_with_chat();
+        mgr.chats.get_mut(&chat_id).unwrap().peer_fingerprint = Some(theirs.clone());
+        let other = mgr.add_contact("Carol".to_string(), None, Some("ef".repeat(16)), None);
+        mgr.contact_to_chat.insert(other, chat_id);
+        let verified = mgr.mark_verified(chat_id).unwrap();
+        assert_ne!(verified, other);
+        assert!(mgr.contacts[&other].verified_at.is_none());
+        assert_eq!(mgr.contacts[&verified].fingerprint.as_deref(), Some(theirs.as_str()));
+    }
+
+    #[test]
+    fn scanned_safety_qr_verifies_the_matching_session() {
+        let (mut mgr, chat_id) = manager_with_chat();
+        let (ours, theirs) = ("ab".repeat(16), "cd".repeat(16));
+        mgr.handle_session_event(chat_id, SessionEvent::NewConnection {
+            peer_addr: "10.0.0.2:5000".to_string(),
+            fingerprint: theirs.clone(),
+            local_fingerprint: ours.clone(),
+            chat_id,
+        });
+
+        let forged = SafetyNumber::new(&"ef".repeat(16), &ours).qr_payload();
+        assert!(mgr.verify_safety_qr(chat_id, &forged).is_err());
+        // Our own screen, copied and scanned back, is not the peer's
+        let own = mgr.safety_numbers[&chat_id].qr_payload();
+        assert!(mgr.verify_safety_qr(chat_id, &own).is_err());
+        assert!(mgr.contacts.is_empty());
+
+        // Another session's code doesn't verify this one, nor the other one
+        let other_chat = Uuid::new_v4();
+        let other = "ef".repeat(16);
+        mgr.safety_numbers.insert(other_chat, SafetyNumber::new(&ours, &other));
+        let other_scan = SafetyNumber::new(&other, &ours).qr_payload();
+        assert!(mgr.verify_safety_qr(chat_id, &other_scan).is_err());
+        assert!(mgr.contacts.is_empty());
+
+        // The peer computed the same number from its side of the handshake
+        let scanned = SafetyNumber::new(&theirs, &ours).qr_payload();
+        mgr.verify_safety_qr(chat_id, &scanned).unwrap();
+        assert!(mgr.fingerprint_verification_request.is_none());
+        let contact = mgr.contacts.values().next().unwrap();
+        assert_eq!(contact.fingerprint.as_deref(), Some(theirs.as_str()));
+        assert!(contact.verified_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn nearby_peer_must_have_the_advertised_key() {
+        use crate::network::discovery::DiscoverySource;
+
+        let port = 47681;
+        let mut host_mgr = ChatManager::new(Config::default());
+        host_mgr.sync_identity(&Identity::new("Mallory".to_string()).unwrap());
+        host_mgr.start_host(port).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // Mallory advertises Alice's fingerprint at her own address
+        let alice = Identity::new("Alice".to_string()).unwrap();
+        let mut mgr = ChatManager::new(Config::default());
+        mgr.sync_identity(&Identity::new("Bob".to_string()).unwrap());
+        mgr.handle_discovery_event(DiscoveryEvent::Found(DiscoveredPeer {
+            name: "Alice".to_string(),
+            fingerprint: alice.fingerprint.clone(),
+            address: format!("127.0.0.1:{}", port).parse().unwrap(),
+            source: DiscoverySource::Mdns,
+            last_seen: chrono::Utc::now(),
+        }));
+        let chat_id = mgr.connect_to_discovered(&alice.fingerprint).await.unwrap();
+
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+        while !mgr.toasts.iter().any(|t| t.message.contains("Handshake rejected")) {
+            assert!(tokio::time::Instant::now() < deadline, "handshake was not rejected");
+            host_mgr.poll_session_events();
+            mgr.poll_session_events();
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(mgr.fingerprint_verification_request.is_none());
+        assert!(mgr.chats[&chat_id].peer_fingerprint.is_none());
+    }
+
+    #[test]
+    fn nearby_peers_are_matched_to_contacts() {
+        use crate::network::discovery::DiscoverySource;
+
+        let mut mgr = ChatManager::default();
+        let bob = mgr.add_contact("Bob".to_string(), None, Some("cd".repeat(16)), None);
+        let found = |name: &str, fingerprint: String| {
+            DiscoveryEvent::Found(DiscoveredPeer {
+                name: name.to_string(),
+                fingerprint,
+                address: "192.168.1.20:5000".parse().unwrap(),
+                source: DiscoverySource::Mdns,
+                last_seen: chrono::Utc::now(),
+            })
+        };
+        mgr.handle_discovery_event(found("Robert", "cd".repeat(16)));
+        mgr.handle_discovery_event(found("Carol", "ef".repeat(16)));
+
+        let nearby = mgr.nearby_peers();
+        assert_eq!(nearby.len(), 2);
+        // A known contact is listed under its own name, not the advertised one
+        assert_eq!(nearby[0].0.name, "Robert");
+        assert_eq!(nearby[0].1.map(|c| c.id), Some(bob));
+        assert_eq!(nearby[1].0.name, "Carol");
+        assert!(nearby[1].1.is_none());
+
+        mgr.handle_discovery_event(DiscoveryEvent::Lost {
+            fingerprint: "ef".repeat(16),
+            source: DiscoverySource::Mdns,
+        });
+        assert_eq!(mgr.discovered_peers.len(), 1);
+
+        // Both methods feed one list; losing one leaves the other's sighting
+        let DiscoveryEvent::Found(mut beacon) = found("Robert", "cd".repeat(16)) else {
+            unreachable!()
+        };
+        beacon.source = DiscoverySource::Broadcast;
+        mgr.handle_discovery_event(DiscoveryEvent::Found(beacon));
+        mgr.handle_discovery_event(DiscoveryEvent::Lost {
+            fingerprint: "cd".repeat(16),
+            source: DiscoverySource::Mdns,
+        });
+        assert_eq!(mgr.discovered_peers.len(), 1);
+        mgr.stop_discovery();
+        assert!(mgr.discovered_peers.is_empty());
+    }
+}
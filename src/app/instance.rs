@@ -0,0 +1,199 @@
+//! Single-instance handoff for `chat-p2p://` links.
+//!
+//! The first GUI to start listens on a local endpoint in the data directory (a
+//! Unix domain socket, or a named pipe on Windows). Launching the binary again
+//! with a link, as the desktop does when one is clicked, writes the link to
+//! that endpoint, one per line, and exits; the running window opens it.
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+
+/// Scheme the app is registered for
+pub const URL_SCHEME: &str = "chat-p2p";
+
+/// Longest link accepted from another launch (invites carry a PEM key)
+const MAX_LINK_LEN: u64 = 16 * 1024;
+
+/// Whether `arg` is one of our links
+pub fn is_app_link(arg: &str) -> bool {
+    arg.trim()
+        .strip_prefix(URL_SCHEME)
+        .is_some_and(|rest| rest.starts_with("://"))
+}
+
+/// Endpoint of the running instance for a data directory
+#[cfg(unix)]
+pub fn instance_endpoint(data_dir: &Path) -> PathBuf {
+    data_dir.join("instance.sock")
+}
+
+/// Endpoint of the running instance for a data directory. Pipe names are
+/// global, so the name is derived from the directory.
+#[cfg(windows)]
+pub fn instance_endpoint(data_dir: &Path) -> PathBuf {
+    use sha2::{Digest, Sha256};
+    let digest = Sha256::digest(data_dir.to_string_lossy().as_bytes());
+    PathBuf::from(format!(r"\\.\pipe\chat-p2p-{}", hex::encode(&digest[..8])))
+}
+
+/// Hand `link` to the running instance. Returns `false` if none is running.
+pub async fn forward_link(endpoint: &Path, link: &str) -> Result<bool> {
+    let Some(mut stream) = connect(endpoint).await else {
+        return Ok(false);
+    };
+    stream.write_all(link.trim().as_bytes()).await?;
+    stream.write_all(b"\n").await?;
+    stream.shutdown().await?;
+    tracing::info!("Link handed to the running instance");
+    Ok(true)
+}
+
+/// Start receiving links from later launches. Returns `None` if another
+/// instance already does.
+pub async fn listen(endpoint: &Path) -> Result<Option<mpsc::UnboundedReceiver<String>>> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    if !platform::serve(endpoint, tx).await? {
+        return Ok(None);
+    }
+    tracing::info!(endpoint = %endpoint.display(), "Listening for links from other launches");
+    Ok(Some(rx))
+}
+
+/// Read the links one connection sends and pass ours on
+async fn read_links<S>(stream: S, tx: mpsc::UnboundedSender<String>)
+where
+    S: AsyncRead + Unpin,
+{
+    let mut lines = BufReader::new(stream.take(MAX_LINK_LEN)).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if is_app_link(&line) {
+            let _ = tx.send(line.trim().to_string());
+        } else if !line.trim().is_empty() {
+            tracing::warn!("Ignoring a non-{} link from another launch", URL_SCHEME);
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn connect(endpoint: &Path) -> Option<tokio::net::UnixStream> {
+    tokio::net::UnixStream::connect(endpoint).await.ok()
+}
+
+#[cfg(windows)]
+async fn connect(endpoint: &Path) -> Option<tokio::net::windows::named_pipe::NamedPipeClient> {
+    tokio::net::windows::named_pipe::ClientOptions::new()
+        .open(endpoint)
+        .ok()
+}
+
+#[cfg(unix)]
+mod platform {
+    use super::*;
+    use tokio::net::UnixStream;
+
+    /// Bind the socket, replacing a stale one, and accept in the background.
+    /// Returns `false` if another instance answers on it.
+    pub(super) async fn serve(endpoint: &Path, tx: mpsc::UnboundedSender<String>) -> Result<bool> {
+        if endpoint.exists() {
+            if UnixStream::connect(endpoint).await.is_ok() {
+                return Ok(false);
+            }
+            std::fs::remove_file(endpoint).with_context(|| {
+                format!("Failed to remove stale socket {}", endpoint.display())
+            })?;
+        }
+        if let Some(parent) = endpoint.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Only the owning user may hand us links
+        let listener = crate::util::bind_private_socket(endpoint)?;
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(read_links(stream, tx.clone()));
+            }
+        });
+        Ok(true)
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::*;
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    /// Create the first instance of the pipe and accept in the background.
+    /// Returns `false` if another instance already owns it.
+    pub(super) async fn serve(endpoint: &Path, tx: mpsc::UnboundedSender<String>) -> Result<bool> {
+        let mut server = match ServerOptions::new()
+            .first_pipe_instance(true)
+            .reject_remote_clients(true)
+            .create(endpoint)
+        {
+            Ok(server) => server,
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => return Ok(false),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to create {}", endpoint.display()));
+            }
+        };
+
+        let endpoint = endpoint.to_path_buf();
+        tokio::spawn(async move {
+            while server.connect().await.is_ok() {
+                let next = match ServerOptions::new()
+                    .reject_remote_clients(true)
+                    .create(&endpoint)
+                {
+                    Ok(next) => next,
+                    Err(e) => {
+                        tracing::warn!("Stopped listening for links: {}", e);
+                        return;
+                    }
+                };
+                tokio::spawn(read_links(std::mem::replace(&mut server, next), tx.clone()));
+            }
+        });
+        Ok(true)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_second_launch_hands_its_link_over() {
+        let dir = tempdir().unwrap();
+        let endpoint = instance_endpoint(dir.path());
+        let link = "chat-p2p://invite/abc";
+
+        assert!(!forward_link(&endpoint, link).await.unwrap(), "nobody is running yet");
+        let mut links = listen(&endpoint).await.unwrap().expect("first instance");
+        let mode = std::fs::metadata(&endpoint).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600, "only the owning user may hand us links");
+        assert!(listen(&endpoint).await.unwrap().is_none(), "only one instance listens");
+
+        assert!(forward_link(&endpoint, "https://example.com").await.unwrap());
+        assert!(forward_link(&endpoint, link).await.unwrap());
+        assert_eq!(links.recv().await.unwrap(), link, "other schemes are dropped");
+
+        // A socket left behind by a crashed instance is replaced
+        drop(links);
+        let dir = tempdir().unwrap();
+        let endpoint = instance_endpoint(dir.path());
+        std::os::unix::net::UnixListener::bind(&endpoint).unwrap();
+        assert!(listen(&endpoint).await.unwrap().is_some());
+    }
+
+    #[test]
+    fn test_is_app_link() {
+        assert!(is_app_link("chat-p2p://invite/abc"));
+        assert!(is_app_link(" chat-p2p://safety/00 "));
+        assert!(!is_app_link("chat-p2px://invite/abc"));
+        assert!(!is_app_link("https://example.com"));
+    }
+}
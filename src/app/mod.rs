@@ -1,5 +1,8 @@
 pub mod chat_manager;
+pub mod instance;
 pub mod persistence;
+pub mod search;
 
 pub use chat_manager::*;
 pub use persistence::*;
+pub use search::*;
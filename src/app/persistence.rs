@@ -1,9 +1,17 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
+use crate::app::search::SearchIndex;
 use crate::types::{Chat, Config};
 
+/// Platform-specific user data directory holding history and identity
+/// (e.g. `~/.local/share/chat-p2p` on Linux, `%APPDATA%\chat-p2p` on Windows)
+pub fn default_data_dir() -> Option<PathBuf> {
+    directories::ProjectDirs::from("com", "chat-p2p", "EncryptedMessenger")
+        .map(|dirs| dirs.data_dir().to_path_buf())
+}
+
 /// History file format for JSON serialization
 #[derive(Serialize, Deserialize)]
 pub struct HistoryFile {
@@ -12,6 +20,12 @@ pub struct HistoryFile {
     pub contacts: Vec<crate::types::Contact>,
     #[serde(default)]
     pub config: Config,
+    /// Invite links we handed out (tokens, expiry, single use)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub issued_invites: Vec<crate::identity::IssuedInvite>,
+    /// Persisted search index (only written when `Config::persist_search_index` is set)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub search_index: Option<SearchIndex>,
 }
 
 impl HistoryFile {
@@ -21,6 +35,8 @@ impl HistoryFile {
             chats,
             contacts: Vec::new(),
             config: Config::default(),
+            issued_invites: Vec::new(),
+            search_index: None,
         }
     }
 
@@ -69,6 +85,16 @@ impl ChatManager {
 
         // Load persisted config (if present)
         self.config = history.config;
+        self.issued_invites = history.issued_invites;
+
+        // Use the persisted search index when available, otherwise rebuild it
+        match history.search_index {
+            Some(index) if self.config.persist_search_index => self.search_index = index,
+            _ => self.rebuild_search_index(),
+        }
+
+        // Drop disappearing messages that expired while we were offline
+        self.purge_expired_messages(chrono::Utc::now());
 
         Ok(())
     }
@@ -78,6 +104,10 @@ impl ChatManager {
         let mut history = HistoryFile::new(self.chats.values().cloned().collect());
         history.contacts = self.contacts.values().cloned().collect();
         history.config = self.config.clone();
+        history.issued_invites = self.issued_invites.clone();
+        if self.config.persist_search_index {
+            history.search_index = Some(self.search_index.clone());
+        }
         history.save(path)
     }
 
@@ -107,6 +137,7 @@ mod tests {
             created_at: chrono::Utc::now(),
             peer_typing: false,
             typing_since: None,
+            disappearing_after_secs: None,
         };
 
         let history = HistoryFile::new(vec![chat.clone()]);
@@ -122,4 +153,37 @@ mod tests {
         assert_eq!(loaded.chats[0].id, chat.id);
         assert_eq!(loaded.chats[0].title, chat.title);
     }
+
+    #[test]
+    fn test_load_history_rebuilds_search_index() {
+        let temp_file = NamedTempFile::new().unwrap();
+
+        let mut chat = Chat {
+            id: Uuid::new_v4(),
+            title: "Search Chat".to_string(),
+            peer_fingerprint: None,
+            participants: Vec::new(),
+            messages: Vec::new(),
+            created_at: chrono::Utc::now(),
+            peer_typing: false,
+            typing_since: None,
+            disappearing_after_secs: None,
+        };
+        chat.messages.push(crate::types::Message::new(
+            true,
+            crate::types::MessageContent::Text {
+                text: "remember the milk".to_string(),
+            },
+        ));
+        HistoryFile::new(vec![chat.clone()])
+            .save(temp_file.path())
+            .unwrap();
+
+        let mut manager = ChatManager::default();
+        manager.load_history(temp_file.path()).unwrap();
+
+        let hits = manager.search("milk", &crate::app::SearchFilters::default());
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].chat_id, chat.id);
+    }
 }
@@ -0,0 +1,389 @@
+//! Full-text message search.
+//!
+//! `SearchIndex` is an in-memory inverted index over the text of
+//! `MessageContent::Text` / `MessageContent::Edited` messages and the names of
+//! transferred files. `ChatManager` keeps it up to date as messages arrive and
+//! rebuilds it from history on load (or restores it from the history file when
+//! `Config::persist_search_index` is enabled).
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use uuid::Uuid;
+
+use crate::types::{Chat, Message, MessageContent};
+
+/// Maximum length (in characters) of the snippet returned with a search hit
+const SNIPPET_MAX_CHARS: usize = 80;
+
+/// Who sent a message, for filtering search results
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SenderFilter {
+    Me,
+    Peer,
+}
+
+/// Optional filters applied to a search query
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchFilters {
+    /// Restrict results to a single chat
+    pub chat_id: Option<Uuid>,
+    /// Only messages sent at or after this time
+    pub from: Option<DateTime<Utc>>,
+    /// Only messages sent at or before this time
+    pub to: Option<DateTime<Utc>>,
+    /// Only messages from us or from the peer
+    pub sender: Option<SenderFilter>,
+}
+
+/// A single search result
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub chat_id: Uuid,
+    pub message_id: Uuid,
+    pub from_me: bool,
+    pub timestamp: DateTime<Utc>,
+    pub snippet: String,
+}
+
+/// Indexed metadata for one message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexedMessage {
+    chat_id: Uuid,
+    from_me: bool,
+    timestamp: DateTime<Utc>,
+    text: String,
+}
+
+/// Inverted index: term -> message IDs
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchIndex {
+    terms: BTreeMap<String, BTreeSet<Uuid>>,
+    messages: HashMap<Uuid, IndexedMessage>,
+}
+
+impl SearchIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build an index from existing chats
+    pub fn from_chats<'a>(chats: impl IntoIterator<Item = &'a Chat>) -> Self {
+        let mut index = Self::new();
+        for chat in chats {
+            for message in &chat.messages {
+                index.add_message(chat.id, message);
+            }
+        }
+        index
+    }
+
+    /// Number of indexed messages
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// Add (or re-index) a message
+    pub fn add_message(&mut self, chat_id: Uuid, message: &Message) {
+        self.remove_message(message.id);
+
+        let text = match searchable_text(&message.content) {
+            Some(text) => text,
+            None => return,
+        };
+
+        for term in tokenize(&text) {
+            self.terms.entry(term).or_default().insert(message.id);
+        }
+        self.messages.insert(
+            message.id,
+            IndexedMessage {
+                chat_id,
+                from_me: message.from_me,
+                timestamp: message.timestamp,
+                text,
+            },
+        );
+    }
+
+    /// Remove a message from the index
+    pub fn remove_message(&mut self, message_id: Uuid) {
+        if let Some(old) = self.messages.remove(&message_id) {
+            for term in tokenize(&old.text) {
+                if let Some(ids) = self.terms.get_mut(&term) {
+                    ids.remove(&message_id);
+                    if ids.is_empty() {
+                        self.terms.remove(&term);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Remove every message belonging to a chat
+    pub fn remove_chat(&mut self, chat_id: Uuid) {
+        let ids: Vec<Uuid> = self
+            .messages
+            .iter()
+            .filter(|(_, m)| m.chat_id == chat_id)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in ids {
+            self.remove_message(id);
+        }
+    }
+
+    /// Drop all indexed data
+    pub fn clear(&mut self) {
+        self.terms.clear();
+        self.messages.clear();
+    }
+
+    /// Search for messages containing every term of `query` (terms match by prefix).
+    /// Results are sorted newest first.
+    pub fn search(&self, query: &str, filters: &SearchFilters) -> Vec<SearchHit> {
+        let query_terms = tokenize(query);
+        if query_terms.is_empty() {
+            return Vec::new();
+        }
+
+        let mut candidates: Option<BTreeSet<Uuid>> = None;
+        for term in &query_terms {
+            let matching: BTreeSet<Uuid> = self
+                .terms
+                .range(term.clone()..)
+                .take_while(|(indexed, _)| indexed.starts_with(term.as_str()))
+                .flat_map(|(_, ids)| ids.iter().copied())
+                .collect();
+
+            candidates = Some(match candidates {
+                Some(current) => current.intersection(&matching).copied().collect(),
+                None => matching,
+            });
+
+            if candidates.as_ref().is_some_and(|c| c.is_empty()) {
+                return Vec::new();
+            }
+        }
+
+        let mut hits: Vec<SearchHit> = candidates
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|id| self.messages.get(&id).map(|m| (id, m)))
+            .filter(|(_, m)| matches_filters(m, filters))
+            .map(|(id, m)| SearchHit {
+                chat_id: m.chat_id,
+                message_id: id,
+                from_me: m.from_me,
+                timestamp: m.timestamp,
+                snippet: make_snippet(&m.text, &query_terms[0]),
+            })
+            .collect();
+
+        hits.sort_by_key(|h| std::cmp::Reverse(h.timestamp));
+        hits
+    }
+}
+
+/// Extract the searchable text of a message, if any
+fn searchable_text(content: &MessageContent) -> Option<String> {
+    match content {
+        MessageContent::Text { text } => Some(text.clone()),
+        MessageContent::Edited { new_text } => Some(new_text.clone()),
+        MessageContent::File { filename, .. } => Some(filename.clone()),
+        MessageContent::Deleted => None,
+    }
+}
+
+fn matches_filters(message: &IndexedMessage, filters: &SearchFilters) -> bool {
+    if filters.chat_id.is_some_and(|id| id != message.chat_id) {
+        return false;
+    }
+    if filters.from.is_some_and(|from| message.timestamp < from) {
+        return false;
+    }
+    if filters.to.is_some_and(|to| message.timestamp > to) {
+        return false;
+    }
+    match filters.sender {
+        Some(SenderFilter::Me) => message.from_me,
+        Some(SenderFilter::Peer) => !message.from_me,
+        None => true,
+    }
+}
+
+/// Split text into lowercase alphanumeric terms
+pub fn tokenize(text: &str) -> Vec<String> {
+    let mut terms: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_lowercase())
+        .collect();
+    terms.sort();
+    terms.dedup();
+    terms
+}
+
+/// Build a short excerpt of `text` around the first occurrence of `term`
+fn make_snippet(text: &str, term: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    if chars.len() <= SNIPPET_MAX_CHARS {
+        return text.to_string();
+    }
+
+    let lower = text.to_lowercase();
+    let start_char = lower
+        .find(term)
+        .map(|byte_idx| lower[..byte_idx].chars().count())
+        .unwrap_or(0)
+        .saturating_sub(SNIPPET_MAX_CHARS / 4);
+    let start_char = start_char.min(chars.len() - SNIPPET_MAX_CHARS);
+
+    let mut snippet: String = chars[start_char..start_char + SNIPPET_MAX_CHARS]
+        .iter()
+        .collect();
+    if start_char > 0 {
+        snippet.insert(0, '…');
+    }
+    if start_char + SNIPPET_MAX_CHARS < chars.len() {
+        snippet.push('…');
+    }
+    snippet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_message(text: &str, from_me: bool, timestamp: DateTime<Utc>) -> Message {
+        let mut message = Message::new(
+            from_me,
+            MessageContent::Text {
+                text: text.to_string(),
+            },
+        );
+        message.timestamp = timestamp;
+        message
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize("Hello, World! hello-again"),
+            vec!["again", "hello", "world"]
+        );
+        assert!(tokenize("  ,;! ").is_empty());
+    }
+
+    #[test]
+    fn test_search_matches_all_terms_by_prefix() {
+        let mut index = SearchIndex::new();
+        let chat_id = Uuid::new_v4();
+        let now = Utc::now();
+        let m1 = text_message("Meeting tomorrow at noon", true, now);
+        let m2 = text_message("Meet me at the station", false, now);
+        index.add_message(chat_id, &m1);
+        index.add_message(chat_id, &m2);
+
+        let hits = index.search("meet", &SearchFilters::default());
+        assert_eq!(hits.len(), 2);
+
+        let hits = index.search("meet noon", &SearchFilters::default());
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].message_id, m1.id);
+
+        assert!(index.search("airport", &SearchFilters::default()).is_empty());
+    }
+
+    #[test]
+    fn test_search_indexes_file_names() {
+        let mut index = SearchIndex::new();
+        let chat_id = Uuid::new_v4();
+        let message = Message::new(
+            false,
+            MessageContent::File {
+                filename: "quarterly_report.pdf".to_string(),
+                size: 1024,
+                path: None,
+            },
+        );
+        index.add_message(chat_id, &message);
+
+        let hits = index.search("report", &SearchFilters::default());
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].chat_id, chat_id);
+    }
+
+    #[test]
+    fn test_search_filters() {
+        let mut index = SearchIndex::new();
+        let chat_a = Uuid::new_v4();
+        let chat_b = Uuid::new_v4();
+        let now = Utc::now();
+        let old = now - chrono::Duration::days(10);
+
+        let recent_mine = text_message("project update", true, now);
+        let old_theirs = text_message("project kickoff", false, old);
+        let other_chat = text_message("project budget", false, now);
+        index.add_message(chat_a, &recent_mine);
+        index.add_message(chat_a, &old_theirs);
+        index.add_message(chat_b, &other_chat);
+
+        let by_chat = SearchFilters {
+            chat_id: Some(chat_a),
+            ..Default::default()
+        };
+        assert_eq!(index.search("project", &by_chat).len(), 2);
+
+        let by_date = SearchFilters {
+            from: Some(now - chrono::Duration::days(1)),
+            ..Default::default()
+        };
+        assert_eq!(index.search("project", &by_date).len(), 2);
+
+        let by_sender = SearchFilters {
+            chat_id: Some(chat_a),
+            sender: Some(SenderFilter::Peer),
+            ..Default::default()
+        };
+        let hits = index.search("project", &by_sender);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].message_id, old_theirs.id);
+    }
+
+    #[test]
+    fn test_remove_message_and_chat() {
+        let mut index = SearchIndex::new();
+        let chat_id = Uuid::new_v4();
+        let m1 = text_message("alpha beta", true, Utc::now());
+        let m2 = text_message("alpha gamma", true, Utc::now());
+        index.add_message(chat_id, &m1);
+        index.add_message(chat_id, &m2);
+
+        index.remove_message(m1.id);
+        assert_eq!(index.search("alpha", &SearchFilters::default()).len(), 1);
+        assert!(index.search("beta", &SearchFilters::default()).is_empty());
+
+        let mut deleted = m2.clone();
+        deleted.content = MessageContent::Deleted;
+        index.add_message(chat_id, &deleted);
+        assert!(index.search("gamma", &SearchFilters::default()).is_empty());
+
+        index.remove_chat(chat_id);
+        assert!(index.is_empty());
+        assert!(index.search("alpha", &SearchFilters::default()).is_empty());
+    }
+
+    #[test]
+    fn test_snippet_is_truncated_around_match() {
+        let long_text = format!("{} needle {}", "a ".repeat(100), "b ".repeat(100));
+        let snippet = make_snippet(&long_text, "needle");
+        assert!(snippet.contains("needle"));
+        assert!(snippet.starts_with('…'));
+        assert!(snippet.ends_with('…'));
+    }
+}
@@ -0,0 +1,263 @@
+//! Headless terminal mode.
+//!
+//! `run` hosts or connects through a `ChatManager`, asks the user to confirm the
+//! peer's fingerprint on stdin, prints incoming messages with timestamps and sends
+//! every stdin line as a message. `/file <path>` sends a file and `/quit` exits.
+//! Nothing is written to the history file in this mode.
+
+use anyhow::Result;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use uuid::Uuid;
+
+use crate::app::ChatManager;
+use crate::types::{Config, Message, MessageContent};
+
+/// How often session events are polled
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What the terminal session should do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CliMode {
+    Host { port: u16 },
+    Connect { host: String, port: u16 },
+}
+
+/// A line typed by the user
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CliCommand {
+    Message(String),
+    File(PathBuf),
+    Help,
+    Quit,
+    Empty,
+}
+
+/// Parse a stdin line into a command
+pub fn parse_command(line: &str) -> std::result::Result<CliCommand, String> {
+    let trimmed = line.trim();
+    if trimmed.is_empty() {
+        return Ok(CliCommand::Empty);
+    }
+    if !trimmed.starts_with('/') {
+        return Ok(CliCommand::Message(line.trim_end().to_string()));
+    }
+
+    let (command, arg) = match trimmed.split_once(char::is_whitespace) {
+        Some((command, arg)) => (command, arg.trim()),
+        None => (trimmed, ""),
+    };
+    match command {
+        "/file" if arg.is_empty() => Err("Usage: /file <path>".to_string()),
+        "/file" => Ok(CliCommand::File(PathBuf::from(arg))),
+        "/help" => Ok(CliCommand::Help),
+        "/quit" | "/exit" => Ok(CliCommand::Quit),
+        other => Err(format!("Unknown command {} (try /help)", other)),
+    }
+}
+
+/// Render a received message for the terminal, or `None` if it has nothing to show
+pub fn format_incoming(message: &Message) -> Option<String> {
+    let time = message
+        .timestamp
+        .with_timezone(&chrono::Local)
+        .format("%H:%M:%S");
+    let body = match &message.content {
+        MessageContent::Text { text } => text.clone(),
+        MessageContent::Edited { new_text } => format!("{} (edited)", new_text),
+        MessageContent::File {
+            filename,
+            size,
+            path,
+        } => match path {
+            Some(path) => format!(
+                "sent file {} ({}), saved to {}",
+                filename,
+                crate::util::format_size(*size),
+                path.display()
+            ),
+            None => format!(
+                "sent file {} ({})",
+                filename,
+                crate::util::format_size(*size)
+            ),
+        },
+        MessageContent::Deleted => return None,
+    };
+    Some(format!("[{}] peer: {}", time, body))
+}
+
+fn print_help() {
+    println!("Type a message and press Enter to send it.");
+    println!("  /file <path>  send a file");
+    println!("  /help         show this help");
+    println!("  /quit         disconnect and exit");
+}
+
+/// Run a headless chat session until the peer disconnects, the user quits or Ctrl-C
+pub async fn run(mode: CliMode) -> Result<()> {
+    let data_dir = crate::app::default_data_dir()
+        .ok_or_else(|| anyhow::anyhow!("Could not determine user data directory"))?;
+    let identity = crate::identity::commands::load_for_session(&data_dir)?;
+    let mut manager = ChatManager::new(Config::default());
+    manager.sync_identity(&identity);
+
+    let chat_id = match &mode {
+        CliMode::Host { port } => {
+            println!("Hosting on port {}, waiting for a peer...", port);
+            manager.start_host(*port).await?
+        }
+        CliMode::Connect { host, port } => {
+            println!("Connecting to {}:{}...", host, port);
+            manager.connect_to_host(host, *port, None).await?
+        }
+    };
+    let is_host = matches!(mode, CliMode::Host { .. });
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut ticker = tokio::time::interval(POLL_INTERVAL);
+    let mut printed: HashSet<Uuid> = HashSet::new();
+    // Fingerprint waiting for a yes/no answer on stdin
+    let mut pending_fingerprint: Option<String> = None;
+    let mut verified = false;
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                manager.poll_session_events();
+
+                for toast in std::mem::take(&mut manager.toasts) {
+                    println!("* {}", toast.message);
+                }
+
+                // Both sides get a verification request; only the client's
+                // session waits for the answer
+                if let Some((fingerprint, _, _)) = manager.fingerprint_verification_request.take()
+                    && pending_fingerprint.is_none()
+                {
+                    match manager.safety_numbers.get(&chat_id) {
+                        Some(safety_number) => {
+                            println!("Safety number:");
+                            for row in safety_number.groups().chunks(4) {
+                                println!("  {}", row.join(" "));
+                            }
+                            println!("  {}", safety_number.emoji().join(" "));
+                            println!("Does it match the one your peer sees? [y/N]");
+                        }
+                        None => {
+                            println!("Peer fingerprint: {}", fingerprint);
+                            println!("Does it match the one your peer sees? [y/N]");
+                        }
+                    }
+                    pending_fingerprint = Some(fingerprint);
+                }
+
+                // Messages are held back until the peer has been verified
+                if verified
+                    && let Some(chat) = manager.get_chat(chat_id)
+                {
+                    for message in chat.messages.iter().filter(|m| !m.from_me) {
+                        if printed.insert(message.id)
+                            && let Some(line) = format_incoming(message)
+                        {
+                            println!("{}", line);
+                        }
+                    }
+                }
+
+                if !manager.is_connected(chat_id) {
+                    println!("Disconnected.");
+                    return Ok(());
+                }
+            }
+
+            line = lines.next_line() => {
+                let Some(line) = line? else {
+                    // stdin closed
+                    return Ok(());
+                };
+
+                if pending_fingerprint.is_some() && !verified {
+                    let accept = matches!(line.trim().to_lowercase().as_str(), "y" | "yes");
+                    if !is_host {
+                        manager.confirm_fingerprint(chat_id, accept)?;
+                    }
+                    if !accept {
+                        println!("Fingerprint rejected, closing the connection.");
+                        manager.delete_chat(chat_id);
+                        return Ok(());
+                    }
+                    verified = true;
+                    manager.mark_verified(chat_id);
+                    println!("Fingerprint accepted. Type /help for commands.");
+                    continue;
+                }
+
+                match parse_command(&line) {
+                    Ok(CliCommand::Empty) => {}
+                    Ok(CliCommand::Help) => print_help(),
+                    Ok(CliCommand::Quit) => {
+                        manager.delete_chat(chat_id);
+                        return Ok(());
+                    }
+                    Ok(_) if !verified => println!("* Not connected to a verified peer yet"),
+                    Ok(CliCommand::Message(text)) => {
+                        if let Err(e) = manager.send_message(chat_id, text, None) {
+                            println!("* Failed to send: {}", e);
+                        }
+                    }
+                    Ok(CliCommand::File(path)) => {
+                        if let Err(e) = manager.send_file(chat_id, path).await {
+                            println!("* Failed to send file: {}", e);
+                        }
+                    }
+                    Err(usage) => println!("* {}", usage),
+                }
+            }
+
+            _ = tokio::signal::ctrl_c() => {
+                manager.delete_chat(chat_id);
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(parse_command("   "), Ok(CliCommand::Empty));
+        assert_eq!(
+            parse_command("  hello there \n"),
+            Ok(CliCommand::Message("  hello there".to_string()))
+        );
+        assert_eq!(
+            parse_command("/file /tmp/my notes.txt"),
+            Ok(CliCommand::File(PathBuf::from("/tmp/my notes.txt")))
+        );
+        assert_eq!(parse_command("/quit"), Ok(CliCommand::Quit));
+        assert!(parse_command("/file").is_err());
+        assert!(parse_command("/nope").is_err());
+    }
+
+    #[test]
+    fn test_format_incoming() {
+        let text = Message::new(
+            false,
+            MessageContent::Text {
+                text: "hi".to_string(),
+            },
+        );
+        let line = format_incoming(&text).unwrap();
+        assert!(line.starts_with('['));
+        assert!(line.ends_with("peer: hi"));
+
+        let deleted = Message::new(false, MessageContent::Deleted);
+        assert!(format_incoming(&deleted).is_none());
+    }
+}
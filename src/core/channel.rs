@@ -0,0 +1,267 @@
+//! Sequenced record layer for v4 sessions.
+//!
+//! The session key is split with HKDF into one key per direction, and every
+//! packet is numbered: `counter(8) || ciphertext || tag(16)`, with the counter
+//! as the AES-GCM nonce. Each side sends its packets in counter order over TCP,
+//! so the receiver only accepts counters above the last one it saw. Anything
+//! else is a replay or a reordering by someone on the path, and is rejected.
+
+use hkdf::Hkdf;
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+use crate::AES_KEY_SIZE;
+use crate::core::AesCipher;
+use crate::types::SecurityEvent;
+
+/// How many recent counters are remembered to tell a replay from a late packet
+pub const REPLAY_WINDOW: u64 = 64;
+
+/// Length of the counter that prefixes every packet
+pub const COUNTER_LEN: usize = 8;
+
+const CLIENT_TO_HOST_INFO: &[u8] = b"p2p-messenger-v4-client-to-host";
+const HOST_TO_CLIENT_INFO: &[u8] = b"p2p-messenger-v4-host-to-client";
+
+/// Counters accepted so far in one direction
+#[derive(Debug, Clone, Default)]
+pub struct ReceiveWindow {
+    /// One past the highest accepted counter
+    next: u64,
+    /// Bit `i` is set if counter `next - 1 - i` was accepted
+    seen: u64,
+}
+
+impl ReceiveWindow {
+    /// Whether `counter` may be accepted
+    pub fn check(&self, counter: u64) -> Result<(), SecurityEvent> {
+        if counter >= self.next {
+            return Ok(());
+        }
+        let age = self.next - 1 - counter;
+        if age < REPLAY_WINDOW && self.seen & (1 << age) != 0 {
+            Err(SecurityEvent::Replay { counter })
+        } else {
+            Err(SecurityEvent::OutOfOrder {
+                counter,
+                expected: self.next,
+            })
+        }
+    }
+
+    /// Record an authenticated packet that passed [`ReceiveWindow::check`].
+    /// Returns a `MissingPackets` event if counters were skipped.
+    pub fn accept(&mut self, counter: u64) -> Option<SecurityEvent> {
+        debug_assert!(counter >= self.next, "check() before accept()");
+        let shift = counter - self.next + 1;
+        self.seen = if shift >= REPLAY_WINDOW {
+            1
+        } else {
+            (self.seen << shift) | 1
+        };
+
+        let gap = (counter > self.next).then_some(SecurityEvent::MissingPackets {
+            expected: self.next,
+            received: counter,
+        });
+        self.next = counter + 1;
+        gap
+    }
+}
+
+fn direction_key(session_key: &[u8; AES_KEY_SIZE], info: &[u8]) -> AesCipher {
+    let hkdf = Hkdf::<Sha256>::new(None, session_key);
+    let mut key = Zeroizing::new([0u8; AES_KEY_SIZE]);
+    hkdf.expand(info, key.as_mut())
+        .expect("HKDF expand should not fail with valid length");
+    AesCipher::new(key.as_ref())
+}
+
+fn counter_nonce(counter: u64) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[4..].copy_from_slice(&counter.to_be_bytes());
+    nonce
+}
+
+/// Directional keys, send counter and receive window of one session
+pub struct SecureChannel {
+    initiator: bool,
+    send: AesCipher,
+    recv: AesCipher,
+    send_counter: u64,
+    window: ReceiveWindow,
+}
+
+impl SecureChannel {
+    /// `initiator` is true on the client side of the handshake
+    pub fn new(session_key: &[u8; AES_KEY_SIZE], initiator: bool) -> Self {
+        Self {
+            initiator,
+            send: direction_key(session_key, Self::send_info(initiator)),
+            recv: direction_key(session_key, Self::send_info(!initiator)),
+            send_counter: 0,
+            window: ReceiveWindow::default(),
+        }
+    }
+
+    fn send_info(initiator: bool) -> &'static [u8] {
+        if initiator {
+            CLIENT_TO_HOST_INFO
+        } else {
+            HOST_TO_CLIENT_INFO
+        }
+    }
+
+    /// Encrypt everything sent from now on under a new session key. The
+    /// counter restarts at 0 and the old key schedule is wiped on drop.
+    pub fn rekey_send(&mut self, session_key: &[u8; AES_KEY_SIZE]) {
+        self.send = direction_key(session_key, Self::send_info(self.initiator));
+        self.send_counter = 0;
+    }
+
+    /// Counterpart of [`SecureChannel::rekey_send`] for the receive direction
+    pub fn rekey_recv(&mut self, session_key: &[u8; AES_KEY_SIZE]) {
+        self.recv = direction_key(session_key, Self::send_info(!self.initiator));
+        self.window = ReceiveWindow::default();
+    }
+
+    /// Encrypt the next packet: counter(8) || ciphertext || tag(16)
+    pub fn seal(&mut self, plaintext: &[u8]) -> Vec<u8> {
+        let counter = self.send_counter;
+        self.send_counter = counter.checked_add(1).expect("packet counter exhausted");
+
+        let mut out = counter.to_be_bytes().to_vec();
+        out.extend(self.send.encrypt_with_nonce(&counter_nonce(counter), plaintext, &[]));
+        out
+    }
+
+    /// Decrypt a packet. Replayed, reordered and forged packets are rejected
+    /// without changing the window. An accepted packet can still come with a
+    /// `MissingPackets` warning.
+    pub fn open(
+        &mut self,
+        packet: &[u8],
+    ) -> Result<(Zeroizing<Vec<u8>>, Option<SecurityEvent>), SecurityEvent> {
+        if packet.len() < COUNTER_LEN {
+            return Err(SecurityEvent::AuthenticationFailed);
+        }
+        let (counter_bytes, ciphertext) = packet.split_at(COUNTER_LEN);
+        let counter = u64::from_be_bytes(counter_bytes.try_into().expect("8 bytes"));
+
+        self.window.check(counter)?;
+        let plaintext = self
+            .recv
+            .decrypt_with_nonce(&counter_nonce(counter), ciphertext, &[])
+            .ok_or(SecurityEvent::AuthenticationFailed)?;
+        Ok((plaintext, self.window.accept(counter)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// [`SecureChannel::open`] with the plaintext copied out for comparisons
+    fn open(
+        channel: &mut SecureChannel,
+        packet: &[u8],
+    ) -> Result<(Vec<u8>, Option<SecurityEvent>), SecurityEvent> {
+        channel
+            .open(packet)
+            .map(|(plaintext, warning)| (plaintext.to_vec(), warning))
+    }
+
+    fn pair() -> (SecureChannel, SecureChannel) {
+        let key = [9u8; AES_KEY_SIZE];
+        (SecureChannel::new(&key, true), SecureChannel::new(&key, false))
+    }
+
+    #[test]
+    fn test_directional_keys_and_counters() {
+        let (mut client, mut host) = pair();
+        let first = client.seal(b"hi");
+        let second = client.seal(b"hi");
+        assert_eq!(&first[..COUNTER_LEN], &0u64.to_be_bytes());
+        assert_eq!(&second[..COUNTER_LEN], &1u64.to_be_bytes());
+        assert_ne!(first[COUNTER_LEN..], second[COUNTER_LEN..]);
+
+        // A packet can't be reflected back to its sender
+        assert_eq!(open(&mut client, &first), Err(SecurityEvent::AuthenticationFailed));
+
+        assert_eq!(open(&mut host, &first).unwrap(), (b"hi".to_vec(), None));
+        assert_eq!(open(&mut host, &second).unwrap(), (b"hi".to_vec(), None));
+        let reply = host.seal(b"yo");
+        assert_eq!(&reply[..COUNTER_LEN], &0u64.to_be_bytes());
+        assert_eq!(open(&mut client, &reply).unwrap().0, b"yo");
+    }
+
+    #[test]
+    fn test_each_violation_is_reported_distinctly() {
+        let (mut client, mut host) = pair();
+        let packets: Vec<_> = (0..4).map(|i| client.seal(&[i])).collect();
+
+        open(&mut host, &packets[0]).unwrap();
+        assert_eq!(open(&mut host, &packets[0]), Err(SecurityEvent::Replay { counter: 0 }));
+
+        // #1 is held back: #2 is accepted with a warning, then #1 is too late
+        assert_eq!(
+            open(&mut host, &packets[2]).unwrap(),
+            (vec![2], Some(SecurityEvent::MissingPackets { expected: 1, received: 2 }))
+        );
+        assert_eq!(
+            open(&mut host, &packets[1]),
+            Err(SecurityEvent::OutOfOrder { counter: 1, expected: 3 })
+        );
+
+        // Changing the counter breaks authentication and leaves the window alone
+        let mut forged = packets[3].clone();
+        forged[COUNTER_LEN - 1] = 9;
+        assert_eq!(open(&mut host, &forged), Err(SecurityEvent::AuthenticationFailed));
+        assert_eq!(open(&mut host, &packets[3]).unwrap(), (vec![3], None));
+        assert_eq!(open(&mut host, &[0; 3]), Err(SecurityEvent::AuthenticationFailed));
+    }
+
+    #[test]
+    fn test_each_direction_switches_keys_on_its_own() {
+        let (mut client, mut host) = pair();
+        let new_key = [10u8; AES_KEY_SIZE];
+        for _ in 0..3 {
+            open(&mut host, &client.seal(b"warm up")).unwrap();
+        }
+
+        // Sent before the switch, read after the host switched its send key
+        let in_flight = client.seal(b"old");
+        client.rekey_send(&new_key);
+        let fresh = client.seal(b"new");
+        assert_eq!(&fresh[..COUNTER_LEN], &0u64.to_be_bytes());
+
+        assert_eq!(open(&mut host, &in_flight).unwrap().0, b"old");
+        // Until the receive key is switched, the restarted counter looks like a replay
+        assert_eq!(open(&mut host, &fresh), Err(SecurityEvent::Replay { counter: 0 }));
+        host.rekey_recv(&new_key);
+        assert_eq!(open(&mut host, &fresh).unwrap(), (b"new".to_vec(), None));
+        assert_eq!(open(&mut host, &in_flight), Err(SecurityEvent::AuthenticationFailed));
+
+        // The other direction still uses the old key
+        assert_eq!(open(&mut client, &host.seal(b"reply")).unwrap().0, b"reply");
+    }
+
+    #[test]
+    fn test_receive_window_vectors() {
+        // (counter, expected result of check, then accepted?)
+        let mut window = ReceiveWindow::default();
+        for (counter, accepted) in [(0, true), (1, true), (1, false), (5, true), (3, false), (70, true)] {
+            assert_eq!(window.check(counter).is_ok(), accepted, "counter {}", counter);
+            if accepted {
+                window.accept(counter);
+            }
+        }
+        assert_eq!(window.check(70), Err(SecurityEvent::Replay { counter: 70 }));
+        // #5 is still remembered (age 65 is outside the window): reported as out of order
+        assert_eq!(
+            window.check(5),
+            Err(SecurityEvent::OutOfOrder { counter: 5, expected: 71 })
+        );
+        assert_eq!(window.check(7), Err(SecurityEvent::OutOfOrder { counter: 7, expected: 71 }));
+    }
+}
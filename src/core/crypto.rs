@@ -1,9 +1,10 @@
 use aes_gcm::{
-    aead::{Aead, KeyInit},
+    aead::{Aead, KeyInit, Payload},
     Aes256Gcm, Nonce,
 };
 use anyhow::{anyhow, Result};
 use hkdf::Hkdf;
+use ml_kem::{Decapsulate, Encapsulate, Kem, Key, KeyExport, MlKem768};
 use rand::{rngs::OsRng, RngCore};
 use rsa::{
     pkcs1::{DecodeRsaPrivateKey, EncodeRsaPrivateKey},
@@ -12,6 +13,7 @@ use rsa::{
 };
 use sha2::{Digest, Sha256};
 use x25519_dalek::{EphemeralSecret, PublicKey as X25519PublicKey};
+use zeroize::{ZeroizeOnDrop, Zeroizing};
 
 use crate::AES_KEY_SIZE;
 
@@ -43,10 +45,9 @@ pub fn pem_decode_public(pem: &str) -> Result<RsaPublicKey> {
 }
 
 /// Export RSA private key to PEM format
-pub fn pem_encode_private(privkey: &RsaPrivateKey) -> Result<String> {
+pub fn pem_encode_private(privkey: &RsaPrivateKey) -> Result<Zeroizing<String>> {
     privkey
         .to_pkcs1_pem(Default::default())
-        .map(|pem| pem.to_string())
         .map_err(|e| anyhow!("Private PEM encode failed: {}", e))
 }
 
@@ -98,21 +99,21 @@ pub fn generate_ephemeral_keypair() -> (EphemeralSecret, X25519PublicKey) {
 /// * `info` - Context string for HKDF (e.g., "p2p-messenger-v2")
 ///
 /// # Returns
-/// 32-byte AES-256 key derived from shared secret
+/// 32-byte AES-256 key derived from shared secret, wiped when dropped
 pub fn derive_session_key(
     our_secret: EphemeralSecret,
     their_public: &X25519PublicKey,
     info: &[u8],
-) -> [u8; AES_KEY_SIZE] {
-    // Perform ECDH to get shared secret
+) -> Zeroizing<[u8; AES_KEY_SIZE]> {
+    // Perform ECDH to get shared secret (zeroized on drop by x25519-dalek)
     let shared_secret = our_secret.diffie_hellman(their_public);
 
     // Use HKDF-SHA256 to derive session key
     // Salt is None (uses zeros), which is acceptable for ephemeral keys
     let hkdf = Hkdf::<Sha256>::new(None, shared_secret.as_bytes());
 
-    let mut session_key = [0u8; AES_KEY_SIZE];
-    hkdf.expand(info, &mut session_key)
+    let mut session_key = Zeroizing::new([0u8; AES_KEY_SIZE]);
+    hkdf.expand(info, session_key.as_mut())
         .expect("HKDF expand should not fail with valid length");
 
     session_key
@@ -132,12 +133,67 @@ pub fn parse_x25519_public(bytes: &[u8]) -> Result<X25519PublicKey> {
     Ok(X25519PublicKey::from(key_bytes))
 }
 
-/// AES-GCM cipher wrapper for encrypting/decrypting messages
-#[derive(Clone)]
+// ============================================================================
+// ML-KEM-768 for the hybrid post-quantum handshake
+// ============================================================================
+
+/// ML-KEM-768 decapsulation (private) key, kept for a single handshake and
+/// wiped on drop
+pub type KemDecapsulationKey = ml_kem::ml_kem_768::DecapsulationKey;
+
+/// Generate an ML-KEM-768 key pair; the public half is returned encoded
+pub fn generate_kem_keypair() -> (KemDecapsulationKey, Vec<u8>) {
+    let (decapsulation_key, encapsulation_key) = MlKem768::generate_keypair();
+    (decapsulation_key, encapsulation_key.to_bytes().to_vec())
+}
+
+/// Encapsulate a fresh shared secret to the peer's encoded ML-KEM-768 public key.
+/// Returns the ciphertext to send and the shared secret.
+pub fn kem_encapsulate(public_key: &[u8]) -> Result<(Vec<u8>, Zeroizing<[u8; 32]>)> {
+    let encoded = Key::<ml_kem::ml_kem_768::EncapsulationKey>::try_from(public_key)
+        .map_err(|_| anyhow!("ML-KEM public key has the wrong length ({} bytes)", public_key.len()))?;
+    let encapsulation_key = ml_kem::ml_kem_768::EncapsulationKey::new(&encoded)
+        .map_err(|_| anyhow!("Invalid ML-KEM public key"))?;
+    let (ciphertext, shared) = encapsulation_key.encapsulate();
+    Ok((ciphertext.to_vec(), Zeroizing::new(shared.into())))
+}
+
+/// Recover the shared secret from an ML-KEM-768 ciphertext
+pub fn kem_decapsulate(key: &KemDecapsulationKey, ciphertext: &[u8]) -> Result<Zeroizing<[u8; 32]>> {
+    key.decapsulate_slice(ciphertext)
+        .map(|shared| Zeroizing::new(shared.into()))
+        .map_err(|_| anyhow!("ML-KEM ciphertext has the wrong length ({} bytes)", ciphertext.len()))
+}
+
+/// Combine the classical session key from [`derive_session_key`] with an ML-KEM
+/// shared secret. The result stays secret as long as either input does.
+pub fn derive_hybrid_session_key(
+    classical_key: &[u8; AES_KEY_SIZE],
+    kem_shared_secret: &[u8; 32],
+    info: &[u8],
+) -> Zeroizing<[u8; AES_KEY_SIZE]> {
+    let mut input_key_material = Zeroizing::new([0u8; AES_KEY_SIZE + 32]);
+    input_key_material[..AES_KEY_SIZE].copy_from_slice(classical_key);
+    input_key_material[AES_KEY_SIZE..].copy_from_slice(kem_shared_secret);
+    let hkdf = Hkdf::<Sha256>::new(None, input_key_material.as_ref());
+
+    let mut session_key = Zeroizing::new([0u8; AES_KEY_SIZE]);
+    hkdf.expand(info, session_key.as_mut())
+        .expect("HKDF expand should not fail with valid length");
+
+    session_key
+}
+
+/// AES-GCM cipher wrapper for encrypting/decrypting messages. Not `Clone`, so
+/// the key schedule exists once and is wiped when the cipher is dropped.
 pub struct AesCipher {
     cipher: Aes256Gcm,
 }
 
+// The AES key schedule and the GHASH key are wiped on drop (`zeroize` features
+// of `aes` and `polyval`)
+impl ZeroizeOnDrop for AesCipher {}
+
 impl AesCipher {
     /// Create new cipher from 32-byte key
     pub fn new(key: &[u8]) -> Self {
@@ -149,6 +205,11 @@ impl AesCipher {
 
     /// Encrypt plaintext, returns nonce(12) || ciphertext || tag(16)
     pub fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+        self.encrypt_with_aad(plaintext, &[])
+    }
+
+    /// Like [`AesCipher::encrypt`], also authenticating `aad` (which is not included in the output)
+    pub fn encrypt_with_aad(&self, plaintext: &[u8], aad: &[u8]) -> Vec<u8> {
         let mut nonce_bytes = [0u8; 12];
         rand::thread_rng().fill_bytes(&mut nonce_bytes);
         // Nonce::try_from accepts an array by value
@@ -156,7 +217,7 @@ impl AesCipher {
 
         let ciphertext = self
             .cipher
-            .encrypt(&nonce, plaintext)
+            .encrypt(&nonce, Payload { msg: plaintext, aad })
             .expect("AES-GCM encryption should not fail");
 
         // Format: nonce || ciphertext (includes tag)
@@ -166,8 +227,30 @@ impl AesCipher {
         output
     }
 
-    /// Decrypt payload: nonce(12) || ciphertext || tag(16)
-    pub fn decrypt(&self, payload: &[u8]) -> Option<Vec<u8>> {
+    /// Encrypt with a caller-chosen nonce, returns ciphertext || tag(16).
+    /// The caller must never reuse a nonce with the same key.
+    pub fn encrypt_with_nonce(&self, nonce: &[u8; 12], plaintext: &[u8], aad: &[u8]) -> Vec<u8> {
+        self.cipher
+            .encrypt(&Nonce::from(*nonce), Payload { msg: plaintext, aad })
+            .expect("AES-GCM encryption should not fail")
+    }
+
+    /// Decrypt ciphertext || tag(16) produced by [`AesCipher::encrypt_with_nonce`]
+    pub fn decrypt_with_nonce(&self, nonce: &[u8; 12], ciphertext: &[u8], aad: &[u8]) -> Option<Zeroizing<Vec<u8>>> {
+        self.cipher
+            .decrypt(&Nonce::from(*nonce), Payload { msg: ciphertext, aad })
+            .ok()
+            .map(Zeroizing::new)
+    }
+
+    /// Decrypt payload: nonce(12) || ciphertext || tag(16). The plaintext is
+    /// wiped when dropped.
+    pub fn decrypt(&self, payload: &[u8]) -> Option<Zeroizing<Vec<u8>>> {
+        self.decrypt_with_aad(payload, &[])
+    }
+
+    /// Decrypt a payload from [`AesCipher::encrypt_with_aad`]; fails unless `aad` matches
+    pub fn decrypt_with_aad(&self, payload: &[u8], aad: &[u8]) -> Option<Zeroizing<Vec<u8>>> {
         if payload.len() < 12 + 16 {
             return None; // Too small
         }
@@ -181,7 +264,10 @@ impl AesCipher {
 
         let nonce = Nonce::from(nonce_arr);
 
-        self.cipher.decrypt(&nonce, ciphertext).ok()
+        self.cipher
+            .decrypt(&nonce, Payload { msg: ciphertext, aad })
+            .ok()
+            .map(Zeroizing::new)
     }
 }
 
@@ -189,6 +275,9 @@ impl AesCipher {
 mod tests {
     use super::*;
 
+    /// Compile-time check that a value wipes its memory when dropped
+    fn assert_zeroize_on_drop<T: ZeroizeOnDrop>(_: &T) {}
+
     #[test]
     fn test_aes_roundtrip() {
         let key = [42u8; 32];
@@ -214,8 +303,8 @@ mod tests {
         assert_ne!(enc1, enc2);
 
         // But both should decrypt correctly
-        assert_eq!(cipher.decrypt(&enc1).unwrap(), plaintext);
-        assert_eq!(cipher.decrypt(&enc2).unwrap(), plaintext);
+        assert_eq!(cipher.decrypt(&enc1).unwrap().as_slice(), plaintext);
+        assert_eq!(cipher.decrypt(&enc2).unwrap().as_slice(), plaintext);
     }
 
     #[test]
@@ -361,8 +450,8 @@ mod tests {
         assert_eq!(alice_key, bob_key);
 
         // 6. Use keys for encryption
-        let alice_cipher = AesCipher::new(&alice_key);
-        let bob_cipher = AesCipher::new(&bob_key);
+        let alice_cipher = AesCipher::new(alice_key.as_ref());
+        let bob_cipher = AesCipher::new(bob_key.as_ref());
 
         let plaintext = b"Forward secrecy test message";
         let encrypted = alice_cipher.encrypt(plaintext);
@@ -370,4 +459,54 @@ mod tests {
 
         assert_eq!(plaintext, &decrypted[..]);
     }
+
+    #[test]
+    fn test_hybrid_key_agreement() {
+        let (alice_secret, alice_public) = generate_ephemeral_keypair();
+        let (bob_secret, bob_public) = generate_ephemeral_keypair();
+        let info = b"p2p-messenger-hybrid";
+        let alice_classical = derive_session_key(alice_secret, &bob_public, info);
+        let bob_classical = derive_session_key(bob_secret, &alice_public, info);
+
+        // Alice publishes an ML-KEM key, Bob encapsulates to it
+        let (alice_kem, alice_kem_public) = generate_kem_keypair();
+        let (ciphertext, bob_shared) = kem_encapsulate(&alice_kem_public).unwrap();
+        let alice_shared = kem_decapsulate(&alice_kem, &ciphertext).unwrap();
+        assert_eq!(alice_shared, bob_shared);
+
+        let alice_key = derive_hybrid_session_key(&alice_classical, &alice_shared, info);
+        let bob_key = derive_hybrid_session_key(&bob_classical, &bob_shared, info);
+        assert_eq!(alice_key, bob_key);
+        assert_ne!(alice_key, alice_classical);
+
+        // A different KEM secret gives a different key
+        let (_, other_shared) = kem_encapsulate(&alice_kem_public).unwrap();
+        assert_ne!(
+            derive_hybrid_session_key(&alice_classical, &other_shared, info),
+            alice_key
+        );
+
+        assert!(kem_encapsulate(&alice_kem_public[1..]).is_err());
+        assert!(kem_decapsulate(&alice_kem, &ciphertext[1..]).is_err());
+    }
+
+    #[test]
+    fn test_session_secrets_are_zeroized_on_drop() {
+        let (alice_secret, _) = generate_ephemeral_keypair();
+        let (_, bob_public) = generate_ephemeral_keypair();
+        let classical = derive_session_key(alice_secret, &bob_public, b"ctx");
+        assert_zeroize_on_drop(&classical);
+
+        let (kem_key, kem_public) = generate_kem_keypair();
+        assert_zeroize_on_drop(&kem_key);
+        let (ciphertext, shared) = kem_encapsulate(&kem_public).unwrap();
+        assert_zeroize_on_drop(&shared);
+        assert_zeroize_on_drop(&kem_decapsulate(&kem_key, &ciphertext).unwrap());
+
+        let session_key = derive_hybrid_session_key(&classical, &shared, b"ctx");
+        assert_zeroize_on_drop(&session_key);
+        let cipher = AesCipher::new(session_key.as_ref());
+        assert_zeroize_on_drop(&cipher);
+        assert_zeroize_on_drop(&cipher.decrypt(&cipher.encrypt(b"secret")).unwrap());
+    }
 }
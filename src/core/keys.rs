@@ -0,0 +1,216 @@
+//! Signature key types: Ed25519 (default) and RSA (legacy identities and peers).
+//!
+//! Both are exchanged and stored as PKCS#8 / SPKI PEM, so the algorithm can be
+//! recovered from the encoding alone and older RSA identity files load unchanged.
+
+use anyhow::{Result, anyhow};
+use ed25519_dalek::Signer as _;
+use rand::rngs::OsRng;
+use rsa::pkcs8::{DecodePrivateKey, DecodePublicKey, EncodePrivateKey, EncodePublicKey, LineEnding};
+use rsa::signature::{RandomizedSigner, SignatureEncoding, Verifier};
+use rsa::{RsaPrivateKey, RsaPublicKey, pss};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+use sha2::{Digest, Sha256};
+
+use crate::RSA_KEY_BITS;
+
+/// Length of an Ed25519 fingerprint in bytes (shown as 40 hex characters)
+pub const ED25519_FINGERPRINT_BYTES: usize = 20;
+
+/// Signature algorithm of an identity or session key
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyAlgorithm {
+    #[default]
+    Ed25519,
+    /// RSA-2048, kept for identities created by earlier versions and old peers
+    Rsa,
+}
+
+impl KeyAlgorithm {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Ed25519 => "Ed25519",
+            Self::Rsa => "RSA-2048",
+        }
+    }
+}
+
+/// Private signing key
+#[derive(Clone, PartialEq)]
+pub enum PrivateKey {
+    Ed25519(ed25519_dalek::SigningKey),
+    Rsa(RsaPrivateKey),
+}
+
+// Only public parts, so a logged key never leaks the secret
+impl std::fmt::Debug for PrivateKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PrivateKey")
+            .field("algorithm", &self.algorithm())
+            .field("public_key", &self.public_key())
+            .finish()
+    }
+}
+
+impl PrivateKey {
+    /// Generate a key pair (blocking for RSA; see `generate_rsa_keypair_async`)
+    pub fn generate(algorithm: KeyAlgorithm) -> Result<Self> {
+        Ok(match algorithm {
+            KeyAlgorithm::Ed25519 => Self::Ed25519(ed25519_dalek::SigningKey::generate(&mut OsRng)),
+            KeyAlgorithm::Rsa => Self::Rsa(crate::core::generate_rsa_keypair(RSA_KEY_BITS)?),
+        })
+    }
+
+    pub fn algorithm(&self) -> KeyAlgorithm {
+        match self {
+            Self::Ed25519(_) => KeyAlgorithm::Ed25519,
+            Self::Rsa(_) => KeyAlgorithm::Rsa,
+        }
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        match self {
+            Self::Ed25519(key) => PublicKey::Ed25519(key.verifying_key()),
+            Self::Rsa(key) => PublicKey::Rsa(RsaPublicKey::from(key)),
+        }
+    }
+
+    /// PKCS#8 PEM encoding, wiped when dropped
+    pub fn to_pem(&self) -> Result<Zeroizing<String>> {
+        Ok(match self {
+            Self::Ed25519(key) => key.to_pkcs8_pem(LineEnding::LF)?,
+            Self::Rsa(key) => key.to_pkcs8_pem(LineEnding::LF)?,
+        })
+    }
+
+    /// Parse a PKCS#8 PEM private key of either algorithm
+    pub fn from_pem(pem: &str) -> Result<Self> {
+        if let Ok(key) = ed25519_dalek::SigningKey::from_pkcs8_pem(pem) {
+            return Ok(Self::Ed25519(key));
+        }
+        RsaPrivateKey::from_pkcs8_pem(pem)
+            .map(Self::Rsa)
+            .map_err(|e| anyhow!("Unsupported private key: {}", e))
+    }
+
+    /// Sign `message` (Ed25519, or RSA-PSS with SHA-256)
+    pub fn sign(&self, message: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Ed25519(key) => key.sign(message).to_vec(),
+            Self::Rsa(key) => pss::SigningKey::<Sha256>::new(key.clone())
+                .sign_with_rng(&mut OsRng, message)
+                .to_vec(),
+        }
+    }
+}
+
+/// Public verification key
+#[derive(Debug, Clone, PartialEq)]
+pub enum PublicKey {
+    Ed25519(ed25519_dalek::VerifyingKey),
+    Rsa(RsaPublicKey),
+}
+
+impl PublicKey {
+    pub fn algorithm(&self) -> KeyAlgorithm {
+        match self {
+            Self::Ed25519(_) => KeyAlgorithm::Ed25519,
+            Self::Rsa(_) => KeyAlgorithm::Rsa,
+        }
+    }
+
+    /// SPKI PEM encoding
+    pub fn to_pem(&self) -> Result<String> {
+        Ok(match self {
+            Self::Ed25519(key) => key.to_public_key_pem(LineEnding::LF)?,
+            Self::Rsa(key) => key.to_public_key_pem(LineEnding::LF)?,
+        })
+    }
+
+    /// Parse an SPKI PEM public key of either algorithm
+    pub fn from_pem(pem: &str) -> Result<Self> {
+        if let Ok(key) = ed25519_dalek::VerifyingKey::from_public_key_pem(pem) {
+            return Ok(Self::Ed25519(key));
+        }
+        RsaPublicKey::from_public_key_pem(pem)
+            .map(Self::Rsa)
+            .map_err(|e| anyhow!("Unsupported public key: {}", e))
+    }
+
+    /// Check a signature made with [`PrivateKey::sign`]
+    pub fn verify(&self, message: &[u8], signature: &[u8]) -> Result<()> {
+        match self {
+            Self::Ed25519(key) => {
+                let signature = ed25519_dalek::Signature::from_slice(signature)
+                    .map_err(|_| anyhow!("Malformed signature"))?;
+                key.verify(message, &signature)
+                    .map_err(|_| anyhow!("Signature does not verify"))
+            }
+            Self::Rsa(key) => {
+                let signature = pss::Signature::try_from(signature)
+                    .map_err(|_| anyhow!("Malformed signature"))?;
+                pss::VerifyingKey::<Sha256>::new(key.clone())
+                    .verify(message, &signature)
+                    .map_err(|_| anyhow!("Signature does not verify"))
+            }
+        }
+    }
+}
+
+/// Fingerprint of a PEM public key. Ed25519 keys get a short SHA-256 prefix of
+/// the raw key; RSA keys keep the historical SHA-256 of the PEM text, so pinned
+/// RSA fingerprints stay valid.
+pub fn fingerprint_public_key_pem(pem: &str) -> String {
+    match PublicKey::from_pem(pem) {
+        Ok(PublicKey::Ed25519(key)) => {
+            hex::encode(&Sha256::digest(key.as_bytes())[..ED25519_FINGERPRINT_BYTES])
+        }
+        _ => hex::encode(Sha256::digest(pem.as_bytes())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ed25519_pem_sign_verify() {
+        let key = PrivateKey::generate(KeyAlgorithm::Ed25519).unwrap();
+        let restored = PrivateKey::from_pem(&key.to_pem().unwrap()).unwrap();
+        assert_eq!(restored, key);
+
+        let public_pem = key.public_key().to_pem().unwrap();
+        let public = PublicKey::from_pem(&public_pem).unwrap();
+        assert_eq!(public.algorithm(), KeyAlgorithm::Ed25519);
+
+        let signature = key.sign(b"hello");
+        public.verify(b"hello", &signature).unwrap();
+        assert!(public.verify(b"hellp", &signature).is_err());
+        assert_eq!(fingerprint_public_key_pem(&public_pem).len(), 40);
+    }
+
+    #[test]
+    fn test_rsa_keys_stay_supported() {
+        let key = PrivateKey::generate(KeyAlgorithm::Rsa).unwrap();
+        let restored = PrivateKey::from_pem(&key.to_pem().unwrap()).unwrap();
+        assert_eq!(restored.algorithm(), KeyAlgorithm::Rsa);
+
+        let public_pem = key.public_key().to_pem().unwrap();
+        let public = PublicKey::from_pem(&public_pem).unwrap();
+        public.verify(b"hello", &key.sign(b"hello")).unwrap();
+        assert_eq!(
+            fingerprint_public_key_pem(&public_pem),
+            crate::core::fingerprint_pubkey(public_pem.as_bytes())
+        );
+
+        // Debug output shows the public half only
+        use rsa::traits::PrivateKeyParts;
+        let PrivateKey::Rsa(inner) = &key else { unreachable!() };
+        let debug = format!("{:?}", key);
+        assert!(debug.contains("Rsa"));
+        assert!(!debug.contains(&inner.d().to_string()));
+        assert!(!debug.contains(&inner.primes()[0].to_string()));
+    }
+}
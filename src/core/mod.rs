@@ -1,7 +1,12 @@
+pub mod channel;
 pub mod crypto;
 pub mod framing;
+pub mod keys;
 pub mod protocol;
+pub mod ratchet;
+pub mod safety;
 
 pub use crypto::*;
 pub use framing::*;
+pub use keys::*;
 pub use protocol::*;
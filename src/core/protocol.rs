@@ -1,7 +1,27 @@
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 /// Protocol version for forward compatibility
-pub const PROTOCOL_VERSION: u8 = 2;
+pub const PROTOCOL_VERSION: u8 = 5;
+
+/// First protocol version whose handshake is signed with the identity keys.
+/// Older peers are refused: their unsigned handshake can be intercepted.
+pub const SIGNED_HANDSHAKE_MIN_VERSION: u8 = 5;
+
+/// Capability: hybrid X25519 + ML-KEM-768 session key
+pub const CAPABILITY_HYBRID_KEM: &str = "mlkem768";
+
+/// Capability: per-message keys from a Double Ratchet instead of one session key
+pub const CAPABILITY_DOUBLE_RATCHET: &str = "double-ratchet";
+
+/// Capability: directional keys and counter nonces, with replay and ordering checks
+pub const CAPABILITY_SEQUENCED: &str = "counter-nonces";
+
+/// Capability: in-session rekeying with `Rekey`/`RekeyAck` (requires `counter-nonces`)
+pub const CAPABILITY_REKEY: &str = "rekey";
+
+/// Capability: the client's first encrypted packet is an `InviteProof`
+pub const CAPABILITY_INVITE_PROOF: &str = "invite-proof";
 
 /// Protocol messages exchanged between peers
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -9,11 +29,68 @@ pub enum ProtocolMessage {
     /// Protocol version announcement (first message)
     Version { version: u8 },
 
+    /// Optional handshake features supported by the sender (v4+)
+    Capabilities { features: Vec<String> },
+
     /// Ephemeral X25519 public key for forward secrecy
     EphemeralKey { public_key: Vec<u8> },
 
-    /// Text message
-    Text { text: String, timestamp: u64 },
+    /// Host's ephemeral ML-KEM-768 public key (hybrid handshake only)
+    KemPublicKey { public_key: Vec<u8> },
+
+    /// Client's ML-KEM-768 ciphertext for the host's key (hybrid handshake only)
+    KemCiphertext { ciphertext: Vec<u8> },
+
+    /// Sender's identity signature over the handshake transcript (v5+)
+    HandshakeSignature { signature: Vec<u8> },
+
+    /// Initial Double Ratchet public key, sent under the handshake key
+    RatchetKey { public_key: Vec<u8> },
+
+    /// Fresh X25519 public key for an in-session rekey, sent under the current key
+    Rekey { public_key: Vec<u8> },
+
+    /// Last packet the sender encrypts with the old key; everything after it uses the new one
+    RekeyAck,
+
+    /// Proof that the client holds the secret of one of the host's invites,
+    /// bound to the session key; empty when it came without an invite
+    InviteProof { proof: Vec<u8> },
+
+    /// Text message. `id` is shared by both peers so later edits/deletions can target it.
+    /// `reply_to` is the ID of the message being answered, if any.
+    Text {
+        id: Uuid,
+        text: String,
+        reply_to: Option<Uuid>,
+        timestamp: u64,
+    },
+
+    /// Replace the text of a previously sent message
+    EditMessage {
+        message_id: Uuid,
+        new_text: String,
+        edited_at: u64,
+    },
+
+    /// Delete a previously sent message
+    DeleteMessage { message_id: Uuid },
+
+    /// Add or remove the sender's emoji reaction on a message
+    Reaction {
+        message_id: Uuid,
+        emoji: String,
+        add: bool,
+    },
+
+    /// Set the disappearing-messages timer of the chat or group `chat_id`, in
+    /// seconds (0 turns it off)
+    SetExpiry { chat_id: Uuid, seconds: u64 },
+
+    /// The sender rotated their identity key; signed by the old key
+    KeyTransition {
+        transition: crate::identity::KeyTransition,
+    },
 
     /// File metadata (sent before chunks)
     FileMeta { filename: String, size: u64 },
@@ -40,13 +117,94 @@ impl ProtocolMessage {
         match self {
             Self::Version { version } => format!("VERSION:{}", version).into_bytes(),
 
+            Self::Capabilities { features } => {
+                format!("CAPABILITIES:{}", features.join(",")).into_bytes()
+            }
+
             Self::EphemeralKey { public_key } => {
                 let mut v = b"EPHEMERAL_KEY:".to_vec();
                 v.extend_from_slice(public_key);
                 v
             }
 
-            Self::Text { text, .. } => format!("TEXT:{}", text).into_bytes(),
+            Self::KemPublicKey { public_key } => {
+                let mut v = b"KEM_PUBLIC_KEY:".to_vec();
+                v.extend_from_slice(public_key);
+                v
+            }
+
+            Self::KemCiphertext { ciphertext } => {
+                let mut v = b"KEM_CIPHERTEXT:".to_vec();
+                v.extend_from_slice(ciphertext);
+                v
+            }
+
+            Self::RatchetKey { public_key } => {
+                let mut v = b"RATCHET_KEY:".to_vec();
+                v.extend_from_slice(public_key);
+                v
+            }
+
+            Self::Rekey { public_key } => {
+                let mut v = b"REKEY:".to_vec();
+                v.extend_from_slice(public_key);
+                v
+            }
+
+            Self::RekeyAck => b"REKEY_ACK".to_vec(),
+
+            Self::HandshakeSignature { signature } => {
+                let mut v = b"HANDSHAKE_SIG:".to_vec();
+                v.extend_from_slice(signature);
+                v
+            }
+
+            Self::InviteProof { proof } => {
+                let mut v = b"INVITE_PROOF:".to_vec();
+                v.extend_from_slice(proof);
+                v
+            }
+
+            Self::Text {
+                id,
+                text,
+                reply_to: Some(reply_to),
+                ..
+            } => format!("REPLY|{}|{}|{}", id, reply_to, text).into_bytes(),
+
+            Self::Text { id, text, .. } => format!("TEXT:{}:{}", id, text).into_bytes(),
+
+            Self::EditMessage {
+                message_id,
+                new_text,
+                edited_at,
+            } => format!("EDIT_MESSAGE|{}|{}|{}", message_id, edited_at, new_text).into_bytes(),
+
+            Self::DeleteMessage { message_id } => {
+                format!("DELETE_MESSAGE|{}", message_id).into_bytes()
+            }
+
+            Self::Reaction {
+                message_id,
+                emoji,
+                add,
+            } => format!(
+                "REACTION|{}|{}|{}",
+                message_id,
+                if *add { '+' } else { '-' },
+                emoji
+            )
+            .into_bytes(),
+
+            Self::SetExpiry { chat_id, seconds } => {
+                format!("SET_EXPIRY:{}:{}", chat_id, seconds).into_bytes()
+            }
+
+            Self::KeyTransition { transition } => {
+                let mut v = b"KEY_TRANSITION:".to_vec();
+                v.extend(serde_json::to_vec(transition).unwrap_or_default());
+                v
+            }
 
             Self::FileMeta { filename, size } => {
                 format!("FILE_META|{}|{}", filename, size).into_bytes()
@@ -76,15 +234,118 @@ impl ProtocolMessage {
                 return Some(Self::Version { version });
             }
             None
+        } else if b.starts_with(b"CAPABILITIES:") {
+            let s = String::from_utf8_lossy(&b[13..]);
+            let features = s
+                .split(',')
+                .map(str::trim)
+                .filter(|f| !f.is_empty())
+                .map(str::to_string)
+                .collect();
+            Some(Self::Capabilities { features })
         } else if b.starts_with(b"EPHEMERAL_KEY:") {
             let public_key = b[14..].to_vec();
             Some(Self::EphemeralKey { public_key })
+        } else if b.starts_with(b"KEM_PUBLIC_KEY:") {
+            let public_key = b[15..].to_vec();
+            Some(Self::KemPublicKey { public_key })
+        } else if b.starts_with(b"KEM_CIPHERTEXT:") {
+            let ciphertext = b[15..].to_vec();
+            Some(Self::KemCiphertext { ciphertext })
+        } else if b.starts_with(b"HANDSHAKE_SIG:") {
+            let signature = b[14..].to_vec();
+            Some(Self::HandshakeSignature { signature })
+        } else if b.starts_with(b"RATCHET_KEY:") {
+            let public_key = b[12..].to_vec();
+            Some(Self::RatchetKey { public_key })
+        } else if b.starts_with(b"REKEY:") {
+            let public_key = b[6..].to_vec();
+            Some(Self::Rekey { public_key })
+        } else if b == b"REKEY_ACK" {
+            Some(Self::RekeyAck)
+        } else if b.starts_with(b"INVITE_PROOF:") {
+            let proof = b[13..].to_vec();
+            Some(Self::InviteProof { proof })
         } else if b.starts_with(b"TEXT:") {
-            let text = String::from_utf8_lossy(&b[5..]).into_owned();
+            let body = String::from_utf8_lossy(&b[5..]);
+            // "TEXT:<uuid>:<text>"; legacy peers send "TEXT:<text>" without an ID
+            let (id, text) = match body.split_once(':') {
+                Some((id, text)) if id.len() == 36 => match Uuid::parse_str(id) {
+                    Ok(id) => (id, text.to_string()),
+                    Err(_) => (Uuid::new_v4(), body.to_string()),
+                },
+                _ => (Uuid::new_v4(), body.to_string()),
+            };
             Some(Self::Text {
+                id,
                 text,
+                reply_to: None,
                 timestamp: crate::util::current_timestamp_millis(),
             })
+        } else if b.starts_with(b"REPLY|") {
+            let s = String::from_utf8_lossy(b);
+            let parts: Vec<&str> = s.splitn(4, '|').collect();
+            if parts.len() == 4
+                && let Ok(id) = Uuid::parse_str(parts[1])
+                && let Ok(reply_to) = Uuid::parse_str(parts[2])
+            {
+                return Some(Self::Text {
+                    id,
+                    text: parts[3].to_string(),
+                    reply_to: Some(reply_to),
+                    timestamp: crate::util::current_timestamp_millis(),
+                });
+            }
+            None
+        } else if b.starts_with(b"EDIT_MESSAGE|") {
+            let s = String::from_utf8_lossy(b);
+            let parts: Vec<&str> = s.splitn(4, '|').collect();
+            if parts.len() == 4
+                && let Ok(message_id) = Uuid::parse_str(parts[1])
+                && let Ok(edited_at) = parts[2].parse::<u64>()
+            {
+                return Some(Self::EditMessage {
+                    message_id,
+                    new_text: parts[3].to_string(),
+                    edited_at,
+                });
+            }
+            None
+        } else if b.starts_with(b"DELETE_MESSAGE|") {
+            let s = String::from_utf8_lossy(&b[15..]);
+            Uuid::parse_str(s.trim())
+                .ok()
+                .map(|message_id| Self::DeleteMessage { message_id })
+        } else if b.starts_with(b"REACTION|") {
+            let s = String::from_utf8_lossy(b);
+            let parts: Vec<&str> = s.splitn(4, '|').collect();
+            if parts.len() == 4
+                && let Ok(message_id) = Uuid::parse_str(parts[1])
+            {
+                let add = match parts[2] {
+                    "+" => true,
+                    "-" => false,
+                    _ => return None,
+                };
+                return Some(Self::Reaction {
+                    message_id,
+                    emoji: parts[3].to_string(),
+                    add,
+                });
+            }
+            None
+        } else if b.starts_with(b"SET_EXPIRY:") {
+            // "SET_EXPIRY:<chat_id>:<seconds>"
+            let s = String::from_utf8_lossy(&b[11..]);
+            let (chat_id, seconds) = s.split_once(':')?;
+            Some(Self::SetExpiry {
+                chat_id: Uuid::parse_str(chat_id).ok()?,
+                seconds: seconds.trim().parse().ok()?,
+            })
+        } else if b.starts_with(b"KEY_TRANSITION:") {
+            serde_json::from_slice(&b[15..])
+                .ok()
+                .map(|transition| Self::KeyTransition { transition })
         } else if b.starts_with(b"FILE_META|") {
             let s = String::from_utf8_lossy(b);
             let parts: Vec<&str> = s.splitn(3, '|').collect();
@@ -119,21 +380,167 @@ mod tests {
     #[test]
     fn test_text_message_roundtrip() {
         let msg = ProtocolMessage::Text {
+            id: Uuid::new_v4(),
             text: "Hello, world!".to_string(),
+            reply_to: None,
             timestamp: 1234567890,
         };
 
         let bytes = msg.to_plain_bytes();
         let parsed = ProtocolMessage::from_plain_bytes(&bytes).unwrap();
 
-        match parsed {
-            ProtocolMessage::Text { text, .. } => {
+        match (parsed, msg) {
+            (ProtocolMessage::Text { id, text, .. }, ProtocolMessage::Text { id: sent_id, .. }) => {
+                assert_eq!(id, sent_id);
                 assert_eq!(text, "Hello, world!");
             }
             _ => panic!("Wrong message type"),
         }
     }
 
+    #[test]
+    fn test_legacy_text_without_id() {
+        let parsed = ProtocolMessage::from_plain_bytes(b"TEXT:time: 10:30").unwrap();
+
+        match parsed {
+            ProtocolMessage::Text { text, .. } => assert_eq!(text, "time: 10:30"),
+            _ => panic!("Wrong message type"),
+        }
+    }
+
+    #[test]
+    fn test_reply_roundtrip() {
+        let id = Uuid::new_v4();
+        let original = Uuid::new_v4();
+        let msg = ProtocolMessage::Text {
+            id,
+            text: "yes | agreed".to_string(),
+            reply_to: Some(original),
+            timestamp: 1234567890,
+        };
+
+        let bytes = msg.to_plain_bytes();
+        assert!(bytes.starts_with(b"REPLY|"));
+        let parsed = ProtocolMessage::from_plain_bytes(&bytes).unwrap();
+
+        match parsed {
+            ProtocolMessage::Text {
+                id: parsed_id,
+                text,
+                reply_to,
+                ..
+            } => {
+                assert_eq!(parsed_id, id);
+                assert_eq!(text, "yes | agreed");
+                assert_eq!(reply_to, Some(original));
+            }
+            _ => panic!("Wrong message type"),
+        }
+    }
+
+    #[test]
+    fn test_reaction_roundtrip() {
+        for add in [true, false] {
+            let msg = ProtocolMessage::Reaction {
+                message_id: Uuid::new_v4(),
+                emoji: "👍".to_string(),
+                add,
+            };
+
+            let bytes = msg.to_plain_bytes();
+            let parsed = ProtocolMessage::from_plain_bytes(&bytes).unwrap();
+
+            assert_eq!(msg, parsed);
+        }
+        assert!(ProtocolMessage::from_plain_bytes(b"REACTION|not-a-uuid|+|x").is_none());
+    }
+
+    #[test]
+    fn test_set_expiry_roundtrip() {
+        let msg = ProtocolMessage::SetExpiry {
+            chat_id: Uuid::new_v4(),
+            seconds: 3600,
+        };
+
+        let bytes = msg.to_plain_bytes();
+        let parsed = ProtocolMessage::from_plain_bytes(&bytes).unwrap();
+
+        assert_eq!(msg, parsed);
+        // A timer must say which chat it is for
+        assert!(ProtocolMessage::from_plain_bytes(b"SET_EXPIRY:3600").is_none());
+    }
+
+    #[test]
+    fn test_key_transition_roundtrip() {
+        let mut identity = crate::identity::Identity::new("Alice".to_string()).unwrap();
+        let msg = ProtocolMessage::KeyTransition {
+            transition: identity.rotate(None).unwrap(),
+        };
+
+        let bytes = msg.to_plain_bytes();
+        let parsed = ProtocolMessage::from_plain_bytes(&bytes).unwrap();
+
+        assert_eq!(msg, parsed);
+        assert!(ProtocolMessage::from_plain_bytes(b"KEY_TRANSITION:{}").is_none());
+    }
+
+    #[test]
+    fn test_hybrid_handshake_messages_roundtrip() {
+        for msg in [
+            ProtocolMessage::Capabilities {
+                features: vec![CAPABILITY_HYBRID_KEM.to_string(), "other".to_string()],
+            },
+            ProtocolMessage::Capabilities { features: vec![] },
+            ProtocolMessage::KemPublicKey {
+                public_key: vec![0, 58, 255, 7],
+            },
+            ProtocolMessage::KemCiphertext {
+                ciphertext: vec![1, 2, 3],
+            },
+            ProtocolMessage::HandshakeSignature {
+                signature: vec![3; 64],
+            },
+            ProtocolMessage::RatchetKey {
+                public_key: vec![4; 32],
+            },
+            ProtocolMessage::Rekey {
+                public_key: vec![5; 32],
+            },
+            ProtocolMessage::RekeyAck,
+            ProtocolMessage::InviteProof { proof: vec![6; 32] },
+            ProtocolMessage::InviteProof { proof: Vec::new() },
+        ] {
+            let parsed = ProtocolMessage::from_plain_bytes(&msg.to_plain_bytes()).unwrap();
+            assert_eq!(msg, parsed);
+        }
+    }
+
+    #[test]
+    fn test_edit_message_roundtrip() {
+        let msg = ProtocolMessage::EditMessage {
+            message_id: Uuid::new_v4(),
+            new_text: "fixed | typo".to_string(),
+            edited_at: 1234567890,
+        };
+
+        let bytes = msg.to_plain_bytes();
+        let parsed = ProtocolMessage::from_plain_bytes(&bytes).unwrap();
+
+        assert_eq!(msg, parsed);
+    }
+
+    #[test]
+    fn test_delete_message_roundtrip() {
+        let msg = ProtocolMessage::DeleteMessage {
+            message_id: Uuid::new_v4(),
+        };
+
+        let bytes = msg.to_plain_bytes();
+        let parsed = ProtocolMessage::from_plain_bytes(&bytes).unwrap();
+
+        assert_eq!(msg, parsed);
+    }
+
     #[test]
     fn test_file_meta_roundtrip() {
         let msg = ProtocolMessage::FileMeta {
@@ -0,0 +1,579 @@
+//! Double Ratchet for per-message keys, layered on [`AesCipher`].
+//!
+//! The session key from the handshake becomes the first root key. Every message
+//! is encrypted under its own key taken from a symmetric chain (KDF_CK), and each
+//! time the conversation changes direction a new X25519 exchange feeds the root
+//! chain (KDF_RK), so a leaked key only exposes a short run of messages. Keys of
+//! messages that arrive out of order are kept (up to [`MAX_SKIP`]) and erased
+//! once used.
+//!
+//! Both sides exchange a ratchet public key right after the handshake. The
+//! responder (the host) starts as if it had already received the initiator's
+//! first message, so either side can send first.
+
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow, bail};
+use hkdf::Hkdf;
+use rand::rngs::OsRng;
+use sha2::Sha256;
+use x25519_dalek::{PublicKey as X25519PublicKey, StaticSecret};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::core::AesCipher;
+
+/// Most message keys that may be skipped (and stored) at once
+pub const MAX_SKIP: u32 = 1000;
+
+/// Encoded header: ratchet public key || previous chain length || message number
+pub const HEADER_LEN: usize = 32 + 4 + 4;
+
+const ROOT_INFO: &[u8] = b"p2p-messenger-ratchet-root";
+const MESSAGE_KEY_INFO: &[u8] = b"p2p-messenger-ratchet-message";
+const CHAIN_KEY_INFO: &[u8] = b"p2p-messenger-ratchet-chain";
+
+/// KDF_RK: mix a DH output into the root key, giving a new root key and chain key
+fn kdf_rk(root_key: &[u8; 32], dh_output: &[u8; 32]) -> ([u8; 32], [u8; 32]) {
+    let hkdf = Hkdf::<Sha256>::new(Some(root_key), dh_output);
+    let mut okm = Zeroizing::new([0u8; 64]);
+    hkdf.expand(ROOT_INFO, okm.as_mut())
+        .expect("HKDF expand should not fail with valid length");
+
+    let mut root = [0u8; 32];
+    let mut chain = [0u8; 32];
+    root.copy_from_slice(&okm[..32]);
+    chain.copy_from_slice(&okm[32..]);
+    (root, chain)
+}
+
+/// KDF_CK: advance a chain key, giving the next chain key and a message key
+fn kdf_ck(chain_key: &[u8; 32]) -> ([u8; 32], Zeroizing<[u8; 32]>) {
+    let hkdf = Hkdf::<Sha256>::from_prk(chain_key).expect("chain key is a valid PRK");
+    let mut next = [0u8; 32];
+    let mut message_key = Zeroizing::new([0u8; 32]);
+    hkdf.expand(CHAIN_KEY_INFO, &mut next)
+        .expect("HKDF expand should not fail with valid length");
+    hkdf.expand(MESSAGE_KEY_INFO, message_key.as_mut())
+        .expect("HKDF expand should not fail with valid length");
+    (next, message_key)
+}
+
+fn diffie_hellman(secret: &StaticSecret, public: &X25519PublicKey) -> Zeroizing<[u8; 32]> {
+    Zeroizing::new(secret.diffie_hellman(public).to_bytes())
+}
+
+/// Per-message header, sent in clear and authenticated as AES-GCM associated data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    /// Sender's current ratchet public key
+    pub public_key: [u8; 32],
+    /// Number of messages in the sender's previous sending chain
+    pub previous_chain_len: u32,
+    /// Message number within the current sending chain
+    pub n: u32,
+}
+
+impl Header {
+    pub fn encode(&self) -> [u8; HEADER_LEN] {
+        let mut out = [0u8; HEADER_LEN];
+        out[..32].copy_from_slice(&self.public_key);
+        out[32..36].copy_from_slice(&self.previous_chain_len.to_be_bytes());
+        out[36..].copy_from_slice(&self.n.to_be_bytes());
+        out
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != HEADER_LEN {
+            return None;
+        }
+        let mut public_key = [0u8; 32];
+        public_key.copy_from_slice(&bytes[..32]);
+        Some(Self {
+            public_key,
+            previous_chain_len: u32::from_be_bytes(bytes[32..36].try_into().ok()?),
+            n: u32::from_be_bytes(bytes[36..].try_into().ok()?),
+        })
+    }
+}
+
+/// Key of a skipped message, by (ratchet public key, message number)
+type SkippedKey = (([u8; 32], u32), Zeroizing<[u8; 32]>);
+
+/// Keys and counters that a received message advances. Small enough to copy
+/// for each packet, so a packet that fails to authenticate leaves the
+/// original untouched.
+#[derive(Clone)]
+struct RatchetState {
+    dh_self: StaticSecret,
+    dh_remote: X25519PublicKey,
+    root_key: [u8; 32],
+    send_chain: Option<[u8; 32]>,
+    recv_chain: Option<[u8; 32]>,
+    send_n: u32,
+    recv_n: u32,
+    previous_send_n: u32,
+}
+
+impl RatchetState {
+    /// Advance the receiving side to `header` and return its message key. The
+    /// keys skipped on the way are added to `skipped`; `stored` are kept already.
+    fn receiving_key(
+        &mut self,
+        header: &Header,
+        stored: usize,
+        skipped: &mut Vec<SkippedKey>,
+    ) -> Result<Zeroizing<[u8; 32]>> {
+        if header.public_key != self.dh_remote.to_bytes() {
+            self.skip_message_keys(header.previous_chain_len, stored, skipped)?;
+            self.dh_ratchet(header);
+        } else if header.n < self.recv_n {
+            bail!("Message {} was already received or its key has expired", header.n);
+        }
+        self.skip_message_keys(header.n, stored, skipped)?;
+
+        let chain = self
+            .recv_chain
+            .as_mut()
+            .ok_or_else(|| anyhow!("No receiving chain for this key"))?;
+        let (next, message_key) = kdf_ck(chain);
+        chain.zeroize();
+        *chain = next;
+        self.recv_n += 1;
+        Ok(message_key)
+    }
+
+    /// Derive the keys of messages `recv_n..until` of the current receiving chain
+    fn skip_message_keys(
+        &mut self,
+        until: u32,
+        stored: usize,
+        skipped: &mut Vec<SkippedKey>,
+    ) -> Result<()> {
+        let Some(chain) = self.recv_chain.as_mut() else {
+            return Ok(());
+        };
+        if until.saturating_sub(self.recv_n) as usize + stored + skipped.len() > MAX_SKIP as usize {
+            bail!("Too many skipped messages");
+        }
+        let remote = self.dh_remote.to_bytes();
+        while self.recv_n < until {
+            let (next, message_key) = kdf_ck(chain);
+            chain.zeroize();
+            *chain = next;
+            skipped.push(((remote, self.recv_n), message_key));
+            self.recv_n += 1;
+        }
+        Ok(())
+    }
+
+    /// The peer has a new ratchet key: derive a receiving chain for it, then a
+    /// sending chain from a fresh key of our own
+    fn dh_ratchet(&mut self, header: &Header) {
+        self.previous_send_n = self.send_n;
+        self.send_n = 0;
+        self.recv_n = 0;
+        self.dh_remote = X25519PublicKey::from(header.public_key);
+
+        let (root_key, recv_chain) =
+            kdf_rk(&self.root_key, &diffie_hellman(&self.dh_self, &self.dh_remote));
+        self.root_key.zeroize();
+        self.replace_chain(true, recv_chain);
+
+        self.dh_self = StaticSecret::random_from_rng(OsRng);
+        let (root_key, send_chain) =
+            kdf_rk(&root_key, &diffie_hellman(&self.dh_self, &self.dh_remote));
+        self.root_key = root_key;
+        self.replace_chain(false, send_chain);
+    }
+
+    fn replace_chain(&mut self, receiving: bool, chain: [u8; 32]) {
+        let slot = if receiving {
+            &mut self.recv_chain
+        } else {
+            &mut self.send_chain
+        };
+        if let Some(old) = slot.as_mut() {
+            old.zeroize();
+        }
+        *slot = Some(chain);
+    }
+
+    /// Overwrite the root and chain keys with zeros
+    fn wipe(&mut self) {
+        self.root_key.zeroize();
+        if let Some(chain) = self.send_chain.as_mut() {
+            chain.zeroize();
+        }
+        if let Some(chain) = self.recv_chain.as_mut() {
+            chain.zeroize();
+        }
+    }
+}
+
+impl Drop for RatchetState {
+    fn drop(&mut self) {
+        self.wipe();
+    }
+}
+
+/// Double Ratchet state for one session
+pub struct DoubleRatchet {
+    state: RatchetState,
+    /// Keys of messages not received yet, by (ratchet public key, message number)
+    skipped: HashMap<([u8; 32], u32), [u8; 32]>,
+}
+
+impl DoubleRatchet {
+    /// State of the side that starts the session (the client)
+    pub fn initiator(
+        shared_key: &[u8; 32],
+        our_secret: StaticSecret,
+        their_public: X25519PublicKey,
+    ) -> Self {
+        let (root_key, send_chain) =
+            kdf_rk(shared_key, &diffie_hellman(&our_secret, &their_public));
+        Self {
+            state: RatchetState {
+                dh_self: our_secret,
+                dh_remote: their_public,
+                root_key,
+                send_chain: Some(send_chain),
+                recv_chain: None,
+                send_n: 0,
+                recv_n: 0,
+                previous_send_n: 0,
+            },
+            skipped: HashMap::new(),
+        }
+    }
+
+    /// State of the other side (the host): it has taken the receiving step for the
+    /// initiator's key and a sending step with a fresh key of its own
+    pub fn responder(
+        shared_key: &[u8; 32],
+        our_secret: StaticSecret,
+        their_public: X25519PublicKey,
+    ) -> Self {
+        let (root_key, recv_chain) =
+            kdf_rk(shared_key, &diffie_hellman(&our_secret, &their_public));
+        let next_secret = StaticSecret::random_from_rng(OsRng);
+        let (root_key, send_chain) =
+            kdf_rk(&root_key, &diffie_hellman(&next_secret, &their_public));
+        Self {
+            state: RatchetState {
+                dh_self: next_secret,
+                dh_remote: their_public,
+                root_key,
+                send_chain: Some(send_chain),
+                recv_chain: Some(recv_chain),
+                send_n: 0,
+                recv_n: 0,
+                previous_send_n: 0,
+            },
+            skipped: HashMap::new(),
+        }
+    }
+
+    /// Our current ratchet public key
+    pub fn public_key(&self) -> X25519PublicKey {
+        X25519PublicKey::from(&self.state.dh_self)
+    }
+
+    /// Number of stored keys for messages that have not arrived yet
+    pub fn skipped_keys(&self) -> usize {
+        self.skipped.len()
+    }
+
+    /// Encrypt one message: header(40) || nonce(12) || ciphertext || tag(16)
+    pub fn encrypt(&mut self, plaintext: &[u8]) -> Vec<u8> {
+        let header = Header {
+            public_key: self.public_key().to_bytes(),
+            previous_chain_len: self.state.previous_send_n,
+            n: self.state.send_n,
+        }
+        .encode();
+        let chain = self
+            .state
+            .send_chain
+            .as_mut()
+            .expect("both sides have a sending chain after initialization");
+        let (next, message_key) = kdf_ck(chain);
+        chain.zeroize();
+        *chain = next;
+        self.state.send_n += 1;
+
+        let mut out = header.to_vec();
+        out.extend(AesCipher::new(message_key.as_ref()).encrypt_with_aad(plaintext, &header));
+        out
+    }
+
+    /// Decrypt one message. The state only changes if the message authenticates,
+    /// so forged or replayed packets cannot desynchronize the ratchet.
+    pub fn decrypt(&mut self, packet: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+        if packet.len() < HEADER_LEN {
+            bail!("Packet too short for a ratchet header");
+        }
+        let (header_bytes, payload) = packet.split_at(HEADER_LEN);
+        let header = Header::decode(header_bytes).ok_or_else(|| anyhow!("Invalid header"))?;
+
+        // A key stored when this message was skipped earlier
+        if let Some(mut key) = self.skipped.remove(&(header.public_key, header.n)) {
+            let plaintext = AesCipher::new(&key).decrypt_with_aad(payload, header_bytes);
+            match plaintext {
+                Some(plaintext) => {
+                    key.zeroize();
+                    return Ok(plaintext);
+                }
+                None => {
+                    self.skipped.insert((header.public_key, header.n), key);
+                    bail!("Message failed to authenticate");
+                }
+            }
+        }
+
+        // Advance a copy of the chains; the skipped keys are only added on success
+        let mut next = self.state.clone();
+        let mut skipped = Vec::new();
+        let message_key = next.receiving_key(&header, self.skipped.len(), &mut skipped)?;
+        let plaintext = AesCipher::new(message_key.as_ref())
+            .decrypt_with_aad(payload, header_bytes)
+            .ok_or_else(|| anyhow!("Message failed to authenticate"))?;
+        self.state = next;
+        self.skipped.extend(skipped.into_iter().map(|(id, key)| (id, *key)));
+        Ok(plaintext)
+    }
+
+    /// Overwrite the root, chain and skipped message keys with zeros
+    fn wipe(&mut self) {
+        self.state.wipe();
+        for key in self.skipped.values_mut() {
+            key.zeroize();
+        }
+    }
+}
+
+impl Drop for DoubleRatchet {
+    fn drop(&mut self) {
+        self.wipe();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair() -> (DoubleRatchet, DoubleRatchet) {
+        let shared = [7u8; 32];
+        let client_secret = StaticSecret::random_from_rng(OsRng);
+        let host_secret = StaticSecret::random_from_rng(OsRng);
+        let client_public = X25519PublicKey::from(&client_secret);
+        let host_public = X25519PublicKey::from(&host_secret);
+        (
+            DoubleRatchet::initiator(&shared, client_secret, host_public),
+            DoubleRatchet::responder(&shared, host_secret, client_public),
+        )
+    }
+
+    #[test]
+    fn test_kdf_vectors() {
+        // Independently computed HKDF-SHA256 outputs
+        let (root, chain) = kdf_rk(&[1u8; 32], &[2u8; 32]);
+        assert_eq!(
+            hex::encode(root),
+            "c2fbaaed59df26d38a57bd66529d02ab99465298f8b38793c5e29af71d1074a5"
+        );
+        assert_eq!(
+            hex::encode(chain),
+            "8a433542eb9b95a21ffde2eaf123de2dd031c637524f951e237ff7aae6f475e3"
+        );
+
+        let (next, message_key) = kdf_ck(&[3u8; 32]);
+        assert_eq!(
+            hex::encode(next),
+            "7eb05772893821312af8e3f59364ffca72c34e6b311bb2793181382273ee8ddc"
+        );
+        assert_eq!(
+            hex::encode(message_key.as_ref()),
+            "bc468a969e807582ec504bded572f842addb998ccb2c9608fe966ee69314c5c5"
+        );
+    }
+
+    #[test]
+    fn test_header_roundtrip() {
+        let header = Header {
+            public_key: [9u8; 32],
+            previous_chain_len: 3,
+            n: 258,
+        };
+        let encoded = header.encode();
+        assert_eq!(&encoded[32..], &[0, 0, 0, 3, 0, 0, 1, 2]);
+        assert_eq!(Header::decode(&encoded), Some(header));
+        assert_eq!(Header::decode(&encoded[1..]), None);
+    }
+
+    #[test]
+    fn test_either_side_can_send_first() {
+        let (mut client, mut host) = pair();
+        let packet = host.encrypt(b"host first");
+        assert_eq!(client.decrypt(&packet).unwrap().as_slice(), b"host first");
+
+        let (mut client, mut host) = pair();
+        let packet = client.encrypt(b"client first");
+        assert_eq!(host.decrypt(&packet).unwrap().as_slice(), b"client first");
+    }
+
+    #[test]
+    fn test_every_message_gets_a_new_key() {
+        let (mut client, mut host) = pair();
+        let first = client.encrypt(b"same");
+        let second = client.encrypt(b"same");
+        assert_eq!(&first[..32], &second[..32], "same chain until the direction changes");
+
+        // A fresh message key for each message: decrypting with the first
+        // message's key fails for the second
+        assert_eq!(host.decrypt(&first).unwrap().as_slice(), b"same");
+        let mut forged = second.clone();
+        forged[36..40].copy_from_slice(&0u32.to_be_bytes());
+        assert!(host.decrypt(&forged).is_err());
+        assert_eq!(host.decrypt(&second).unwrap().as_slice(), b"same");
+    }
+
+    #[test]
+    fn test_dh_ratchet_turns_over_keys() {
+        let (mut client, mut host) = pair();
+        let mut client_keys = vec![client.public_key().to_bytes()];
+        let mut host_keys = vec![host.public_key().to_bytes()];
+
+        // Ping-pong: each change of direction brings a new ratchet key
+        for round in 0..3 {
+            let text = format!("client {}", round);
+            assert_eq!(host.decrypt(&client.encrypt(text.as_bytes())).unwrap().as_slice(), text.as_bytes());
+            let text = format!("host {}", round);
+            assert_eq!(client.decrypt(&host.encrypt(text.as_bytes())).unwrap().as_slice(), text.as_bytes());
+            client_keys.push(client.public_key().to_bytes());
+            host_keys.push(host.public_key().to_bytes());
+        }
+        client_keys.dedup();
+        host_keys.dedup();
+        assert_eq!(client_keys.len(), 4);
+        // The host already stepped at initialization, so the client's first
+        // message (still under its initial key) does not move it
+        assert_eq!(host_keys.len(), 3);
+    }
+
+    #[test]
+    fn test_out_of_order_and_replay() {
+        let (mut client, mut host) = pair();
+        let packets: Vec<_> = (0..4)
+            .map(|i| client.encrypt(format!("m{}", i).as_bytes()))
+            .collect();
+
+        // m3 first: keys for m0..m2 are stored
+        assert_eq!(host.decrypt(&packets[3]).unwrap().as_slice(), b"m3");
+        assert_eq!(host.skipped_keys(), 3);
+        assert_eq!(host.decrypt(&packets[1]).unwrap().as_slice(), b"m1");
+        assert_eq!(host.skipped_keys(), 2);
+
+        // A used key is gone: replays fail
+        assert!(host.decrypt(&packets[1]).is_err());
+        assert!(host.decrypt(&packets[3]).is_err());
+
+        // Late messages from a previous chain still decrypt after a DH ratchet step
+        client.decrypt(&host.encrypt(b"reply")).unwrap();
+        let next_chain = client.encrypt(b"new chain");
+        assert_eq!(host.decrypt(&next_chain).unwrap().as_slice(), b"new chain");
+        assert_eq!(host.decrypt(&packets[0]).unwrap().as_slice(), b"m0");
+        assert_eq!(host.decrypt(&packets[2]).unwrap().as_slice(), b"m2");
+        assert_eq!(host.skipped_keys(), 0);
+    }
+
+    /// One step of a scripted exchange
+    enum Step {
+        /// The client (true) or host (false) encrypts message `label`
+        Send(bool, &'static str),
+        /// Message `label` is delivered; whether it should decrypt
+        Deliver(&'static str, bool),
+    }
+
+    #[test]
+    fn test_state_machine_script() {
+        use Step::*;
+        // Interleaved sends, reordering across DH steps, replays and a late message
+        let script = [
+            Send(true, "c1"),
+            Send(true, "c2"),
+            Send(false, "h1"),
+            Deliver("c2", true),
+            Send(false, "h2"),
+            Deliver("h2", true),
+            Send(true, "c3"),
+            Deliver("h1", true),
+            Deliver("c3", true),
+            Deliver("c2", false),
+            Send(false, "h3"),
+            Send(false, "h4"),
+            Deliver("h4", true),
+            Deliver("c1", true),
+            Deliver("h3", true),
+            Deliver("h3", false),
+            Deliver("c1", false),
+        ];
+
+        let (mut client, mut host) = pair();
+        let mut in_flight = HashMap::new();
+        for (i, step) in script.iter().enumerate() {
+            match step {
+                Send(from_client, label) => {
+                    let sender = if *from_client { &mut client } else { &mut host };
+                    in_flight.insert(*label, (*from_client, sender.encrypt(label.as_bytes())));
+                }
+                Deliver(label, ok) => {
+                    let (from_client, packet) = &in_flight[label];
+                    let receiver = if *from_client { &mut host } else { &mut client };
+                    match receiver.decrypt(packet) {
+                        Ok(plaintext) => {
+                            assert!(ok, "step {}: {} should be rejected", i, label);
+                            assert_eq!(plaintext.as_slice(), label.as_bytes());
+                        }
+                        Err(e) => assert!(!ok, "step {}: {} failed: {}", i, label, e),
+                    }
+                }
+            }
+        }
+        assert_eq!(client.skipped_keys(), 0);
+        assert_eq!(host.skipped_keys(), 0);
+    }
+
+    #[test]
+    fn test_wipe_clears_every_key() {
+        let (mut client, mut host) = pair();
+        let packets: Vec<_> = (0..3).map(|_| client.encrypt(b"m")).collect();
+        host.decrypt(&packets[2]).unwrap();
+        assert_eq!(host.skipped_keys(), 2);
+
+        host.wipe();
+        assert_eq!(host.state.root_key, [0u8; 32]);
+        assert_eq!(host.state.send_chain, Some([0u8; 32]));
+        assert_eq!(host.state.recv_chain, Some([0u8; 32]));
+        assert!(host.skipped.values().all(|key| *key == [0u8; 32]));
+    }
+
+    #[test]
+    fn test_forged_packets_leave_state_untouched() {
+        let (mut client, mut host) = pair();
+        let packet = client.encrypt(b"hello");
+
+        let mut tampered = packet.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(host.decrypt(&tampered).is_err());
+
+        // A header claiming a far-away message number is refused
+        let mut far = packet.clone();
+        far[36..40].copy_from_slice(&(MAX_SKIP + 1).to_be_bytes());
+        assert!(host.decrypt(&far).is_err());
+        assert_eq!(host.skipped_keys(), 0);
+
+        assert!(host.decrypt(&packet[..HEADER_LEN - 1]).is_err());
+        assert_eq!(host.decrypt(&packet).unwrap().as_slice(), b"hello");
+    }
+}
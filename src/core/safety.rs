@@ -0,0 +1,187 @@
+//! Safety numbers.
+//!
+//! Both peers hash the fingerprints of their two identity keys, which sign the
+//! handshake, sorted so the order doesn't depend on who is host. The number
+//! stays the same from one session to the next until either side rotates its
+//! key. The result is shown as twelve groups of five digits, a row of emoji, a
+//! color grid and a QR code: if one screen matches the other, nobody sits in
+//! the middle of the connection.
+
+use std::fmt;
+
+use sha2::{Digest, Sha512};
+
+/// Number of five-digit groups in a safety number
+pub const SAFETY_NUMBER_GROUPS: usize = 12;
+
+/// Number of emoji in the short form
+pub const SAFETY_EMOJI_COUNT: usize = 8;
+
+const SAFETY_NUMBER_CONTEXT: &[u8] = b"p2p-messenger-safety-number-v1";
+
+/// Prefix of the text encoded in a safety-number QR code
+pub const SAFETY_QR_PREFIX: &str = "chat-p2p://safety/";
+
+/// Easy to tell apart and to read out loud
+const EMOJI: [&str; 64] = [
+    "🐶", "🐱", "🐭", "🐰", "🦊", "🐻", "🐼", "🐨",
+    "🐯", "🦁", "🐮", "🐷", "🐸", "🐵", "🐔", "🐧",
+    "🐦", "🦆", "🦉", "🐺", "🐴", "🦄", "🐝", "🐛",
+    "🦋", "🐌", "🐞", "🐢", "🐍", "🐙", "🦀", "🐟",
+    "🐬", "🐳", "🦈", "🐘", "🦒", "🐪", "🌵", "🌲",
+    "🌻", "🍄", "🌙", "⭐", "🔥", "🌈", "❄", "☂",
+    "🍎", "🍌", "🍇", "🍓", "🍒", "🍍", "🥕", "🌽",
+    "🍕", "🍩", "🎈", "🎁", "🔑", "🔔", "⚓", "🚲",
+];
+
+/// Code that both peers of a session compute identically
+#[derive(Debug, Clone)]
+pub struct SafetyNumber {
+    digest: [u8; 64],
+    /// Our side, kept to tell our QR code from the peer's
+    local_fingerprint: String,
+    peer_fingerprint: String,
+}
+
+/// Both sides' numbers are equal, whoever computed them
+impl PartialEq for SafetyNumber {
+    fn eq(&self, other: &Self) -> bool {
+        self.digest == other.digest
+    }
+}
+
+impl Eq for SafetyNumber {}
+
+impl SafetyNumber {
+    /// Combine the fingerprint of our identity key with the peer's
+    pub fn new(local_fingerprint: &str, peer_fingerprint: &str) -> Self {
+        let (first, second) = if local_fingerprint <= peer_fingerprint {
+            (local_fingerprint, peer_fingerprint)
+        } else {
+            (peer_fingerprint, local_fingerprint)
+        };
+
+        let mut hasher = Sha512::new();
+        hasher.update(SAFETY_NUMBER_CONTEXT);
+        for fingerprint in [first, second] {
+            hasher.update((fingerprint.len() as u32).to_be_bytes());
+            hasher.update(fingerprint.as_bytes());
+        }
+        Self {
+            digest: hasher.finalize().into(),
+            local_fingerprint: local_fingerprint.to_string(),
+            peer_fingerprint: peer_fingerprint.to_string(),
+        }
+    }
+
+    /// Twelve groups of five digits, each taken from five bytes of the hash
+    pub fn groups(&self) -> Vec<String> {
+        self.digest
+            .chunks_exact(5)
+            .take(SAFETY_NUMBER_GROUPS)
+            .map(|chunk| {
+                let value = chunk.iter().fold(0u64, |acc, &b| (acc << 8) | b as u64);
+                format!("{:05}", value % 100_000)
+            })
+            .collect()
+    }
+
+    /// The short form: one emoji per byte of the hash
+    pub fn emoji(&self) -> Vec<&'static str> {
+        self.digest[..SAFETY_EMOJI_COUNT]
+            .iter()
+            .map(|&b| EMOJI[b as usize % EMOJI.len()])
+            .collect()
+    }
+
+    /// Text for a QR code the peer can scan instead of reading the digits:
+    /// `<prefix><our fingerprint>/<peer fingerprint>/<hex>`. It names who
+    /// shows it, so our own code can't be scanned back as the peer's.
+    pub fn qr_payload(&self) -> String {
+        format!(
+            "{}{}/{}/{}",
+            SAFETY_QR_PREFIX,
+            self.local_fingerprint,
+            self.peer_fingerprint,
+            hex::encode(&self.digest[..32])
+        )
+    }
+
+    /// Whether a scanned QR code is the peer's, showing this safety number
+    pub fn matches_qr_payload(&self, payload: &str) -> bool {
+        let Some(rest) = payload.trim().strip_prefix(SAFETY_QR_PREFIX) else {
+            return false;
+        };
+        let mut parts = rest.split('/');
+        let (Some(sender), Some(receiver), Some(digest), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return false;
+        };
+        sender == self.peer_fingerprint
+            && receiver == self.local_fingerprint
+            && hex::decode(digest).is_ok_and(|bytes| bytes == self.digest[..32])
+    }
+
+    /// Hex input for [`crate::util::generate_color_grid`]
+    pub fn color_key(&self) -> String {
+        hex::encode(&self.digest[..16])
+    }
+}
+
+impl fmt::Display for SafetyNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.groups().join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_both_sides_compute_the_same_number() {
+        let (host, client) = ("ab".repeat(16), "cd".repeat(16));
+        let on_host = SafetyNumber::new(&host, &client);
+        let on_client = SafetyNumber::new(&client, &host);
+        assert_eq!(on_host, on_client);
+        assert_eq!(on_host.to_string(), on_client.to_string());
+
+        let groups = on_host.groups();
+        assert_eq!(groups.len(), SAFETY_NUMBER_GROUPS);
+        assert!(groups.iter().all(|g| g.len() == 5 && g.bytes().all(|b| b.is_ascii_digit())));
+        assert_eq!(on_host.emoji().len(), SAFETY_EMOJI_COUNT);
+        assert_eq!(on_host.color_key().len(), 32);
+    }
+
+    #[test]
+    fn test_own_qr_code_is_not_the_peers() {
+        let (host, client) = ("ab".repeat(16), "cd".repeat(16));
+        let on_host = SafetyNumber::new(&host, &client);
+        let on_client = SafetyNumber::new(&client, &host);
+        assert_ne!(on_host.qr_payload(), on_client.qr_payload());
+        assert!(on_host.matches_qr_payload(&on_client.qr_payload()));
+        assert!(!on_host.matches_qr_payload(&on_host.qr_payload()));
+
+        // The digest without the two fingerprints is not enough
+        let digest_only = format!("{}{}", SAFETY_QR_PREFIX, hex::encode(&on_client.digest[..32]));
+        assert!(!on_host.matches_qr_payload(&digest_only));
+    }
+
+    #[test]
+    fn test_a_different_key_changes_every_form() {
+        let (host, client, mallory) = ("ab".repeat(16), "cd".repeat(16), "ce".repeat(16));
+        let genuine = SafetyNumber::new(&host, &client);
+        let attacked = SafetyNumber::new(&host, &mallory);
+        assert_ne!(genuine.groups(), attacked.groups());
+        assert_ne!(genuine.emoji(), attacked.emoji());
+        assert_ne!(genuine.color_key(), attacked.color_key());
+
+        assert!(genuine.matches_qr_payload(&SafetyNumber::new(&client, &host).qr_payload()));
+        assert!(!genuine.matches_qr_payload(&attacked.qr_payload()));
+        assert!(!genuine.matches_qr_payload("chat-p2p://invite/abc"));
+
+        // Length prefixes keep the split between the two fingerprints unambiguous
+        assert_ne!(SafetyNumber::new("a", "bc"), SafetyNumber::new("ab", "c"));
+    }
+}
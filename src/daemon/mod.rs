@@ -0,0 +1,348 @@
+//! Background service mode.
+//!
+//! The daemon runs a `ChatManager` without the GUI and exposes it over a local
+//! Unix domain socket speaking newline-delimited JSON-RPC 2.0 (see `rpc` for the
+//! method list). A client that calls `subscribe` receives every session event as
+//! a `session_event` notification on the same connection.
+
+pub mod rpc;
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{Mutex, broadcast, mpsc};
+
+use crate::app::ChatManager;
+use crate::types::Config;
+use rpc::{
+    DaemonEvent, EventNotification, INVALID_REQUEST, PARSE_ERROR, RpcError, RpcNotification,
+    RpcRequest, RpcResponse,
+};
+
+/// How often session events are polled
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How often history is written to disk
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Where the daemon keeps its socket, history and identity
+#[derive(Debug, Clone)]
+pub struct DaemonOptions {
+    pub socket_path: PathBuf,
+    pub history_path: PathBuf,
+    pub data_dir: PathBuf,
+}
+
+impl DaemonOptions {
+    /// Socket, history and identity inside the user data directory
+    pub fn from_data_dir(data_dir: &Path) -> Self {
+        Self {
+            socket_path: data_dir.join("daemon.sock"),
+            history_path: data_dir.join("history.json"),
+            data_dir: data_dir.to_path_buf(),
+        }
+    }
+}
+
+/// Run the daemon until Ctrl-C
+pub async fn run(options: DaemonOptions) -> Result<()> {
+    let identity = crate::identity::commands::load_for_session(&options.data_dir)?;
+    let mut manager = ChatManager::new(Config::default());
+    manager.sync_identity(&identity);
+    if options.history_path.exists() {
+        manager
+            .load_history(&options.history_path)
+            .with_context(|| format!("Failed to load {}", options.history_path.display()))?;
+    }
+    let manager = Arc::new(Mutex::new(manager));
+
+    let listener = bind_socket(&options.socket_path).await?;
+    tracing::info!(socket = %options.socket_path.display(), "Daemon listening");
+    println!("Daemon listening on {}", options.socket_path.display());
+
+    let pump = tokio::spawn(pump_events(manager.clone(), options.history_path.clone()));
+
+    let result = tokio::select! {
+        result = serve(listener, manager.clone()) => result,
+        _ = tokio::signal::ctrl_c() => Ok(()),
+    };
+
+    pump.abort();
+    if let Err(e) = manager.lock().await.save_history(&options.history_path) {
+        tracing::warn!("Failed to save history on shutdown: {}", e);
+    }
+    let _ = std::fs::remove_file(&options.socket_path);
+    tracing::info!("Daemon stopped");
+    result
+}
+
+/// Bind the control socket, replacing a stale socket file but refusing to
+/// start if another daemon is still answering on it
+async fn bind_socket(path: &Path) -> Result<UnixListener> {
+    if path.exists() {
+        if UnixStream::connect(path).await.is_ok() {
+            anyhow::bail!("A daemon is already running on {}", path.display());
+        }
+        std::fs::remove_file(path)
+            .with_context(|| format!("Failed to remove stale socket {}", path.display()))?;
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    // Only the owning user may control the daemon
+    crate::util::bind_private_socket(path)
+}
+
+/// Accept control connections forever
+pub async fn serve(listener: UnixListener, manager: Arc<Mutex<ChatManager>>) -> Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let manager = manager.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, manager).await {
+                tracing::debug!("Control connection closed: {}", e);
+            }
+        });
+    }
+}
+
+/// Drive the manager: handle session events, purge expired messages and save history
+async fn pump_events(manager: Arc<Mutex<ChatManager>>, history_path: PathBuf) {
+    let mut ticker = tokio::time::interval(POLL_INTERVAL);
+    let mut last_save = tokio::time::Instant::now();
+    loop {
+        ticker.tick().await;
+        let mut mgr = manager.lock().await;
+        mgr.poll_session_events();
+        // Subscribers get the verification request as a session event
+        mgr.fingerprint_verification_request = None;
+        for toast in std::mem::take(&mut mgr.toasts) {
+            tracing::info!("{}", toast.message);
+        }
+
+        let purged = mgr.purge_expired_messages(chrono::Utc::now());
+        if purged > 0 || last_save.elapsed() >= SAVE_INTERVAL {
+            if let Err(e) = mgr.save_history(&history_path) {
+                tracing::warn!("Failed to save history: {}", e);
+            }
+            last_save = tokio::time::Instant::now();
+        }
+    }
+}
+
+/// Serve one control connection: one JSON-RPC request per line
+async fn handle_connection(stream: UnixStream, manager: Arc<Mutex<ChatManager>>) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    // Responses and event notifications share the socket through one writer task
+    let (out_tx, mut out_rx) = mpsc::unbounded_channel::<String>();
+    let writer_task = tokio::spawn(async move {
+        while let Some(line) = out_rx.recv().await {
+            if writer.write_all(line.as_bytes()).await.is_err()
+                || writer.write_all(b"\n").await.is_err()
+            {
+                break;
+            }
+        }
+    });
+    let mut forwarder: Option<tokio::task::JoinHandle<()>> = None;
+
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let request: RpcRequest = match serde_json::from_str::<Value>(&line) {
+            Err(e) => {
+                send_response(
+                    &out_tx,
+                    Value::Null,
+                    Err(RpcError::new(PARSE_ERROR, e.to_string())),
+                );
+                continue;
+            }
+            Ok(value) => match serde_json::from_value(value) {
+                Ok(request) => request,
+                Err(e) => {
+                    send_response(
+                        &out_tx,
+                        Value::Null,
+                        Err(RpcError::new(INVALID_REQUEST, e.to_string())),
+                    );
+                    continue;
+                }
+            },
+        };
+
+        let outcome = if request.method == "subscribe" {
+            if forwarder.is_none() {
+                let events = manager.lock().await.subscribe_session_events();
+                forwarder = Some(tokio::spawn(forward_events(events, out_tx.clone())));
+            }
+            Ok(Value::Bool(true))
+        } else {
+            let mut mgr = manager.lock().await;
+            rpc::dispatch(&mut mgr, &request.method, request.params).await
+        };
+
+        if let Some(id) = request.id {
+            send_response(&out_tx, id, outcome);
+        }
+    }
+
+    if let Some(forwarder) = forwarder {
+        forwarder.abort();
+    }
+    drop(out_tx);
+    let _ = writer_task.await;
+    Ok(())
+}
+
+fn send_response(
+    out_tx: &mpsc::UnboundedSender<String>,
+    id: Value,
+    outcome: Result<Value, RpcError>,
+) {
+    if let Ok(line) = serde_json::to_string(&RpcResponse::new(id, outcome)) {
+        let _ = out_tx.send(line);
+    }
+}
+
+/// Push session events to a subscribed connection as `session_event` notifications
+async fn forward_events(
+    mut events: broadcast::Receiver<(uuid::Uuid, crate::types::SessionEvent)>,
+    out_tx: mpsc::UnboundedSender<String>,
+) {
+    loop {
+        let (chat_id, event) = match events.recv().await {
+            Ok(item) => item,
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                tracing::warn!("Event subscriber lagged, {} events dropped", skipped);
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        let Some(event) = DaemonEvent::from_session_event(&event) else {
+            continue;
+        };
+        let notification = RpcNotification {
+            jsonrpc: "2.0",
+            method: "session_event",
+            params: EventNotification { chat_id, event },
+        };
+        match serde_json::to_string(&notification) {
+            Ok(line) => {
+                if out_tx.send(line).is_err() {
+                    return;
+                }
+            }
+            Err(e) => tracing::warn!("Failed to serialize session event: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use uuid::Uuid;
+
+    async fn call(
+        lines: &mut tokio::io::Lines<BufReader<tokio::net::unix::OwnedReadHalf>>,
+        writer: &mut tokio::net::unix::OwnedWriteHalf,
+        request: Value,
+    ) -> Value {
+        writer
+            .write_all(format!("{}\n", request).as_bytes())
+            .await
+            .unwrap();
+        let line = lines.next_line().await.unwrap().unwrap();
+        serde_json::from_str(&line).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_rpc_over_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("daemon.sock");
+        let listener = bind_socket(&socket).await.unwrap();
+        let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1, "no staging directory left");
+
+        let mut manager = ChatManager::new(Config::default());
+        let chat = crate::types::Chat {
+            id: Uuid::new_v4(),
+            title: "Alice".to_string(),
+            peer_fingerprint: None,
+            participants: Vec::new(),
+            messages: Vec::new(),
+            created_at: chrono::Utc::now(),
+            peer_typing: false,
+            typing_since: None,
+            disappearing_after_secs: None,
+        };
+        let chat_id = chat.id;
+        manager.chats.insert(chat_id, chat);
+        tokio::spawn(serve(listener, Arc::new(Mutex::new(manager))));
+
+        // A second daemon must not steal a live socket
+        assert!(bind_socket(&socket).await.is_err());
+
+        let (reader, mut writer) = UnixStream::connect(&socket).await.unwrap().into_split();
+        let mut lines = BufReader::new(reader).lines();
+
+        let response = call(
+            &mut lines,
+            &mut writer,
+            serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": "list_chats"}),
+        )
+        .await;
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["result"][0]["title"], "Alice");
+        assert_eq!(response["result"][0]["connected"], false);
+
+        let response = call(
+            &mut lines,
+            &mut writer,
+            serde_json::json!({"jsonrpc": "2.0", "id": 2, "method": "send_message",
+                               "params": {"chat_id": Uuid::new_v4(), "text": "hi"}}),
+        )
+        .await;
+        assert_eq!(response["error"]["code"], rpc::SERVER_ERROR);
+
+        let response = call(
+            &mut lines,
+            &mut writer,
+            serde_json::json!({"jsonrpc": "2.0", "id": 3, "method": "get_messages",
+                               "params": {"chat": "missing"}}),
+        )
+        .await;
+        assert_eq!(response["error"]["code"], rpc::INVALID_PARAMS);
+
+        let response = call(
+            &mut lines,
+            &mut writer,
+            serde_json::json!({"jsonrpc": "2.0", "id": 4, "method": "nope"}),
+        )
+        .await;
+        assert_eq!(response["error"]["code"], rpc::METHOD_NOT_FOUND);
+
+        writer.write_all(b"{not json\n").await.unwrap();
+        let response: Value =
+            serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(response["error"]["code"], PARSE_ERROR);
+
+        let response = call(
+            &mut lines,
+            &mut writer,
+            serde_json::json!({"jsonrpc": "2.0", "id": 5, "method": "subscribe"}),
+        )
+        .await;
+        assert_eq!(response["result"], true);
+    }
+}
@@ -0,0 +1,350 @@
+//! JSON-RPC 2.0 messages and method dispatch for the daemon.
+//!
+//! Requests and responses are single-line JSON objects. Methods:
+//!
+//! | method                | params                                   | result            |
+//! |-----------------------|------------------------------------------|-------------------|
+//! | `list_chats`          | –                                        | `[ChatSummary]`   |
+//! | `list_contacts`       | –                                        | `[Contact]`       |
+//! | `get_messages`        | `chat_id`, `limit?`                      | `[Message]`       |
+//! | `send_message`        | `chat_id`, `text`, `reply_to?`           | `null`            |
+//! | `send_file`           | `chat_id`, `path`                        | `null`            |
+//! | `host`                | `port`                                   | chat ID           |
+//! | `connect`             | `host`, `port`                           | chat ID           |
+//! | `connect_contact`     | `contact_id`                             | chat ID           |
+//! | `confirm_fingerprint` | `chat_id`, `accept`                      | `null`            |
+//! | `delete_chat`         | `chat_id`                                | `null`            |
+//! | `subscribe`           | –                                        | `true`, then `session_event` notifications |
+
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+use crate::app::ChatManager;
+use crate::core::ProtocolMessage;
+use crate::core::safety::SafetyNumber;
+use crate::types::{SecurityEvent, SessionEvent};
+
+pub const PARSE_ERROR: i32 = -32700;
+pub const INVALID_REQUEST: i32 = -32600;
+pub const METHOD_NOT_FOUND: i32 = -32601;
+pub const INVALID_PARAMS: i32 = -32602;
+/// Application-level failure (e.g. chat not found, send failed)
+pub const SERVER_ERROR: i32 = -32000;
+
+/// An incoming request. Requests without an `id` are notifications and get no response.
+#[derive(Debug, Deserialize)]
+pub struct RpcRequest {
+    #[serde(default)]
+    pub id: Option<Value>,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i32,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new(code: i32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(e: anyhow::Error) -> Self {
+        Self::new(SERVER_ERROR, e.to_string())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RpcResponse {
+    pub jsonrpc: &'static str,
+    pub id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+}
+
+impl RpcResponse {
+    pub fn new(id: Value, outcome: Result<Value, RpcError>) -> Self {
+        let (result, error) = match outcome {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        Self {
+            jsonrpc: "2.0",
+            id,
+            result,
+            error,
+        }
+    }
+}
+
+/// Server-initiated notification (no `id`)
+#[derive(Debug, Serialize)]
+pub struct RpcNotification<T: Serialize> {
+    pub jsonrpc: &'static str,
+    pub method: &'static str,
+    pub params: T,
+}
+
+/// Overview of a chat returned by `list_chats`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChatSummary {
+    pub id: Uuid,
+    pub title: String,
+    pub peer_fingerprint: Option<String>,
+    pub participants: Vec<Uuid>,
+    pub connected: bool,
+    pub message_count: usize,
+    pub last_message_at: Option<DateTime<Utc>>,
+    pub disappearing_after_secs: Option<u64>,
+}
+
+/// Session event pushed to subscribers as a `session_event` notification
+#[derive(Debug, Serialize)]
+pub struct EventNotification {
+    pub chat_id: Uuid,
+    pub event: DaemonEvent,
+}
+
+/// Serializable view of a `SessionEvent`
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DaemonEvent {
+    Listening {
+        port: u16,
+    },
+    Connected {
+        peer: String,
+    },
+    NewConnection {
+        peer_addr: String,
+        fingerprint: String,
+        safety_number: String,
+    },
+    FingerprintVerification {
+        fingerprint: String,
+        peer_name: String,
+        safety_number: String,
+    },
+    Ready {
+        post_quantum: bool,
+    },
+    Message {
+        message: ProtocolMessage,
+    },
+    Disconnected,
+    Error {
+        message: String,
+    },
+    Warning {
+        message: String,
+    },
+    SecurityViolation {
+        event: SecurityEvent,
+    },
+}
+
+impl DaemonEvent {
+    /// Convert a session event; file chunks are not forwarded
+    pub fn from_session_event(event: &SessionEvent) -> Option<Self> {
+        Some(match event {
+            SessionEvent::Listening { port } => Self::Listening { port: *port },
+            SessionEvent::Connected { peer } => Self::Connected { peer: peer.clone() },
+            SessionEvent::NewConnection {
+                peer_addr,
+                fingerprint,
+                local_fingerprint,
+                ..
+            } => Self::NewConnection {
+                peer_addr: peer_addr.clone(),
+                fingerprint: fingerprint.clone(),
+                safety_number: SafetyNumber::new(local_fingerprint, fingerprint).to_string(),
+            },
+            SessionEvent::ShowFingerprintVerification {
+                fingerprint,
+                local_fingerprint,
+                peer_name,
+                ..
+            } => Self::FingerprintVerification {
+                fingerprint: fingerprint.clone(),
+                peer_name: peer_name.clone(),
+                safety_number: SafetyNumber::new(local_fingerprint, fingerprint).to_string(),
+            },
+            SessionEvent::Ready { post_quantum } => Self::Ready {
+                post_quantum: *post_quantum,
+            },
+            // Handled by the manager; the binding is derived from the session key
+            SessionEvent::InviteProof { .. } => return None,
+            SessionEvent::MessageReceived(ProtocolMessage::FileChunk { .. }) => return None,
+            SessionEvent::MessageReceived(message) => Self::Message {
+                message: message.clone(),
+            },
+            SessionEvent::Disconnected => Self::Disconnected,
+            SessionEvent::Error(message) => Self::Error {
+                message: message.clone(),
+            },
+            SessionEvent::Warning(message) => Self::Warning {
+                message: message.clone(),
+            },
+            SessionEvent::SecurityViolation(event) => Self::SecurityViolation { event: *event },
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct ChatParams {
+    chat_id: Uuid,
+}
+
+#[derive(Deserialize)]
+struct GetMessagesParams {
+    chat_id: Uuid,
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct SendMessageParams {
+    chat_id: Uuid,
+    text: String,
+    reply_to: Option<Uuid>,
+}
+
+#[derive(Deserialize)]
+struct SendFileParams {
+    chat_id: Uuid,
+    path: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct HostParams {
+    port: u16,
+}
+
+#[derive(Deserialize)]
+struct ConnectParams {
+    host: String,
+    port: u16,
+}
+
+#[derive(Deserialize)]
+struct ConnectContactParams {
+    contact_id: Uuid,
+}
+
+#[derive(Deserialize)]
+struct ConfirmFingerprintParams {
+    chat_id: Uuid,
+    accept: bool,
+}
+
+fn params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    // Methods without parameters accept a missing `params` member
+    let params = if params.is_null() {
+        Value::Object(Default::default())
+    } else {
+        params
+    };
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))
+}
+
+fn to_value<T: Serialize>(value: T) -> Result<Value, RpcError> {
+    serde_json::to_value(value).map_err(|e| RpcError::new(SERVER_ERROR, e.to_string()))
+}
+
+/// Run one method against the manager. `subscribe` is handled by the connection itself.
+pub async fn dispatch(
+    manager: &mut ChatManager,
+    method: &str,
+    raw_params: Value,
+) -> Result<Value, RpcError> {
+    match method {
+        "list_chats" => {
+            let mut chats: Vec<ChatSummary> = manager
+                .chats
+                .values()
+                .map(|chat| ChatSummary {
+                    id: chat.id,
+                    title: chat.title.clone(),
+                    peer_fingerprint: chat.peer_fingerprint.clone(),
+                    participants: chat.participants.clone(),
+                    connected: manager.is_connected(chat.id),
+                    message_count: chat.messages.len(),
+                    last_message_at: chat.messages.last().map(|m| m.timestamp),
+                    disappearing_after_secs: chat.disappearing_after_secs,
+                })
+                .collect();
+            chats.sort_by_key(|c| std::cmp::Reverse(c.last_message_at));
+            to_value(chats)
+        }
+        "list_contacts" => {
+            let mut contacts: Vec<_> = manager.contacts.values().cloned().collect();
+            contacts.sort_by(|a, b| a.name.cmp(&b.name));
+            to_value(contacts)
+        }
+        "get_messages" => {
+            let p: GetMessagesParams = params(raw_params)?;
+            let chat = manager
+                .get_chat(p.chat_id)
+                .ok_or_else(|| RpcError::new(SERVER_ERROR, "Chat not found"))?;
+            let skip = p
+                .limit
+                .map_or(0, |limit| chat.messages.len().saturating_sub(limit));
+            to_value(&chat.messages[skip..])
+        }
+        "send_message" => {
+            let p: SendMessageParams = params(raw_params)?;
+            if manager.get_chat(p.chat_id).is_none() {
+                return Err(RpcError::new(SERVER_ERROR, "Chat not found"));
+            }
+            manager.send_message(p.chat_id, p.text, p.reply_to)?;
+            Ok(Value::Null)
+        }
+        "send_file" => {
+            let p: SendFileParams = params(raw_params)?;
+            manager.send_file(p.chat_id, p.path).await?;
+            Ok(Value::Null)
+        }
+        "host" => {
+            let p: HostParams = params(raw_params)?;
+            to_value(manager.start_host(p.port).await?)
+        }
+        "connect" => {
+            let p: ConnectParams = params(raw_params)?;
+            to_value(manager.connect_to_host(&p.host, p.port, None).await?)
+        }
+        "connect_contact" => {
+            let p: ConnectContactParams = params(raw_params)?;
+            to_value(manager.connect_to_contact(p.contact_id, None).await?)
+        }
+        "confirm_fingerprint" => {
+            let p: ConfirmFingerprintParams = params(raw_params)?;
+            if p.accept {
+                manager.mark_verified(p.chat_id);
+            }
+            manager.confirm_fingerprint(p.chat_id, p.accept)?;
+            Ok(Value::Null)
+        }
+        "delete_chat" => {
+            let p: ChatParams = params(raw_params)?;
+            manager.delete_chat(p.chat_id);
+            Ok(Value::Null)
+        }
+        other => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("Unknown method: {}", other),
+        )),
+    }
+}
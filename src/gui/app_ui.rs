@@ -24,7 +24,16 @@ pub struct App {
     pub new_contact_fingerprint: String,
     pub new_contact_pubkey: String,
     pub invite_link_input: String,
+    /// Secret of the invite link being added
+    pub new_contact_invite_token: Option<String>,
     pub my_invite_link: Option<String>,
+    /// Lifetime of the next invite link in hours (0 = no expiry)
+    pub invite_valid_hours: i64,
+    pub invite_single_use: bool,
+    /// Who the next invite link is for; names the contact it creates
+    pub invite_label: String,
+    /// The label was edited since the current link was issued
+    pub invite_label_edited: bool,
     pub show_create_group: bool,
     pub group_wizard_step: usize, // 0=Name, 1=Members, 2=Confirm
     pub group_selected: Vec<Uuid>,
@@ -34,11 +43,18 @@ pub struct App {
     pub show_rename_dialog: bool,
     pub rename_chat_id: Option<Uuid>,
     pub rename_input: String,
+    // Message being replied to: (chat_id, message_id)
+    pub reply_to: Option<(Uuid, Uuid)>,
+    // Edit message dialog: (chat_id, message_id)
+    pub editing_message: Option<(Uuid, Uuid)>,
+    pub edit_input: String,
     pub show_connect_dialog: bool,
     pub connect_host: String,
     pub connect_port: String,
     pub show_host_dialog: bool,
     pub host_port: String,
+    /// Beacon port being edited in Settings, applied when the field loses focus
+    pub beacon_port_input: String,
     pub show_settings: bool,
     pub show_welcome: bool,
     pub file_to_send: Option<PathBuf>,
@@ -46,16 +62,52 @@ pub struct App {
     pub chat_to_delete: Option<Uuid>,
     pub history_path: PathBuf,
     pub show_emoji_picker: bool,
+    /// When set, the emoji picker reacts to this message instead of inserting into the composer
+    pub reaction_target: Option<Uuid>,
     pub last_typing_time: Option<std::time::Instant>,
+    /// Last time expired disappearing messages were purged
+    pub last_expiry_sweep: Option<std::time::Instant>,
     pub typing_stopped: bool,
     // Fingerprint verification dialog
     pub show_fingerprint_dialog: bool,
     pub fingerprint_to_verify: Option<String>,
     pub peer_name_to_verify: Option<String>,
     pub chat_id_to_verify: Option<Uuid>,
+    /// QR code of the safety number on screen, with the text it encodes
+    pub safety_qr_texture: Option<(String, egui::TextureHandle)>,
     pub show_log_terminal: bool,
     pub show_clear_history_dialog: bool,
+    // Identity backup export / restore dialogs
+    pub show_export_backup: bool,
+    pub show_restore_backup: bool,
+    pub backup_passphrase: String,
+    pub backup_passphrase_confirm: String,
+    /// Password of the current identity when its key is still locked
+    pub backup_unlock_password: String,
+    pub restore_backup_path: Option<PathBuf>,
+    pub restore_replace_confirmed: bool,
+    // First-run identity wizard and startup unlock screen
+    pub show_identity_setup: bool,
+    pub setup_step: usize, // 0=Name, 1=Password
+    pub setup_name: String,
+    pub show_unlock: bool,
+    pub show_change_password: bool,
+    pub show_rotate_key: bool,
+    // Password fields shared by the wizard, unlock screen and change-password dialog
+    pub password_current: String,
+    pub password_new: String,
+    pub password_confirm: String,
+    pub password_error: Option<String>,
     pub event_collector: EventCollector,
+    // Message search panel
+    pub search_query: String,
+    pub search_filters: crate::app::SearchFilters,
+    pub search_date_range: usize, // 0=Any time, 1=Last 24h, 2=Last 7 days, 3=Last 30 days
+    pub show_search_filters: bool,
+    pub highlighted_message: Option<Uuid>,
+    pub scroll_to_message: Option<Uuid>,
+    /// chat-p2p:// links handed over by later launches of the binary
+    pub incoming_links: Option<tokio::sync::mpsc::UnboundedReceiver<String>>,
 }
 
 impl App {
@@ -108,28 +160,32 @@ impl App {
         // Windows: %APPDATA%\chat-p2p\history.json
         // Linux: ~/.local/share/chat-p2p/history.json
         // macOS: ~/Library/Application Support/chat-p2p/history.json
-        let (history_path, identity) = if let Some(proj_dirs) =
-            directories::ProjectDirs::from("com", "chat-p2p", "EncryptedMessenger")
-        {
-            let data_dir = proj_dirs.data_dir();
-            std::fs::create_dir_all(data_dir).ok(); // Ensure directory exists
-
-            // Load or create user identity
-            let identity = crate::identity::Identity::get_or_create(data_dir, "User")
-                .unwrap_or_else(|e| {
-                    tracing::error!("Failed to load/create identity: {}", e);
-                    crate::identity::Identity::new("User".to_string())
-                        .expect("Failed to create identity")
-                });
+        let (history_path, identity, needs_setup) = if let Some(data_dir) = crate::app::default_data_dir() {
+            std::fs::create_dir_all(&data_dir).ok(); // Ensure directory exists
+
+            // Load the user identity; on first run the wizard names and saves a fresh one
+            let loaded = if crate::identity::Identity::exists(&data_dir) {
+                crate::identity::Identity::load(&data_dir.join("identity.json"))
+                    .map_err(|e| tracing::error!("Failed to load identity: {}", e))
+                    .ok()
+            } else {
+                None
+            };
+            let needs_setup = loaded.is_none();
+            let identity = loaded.unwrap_or_else(|| {
+                crate::identity::Identity::new("User".to_string())
+                    .expect("Failed to create identity")
+            });
 
-            (data_dir.join("history.json"), identity)
+            (data_dir.join("history.json"), identity, needs_setup)
         } else {
             // Fallback to relative path if directories crate fails
             tracing::warn!("Could not determine user data directory, using fallback path");
             let identity = crate::identity::Identity::new("User".to_string())
                 .expect("Failed to create identity");
-            (PathBuf::from("Downloads").join("history.json"), identity)
+            (PathBuf::from("Downloads").join("history.json"), identity, false)
         };
+        let needs_unlock = identity.is_encrypted() && !identity.is_unlocked();
 
         tracing::info!("Using history path: {}", history_path.display());
         tracing::info!(
@@ -146,11 +202,18 @@ impl App {
             }
         }
 
+        // Sessions sign their handshakes with the identity; a locked one
+        // waits for the unlock screen, and auto-host starts from `update`.
+        // Peers still pinned to a retired key are told on their next connection.
+        chat_manager.sync_identity(&identity);
+
         // Capture config before moving manager
-        let auto_host_enabled = chat_manager.config.auto_host_on_startup;
+        let auto_host_enabled =
+            chat_manager.config.auto_host_on_startup && chat_manager.has_identity();
         let auto_host_port = chat_manager.config.listen_port;
         // Capture listen_port for initializing the UI field before moving manager
         let host_port_ui = auto_host_port.to_string();
+        let beacon_port_ui = chat_manager.config.discovery_beacon_port.to_string();
         // Wrap manager in Arc<Mutex<..>> once and reuse
         let manager_arc = Arc::new(Mutex::new(chat_manager));
         // Auto-start host on startup if enabled in settings
@@ -178,6 +241,7 @@ impl App {
             connect_port: PORT_DEFAULT.to_string(),
             show_host_dialog: false,
             host_port: host_port_ui,
+            beacon_port_input: beacon_port_ui,
             show_settings: false,
             show_welcome: true, // Show welcome screen on first launch
             file_to_send: None,
@@ -191,7 +255,12 @@ impl App {
             new_contact_fingerprint: String::new(),
             new_contact_pubkey: String::new(),
             invite_link_input: String::new(),
+            new_contact_invite_token: None,
             my_invite_link: None,
+            invite_valid_hours: 0,
+            invite_single_use: false,
+            invite_label: String::new(),
+            invite_label_edited: false,
             show_create_group: false,
             group_wizard_step: 0,
             group_selected: Vec::new(),
@@ -200,18 +269,64 @@ impl App {
             show_rename_dialog: false,
             rename_chat_id: None,
             rename_input: String::new(),
+            reply_to: None,
+            editing_message: None,
+            edit_input: String::new(),
             history_path,
             show_emoji_picker: false,
+            reaction_target: None,
             last_typing_time: None,
+            last_expiry_sweep: None,
             typing_stopped: false,
             // Fingerprint verification dialog
             show_fingerprint_dialog: false,
             fingerprint_to_verify: None,
             peer_name_to_verify: None,
             chat_id_to_verify: None,
+            safety_qr_texture: None,
             show_log_terminal: initial_show_log_terminal,
             show_clear_history_dialog: false,
+            show_export_backup: false,
+            show_restore_backup: false,
+            backup_passphrase: String::new(),
+            backup_passphrase_confirm: String::new(),
+            backup_unlock_password: String::new(),
+            restore_backup_path: None,
+            restore_replace_confirmed: false,
+            show_identity_setup: needs_setup,
+            setup_step: 0,
+            setup_name: String::new(),
+            show_unlock: needs_unlock,
+            show_change_password: false,
+            show_rotate_key: false,
+            password_current: String::new(),
+            password_new: String::new(),
+            password_confirm: String::new(),
+            password_error: None,
             event_collector,
+            search_query: String::new(),
+            search_filters: crate::app::SearchFilters::default(),
+            search_date_range: 0,
+            show_search_filters: false,
+            highlighted_message: None,
+            scroll_to_message: None,
+            incoming_links: None,
+        }
+    }
+
+    /// Open a chat-p2p:// link given on the command line or by another launch:
+    /// invites go to the Add Contact dialog, pre-filled from the link
+    pub fn open_link(&mut self, link: &str) {
+        tracing::info!("Opening link");
+        if link.starts_with(crate::identity::invite::INVITE_PREFIX) {
+            self.invite_link_input = link.to_string();
+            self.contact_tab = 1;
+            self.show_add_contact = true;
+        } else if let Ok(mut manager) = self.chat_manager.try_lock() {
+            manager.add_toast(
+                crate::types::ToastLevel::Error,
+                "This kind of link is not supported".to_string(),
+            );
         }
     }
 
@@ -221,14 +336,19 @@ impl App {
         }
 
         let text = std::mem::take(&mut self.input_text);
-
-        if let Ok(mut manager) = self.chat_manager.try_lock() {
-            if let Err(e) = manager.send_message(chat_id, text) {
-                manager.add_toast(
-                    crate::types::ToastLevel::Error,
-                    format!("Failed to send: {}", e),
-                );
-            }
+        let reply_to = self
+            .reply_to
+            .take()
+            .filter(|(reply_chat, _)| *reply_chat == chat_id)
+            .map(|(_, message_id)| message_id);
+
+        if let Ok(mut manager) = self.chat_manager.try_lock()
+            && let Err(e) = manager.send_message(chat_id, text, reply_to)
+        {
+            manager.add_toast(
+                crate::types::ToastLevel::Error,
+                format!("Failed to send: {}", e),
+            );
         }
     }
 
@@ -276,6 +396,11 @@ impl App {
 
 impl eframe::App for App {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        while let Some(link) = self.incoming_links.as_mut().and_then(|rx| rx.try_recv().ok()) {
+            self.open_link(&link);
+            ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+        }
+
         // Poll session events to process received messages
         if let Ok(mut manager) = self.chat_manager.try_lock() {
             manager.poll_session_events();
@@ -285,7 +410,29 @@ impl eframe::App for App {
                 self.chat_id_to_verify = Some(chat_id);
                 self.show_fingerprint_dialog = true;
             }
+            // The peer proved our invite while the dialog was open
+            if self.show_fingerprint_dialog
+                && self.chat_id_to_verify.is_some_and(|id| manager.joined_with_invite(id))
+            {
+                self.show_fingerprint_dialog = false;
+            }
             manager.cleanup_expired_toasts();
+            manager.sync_discovery(&self.identity);
+            manager.sync_identity(&self.identity);
+
+            // Purge expired disappearing messages about once a second
+            let now = std::time::Instant::now();
+            if self
+                .last_expiry_sweep
+                .is_none_or(|last| now.duration_since(last).as_secs() >= 1)
+            {
+                self.last_expiry_sweep = Some(now);
+                if manager.purge_expired_messages(chrono::Utc::now()) > 0
+                    && let Err(e) = manager.save_history(&self.history_path)
+                {
+                    tracing::warn!("Failed to save history after purging expired messages: {}", e);
+                }
+            }
 
             // Auto-save history periodically
             static mut LAST_SAVE: Option<std::time::Instant> = None;
@@ -304,7 +451,7 @@ impl eframe::App for App {
 
             // Auto-rehost: if auto-host is enabled and no placeholder host chat exists,
             // spawn a new host to replace the one that was consumed by a connection.
-            if manager.config.auto_host_on_startup {
+            if manager.config.auto_host_on_startup && manager.has_identity() {
                 let has_placeholder = manager
                     .chats
                     .values()
@@ -339,6 +486,15 @@ impl eframe::App for App {
             }
         }
 
+        // Nothing else is shown until the identity is set up or unlocked
+        if self.show_identity_setup || self.show_unlock {
+            egui::CentralPanel::default().show(ctx, |_ui| {});
+            crate::gui::dialogs::render_toasts(self, ctx);
+            crate::gui::dialogs::render_identity_gate(self, ctx);
+            ctx.request_repaint_after(std::time::Duration::from_millis(100));
+            return;
+        }
+
         // Top panel - Menu bar
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             ui.horizontal(|ui| {
@@ -1,88 +1,197 @@
 use crate::gui::app_ui::App;
-use crate::types::{Message, MessageContent};
+use crate::types::{Contact, Message, MessageContent, Reactor, QUICK_REACTIONS};
+use std::collections::HashMap;
 use eframe::egui;
 use uuid::Uuid;
 
+/// Maximum length (in characters) of a quoted message preview
+const QUOTE_MAX_CHARS: usize = 60;
+
+/// Disappearing-message timers offered in the chat header, in seconds
+const DISAPPEARING_TIMERS: [u64; 5] = [300, 3_600, 86_400, 604_800, 2_592_000];
+
+/// Action requested from a message bubble
+enum MessageAction {
+    Reply(Uuid),
+    React(Uuid, String),
+    PickReaction(Uuid),
+    Edit(Uuid),
+    Delete(Uuid),
+    JumpTo(Uuid),
+}
+
 pub fn render_chat(app: &mut App, ui: &mut egui::Ui, chat_id: Uuid) {
     // Handle dropped files
     let dropped_files = ui.input(|i| i.raw.dropped_files.clone());
-    if !dropped_files.is_empty() {
-        if let Some(file) = dropped_files.first() {
-            if let Some(path) = &file.path {
-                app.file_to_send = Some(path.clone());
-            }
-        }
+    if !dropped_files.is_empty()
+        && let Some(file) = dropped_files.first()
+        && let Some(path) = &file.path
+    {
+        app.file_to_send = Some(path.clone());
     }
 
     // Header with connection status
+    let mut new_timer = None;
     egui::TopBottomPanel::top("chat_header")
         .exact_height(60.0)
         .show_inside(ui, |ui| {
-            if let Ok(manager) = app.chat_manager.try_lock() {
-                if let Some(chat) = manager.get_chat(chat_id) {
-                    ui.add_space(8.0);
-                    ui.horizontal(|ui| {
-                        // Avatar
-                        let color = if let Some(fp) = &chat.peer_fingerprint {
-                            crate::gui::widgets::fingerprint_to_color(fp)
-                        } else {
-                            egui::Color32::GRAY
-                        };
+            if let Ok(manager) = app.chat_manager.try_lock()
+                && let Some(chat) = manager.get_chat(chat_id)
+            {
+                ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    // Avatar
+                    let color = if let Some(fp) = &chat.peer_fingerprint {
+                        crate::gui::widgets::fingerprint_to_color(fp)
+                    } else {
+                        egui::Color32::GRAY
+                    };
+
+                    let (rect, _) =
+                        ui.allocate_exact_size(egui::vec2(40.0, 40.0), egui::Sense::hover());
+                    ui.painter().circle_filled(rect.center(), 20.0, color);
+
+                    let initials = crate::gui::widgets::get_initials(&chat.title);
+                    ui.painter().text(
+                        rect.center(),
+                        egui::Align2::CENTER_CENTER,
+                        initials,
+                        egui::FontId::proportional(16.0),
+                        egui::Color32::WHITE,
+                    );
 
-                        let (rect, _) =
-                            ui.allocate_exact_size(egui::vec2(40.0, 40.0), egui::Sense::hover());
-                        ui.painter().circle_filled(rect.center(), 20.0, color);
-
-                        let initials = crate::gui::widgets::get_initials(&chat.title);
-                        ui.painter().text(
-                            rect.center(),
-                            egui::Align2::CENTER_CENTER,
-                            initials,
-                            egui::FontId::proportional(16.0),
-                            egui::Color32::WHITE,
-                        );
+                    ui.add_space(8.0);
 
-                        ui.add_space(8.0);
+                    // Title and status
+                    ui.vertical(|ui| {
+                        ui.heading(&chat.title);
+                        // Show typing indicator or connection status
+                        if chat.peer_typing {
+                            ui.label(
+                                egui::RichText::new("✍️ typing...")
+                                    .size(12.0)
+                                    .color(crate::gui::styling::SUBTLE_TEXT_COLOR),
+                            );
+                        } else {
+                            ui.label(
+                                egui::RichText::new("🟢 Connected")
+                                    .size(12.0)
+                                    .color(crate::gui::styling::SUCCESS),
+                            );
+                        }
+                    });
 
-                        // Title and status
-                        ui.vertical(|ui| {
-                            ui.heading(&chat.title);
-                            // Show typing indicator or connection status
-                            if chat.peer_typing {
-                                ui.label(
-                                    egui::RichText::new("✍️ typing...")
-                                        .size(12.0)
-                                        .color(crate::gui::styling::SUBTLE_TEXT_COLOR),
-                                );
-                            } else {
-                                ui.label(
-                                    egui::RichText::new("🟢 Connected")
-                                        .size(12.0)
-                                        .color(crate::gui::styling::SUCCESS),
-                                );
+                    // Fingerprint on right
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        // Disappearing-messages timer
+                        let timer_label = match chat.disappearing_after_secs {
+                            Some(secs) => format!("⏱ {}", crate::util::format_duration_short(secs)),
+                            None => "⏱ Off".to_string(),
+                        };
+                        ui.menu_button(timer_label, |ui| {
+                            ui.label("Disappearing messages");
+                            ui.separator();
+                            if ui
+                                .selectable_label(chat.disappearing_after_secs.is_none(), "Off")
+                                .clicked()
+                            {
+                                new_timer = Some(None);
+                                ui.close_menu();
                             }
-                        });
-
-                        // Fingerprint on right
-                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                            if let Some(fp) = &chat.peer_fingerprint {
-                                if ui.button("📋 Copy Fingerprint").clicked() {
-                                    ui.output_mut(|o| o.copied_text = fp.clone());
+                            for secs in DISAPPEARING_TIMERS {
+                                if ui
+                                    .selectable_label(
+                                        chat.disappearing_after_secs == Some(secs),
+                                        crate::util::format_duration_short(secs),
+                                    )
+                                    .clicked()
+                                {
+                                    new_timer = Some(Some(secs));
+                                    ui.close_menu();
                                 }
-                                ui.monospace(crate::util::format_fingerprint_short(fp));
                             }
-                        });
+                        })
+                        .response
+                        .on_hover_text("Messages older than this are deleted on both sides");
+
+                        if manager.is_post_quantum(chat_id) {
+                            ui.label(
+                                egui::RichText::new("🛡 PQ")
+                                    .size(12.0)
+                                    .color(crate::gui::styling::SUCCESS),
+                            )
+                            .on_hover_text(
+                                "Post-quantum protected: the session key combines X25519 and ML-KEM-768",
+                            );
+                        } else if manager.is_connected(chat_id) {
+                            ui.label(
+                                egui::RichText::new("X25519")
+                                    .size(12.0)
+                                    .color(crate::gui::styling::SUBTLE_TEXT_COLOR),
+                            )
+                            .on_hover_text(
+                                "Classical key exchange only (the peer does not support the hybrid handshake)",
+                            );
+                        }
+
+                        if let Some(fp) = &chat.peer_fingerprint {
+                            if ui.button("📋 Copy Fingerprint").clicked() {
+                                ui.output_mut(|o| o.copied_text = fp.clone());
+                            }
+                            ui.monospace(crate::util::format_fingerprint_short(fp));
+                        }
                     });
-                }
+                });
             }
         });
 
+    if let Some(secs) = new_timer
+        && let Ok(mut manager) = app.chat_manager.try_lock()
+    {
+        if let Err(e) = manager.set_disappearing_timer(chat_id, secs) {
+            manager.add_toast(
+                crate::types::ToastLevel::Error,
+                format!("Failed to set timer: {}", e),
+            );
+        } else {
+            let _ = manager.save_history(&app.history_path);
+        }
+    }
+
+    // Drop a pending reply that belongs to another chat
+    if app.reply_to.is_some_and(|(reply_chat, _)| reply_chat != chat_id) {
+        app.reply_to = None;
+    }
+    let reply_preview = app.reply_to.and_then(|(_, message_id)| {
+        let manager = app.chat_manager.try_lock().ok()?;
+        let chat = manager.get_chat(chat_id)?;
+        chat.messages
+            .iter()
+            .find(|m| m.id == message_id)
+            .map(quote_snippet)
+    });
+
     // Input area - FIXED AT BOTTOM
     egui::TopBottomPanel::bottom("chat_input")
-        .exact_height(120.0)
+        .exact_height(if reply_preview.is_some() { 150.0 } else { 120.0 })
         .show_inside(ui, |ui| {
             ui.add_space(5.0);
 
+            // Reply preview if replying to a message
+            if let Some(preview) = &reply_preview {
+                ui.horizontal(|ui| {
+                    ui.label("↩ Replying to:");
+                    ui.label(
+                        egui::RichText::new(preview)
+                            .italics()
+                            .color(crate::gui::styling::SUBTLE_TEXT_COLOR),
+                    );
+                    if ui.small_button("❌").on_hover_text("Cancel reply").clicked() {
+                        app.reply_to = None;
+                    }
+                });
+            }
+
             // File preview if selected
             if app.file_to_send.is_some() {
                 let file_path = app.file_to_send.clone().unwrap();
@@ -124,10 +233,9 @@ pub fn render_chat(app: &mut App, ui: &mut egui::Ui, chat_id: Uuid) {
                     .button(egui::RichText::new("📎").size(20.0))
                     .on_hover_text("Attach file (or drag & drop)")
                     .clicked()
+                    && let Some(path) = rfd::FileDialog::new().pick_file()
                 {
-                    if let Some(path) = rfd::FileDialog::new().pick_file() {
-                        app.file_to_send = Some(path);
-                    }
+                    app.file_to_send = Some(path);
                 }
 
                 // Emoji picker button
@@ -137,6 +245,7 @@ pub fn render_chat(app: &mut App, ui: &mut egui::Ui, chat_id: Uuid) {
                     .clicked()
                 {
                     app.show_emoji_picker = !app.show_emoji_picker;
+                    app.reaction_target = None;
                 }
 
                 // Multiline text input
@@ -209,7 +318,12 @@ pub fn render_chat(app: &mut App, ui: &mut egui::Ui, chat_id: Uuid) {
 
     // Emoji picker overlay
     if app.show_emoji_picker {
-        egui::Window::new("😊 Emoji Picker")
+        let title = if app.reaction_target.is_some() {
+            "😊 React"
+        } else {
+            "😊 Emoji Picker"
+        };
+        egui::Window::new(title)
             .resizable(false)
             .collapsible(false)
             .default_width(300.0)
@@ -223,7 +337,11 @@ pub fn render_chat(app: &mut App, ui: &mut egui::Ui, chat_id: Uuid) {
 
                     for emoji in &common_emojis {
                         if ui.button(egui::RichText::new(*emoji).size(24.0)).clicked() {
-                            app.input_text.push_str(emoji);
+                            if let Some(message_id) = app.reaction_target.take() {
+                                toggle_reaction(app, chat_id, message_id, emoji);
+                            } else {
+                                app.input_text.push_str(emoji);
+                            }
                             app.show_emoji_picker = false;
                         }
                     }
@@ -232,45 +350,157 @@ pub fn render_chat(app: &mut App, ui: &mut egui::Ui, chat_id: Uuid) {
                 ui.separator();
                 if ui.button("Close").clicked() {
                     app.show_emoji_picker = false;
+                    app.reaction_target = None;
                 }
             });
     }
 
     // Messages area - fills remaining space
+    let mut pending_action = None;
     egui::CentralPanel::default().show_inside(ui, |ui| {
         egui::ScrollArea::vertical()
             .auto_shrink([false; 2])
             .stick_to_bottom(true)
             .show(ui, |ui| {
-                if let Ok(manager) = app.chat_manager.try_lock() {
-                    if let Some(chat) = manager.get_chat(chat_id) {
-                        if chat.messages.is_empty() {
-                            ui.vertical_centered(|ui| {
-                                ui.add_space(100.0);
-                                ui.label(
-                                    egui::RichText::new("🔒 End-to-end encrypted conversation")
-                                        .size(16.0)
-                                        .color(crate::gui::styling::SUBTLE_TEXT_COLOR),
-                                );
-                                ui.label(
-                                    egui::RichText::new("Send your first message below!")
-                                        .size(14.0)
-                                        .color(crate::gui::styling::SUBTLE_TEXT_COLOR),
-                                );
-                            });
-                        } else {
-                            for message in &chat.messages {
-                                render_message(app, ui, message);
-                                ui.add_space(8.0);
+                if let Ok(manager) = app.chat_manager.try_lock()
+                    && let Some(chat) = manager.get_chat(chat_id)
+                {
+                    if chat.messages.is_empty() {
+                        ui.vertical_centered(|ui| {
+                            ui.add_space(100.0);
+                            ui.label(
+                                egui::RichText::new("🔒 End-to-end encrypted conversation")
+                                    .size(16.0)
+                                    .color(crate::gui::styling::SUBTLE_TEXT_COLOR),
+                            );
+                            ui.label(
+                                egui::RichText::new("Send your first message below!")
+                                    .size(14.0)
+                                    .color(crate::gui::styling::SUBTLE_TEXT_COLOR),
+                            );
+                        });
+                    } else {
+                        for message in &chat.messages {
+                            let highlighted = app.highlighted_message == Some(message.id);
+                            let scroll_to = app.scroll_to_message == Some(message.id);
+                            let quoted = message
+                                .reply_to
+                                .map(|id| chat.messages.iter().find(|m| m.id == id));
+                            if let Some(action) = render_message(
+                                app,
+                                ui,
+                                message,
+                                quoted,
+                                &manager.contacts,
+                                highlighted,
+                                scroll_to,
+                            ) {
+                                pending_action = Some(action);
                             }
+                            if scroll_to {
+                                app.scroll_to_message = None;
+                            }
+                            ui.add_space(8.0);
                         }
                     }
                 }
             });
     });
+
+    match pending_action {
+        Some(MessageAction::Reply(message_id)) => {
+            app.reply_to = Some((chat_id, message_id));
+        }
+        Some(MessageAction::React(message_id, emoji)) => {
+            toggle_reaction(app, chat_id, message_id, &emoji);
+        }
+        Some(MessageAction::PickReaction(message_id)) => {
+            app.reaction_target = Some(message_id);
+            app.show_emoji_picker = true;
+        }
+        Some(MessageAction::JumpTo(message_id)) => {
+            app.highlighted_message = Some(message_id);
+            app.scroll_to_message = Some(message_id);
+        }
+        Some(MessageAction::Edit(message_id)) => {
+            if let Ok(manager) = app.chat_manager.try_lock()
+                && let Some(text) = manager
+                    .get_chat(chat_id)
+                    .and_then(|c| c.messages.iter().find(|m| m.id == message_id))
+                    .and_then(|m| m.text())
+            {
+                app.edit_input = text.to_string();
+                app.editing_message = Some((chat_id, message_id));
+            }
+        }
+        Some(MessageAction::Delete(message_id)) => {
+            if let Ok(mut manager) = app.chat_manager.try_lock() {
+                if let Err(e) = manager.delete_message(chat_id, message_id) {
+                    manager.add_toast(
+                        crate::types::ToastLevel::Error,
+                        format!("Failed to delete message: {}", e),
+                    );
+                } else {
+                    let _ = manager.save_history(&app.history_path);
+                }
+            }
+        }
+        None => {}
+    }
+}
+
+fn toggle_reaction(app: &mut App, chat_id: Uuid, message_id: Uuid, emoji: &str) {
+    if let Ok(mut manager) = app.chat_manager.try_lock() {
+        if let Err(e) = manager.toggle_reaction(chat_id, message_id, emoji) {
+            manager.add_toast(
+                crate::types::ToastLevel::Error,
+                format!("Failed to react: {}", e),
+            );
+        } else {
+            let _ = manager.save_history(&app.history_path);
+        }
+    }
 }
 
-fn render_message(_app: &App, ui: &mut egui::Ui, message: &Message) {
+/// Display name of someone who reacted
+fn reactor_name(reactor: &Reactor, contacts: &HashMap<Uuid, Contact>) -> String {
+    match reactor {
+        Reactor::Me => "You".to_string(),
+        Reactor::Peer => "Peer".to_string(),
+        Reactor::Member(contact_id) => contacts
+            .get(contact_id)
+            .map(|c| c.name.clone())
+            .unwrap_or_else(|| "Unknown member".to_string()),
+    }
+}
+
+/// Short one-line preview of a message for reply quotes
+pub(crate) fn quote_snippet(message: &Message) -> String {
+    let text = match &message.content {
+        MessageContent::File { filename, .. } => format!("📄 {}", filename),
+        MessageContent::Deleted => "🗑 Deleted message".to_string(),
+        _ => message.text().unwrap_or_default().replace('\n', " "),
+    };
+    if text.chars().count() > QUOTE_MAX_CHARS {
+        format!("{}…", text.chars().take(QUOTE_MAX_CHARS).collect::<String>())
+    } else {
+        text
+    }
+}
+
+/// `quoted` is `Some(_)` when the message is a reply: `Some(None)` means the
+/// original is no longer in this chat's history.
+fn render_message(
+    _app: &App,
+    ui: &mut egui::Ui,
+    message: &Message,
+    quoted: Option<Option<&Message>>,
+    contacts: &HashMap<Uuid, Contact>,
+    highlighted: bool,
+    scroll_to: bool,
+) -> Option<MessageAction> {
+    let mut action = None;
+
     let align = if message.from_me {
         egui::Layout::right_to_left(egui::Align::TOP)
     } else {
@@ -294,6 +524,47 @@ fn render_message(_app: &App, ui: &mut egui::Ui, message: &Message) {
         let frame_response = frame.show(ui, |ui| {
             ui.set_max_width(400.0);
 
+            // Quoted original for replies; clicking it jumps to the original
+            if let Some(quoted) = quoted {
+                let quote = egui::Frame::none()
+                    .fill(crate::gui::styling::PRIMARY_BACKGROUND)
+                    .rounding(egui::Rounding::same(6.0))
+                    .inner_margin(egui::Margin::symmetric(8.0, 4.0))
+                    .show(ui, |ui| {
+                        let (author, snippet) = match quoted {
+                            Some(original) => (
+                                if original.from_me { "You" } else { "Peer" },
+                                quote_snippet(original),
+                            ),
+                            None => ("", "Original message unavailable".to_string()),
+                        };
+                        if !author.is_empty() {
+                            ui.label(
+                                egui::RichText::new(author)
+                                    .size(11.0)
+                                    .strong()
+                                    .color(crate::gui::styling::TEXT_PRIMARY),
+                            );
+                        }
+                        ui.label(
+                            egui::RichText::new(snippet)
+                                .size(12.0)
+                                .italics()
+                                .color(crate::gui::styling::SUBTLE_TEXT_COLOR),
+                        );
+                    });
+                if let Some(original) = quoted
+                    && quote
+                        .response
+                        .interact(egui::Sense::click())
+                        .on_hover_text("Jump to original message")
+                        .clicked()
+                {
+                    action = Some(MessageAction::JumpTo(original.id));
+                }
+                ui.add_space(4.0);
+            }
+
             match &message.content {
                 MessageContent::Text { text } => {
                     // Text message with white color
@@ -355,11 +626,34 @@ fn render_message(_app: &App, ui: &mut egui::Ui, message: &Message) {
                     }
                 }
                 MessageContent::Edited { new_text } => {
-                    ui.label(
+                    let response = ui.label(
                         egui::RichText::new(format!("{} (Edited)", new_text))
                             .color(crate::gui::styling::TEXT_PRIMARY)
                             .size(14.0),
                     );
+                    if !message.edit_history.is_empty() {
+                        let history = message
+                            .edit_history
+                            .iter()
+                            .map(|edit| {
+                                format!(
+                                    "{} — {}",
+                                    edit.replaced_at.format("%Y-%m-%d %H:%M"),
+                                    edit.text
+                                )
+                            })
+                            .collect::<Vec<_>>()
+                            .join("\n");
+                        response.on_hover_text(format!("Previous versions:\n{}", history));
+                    }
+                }
+                MessageContent::Deleted => {
+                    ui.label(
+                        egui::RichText::new("🗑 This message was deleted")
+                            .italics()
+                            .color(crate::gui::styling::SUBTLE_TEXT_COLOR)
+                            .size(14.0),
+                    );
                 }
             }
 
@@ -374,6 +668,20 @@ fn render_message(_app: &App, ui: &mut egui::Ui, message: &Message) {
             );
         });
 
+        // Highlight a message selected from search results
+        if highlighted {
+            ui.painter().rect_stroke(
+                frame_response.response.rect.expand(2.0),
+                12.0,
+                egui::Stroke::new(2.0, crate::gui::styling::WARNING),
+            );
+        }
+        if scroll_to {
+            frame_response
+                .response
+                .scroll_to_me(Some(egui::Align::Center));
+        }
+
         // Add hover effect
         if frame_response.response.hovered() {
             ui.painter().rect_stroke(
@@ -382,5 +690,73 @@ fn render_message(_app: &App, ui: &mut egui::Ui, message: &Message) {
                 egui::Stroke::new(1.0, crate::gui::styling::ACCENT_SECONDARY),
             );
         }
+
+        // Any message can be replied to; own messages can also be edited
+        // (text only) or deleted for everyone
+        if !matches!(message.content, MessageContent::Deleted) {
+            frame_response
+                .response
+                .interact(egui::Sense::click())
+                .context_menu(|ui| {
+                    ui.horizontal(|ui| {
+                        for emoji in QUICK_REACTIONS {
+                            if ui
+                                .selectable_label(
+                                    message.has_my_reaction(emoji),
+                                    egui::RichText::new(emoji).size(18.0),
+                                )
+                                .clicked()
+                            {
+                                action = Some(MessageAction::React(message.id, emoji.to_string()));
+                                ui.close_menu();
+                            }
+                        }
+                        if ui.button("➕").on_hover_text("More reactions").clicked() {
+                            action = Some(MessageAction::PickReaction(message.id));
+                            ui.close_menu();
+                        }
+                    });
+                    ui.separator();
+                    if ui.button("↩ Reply").clicked() {
+                        action = Some(MessageAction::Reply(message.id));
+                        ui.close_menu();
+                    }
+                    if message.from_me {
+                        if message.text().is_some() && ui.button("✏️ Edit message").clicked() {
+                            action = Some(MessageAction::Edit(message.id));
+                            ui.close_menu();
+                        }
+                        if ui.button("🗑 Delete for everyone").clicked() {
+                            action = Some(MessageAction::Delete(message.id));
+                            ui.close_menu();
+                        }
+                    }
+                });
+        }
     });
+
+    // Reaction chips under the bubble; clicking one toggles our own reaction
+    if !message.reactions.is_empty() {
+        ui.with_layout(align, |ui| {
+            for (emoji, reactors) in &message.reactions {
+                let names = reactors
+                    .iter()
+                    .map(|r| reactor_name(r, contacts))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                if ui
+                    .selectable_label(
+                        reactors.contains(&Reactor::Me),
+                        egui::RichText::new(format!("{} {}", emoji, reactors.len())).size(12.0),
+                    )
+                    .on_hover_text(names)
+                    .clicked()
+                {
+                    action = Some(MessageAction::React(message.id, emoji.clone()));
+                }
+            }
+        });
+    }
+
+    action
 }
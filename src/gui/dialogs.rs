@@ -37,6 +37,10 @@ pub fn render_dialogs(app: &mut App, ctx: &egui::Context) {
         render_rename_dialog(app, ctx);
     }
 
+    if app.editing_message.is_some() {
+        render_edit_message_dialog(app, ctx);
+    }
+
     if app.show_settings {
         render_settings_dialog(app, ctx);
     }
@@ -56,6 +60,36 @@ pub fn render_dialogs(app: &mut App, ctx: &egui::Context) {
     if app.show_clear_history_dialog {
         render_clear_history_dialog(app, ctx);
     }
+
+    if app.show_export_backup {
+        render_export_backup_dialog(app, ctx);
+    }
+
+    if app.show_restore_backup {
+        render_restore_backup_dialog(app, ctx);
+    }
+
+    if app.show_change_password {
+        render_change_password_dialog(app, ctx);
+    }
+
+    if app.show_rotate_key {
+        render_rotate_key_dialog(app, ctx);
+    }
+}
+
+/// Startup screens shown instead of the main window until an identity is usable
+pub fn render_identity_gate(app: &mut App, ctx: &egui::Context) {
+    if app.show_identity_setup {
+        render_identity_setup(app, ctx);
+    } else if app.show_unlock {
+        render_unlock_screen(app, ctx);
+    }
+
+    // Both screens offer restoring a backup instead
+    if app.show_restore_backup {
+        render_restore_backup_dialog(app, ctx);
+    }
 }
 
 fn render_fingerprint_dialog(app: &mut App, ctx: &egui::Context) {
@@ -71,14 +105,96 @@ fn render_fingerprint_dialog(app: &mut App, ctx: &egui::Context) {
             .show(ctx, |ui| {
                 ui.heading(format!("Connecting to {}", peer_name));
                 ui.add_space(10.0);
-                ui.label("Please verify that the fingerprint below matches the one provided by your peer.");
-                ui.add_space(10.0);
 
-                let grid = generate_color_grid(fingerprint);
-                ui.add(ColorGrid::new(grid));
+                let safety_number = app
+                    .chat_manager
+                    .try_lock()
+                    .ok()
+                    .and_then(|manager| manager.safety_numbers.get(&chat_id).cloned());
+                match safety_number {
+                    Some(safety_number) => {
+                        ui.label("Compare this safety number with your peer, in person or over a call. It is the same on both screens.");
+                        ui.add_space(10.0);
+
+                        ui.add(ColorGrid::new(generate_color_grid(&safety_number.color_key())));
+                        ui.add_space(10.0);
+                        for row in safety_number.groups().chunks(4) {
+                            ui.monospace(egui::RichText::new(row.join("  ")).size(18.0));
+                        }
+                        ui.add_space(5.0);
+                        ui.label(egui::RichText::new(safety_number.emoji().join(" ")).size(22.0));
+                        ui.add_space(5.0);
+                        ui.small(format!(
+                            "Peer key: {}",
+                            crate::util::format_fingerprint_short(fingerprint)
+                        ));
+                        ui.add_space(5.0);
+
+                        // Exchange QR images instead of reading the digits
+                        let payload = safety_number.qr_payload();
+                        if app.safety_qr_texture.as_ref().is_none_or(|(shown, _)| *shown != payload)
+                            && let Ok(png) = crate::util::render_qr_png(&payload)
+                            && let Ok(qr) = image::load_from_memory(&png)
+                        {
+                            let qr = qr.to_rgba8();
+                            let size = [qr.width() as usize, qr.height() as usize];
+                            let texture = ctx.load_texture(
+                                "safety-qr",
+                                egui::ColorImage::from_rgba_unmultiplied(size, qr.as_raw()),
+                                egui::TextureOptions::NEAREST,
+                            );
+                            app.safety_qr_texture = Some((payload.clone(), texture));
+                        }
+                        ui.collapsing("🔳 Show as QR code", |ui| {
+                            if let Some((_, texture)) = &app.safety_qr_texture {
+                                ui.image((texture.id(), egui::vec2(200.0, 200.0)));
+                            }
+                        });
 
-                ui.add_space(10.0);
-                ui.monospace(fingerprint);
+                        let mut scanned = None;
+                        ui.horizontal(|ui| {
+                            if ui.button("📋 Copy my QR").clicked()
+                                && let Err(e) = crate::util::copy_qr_to_clipboard(&payload)
+                            {
+                                tracing::warn!("Failed to copy safety QR: {}", e);
+                            }
+                            if ui.button("🖼 Scan peer's QR…").clicked()
+                                && let Some(path) = rfd::FileDialog::new()
+                                    .add_filter("Images", &["png", "jpg", "jpeg", "gif", "bmp", "webp"])
+                                    .pick_file()
+                            {
+                                scanned = Some(crate::util::decode_qr_file(&path));
+                            }
+                            if ui.button("📋 Scan from clipboard").clicked() {
+                                scanned = Some(crate::util::decode_qr_clipboard());
+                            }
+                        });
+                        if let Some(scanned) = scanned
+                            && let Ok(mut manager) = app.chat_manager.try_lock()
+                        {
+                            match scanned.and_then(|payload| manager.verify_safety_qr(chat_id, &payload)) {
+                                Ok(_) => {
+                                    manager.add_toast(
+                                        crate::types::ToastLevel::Success,
+                                        "Safety number matches, peer verified".to_string(),
+                                    );
+                                    app.show_fingerprint_dialog = false;
+                                }
+                                Err(e) => manager.add_toast(
+                                    crate::types::ToastLevel::Error,
+                                    format!("QR check failed: {}", e),
+                                ),
+                            }
+                        }
+                    }
+                    None => {
+                        ui.label("Please verify that the fingerprint below matches the one provided by your peer.");
+                        ui.add_space(10.0);
+                        ui.add(ColorGrid::new(generate_color_grid(fingerprint)));
+                        ui.add_space(10.0);
+                        ui.monospace(fingerprint);
+                    }
+                }
                 ui.add_space(10.0);
 
                 ui.horizontal(|ui| {
@@ -90,6 +206,7 @@ fn render_fingerprint_dialog(app: &mut App, ctx: &egui::Context) {
                             if let Some(chat) = manager.chats.get_mut(&chat_id) {
                                 chat.peer_fingerprint = Some(fingerprint.clone());
                             }
+                            manager.mark_verified(chat_id);
                             manager.add_toast(crate::types::ToastLevel::Success, "Fingerprint accepted".to_string());
                         }
                         app.show_fingerprint_dialog = false;
@@ -328,6 +445,13 @@ fn render_contacts_window(app: &mut App, ctx: &egui::Context) {
                             if let Some(fp) = &contact.fingerprint {
                                 ui.monospace(crate::util::format_fingerprint_short(fp));
                             }
+                            if let Some(verified_at) = contact.verified_at {
+                                ui.colored_label(egui::Color32::from_rgb(60, 180, 75), "✔")
+                                    .on_hover_text(format!(
+                                        "Safety number verified on {}",
+                                        verified_at.format("%Y-%m-%d %H:%M")
+                                    ));
+                            }
 
                             if ui.small_button("🔗").on_hover_text("Open chat").clicked() {
                                 // Check if there's already a mapped chat for this contact
@@ -373,6 +497,7 @@ fn render_contacts_window(app: &mut App, ctx: &egui::Context) {
                                             created_at: chrono::Utc::now(),
                                             peer_typing: false,
                                             typing_since: None,
+                                            disappearing_after_secs: None,
                                         };
                                         mgr.chats.insert(chat_id, chat);
                                         mgr.associate_contact_with_chat(contact_clone.id, chat_id);
@@ -557,6 +682,31 @@ fn render_add_contact_dialog(app: &mut App, ctx: &egui::Context) {
                             app.invite_link_input = text;
                         }
                     });
+                    ui.horizontal(|ui| {
+                        let mut scanned = None;
+                        if ui.button("🖼 From QR image…").clicked()
+                            && let Some(path) = rfd::FileDialog::new()
+                                .add_filter("Images", &["png", "jpg", "jpeg", "gif", "bmp", "webp"])
+                                .pick_file()
+                        {
+                            scanned = Some(crate::util::decode_qr_file(&path));
+                        }
+                        if ui.button("📋 QR from clipboard").clicked() {
+                            scanned = Some(crate::util::decode_qr_clipboard());
+                        }
+                        match scanned {
+                            Some(Ok(text)) => app.invite_link_input = text,
+                            Some(Err(e)) => {
+                                if let Ok(mut manager) = app.chat_manager.try_lock() {
+                                    manager.add_toast(
+                                        crate::types::ToastLevel::Error,
+                                        format!("Could not read QR code: {}", e),
+                                    );
+                                }
+                            }
+                            None => {}
+                        }
+                    });
                     ui.text_edit_singleline(&mut app.invite_link_input);
 
                     if !app.invite_link_input.is_empty() {
@@ -573,6 +723,7 @@ fn render_add_contact_dialog(app: &mut App, ctx: &egui::Context) {
                                     app.new_contact_fingerprint =
                                         contact.fingerprint.unwrap_or_default();
                                     app.new_contact_pubkey = contact.public_key.unwrap_or_default();
+                                    app.new_contact_invite_token = contact.invite_token;
                                 }
                                 Err(e) => {
                                     ui.label(
@@ -621,10 +772,12 @@ fn render_add_contact_dialog(app: &mut App, ctx: &egui::Context) {
                             if !name.is_empty() {
                                 let manager = app.chat_manager.clone();
                                 let history_path = app.history_path.clone();
+                                let invite_token = app.new_contact_invite_token.take();
 
                                 tokio::spawn(async move {
                                     let mut mgr = manager.lock().await;
-                                    mgr.add_contact(name, address, fp, pk);
+                                    let id = mgr.add_contact(name, address, fp, pk);
+                                    mgr.set_invite_token(id, invite_token);
                                     let _ = mgr.save_history(&history_path);
                                     mgr.add_toast(
                                         crate::types::ToastLevel::Success,
@@ -651,14 +804,63 @@ fn render_add_contact_dialog(app: &mut App, ctx: &egui::Context) {
                     ui.label("📤 Share this link with your friends so they can add you:");
                     ui.add_space(10.0);
 
+                    ui.horizontal(|ui| {
+                        ui.label("Expires:");
+                        let label = |hours: i64| match hours {
+                            0 => "Never".to_string(),
+                            hours => crate::util::format_duration_short(hours as u64 * 3_600),
+                        };
+                        let before = (app.invite_valid_hours, app.invite_single_use);
+                        egui::ComboBox::from_id_salt("invite_expiry")
+                            .selected_text(label(app.invite_valid_hours))
+                            .show_ui(ui, |ui| {
+                                for hours in [0, 1, 24, 24 * 7] {
+                                    ui.selectable_value(&mut app.invite_valid_hours, hours, label(hours));
+                                }
+                            });
+                        ui.checkbox(&mut app.invite_single_use, "Single use")
+                            .on_hover_text("The link stops working once someone has used it");
+                        if before != (app.invite_valid_hours, app.invite_single_use) {
+                            app.my_invite_link = None;
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("For:");
+                        let label_edit = ui
+                            .add(
+                                egui::TextEdit::singleline(&mut app.invite_label)
+                                    .hint_text("Name (optional)")
+                                    .desired_width(160.0),
+                            )
+                            .on_hover_text("Whoever connects with this link is added under this name, already verified");
+                        // A new link once editing is done, not one per keystroke
+                        app.invite_label_edited |= label_edit.changed();
+                        if app.invite_label_edited && label_edit.lost_focus() {
+                            app.invite_label_edited = false;
+                            app.my_invite_link = None;
+                        }
+                    });
+                    ui.add_space(5.0);
+
                     // Generate link using actual identity
-                    if app.my_invite_link.is_none() {
+                    if app.my_invite_link.is_none()
+                        && let Ok(mut manager) = app.chat_manager.try_lock()
+                    {
                         // Do not include a placeholder address in the generated invite link.
                         // If the application knows the public IP and listening port it can be
                         // supplied here; otherwise leave it empty so contacts won't receive
                         // an invalid/default address like "YOUR_IP:PORT".
-                        match app.identity.generate_invite_link(None) {
+                        let options = crate::identity::InviteOptions {
+                            address: None,
+                            valid_for: (app.invite_valid_hours > 0)
+                                .then(|| chrono::Duration::hours(app.invite_valid_hours)),
+                            single_use: app.invite_single_use,
+                            label: Some(app.invite_label.trim().to_string())
+                                .filter(|label| !label.is_empty()),
+                        };
+                        match manager.issue_invite(&app.identity, &options) {
                             Ok(link) => {
+                                let _ = manager.save_history(&app.history_path);
                                 app.my_invite_link = Some(link);
                             }
                             Err(e) => {
@@ -681,6 +883,11 @@ fn render_add_contact_dialog(app: &mut App, ctx: &egui::Context) {
                                 {
                                     ui.output_mut(|o| o.copied_text = link.clone());
                                 }
+                                if crate::gui::widgets::secondary_button(ui, "🔳 Copy as QR").clicked()
+                                    && let Err(e) = crate::util::copy_qr_to_clipboard(link)
+                                {
+                                    tracing::warn!("Failed to copy invite QR: {}", e);
+                                }
                             });
                         });
                     }
@@ -693,7 +900,7 @@ fn render_add_contact_dialog(app: &mut App, ctx: &egui::Context) {
                     ui.add_space(10.0);
                     ui.label("💡 Tip: You can share this via:");
                     ui.label("  • Email, WhatsApp, SMS");
-                    ui.label("  • QR code (future feature)");
+                    ui.label("  • QR code (🔳 Copy as QR, then paste the image)");
 
                     ui.add_space(10.0);
                     if crate::gui::widgets::secondary_button(ui, "Close").clicked() {
@@ -1013,6 +1220,57 @@ fn render_rename_dialog(app: &mut App, ctx: &egui::Context) {
     }
 }
 
+fn render_edit_message_dialog(app: &mut App, ctx: &egui::Context) {
+    if let Some((chat_id, message_id)) = app.editing_message {
+        let mut save = false;
+        let mut close = false;
+
+        egui::Window::new("Edit Message")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                let response = ui.text_edit_singleline(&mut app.edit_input);
+                ui.add_space(10.0);
+
+                ui.horizontal(|ui| {
+                    if crate::gui::widgets::primary_button(ui, "✅ Save").clicked() {
+                        save = true;
+                    }
+                    if crate::gui::widgets::secondary_button(ui, "❌ Cancel").clicked() {
+                        close = true;
+                    }
+                });
+
+                // Allow Enter key to save
+                if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                    save = true;
+                }
+            });
+
+        let new_text = app.edit_input.trim().to_string();
+        if save && !new_text.is_empty() {
+            if let Ok(mut manager) = app.chat_manager.try_lock() {
+                if let Err(e) = manager.edit_message(chat_id, message_id, new_text) {
+                    manager.add_toast(
+                        crate::types::ToastLevel::Error,
+                        format!("Failed to edit message: {}", e),
+                    );
+                } else {
+                    let _ = manager.save_history(&app.history_path);
+                    ctx.request_repaint();
+                }
+            }
+            close = true;
+        }
+
+        if close {
+            app.editing_message = None;
+            app.edit_input.clear();
+        }
+    }
+}
+
 fn render_settings_dialog(app: &mut App, ctx: &egui::Context) {
     egui::Window::new("⚙️ Settings")
         .collapsible(false)
@@ -1130,6 +1388,60 @@ fn render_settings_dialog(app: &mut App, ctx: &egui::Context) {
 
                 ui.add_space(10.0);
 
+                if ui.checkbox(
+                    &mut manager.config.persist_search_index,
+                    "Save search index with history",
+                ).changed() {
+                    let _ = manager.save_history(&app.history_path);
+                }
+
+                ui.add_space(10.0);
+
+                if ui.checkbox(
+                    &mut manager.config.enable_post_quantum,
+                    "Post-quantum key exchange (X25519 + ML-KEM)",
+                ).on_hover_text("Applies to new connections; older peers use X25519 only").changed() {
+                    let _ = manager.save_history(&app.history_path);
+                }
+
+                ui.add_space(10.0);
+
+                if ui.checkbox(
+                    &mut manager.config.enable_lan_discovery,
+                    "Find peers on the local network",
+                ).on_hover_text("Lists nearby peers in the sidebar and announces your name and fingerprint while you host").changed() {
+                    // Started or stopped on the next frame
+                    let _ = manager.save_history(&app.history_path);
+                }
+                if manager.config.enable_lan_discovery {
+                    ui.horizontal(|ui| {
+                        ui.label("Beacon port:");
+                        let response = ui.text_edit_singleline(&mut app.beacon_port_input)
+                            .on_hover_text("UDP port for signed broadcast beacons, for networks that block mDNS. Peers must use the same port");
+                        if response.lost_focus() {
+                            match app.beacon_port_input.trim().parse::<u16>() {
+                                Ok(port) if port > 0 => {
+                                    manager.config.discovery_beacon_port = port;
+                                    let _ = manager.save_history(&app.history_path);
+                                }
+                                _ => app.beacon_port_input = manager.config.discovery_beacon_port.to_string(),
+                            }
+                        }
+                    });
+                }
+
+                ui.add_space(10.0);
+
+                ui.label("Replace the session key every:");
+                if ui.add(egui::Slider::new(&mut manager.config.rekey_policy.after_minutes, 0..=240).suffix(" min"))
+                    .on_hover_text("0 turns off the time limit. Keys are also replaced after 10,000 messages or 256 MB")
+                    .changed()
+                {
+                    let _ = manager.save_history(&app.history_path);
+                }
+
+                ui.add_space(10.0);
+
                 // Theme selection
                 ui.horizontal(|ui| {
                     ui.label("Theme:");
@@ -1199,6 +1511,50 @@ fn render_settings_dialog(app: &mut App, ctx: &egui::Context) {
                 }
             }
 
+            ui.add_space(20.0);
+            ui.heading("Identity Key");
+            ui.separator();
+            ui.label(format!(
+                "Fingerprint: {}",
+                crate::util::format_fingerprint_short(&app.identity.fingerprint)
+            ));
+            if crate::gui::widgets::secondary_button(ui, "♻ Rotate key").clicked() {
+                app.show_rotate_key = true;
+            }
+
+            ui.add_space(20.0);
+            ui.heading("Identity Password");
+            ui.separator();
+            if app.identity.is_encrypted() {
+                ui.label("Your private key is protected by a password.");
+            } else {
+                ui.colored_label(
+                    crate::gui::styling::ERROR,
+                    "Your private key is stored without a password.",
+                );
+            }
+            let label = if app.identity.is_encrypted() {
+                "🔑 Change password"
+            } else {
+                "🔑 Set password"
+            };
+            if crate::gui::widgets::secondary_button(ui, label).clicked() {
+                app.show_change_password = true;
+            }
+
+            ui.add_space(20.0);
+            ui.heading("Identity Backup");
+            ui.separator();
+            ui.label("Save your identity, contacts and verified fingerprints to an encrypted file.");
+            ui.horizontal(|ui| {
+                if crate::gui::widgets::secondary_button(ui, "💾 Export backup").clicked() {
+                    app.show_export_backup = true;
+                }
+                if crate::gui::widgets::secondary_button(ui, "📂 Restore backup").clicked() {
+                    app.show_restore_backup = true;
+                }
+            });
+
             ui.add_space(20.0);
             ui.heading("Danger Zone");
             ui.separator();
@@ -1252,6 +1608,462 @@ fn render_clear_history_dialog(app: &mut App, ctx: &egui::Context) {
         });
 }
 
+fn reset_backup_fields(app: &mut App) {
+    app.backup_passphrase.clear();
+    app.backup_passphrase_confirm.clear();
+    app.backup_unlock_password.clear();
+    app.restore_backup_path = None;
+    app.restore_replace_confirmed = false;
+}
+
+fn render_export_backup_dialog(app: &mut App, ctx: &egui::Context) {
+    let chat_manager = app.chat_manager.clone();
+    egui::Window::new("💾 Export Identity Backup")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(ctx, |ui| {
+            ui.label("The backup holds your private key, contacts and verified fingerprints,");
+            ui.label("encrypted with the passphrase below. Keep both somewhere safe.");
+            ui.add_space(10.0);
+
+            let locked = !app.identity.is_unlocked();
+            if locked {
+                ui.label("Current identity password:");
+                ui.add(egui::TextEdit::singleline(&mut app.backup_unlock_password).password(true));
+            }
+            ui.label("Backup passphrase:");
+            ui.add(egui::TextEdit::singleline(&mut app.backup_passphrase).password(true));
+            ui.label("Repeat passphrase:");
+            ui.add(egui::TextEdit::singleline(&mut app.backup_passphrase_confirm).password(true));
+
+            let mismatch = app.backup_passphrase != app.backup_passphrase_confirm;
+            if mismatch && !app.backup_passphrase_confirm.is_empty() {
+                ui.colored_label(crate::gui::styling::ERROR, "Passphrases do not match");
+            }
+            ui.add_space(10.0);
+
+            ui.horizontal(|ui| {
+                let ready = !app.backup_passphrase.is_empty() && !mismatch;
+                if ui
+                    .add_enabled(ready, egui::Button::new("💾 Save backup..."))
+                    .clicked()
+                    && let Some(path) = rfd::FileDialog::new()
+                        .set_file_name("chat-p2p-backup.json")
+                        .save_file()
+                    && let Ok(mut manager) = chat_manager.try_lock()
+                {
+                    let mut identity = app.identity.clone();
+                    let result = if locked {
+                        identity.decrypt(&app.backup_unlock_password)
+                    } else {
+                        Ok(())
+                    }
+                    .and_then(|_| {
+                        crate::identity::backup::BackupBundle::create(
+                            &identity,
+                            manager.contacts.values().cloned().collect(),
+                            manager.trusted_peers(),
+                            &app.backup_passphrase,
+                        )
+                    })
+                    .and_then(|bundle| bundle.save(&path));
+
+                    match result {
+                        Ok(()) => {
+                            manager.add_toast(
+                                crate::types::ToastLevel::Success,
+                                format!("Backup saved to {}", path.display()),
+                            );
+                            reset_backup_fields(app);
+                            app.show_export_backup = false;
+                        }
+                        Err(e) => manager.add_toast(
+                            crate::types::ToastLevel::Error,
+                            format!("Backup failed: {}", e),
+                        ),
+                    }
+                }
+                if crate::gui::widgets::secondary_button(ui, "Cancel").clicked() {
+                    reset_backup_fields(app);
+                    app.show_export_backup = false;
+                }
+            });
+        });
+}
+
+fn render_restore_backup_dialog(app: &mut App, ctx: &egui::Context) {
+    let data_dir = data_dir(app);
+    let has_identity = crate::identity::Identity::exists(&data_dir);
+    let chat_manager = app.chat_manager.clone();
+
+    egui::Window::new("📂 Restore Identity Backup")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("📂 Choose backup file...").clicked()
+                    && let Some(path) = rfd::FileDialog::new()
+                        .add_filter("Backup", &["json"])
+                        .pick_file()
+                {
+                    app.restore_backup_path = Some(path);
+                }
+                if let Some(path) = &app.restore_backup_path {
+                    ui.monospace(path.display().to_string());
+                }
+            });
+
+            ui.label("Backup passphrase:");
+            ui.add(egui::TextEdit::singleline(&mut app.backup_passphrase).password(true));
+
+            if has_identity {
+                ui.add_space(10.0);
+                ui.label(
+                    egui::RichText::new(format!(
+                        "This replaces your current identity ({}). Peers who verified it will see a new fingerprint.",
+                        crate::util::format_fingerprint_short(&app.identity.fingerprint)
+                    ))
+                    .color(crate::gui::styling::ERROR),
+                );
+                ui.checkbox(&mut app.restore_replace_confirmed, "Replace my current identity");
+            }
+            ui.add_space(10.0);
+
+            ui.horizontal(|ui| {
+                let ready = app.restore_backup_path.is_some()
+                    && !app.backup_passphrase.is_empty()
+                    && (!has_identity || app.restore_replace_confirmed);
+                if ui.add_enabled(ready, egui::Button::new("Restore")).clicked()
+                    && let Some(path) = app.restore_backup_path.clone()
+                    && let Ok(mut manager) = chat_manager.try_lock()
+                {
+                    let result = crate::identity::backup::BackupBundle::load(&path)
+                        .and_then(|bundle| bundle.open(&app.backup_passphrase))
+                        .and_then(|contents| {
+                            contents
+                                .identity
+                                .restore_to(&data_dir, app.restore_replace_confirmed)?;
+                            Ok(contents)
+                        });
+
+                    match result {
+                        Ok(contents) => {
+                            let added =
+                                manager.import_contacts(contents.contacts, contents.trusted_peers);
+                            let _ = manager.save_history(&app.history_path);
+                            app.identity = contents.identity;
+                            manager.add_toast(
+                                crate::types::ToastLevel::Success,
+                                format!(
+                                    "Identity {} restored, {} contact(s) added",
+                                    app.identity.name, added
+                                ),
+                            );
+                            reset_backup_fields(app);
+                            app.show_restore_backup = false;
+                            // The restored identity is unlocked and saved
+                            app.show_identity_setup = false;
+                            app.show_unlock = false;
+                            reset_password_fields(app);
+                        }
+                        Err(e) => manager.add_toast(
+                            crate::types::ToastLevel::Error,
+                            format!("Restore failed: {}", e),
+                        ),
+                    }
+                }
+                if crate::gui::widgets::secondary_button(ui, "Cancel").clicked() {
+                    reset_backup_fields(app);
+                    app.show_restore_backup = false;
+                }
+            });
+        });
+}
+
+/// User data directory holding `identity.json` and the history
+fn data_dir(app: &App) -> std::path::PathBuf {
+    app.history_path
+        .parent()
+        .map(|p| p.to_path_buf())
+        .unwrap_or_default()
+}
+
+fn reset_password_fields(app: &mut App) {
+    app.password_current.clear();
+    app.password_new.clear();
+    app.password_confirm.clear();
+    app.password_error = None;
+}
+
+/// New password and its confirmation; returns whether they can be used
+fn new_password_fields(app: &mut App, ui: &mut egui::Ui) -> bool {
+    ui.label("Password:");
+    ui.add(egui::TextEdit::singleline(&mut app.password_new).password(true));
+    ui.label("Repeat password:");
+    ui.add(egui::TextEdit::singleline(&mut app.password_confirm).password(true));
+
+    let mismatch = app.password_new != app.password_confirm;
+    if mismatch && !app.password_confirm.is_empty() {
+        ui.colored_label(crate::gui::styling::ERROR, "Passwords do not match");
+    }
+    !app.password_new.is_empty() && !mismatch
+}
+
+fn render_identity_setup(app: &mut App, ctx: &egui::Context) {
+    egui::Window::new("👋 Set Up Your Identity")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(ctx, |ui| {
+            ui.set_min_width(380.0);
+            ui.label(format!("Step {} of 2", app.setup_step + 1));
+            ui.separator();
+
+            match app.setup_step {
+                0 => {
+                    ui.label("Choose the name your peers will see:");
+                    let response = ui.text_edit_singleline(&mut app.setup_name);
+                    ui.add_space(5.0);
+                    ui.label(
+                        egui::RichText::new(format!(
+                            "Your fingerprint: {}",
+                            crate::util::format_fingerprint_short(&app.identity.fingerprint)
+                        ))
+                        .color(crate::gui::styling::SUBTLE_TEXT_COLOR),
+                    );
+                    ui.add_space(10.0);
+
+                    let ready = !app.setup_name.trim().is_empty();
+                    let enter = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    ui.horizontal(|ui| {
+                        if ui.add_enabled(ready, egui::Button::new("Next ➡")).clicked()
+                            || (ready && enter)
+                        {
+                            app.setup_step = 1;
+                        }
+                        if crate::gui::widgets::secondary_button(ui, "📂 Restore a backup instead")
+                            .clicked()
+                        {
+                            app.show_restore_backup = true;
+                        }
+                    });
+                }
+                _ => {
+                    ui.label("Protect your private key with a password.");
+                    ui.label("You will enter it each time the app starts.");
+                    ui.add_space(10.0);
+                    let ready = new_password_fields(app, ui);
+                    ui.add_space(10.0);
+
+                    let mut finish = None;
+                    ui.horizontal(|ui| {
+                        if ui.button("⬅ Back").clicked() {
+                            app.setup_step = 0;
+                        }
+                        if ui
+                            .add_enabled(ready, egui::Button::new("✅ Create identity"))
+                            .clicked()
+                        {
+                            finish = Some(true);
+                        }
+                        if crate::gui::widgets::secondary_button(ui, "Skip, no password").clicked() {
+                            finish = Some(false);
+                        }
+                    });
+                    if app.password_new.is_empty() {
+                        ui.label(
+                            egui::RichText::new(
+                                "Without a password anyone with access to this computer can use your identity.",
+                            )
+                            .color(crate::gui::styling::SUBTLE_TEXT_COLOR),
+                        );
+                    }
+                    if let Some(with_password) = finish {
+                        finish_identity_setup(app, with_password);
+                    }
+                    if let Some(error) = &app.password_error {
+                        ui.colored_label(crate::gui::styling::ERROR, error);
+                    }
+                }
+            }
+        });
+}
+
+fn finish_identity_setup(app: &mut App, with_password: bool) {
+    let mut identity = app.identity.clone();
+    identity.name = app.setup_name.trim().to_string();
+    let result = if with_password {
+        identity.change_password(None, &app.password_new)
+    } else {
+        Ok(())
+    }
+    .and_then(|_| identity.save(&data_dir(app).join("identity.json")));
+
+    match result {
+        Ok(()) => {
+            tracing::info!("Created identity {}", identity.name);
+            app.identity = identity;
+            app.show_identity_setup = false;
+            app.setup_step = 0;
+            app.setup_name.clear();
+            reset_password_fields(app);
+        }
+        Err(e) => app.password_error = Some(format!("Could not save identity: {}", e)),
+    }
+}
+
+fn render_unlock_screen(app: &mut App, ctx: &egui::Context) {
+    egui::Window::new("🔒 Unlock Identity")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(ctx, |ui| {
+            ui.set_min_width(340.0);
+            ui.label(format!("Welcome back, {}.", app.identity.name));
+            ui.label("Enter your password to unlock your private key.");
+            ui.add_space(10.0);
+
+            let response =
+                ui.add(egui::TextEdit::singleline(&mut app.password_current).password(true));
+            response.request_focus();
+            if let Some(error) = &app.password_error {
+                ui.colored_label(crate::gui::styling::ERROR, error);
+            }
+            ui.add_space(10.0);
+
+            let enter = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            ui.horizontal(|ui| {
+                let ready = !app.password_current.is_empty();
+                if ui.add_enabled(ready, egui::Button::new("🔓 Unlock")).clicked()
+                    || (ready && enter)
+                {
+                    match app.identity.decrypt(&app.password_current) {
+                        Ok(()) => {
+                            app.show_unlock = false;
+                            reset_password_fields(app);
+                        }
+                        Err(_) => {
+                            app.password_current.clear();
+                            app.password_error = Some("Wrong password".to_string());
+                        }
+                    }
+                }
+                if crate::gui::widgets::secondary_button(ui, "📂 Restore a backup").clicked() {
+                    app.show_restore_backup = true;
+                }
+            });
+        });
+}
+
+fn render_change_password_dialog(app: &mut App, ctx: &egui::Context) {
+    let chat_manager = app.chat_manager.clone();
+    let encrypted = app.identity.is_encrypted();
+    egui::Window::new("🔑 Identity Password")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(ctx, |ui| {
+            if encrypted {
+                ui.label("Current password:");
+                ui.add(egui::TextEdit::singleline(&mut app.password_current).password(true));
+                ui.add_space(5.0);
+            }
+            ui.label("Choose a new password:");
+            let ready = new_password_fields(app, ui)
+                && (!encrypted || !app.password_current.is_empty());
+            if let Some(error) = &app.password_error {
+                ui.colored_label(crate::gui::styling::ERROR, error);
+            }
+            ui.add_space(10.0);
+
+            ui.horizontal(|ui| {
+                if ui.add_enabled(ready, egui::Button::new("Save")).clicked() {
+                    let mut identity = app.identity.clone();
+                    let current = encrypted.then_some(app.password_current.as_str());
+                    let result = identity
+                        .change_password(current, &app.password_new)
+                        .and_then(|_| identity.save(&data_dir(app).join("identity.json")));
+                    match result {
+                        Ok(()) => {
+                            app.identity = identity;
+                            if let Ok(mut manager) = chat_manager.try_lock() {
+                                manager.add_toast(
+                                    crate::types::ToastLevel::Success,
+                                    "Password updated".to_string(),
+                                );
+                            }
+                            reset_password_fields(app);
+                            app.show_change_password = false;
+                        }
+                        Err(e) => app.password_error = Some(e.to_string()),
+                    }
+                }
+                if crate::gui::widgets::secondary_button(ui, "Cancel").clicked() {
+                    reset_password_fields(app);
+                    app.show_change_password = false;
+                }
+            });
+        });
+}
+
+fn render_rotate_key_dialog(app: &mut App, ctx: &egui::Context) {
+    let chat_manager = app.chat_manager.clone();
+    let encrypted = app.identity.is_encrypted();
+    egui::Window::new("♻ Rotate Identity Key")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(ctx, |ui| {
+            ui.label("Generate a new key pair if you think your private key was exposed.");
+            ui.label("Contacts receive a statement signed by your current key and update");
+            ui.label("your fingerprint automatically the next time you are connected.");
+            ui.add_space(10.0);
+
+            if encrypted {
+                ui.label("Identity password:");
+                ui.add(egui::TextEdit::singleline(&mut app.password_current).password(true));
+            }
+            if let Some(error) = &app.password_error {
+                ui.colored_label(crate::gui::styling::ERROR, error);
+            }
+            ui.add_space(10.0);
+
+            ui.horizontal(|ui| {
+                let ready = !encrypted || !app.password_current.is_empty();
+                if ui.add_enabled(ready, egui::Button::new("♻ Rotate")).clicked() {
+                    let mut identity = app.identity.clone();
+                    let password = encrypted.then_some(app.password_current.as_str());
+                    let result = identity.rotate(password).and_then(|transition| {
+                        identity.save(&data_dir(app).join("identity.json"))?;
+                        Ok(transition)
+                    });
+                    match result {
+                        Ok(transition) => {
+                            app.identity = identity;
+                            app.my_invite_link = None;
+                            if let Ok(mut manager) = chat_manager.try_lock() {
+                                let notified = manager.announce_key_transition(transition);
+                                manager.add_toast(
+                                    crate::types::ToastLevel::Success,
+                                    format!("Key rotated; {} connected peer(s) notified", notified),
+                                );
+                            }
+                            reset_password_fields(app);
+                            app.show_rotate_key = false;
+                        }
+                        Err(e) => app.password_error = Some(e.to_string()),
+                    }
+                }
+                if crate::gui::widgets::secondary_button(ui, "Cancel").clicked() {
+                    reset_password_fields(app);
+                    app.show_rotate_key = false;
+                }
+            });
+        });
+}
+
 fn render_about_dialog(app: &mut App, ctx: &egui::Context) {
     egui::Window::new("ℹ️ About")
         .collapsible(false)
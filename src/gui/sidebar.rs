@@ -1,3 +1,4 @@
+use crate::app::SenderFilter;
 use crate::gui::app_ui::App;
 use eframe::egui;
 
@@ -22,10 +23,17 @@ pub fn render_sidebar(app: &mut App, ui: &mut egui::Ui) {
     });
     ui.separator();
 
+    render_search_bar(app, ui);
+
+    if !app.search_query.trim().is_empty() {
+        render_search_results(app, ui);
+        return;
+    }
+
     egui::ScrollArea::vertical().show(ui, |ui| {
         if let Ok(manager) = app.chat_manager.try_lock() {
             let mut chats: Vec<_> = manager.chats.values().collect();
-            chats.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+            chats.sort_by_key(|c| std::cmp::Reverse(c.created_at));
 
             if chats.is_empty() {
                 ui.vertical_centered(|ui| {
@@ -67,5 +75,212 @@ pub fn render_sidebar(app: &mut App, ui: &mut egui::Ui) {
                 });
             }
         }
+
+        render_nearby_peers(app, ui);
+    });
+}
+
+/// Peers found by LAN discovery; clicking one connects
+fn render_nearby_peers(app: &mut App, ui: &mut egui::Ui) {
+    // (label, fingerprint, address, known contact)
+    let peers: Vec<(String, String, String, bool)> = match app.chat_manager.try_lock() {
+        Ok(manager) if manager.is_discovering() => manager
+            .nearby_peers()
+            .into_iter()
+            .map(|(peer, contact)| {
+                let label = contact.map_or_else(|| peer.name.clone(), |c| c.name.clone());
+                (label, peer.fingerprint.clone(), peer.address.to_string(), contact.is_some())
+            })
+            .collect(),
+        _ => return,
+    };
+
+    ui.add_space(8.0);
+    ui.separator();
+    ui.label(egui::RichText::new("📡 Nearby").strong());
+    if peers.is_empty() {
+        ui.label(
+            egui::RichText::new("Nobody found on this network yet")
+                .color(crate::gui::styling::SUBTLE_TEXT_COLOR),
+        );
+        return;
+    }
+
+    for (label, fingerprint, address, known) in peers {
+        let text = if known {
+            format!("✔ {}", label)
+        } else {
+            format!("{} ({}…)", label, &fingerprint[..fingerprint.len().min(8)])
+        };
+        let hover = if known {
+            format!("Saved contact at {}. Click to connect", address)
+        } else {
+            format!("{}\nFingerprint {}\nClick to connect, then compare the safety number", address, fingerprint)
+        };
+        if ui.selectable_label(false, text).on_hover_text(hover).clicked() {
+            let manager = app.chat_manager.clone();
+            tokio::spawn(async move {
+                let mut mgr = manager.lock().await;
+                if let Err(e) = mgr.connect_to_discovered(&fingerprint).await {
+                    mgr.add_toast(
+                        crate::types::ToastLevel::Error,
+                        format!("Failed to connect: {}", e),
+                    );
+                }
+            });
+        }
+    }
+}
+
+fn render_search_bar(app: &mut App, ui: &mut egui::Ui) {
+    ui.horizontal(|ui| {
+        ui.label("🔍");
+        ui.add(
+            egui::TextEdit::singleline(&mut app.search_query)
+                .hint_text("Search messages...")
+                .desired_width(ui.available_width() - 60.0),
+        );
+        if ui
+            .small_button("⚙")
+            .on_hover_text("Search filters")
+            .clicked()
+        {
+            app.show_search_filters = !app.show_search_filters;
+        }
+        if !app.search_query.is_empty()
+            && ui.small_button("✖").on_hover_text("Clear search").clicked()
+        {
+            app.search_query.clear();
+            app.highlighted_message = None;
+        }
+    });
+
+    if app.show_search_filters {
+        egui::Frame::group(ui.style()).show(ui, |ui| {
+            // Chat filter
+            let chats: Vec<(uuid::Uuid, String)> = match app.chat_manager.try_lock() {
+                Ok(manager) => manager
+                    .chats
+                    .values()
+                    .map(|c| (c.id, c.title.clone()))
+                    .collect(),
+                Err(_) => Vec::new(),
+            };
+            let selected_chat_title = app
+                .search_filters
+                .chat_id
+                .and_then(|id| chats.iter().find(|(cid, _)| *cid == id))
+                .map(|(_, title)| title.clone())
+                .unwrap_or_else(|| "All chats".to_string());
+            egui::ComboBox::from_label("Chat")
+                .selected_text(selected_chat_title)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut app.search_filters.chat_id, None, "All chats");
+                    for (id, title) in &chats {
+                        ui.selectable_value(&mut app.search_filters.chat_id, Some(*id), title);
+                    }
+                });
+
+            // Sender filter
+            let sender_text = match app.search_filters.sender {
+                None => "Anyone",
+                Some(SenderFilter::Me) => "Me",
+                Some(SenderFilter::Peer) => "Peer",
+            };
+            egui::ComboBox::from_label("Sender")
+                .selected_text(sender_text)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut app.search_filters.sender, None, "Anyone");
+                    ui.selectable_value(&mut app.search_filters.sender, Some(SenderFilter::Me), "Me");
+                    ui.selectable_value(&mut app.search_filters.sender, Some(SenderFilter::Peer), "Peer");
+                });
+
+            // Date range filter
+            let ranges = ["Any time", "Last 24 hours", "Last 7 days", "Last 30 days"];
+            egui::ComboBox::from_label("Date")
+                .selected_text(ranges[app.search_date_range.min(ranges.len() - 1)])
+                .show_ui(ui, |ui| {
+                    for (i, label) in ranges.iter().enumerate() {
+                        ui.selectable_value(&mut app.search_date_range, i, *label);
+                    }
+                });
+            app.search_filters.from = match app.search_date_range {
+                1 => Some(chrono::Utc::now() - chrono::Duration::hours(24)),
+                2 => Some(chrono::Utc::now() - chrono::Duration::days(7)),
+                3 => Some(chrono::Utc::now() - chrono::Duration::days(30)),
+                _ => None,
+            };
+        });
+    }
+    ui.add_space(4.0);
+}
+
+fn render_search_results(app: &mut App, ui: &mut egui::Ui) {
+    let results = match app.chat_manager.try_lock() {
+        Ok(manager) => manager
+            .search(&app.search_query, &app.search_filters)
+            .into_iter()
+            .map(|hit| {
+                let title = manager
+                    .get_chat(hit.chat_id)
+                    .map(|c| c.title.clone())
+                    .unwrap_or_default();
+                (hit, title)
+            })
+            .collect::<Vec<_>>(),
+        Err(_) => return,
+    };
+
+    ui.label(
+        egui::RichText::new(format!("{} result(s)", results.len()))
+            .size(12.0)
+            .color(crate::gui::styling::SUBTLE_TEXT_COLOR),
+    );
+    ui.separator();
+
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        for (hit, chat_title) in results {
+            let is_selected = app.highlighted_message == Some(hit.message_id);
+            let frame = egui::Frame::none()
+                .fill(if is_selected {
+                    crate::gui::styling::ACCENT_SECONDARY
+                } else {
+                    crate::gui::styling::SECONDARY_BACKGROUND
+                })
+                .rounding(egui::Rounding::same(6.0))
+                .inner_margin(egui::Margin::same(6.0));
+
+            let response = frame
+                .show(ui, |ui| {
+                    ui.set_width(ui.available_width());
+                    ui.horizontal(|ui| {
+                        ui.label(egui::RichText::new(&chat_title).strong());
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            ui.label(
+                                egui::RichText::new(
+                                    crate::gui::widgets::format_timestamp_relative(&hit.timestamp),
+                                )
+                                .size(11.0)
+                                .color(crate::gui::styling::SUBTLE_TEXT_COLOR),
+                            );
+                        });
+                    });
+                    let sender = if hit.from_me { "You: " } else { "" };
+                    ui.label(
+                        egui::RichText::new(format!("{}{}", sender, hit.snippet))
+                            .size(12.0)
+                            .color(crate::gui::styling::TEXT_PRIMARY),
+                    );
+                })
+                .response
+                .interact(egui::Sense::click());
+
+            if response.clicked() {
+                app.selected_chat = Some(hit.chat_id);
+                app.highlighted_message = Some(hit.message_id);
+                app.scroll_to_message = Some(hit.message_id);
+            }
+            ui.add_space(4.0);
+        }
     });
 }
@@ -0,0 +1,259 @@
+//! Encrypted backup bundle: identity, contacts and trusted fingerprints in one file.
+//!
+//! The bundle is JSON with a small clear-text header (`format`, `version`,
+//! `created_at`) and an encrypted payload. The payload is sealed with
+//! ChaCha20-Poly1305 under an Argon2 key derived from the backup passphrase, with
+//! the header as associated data, so any modification of the file is detected
+//! when it is opened. Inside the payload the identity's private key is itself
+//! encrypted under the same passphrase.
+
+use anyhow::{Context, Result, anyhow, bail};
+use chacha20poly1305::{
+    ChaCha20Poly1305, Nonce,
+    aead::{Aead, AeadCore, KeyInit, Payload},
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use super::{Identity, derive_key};
+use crate::types::Contact;
+
+/// Value of the `format` field
+pub const BACKUP_FORMAT: &str = "chat-p2p-backup";
+/// Current bundle version; older readers refuse newer bundles
+pub const BACKUP_VERSION: u32 = 1;
+
+/// A peer fingerprint the user verified in a conversation
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TrustedPeer {
+    pub name: String,
+    pub fingerprint: String,
+}
+
+/// What a backup restores
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BackupContents {
+    pub identity: Identity,
+    pub contacts: Vec<Contact>,
+    #[serde(default)]
+    pub trusted_peers: Vec<TrustedPeer>,
+}
+
+/// Backup file as stored on disk
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BackupBundle {
+    pub format: String,
+    pub version: u32,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub salt: Vec<u8>,
+    pub nonce: Vec<u8>,
+    pub ciphertext: Vec<u8>,
+}
+
+impl BackupBundle {
+    /// Seal a backup. `identity` must be unlocked so its key can be re-encrypted
+    /// under `passphrase`.
+    pub fn create(
+        identity: &Identity,
+        contacts: Vec<Contact>,
+        trusted_peers: Vec<TrustedPeer>,
+        passphrase: &str,
+    ) -> Result<Self> {
+        if passphrase.is_empty() {
+            bail!("The backup passphrase must not be empty");
+        }
+        let mut identity = identity.clone();
+        identity
+            .encrypt(passphrase)
+            .context("Unlock the identity before creating a backup")?;
+        let contents = BackupContents {
+            identity,
+            contacts,
+            trusted_peers,
+        };
+        let plaintext = zeroize::Zeroizing::new(serde_json::to_vec(&contents)?);
+
+        let mut salt = [0u8; 16];
+        rand::rngs::OsRng.fill_bytes(&mut salt);
+        let key = derive_key(passphrase, &salt)?;
+        let cipher = ChaCha20Poly1305::new((&key[..]).into());
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut rand::rngs::OsRng);
+
+        let mut bundle = Self {
+            format: BACKUP_FORMAT.to_string(),
+            version: BACKUP_VERSION,
+            created_at: chrono::Utc::now(),
+            salt: salt.to_vec(),
+            nonce: nonce.to_vec(),
+            ciphertext: Vec::new(),
+        };
+        let aad = bundle.associated_data();
+        bundle.ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: &plaintext,
+                    aad: &aad,
+                },
+            )
+            .map_err(|e| anyhow!("Backup encryption failed: {}", e))?;
+        Ok(bundle)
+    }
+
+    /// Header fields bound to the ciphertext
+    fn associated_data(&self) -> Vec<u8> {
+        format!(
+            "{}|{}|{}",
+            self.format,
+            self.version,
+            self.created_at.to_rfc3339()
+        )
+        .into_bytes()
+    }
+
+    /// Check the bundle and decrypt it. The returned identity is unlocked; its
+    /// stored key stays encrypted under the backup passphrase.
+    pub fn open(&self, passphrase: &str) -> Result<BackupContents> {
+        if self.format != BACKUP_FORMAT {
+            bail!("Not a chat-p2p backup");
+        }
+        if self.version > BACKUP_VERSION {
+            bail!(
+                "Backup version {} is newer than this app supports ({})",
+                self.version,
+                BACKUP_VERSION
+            );
+        }
+
+        let key = derive_key(passphrase, &self.salt)?;
+        let cipher = ChaCha20Poly1305::new((&key[..]).into());
+        let nonce: [u8; 12] = self
+            .nonce
+            .as_slice()
+            .try_into()
+            .map_err(|_| anyhow!("Invalid nonce length"))?;
+        let aad = self.associated_data();
+        let plaintext = zeroize::Zeroizing::new(
+            cipher
+                .decrypt(
+                    &Nonce::from(nonce),
+                    Payload {
+                        msg: &self.ciphertext,
+                        aad: &aad,
+                    },
+                )
+                .map_err(|_| anyhow!("Wrong passphrase or corrupted backup"))?,
+        );
+
+        let mut contents: BackupContents = serde_json::from_slice(&plaintext)?;
+        contents.identity.decrypt(passphrase)?;
+        Ok(contents)
+    }
+
+    /// Read a bundle from a file
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&content).map_err(|_| anyhow!("Not a chat-p2p backup"))
+    }
+
+    /// Write the bundle to a file readable only by the owner
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        }
+        tracing::info!("Saved identity backup to {}", path.display());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+    use uuid::Uuid;
+
+    fn contact(name: &str) -> Contact {
+        Contact {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            address: Some("10.0.0.2:12345".to_string()),
+            fingerprint: Some("ab".repeat(32)),
+            public_key: None,
+            created_at: chrono::Utc::now(),
+            verified_at: None,
+            invite_token: None,
+        }
+    }
+
+    #[test]
+    fn test_backup_roundtrip_and_integrity() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("backup.json");
+        let identity = Identity::new("Alice".to_string()).unwrap();
+        let trusted = vec![TrustedPeer {
+            name: "Bob".to_string(),
+            fingerprint: "cd".repeat(32),
+        }];
+
+        let bundle =
+            BackupBundle::create(&identity, vec![contact("Carol")], trusted.clone(), "pass")
+                .unwrap();
+        bundle.save(&path).unwrap();
+
+        let loaded = BackupBundle::load(&path).unwrap();
+        let contents = loaded.open("pass").unwrap();
+        assert_eq!(contents.identity.fingerprint, identity.fingerprint);
+        assert_eq!(
+            contents.identity.private_key().unwrap(),
+            identity.private_key().unwrap()
+        );
+        assert!(contents.identity.is_encrypted());
+        assert_eq!(contents.contacts[0].name, "Carol");
+        assert_eq!(contents.trusted_peers, trusted);
+
+        assert!(loaded.open("wrong").is_err());
+
+        let mut tampered = loaded.clone();
+        tampered.ciphertext[0] ^= 1;
+        assert!(tampered.open("pass").is_err());
+
+        // The header is authenticated too
+        let mut redated = loaded.clone();
+        redated.created_at += chrono::Duration::seconds(1);
+        assert!(redated.open("pass").is_err());
+
+        let mut future = loaded;
+        future.version = BACKUP_VERSION + 1;
+        let error = future.open("pass").err().unwrap();
+        assert!(error.to_string().contains("newer"));
+    }
+
+    #[test]
+    fn test_restore_refuses_to_overwrite() {
+        let dir = tempdir().unwrap();
+        let current = Identity::new("Current".to_string()).unwrap();
+        current.restore_to(dir.path(), false).unwrap();
+
+        let restored = Identity::new("Restored".to_string()).unwrap();
+        assert!(restored.restore_to(dir.path(), false).is_err());
+        assert_eq!(
+            Identity::load(&dir.path().join("identity.json"))
+                .unwrap()
+                .name,
+            "Current"
+        );
+
+        restored.restore_to(dir.path(), true).unwrap();
+        assert_eq!(
+            Identity::load(&dir.path().join("identity.json"))
+                .unwrap()
+                .name,
+            "Restored"
+        );
+    }
+}
@@ -0,0 +1,352 @@
+//! `identity` subcommands: inspect, share, re-key and back up the local identity
+//! without starting the GUI.
+
+use anyhow::{Context, Result, bail};
+use clap::{Args, Subcommand};
+use std::path::{Path, PathBuf};
+
+use super::{Identity, InviteOptions};
+use super::backup::BackupBundle;
+use crate::app::ChatManager;
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum IdentityCommand {
+    /// Show the display name and fingerprint
+    Show,
+    /// Print a signed invite link for this identity
+    Invite {
+        #[command(flatten)]
+        options: InviteArgs,
+    },
+    /// Write a signed invite link as a QR code PNG
+    Qr {
+        /// Output PNG file
+        #[arg(long)]
+        out: PathBuf,
+        #[command(flatten)]
+        options: InviteArgs,
+    },
+    /// Set or change the password protecting the private key
+    Passwd,
+    /// Replace the key pair; contacts are told through a statement signed by the old key
+    Rotate,
+    /// Write an encrypted backup of the identity, contacts and trusted fingerprints
+    Export {
+        /// Backup file to create
+        file: PathBuf,
+    },
+    /// Restore the identity from an encrypted backup
+    Import {
+        /// Backup file to read
+        file: PathBuf,
+        /// Replace the existing identity
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+/// How a new invite link may be used
+#[derive(Debug, Clone, Args)]
+pub struct InviteArgs {
+    /// Address peers should connect to (HOST:PORT)
+    #[arg(long)]
+    address: Option<String>,
+    /// Hours until the link expires (default: never)
+    #[arg(long, value_name = "HOURS")]
+    valid_hours: Option<i64>,
+    /// Stop accepting the link after its first use
+    #[arg(long)]
+    single_use: bool,
+    /// Who the link is for; names the contact created when it is used
+    #[arg(long)]
+    label: Option<String>,
+}
+
+impl From<InviteArgs> for InviteOptions {
+    fn from(args: InviteArgs) -> Self {
+        Self {
+            address: args.address,
+            valid_for: args.valid_hours.map(chrono::Duration::hours),
+            single_use: args.single_use,
+            label: args.label,
+        }
+    }
+}
+
+/// Sign an invite and record it in the history in `data_dir`, so its expiry
+/// and single use are enforced
+pub fn issue_invite(identity: &Identity, data_dir: &Path, options: &InviteOptions) -> Result<String> {
+    let history_path = data_dir.join("history.json");
+    let mut manager = load_manager(&history_path)?;
+    let link = manager.issue_invite(identity, options)?;
+    manager.save_history(&history_path)?;
+    Ok(link)
+}
+
+/// Group a fingerprint in blocks of four for reading aloud
+fn format_fingerprint_blocks(fingerprint: &str) -> String {
+    fingerprint
+        .as_bytes()
+        .chunks(4)
+        .map(|chunk| String::from_utf8_lossy(chunk).into_owned())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn prompt_new_password(prompt: &str) -> Result<String> {
+    let password = rpassword::prompt_password(prompt)?;
+    if password.is_empty() {
+        bail!("The password must not be empty");
+    }
+    let confirm = rpassword::prompt_password("Repeat: ")?;
+    if password != confirm {
+        bail!("Passwords do not match");
+    }
+    Ok(password)
+}
+
+/// Ask for the password of an encrypted identity key
+fn unlock(identity: &mut Identity) -> Result<()> {
+    if identity.is_encrypted() {
+        let password = rpassword::prompt_password("Identity password: ")?;
+        identity.decrypt(&password)?;
+    }
+    Ok(())
+}
+
+/// Load the identity at `path`, asking for its password if the key is encrypted
+fn load_unlocked(path: &Path) -> Result<Identity> {
+    let mut identity = Identity::load(path)?;
+    unlock(&mut identity)?;
+    Ok(identity)
+}
+
+/// Identity the CLI, TUI and daemon sign their handshakes with: the one in
+/// `data_dir` (created on first use), unlocked on the terminal
+pub fn load_for_session(data_dir: &Path) -> Result<Identity> {
+    std::fs::create_dir_all(data_dir)?;
+    let mut identity = Identity::get_or_create(data_dir, "User")?;
+    unlock(&mut identity)?;
+    Ok(identity)
+}
+
+/// Replace the key password of the identity stored at `path`.
+/// `current` is needed when the key is already encrypted.
+pub fn change_password(path: &Path, current: Option<&str>, new_password: &str) -> Result<()> {
+    let mut identity = Identity::load(path)?;
+    identity.change_password(current, new_password)?;
+    identity.save(path)
+}
+
+fn load_manager(history_path: &Path) -> Result<ChatManager> {
+    let mut manager = ChatManager::default();
+    if history_path.exists() {
+        manager.load_history(history_path)?;
+    }
+    Ok(manager)
+}
+
+/// Write a backup of `identity` (which must be unlocked) together with the
+/// contacts and trusted fingerprints from the history in `data_dir`
+pub fn export_backup(
+    identity: &Identity,
+    data_dir: &Path,
+    out: &Path,
+    passphrase: &str,
+) -> Result<()> {
+    let manager = load_manager(&data_dir.join("history.json"))?;
+    let contacts = manager.contacts.values().cloned().collect();
+    BackupBundle::create(identity, contacts, manager.trusted_peers(), passphrase)?.save(out)
+}
+
+/// Restore a backup into `data_dir`: the identity (its key stays encrypted under
+/// the backup passphrase) and any contacts not already known.
+/// Returns the identity and the number of contacts added.
+pub fn import_backup(
+    backup: &Path,
+    passphrase: &str,
+    data_dir: &Path,
+    force: bool,
+) -> Result<(Identity, usize)> {
+    // Check the passphrase before touching the current identity
+    let contents = BackupBundle::load(backup)?.open(passphrase)?;
+    if Identity::exists(data_dir) && !force {
+        bail!(
+            "An identity already exists in {} (use --force to replace it)",
+            data_dir.display()
+        );
+    }
+    contents.identity.restore_to(data_dir, force)?;
+
+    let history_path = data_dir.join("history.json");
+    let mut manager = load_manager(&history_path)?;
+    let added = manager.import_contacts(contents.contacts, contents.trusted_peers);
+    manager.save_history(&history_path)?;
+    Ok((contents.identity, added))
+}
+
+/// Run an `identity` subcommand against the identity in `data_dir`
+pub fn run(command: IdentityCommand, data_dir: &Path) -> Result<()> {
+    let identity_path = data_dir.join("identity.json");
+
+    // Importing must not create an identity first
+    if let IdentityCommand::Import { file, force } = &command {
+        let passphrase = rpassword::prompt_password("Backup passphrase: ")?;
+        let (identity, added) = import_backup(file, &passphrase, data_dir, *force)?;
+        println!(
+            "Restored identity {} ({})",
+            identity.name, identity.fingerprint
+        );
+        println!("Added {} contact(s).", added);
+        println!(
+            "The key password is now the backup passphrase; use `identity passwd` to change it."
+        );
+        return Ok(());
+    }
+
+    // The GUI asks through its first-run wizard; here the first use creates a default identity
+    if !identity_path.exists() {
+        std::fs::create_dir_all(data_dir)?;
+        Identity::get_or_create(data_dir, "User")?;
+        println!("Created a new identity in {}", identity_path.display());
+    }
+
+    match command {
+        IdentityCommand::Show => {
+            let identity = Identity::load(&identity_path)?;
+            println!("Name:        {}", identity.name);
+            println!("ID:          {}", identity.id);
+            println!(
+                "Created:     {}",
+                identity.created_at.format("%Y-%m-%d %H:%M UTC")
+            );
+            println!(
+                "Fingerprint: {}",
+                format_fingerprint_blocks(&identity.fingerprint)
+            );
+            println!("Key type:    {}", identity.algorithm().label());
+            println!(
+                "Private key: {}",
+                if identity.is_encrypted() {
+                    "password protected"
+                } else {
+                    "not password protected"
+                }
+            );
+        }
+        IdentityCommand::Invite { options } => {
+            let identity = load_unlocked(&identity_path)?;
+            println!("{}", issue_invite(&identity, data_dir, &options.into())?);
+        }
+        IdentityCommand::Qr { out, options } => {
+            let identity = load_unlocked(&identity_path)?;
+            let link = issue_invite(&identity, data_dir, &options.into())?;
+            std::fs::write(&out, crate::util::render_qr_png(&link)?)
+                .with_context(|| format!("Failed to write {}", out.display()))?;
+            println!("Wrote invite QR code to {}", out.display());
+        }
+        IdentityCommand::Passwd => {
+            let identity = Identity::load(&identity_path)?;
+            let current = if identity.is_encrypted() {
+                Some(rpassword::prompt_password("Current password: ")?)
+            } else {
+                None
+            };
+            let new_password = prompt_new_password("New password: ")?;
+            change_password(&identity_path, current.as_deref(), &new_password)?;
+            println!("Password updated.");
+        }
+        IdentityCommand::Rotate => {
+            let mut identity = Identity::load(&identity_path)?;
+            let password = if identity.is_encrypted() {
+                Some(rpassword::prompt_password("Identity password: ")?)
+            } else {
+                None
+            };
+            let old_fingerprint = identity.fingerprint.clone();
+            identity.rotate(password.as_deref())?;
+            identity.save(&identity_path)?;
+            println!("Old fingerprint: {}", format_fingerprint_blocks(&old_fingerprint));
+            println!("New fingerprint: {}", format_fingerprint_blocks(&identity.fingerprint));
+            println!("Peers are sent the signed key change the next time you connect.");
+        }
+        IdentityCommand::Export { file } => {
+            let identity = load_unlocked(&identity_path)?;
+            let passphrase = prompt_new_password("Backup passphrase: ")?;
+            export_backup(&identity, data_dir, &file, &passphrase)?;
+            println!("Wrote encrypted backup to {}", file.display());
+        }
+        IdentityCommand::Import { .. } => unreachable!("handled above"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_change_password_and_backup_roundtrip() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("identity.json");
+        let identity = Identity::new("Alice".to_string()).unwrap();
+        let key = identity.private_key().unwrap();
+        identity.save(&path).unwrap();
+
+        // First password, then a change that needs the current one
+        change_password(&path, None, "first").unwrap();
+        assert!(change_password(&path, Some("wrong"), "second").is_err());
+        change_password(&path, Some("first"), "second").unwrap();
+        let mut stored = Identity::load(&path).unwrap();
+        assert!(stored.decrypt("first").is_err());
+        stored.decrypt("second").unwrap();
+        assert_eq!(stored.private_key().unwrap(), key);
+
+        let mut manager = ChatManager::default();
+        manager.add_contact("Bob".to_string(), None, Some("ab".repeat(32)), None);
+        manager
+            .save_history(&dir.path().join("history.json"))
+            .unwrap();
+        let backup = dir.path().join("backup.json");
+        export_backup(&stored, dir.path(), &backup, "backup-pass").unwrap();
+
+        // Refuses to overwrite without force, and checks the passphrase
+        assert!(import_backup(&backup, "backup-pass", dir.path(), false).is_err());
+        assert!(import_backup(&backup, "nope", dir.path(), true).is_err());
+
+        let restore_dir = tempdir().unwrap();
+        let (restored, added) =
+            import_backup(&backup, "backup-pass", restore_dir.path(), false).unwrap();
+        assert_eq!(restored.fingerprint, identity.fingerprint);
+        assert_eq!(added, 1);
+        let mut reloaded = Identity::load(&restore_dir.path().join("identity.json")).unwrap();
+        reloaded.decrypt("backup-pass").unwrap();
+        assert_eq!(reloaded.private_key().unwrap(), key);
+    }
+
+    #[test]
+    fn test_issued_invites_are_saved() {
+        let dir = tempdir().unwrap();
+        let identity = Identity::new("Alice".to_string()).unwrap();
+        let options = InviteArgs {
+            address: None,
+            valid_hours: Some(24),
+            single_use: true,
+            label: Some("Bob".to_string()),
+        };
+        let link = issue_invite(&identity, dir.path(), &options.into()).unwrap();
+
+        let mut manager = load_manager(&dir.path().join("history.json")).unwrap();
+        assert_eq!(manager.parse_invite_link(&link).unwrap().name, "Alice");
+        let issued = manager.issued_invites[0].clone();
+        assert!(issued.single_use && issued.expires_at.is_some());
+        assert_eq!(issued.label.as_deref(), Some("Bob"));
+        manager.redeem_invite(&issued.token, chrono::Utc::now()).unwrap();
+    }
+
+    #[test]
+    fn test_format_fingerprint_blocks() {
+        assert_eq!(format_fingerprint_blocks("abcd1234ef"), "abcd 1234 ef");
+    }
+}
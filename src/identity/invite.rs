@@ -0,0 +1,282 @@
+//! Signed invite links.
+//!
+//! An invite is `chat-p2p://invite/<base64 JSON>` naming the issuer, an optional
+//! address and the issuer's public key, signed by that key. The recipient checks
+//! the signature and that the fingerprint belongs to the key, so the name or
+//! address can't be swapped. Every invite carries a random token; the issuer
+//! keeps an [`IssuedInvite`] for it, with the expiry and whether it may be used
+//! only once.
+//!
+//! The token doubles as a secret: a client connecting with an invite sends
+//! [`invite_proof`], an HMAC of the session binding keyed with the token, so the
+//! host knows which invite was used without the token crossing the wire.
+
+use anyhow::{Result, anyhow, bail};
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use super::Identity;
+use crate::core::PublicKey;
+
+/// Scheme and path of every invite link
+pub const INVITE_PREFIX: &str = "chat-p2p://invite/";
+
+/// Domain separation tag for the signed payload
+const INVITE_TAG: &str = "chat-p2p-invite|v1";
+
+/// Length of the random invite token, in bytes
+const TOKEN_LEN: usize = 16;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// How an invite may be used
+#[derive(Debug, Clone, Default)]
+pub struct InviteOptions {
+    /// Address peers should connect to (HOST:PORT)
+    pub address: Option<String>,
+    /// Lifetime of the link; `None` never expires
+    pub valid_for: Option<Duration>,
+    /// Stop accepting the link after its first use
+    pub single_use: bool,
+    /// Who the link is for; names the contact created when it is used
+    pub label: Option<String>,
+}
+
+/// Contents of an invite link
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Invite {
+    pub name: String,
+    pub address: Option<String>,
+    pub fingerprint: String,
+    pub public_key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Random token the issuer recognizes the invite by
+    #[serde(default)]
+    pub token: String,
+    /// Base64 signature by `public_key` over [`Invite::signed_bytes`]
+    #[serde(default)]
+    pub signature: String,
+}
+
+/// An invite we handed out, as remembered by its issuer
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IssuedInvite {
+    pub token: String,
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub single_use: bool,
+    /// First time a peer used the invite
+    #[serde(default)]
+    pub redeemed_at: Option<DateTime<Utc>>,
+    /// Who the link is for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+impl IssuedInvite {
+    /// Whether the invite may still be used at `now`
+    pub fn check(&self, now: DateTime<Utc>) -> Result<()> {
+        if self.expires_at.is_some_and(|expires_at| now >= expires_at) {
+            bail!("Invite expired");
+        }
+        if self.single_use && self.redeemed_at.is_some() {
+            bail!("Invite was already used");
+        }
+        Ok(())
+    }
+
+    /// Whether `proof` was made with this invite's token for `binding`.
+    /// The comparison is constant-time.
+    pub fn matches_proof(&self, binding: &[u8], proof: &[u8]) -> bool {
+        invite_mac(&self.token, binding).verify_slice(proof).is_ok()
+    }
+}
+
+fn invite_mac(token: &str, binding: &[u8]) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(token.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(binding);
+    mac
+}
+
+/// Prove knowledge of an invite's token for one session. `binding` is
+/// derived from the session key, so the proof can't be replayed on another
+/// connection or relayed by someone in the middle.
+pub fn invite_proof(token: &str, binding: &[u8]) -> Vec<u8> {
+    invite_mac(token, binding).finalize().into_bytes().to_vec()
+}
+
+impl Invite {
+    /// Bytes covered by the signature. JSON keeps the fields apart even when
+    /// the name contains separators.
+    fn signed_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(&(
+            INVITE_TAG,
+            &self.name,
+            &self.address,
+            &self.fingerprint,
+            &self.public_key,
+            self.expires_at.map(|t| t.to_rfc3339()),
+            &self.token,
+        ))
+        .expect("serializing strings cannot fail")
+    }
+
+    /// Encode as a `chat-p2p://invite/...` link
+    pub fn to_link(&self) -> Result<String> {
+        let json = serde_json::to_string(self)?;
+        let encoded = base64::engine::general_purpose::STANDARD.encode(json);
+        Ok(format!("{}{}", INVITE_PREFIX, encoded))
+    }
+
+    /// Decode a link (with or without the prefix) without checking it
+    pub fn from_link(link: &str) -> Result<Self> {
+        let encoded = link.trim();
+        let encoded = encoded.strip_prefix(INVITE_PREFIX).unwrap_or(encoded);
+        let json = base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(|e| anyhow!("Invalid invite link: {}", e))?;
+        let json_str = String::from_utf8(json)
+            .map_err(|e| anyhow!("Invalid UTF-8 in invite link: {}", e))?;
+        serde_json::from_str(&json_str).map_err(|e| anyhow!("Invalid invite data: {}", e))
+    }
+
+    /// Check the signature, that the fingerprint is the key's, and the expiry
+    pub fn verify(&self, now: DateTime<Utc>) -> Result<()> {
+        if self.signature.is_empty() {
+            bail!("Invite link is not signed; ask for a new one");
+        }
+        let key = PublicKey::from_pem(&self.public_key)
+            .map_err(|e| anyhow!("Invalid public key in invite: {}", e))?;
+        if Identity::calculate_fingerprint(&self.public_key) != self.fingerprint {
+            bail!("Invite fingerprint does not match its public key");
+        }
+        let signature = base64::engine::general_purpose::STANDARD
+            .decode(&self.signature)
+            .map_err(|e| anyhow!("Invalid invite signature: {}", e))?;
+        key.verify(&self.signed_bytes(), &signature)
+            .map_err(|e| anyhow!("Invalid invite signature: {}", e))?;
+
+        if self.expires_at.is_some_and(|expires_at| now >= expires_at) {
+            bail!("Invite expired");
+        }
+        Ok(())
+    }
+}
+
+impl Identity {
+    /// Create a signed invite link; the identity must be unlocked. The
+    /// returned [`IssuedInvite`] is what the issuer keeps.
+    pub fn generate_invite_link(&self, options: &InviteOptions) -> Result<(String, IssuedInvite)> {
+        let mut token = [0u8; TOKEN_LEN];
+        rand::thread_rng().fill_bytes(&mut token);
+        let issued_at = Utc::now();
+
+        let mut invite = Invite {
+            name: self.name.clone(),
+            address: options.address.clone(),
+            fingerprint: self.fingerprint.clone(),
+            public_key: self.public_key_pem.clone(),
+            expires_at: options.valid_for.map(|valid_for| issued_at + valid_for),
+            token: hex::encode(token),
+            signature: String::new(),
+        };
+        let signature = self.private_key()?.sign(&invite.signed_bytes());
+        invite.signature = base64::engine::general_purpose::STANDARD.encode(signature);
+
+        let issued = IssuedInvite {
+            token: invite.token.clone(),
+            issued_at,
+            expires_at: invite.expires_at,
+            single_use: options.single_use,
+            redeemed_at: None,
+            label: options.label.clone(),
+        };
+        Ok((invite.to_link()?, issued))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signed(options: &InviteOptions) -> (Invite, IssuedInvite) {
+        let identity = Identity::new("Alice".to_string()).unwrap();
+        let (link, issued) = identity.generate_invite_link(options).unwrap();
+        assert!(link.starts_with(INVITE_PREFIX));
+        (Invite::from_link(&link).unwrap(), issued)
+    }
+
+    #[test]
+    fn test_signed_invite_rejects_edits() {
+        let (invite, issued) = signed(&InviteOptions {
+            address: Some("10.0.0.1:5000".to_string()),
+            ..Default::default()
+        });
+        invite.verify(Utc::now()).unwrap();
+        assert_eq!(invite.token, issued.token);
+        assert_eq!(invite.token.len(), TOKEN_LEN * 2);
+
+        let mut renamed = invite.clone();
+        renamed.name = "Alice (work)".to_string();
+        assert!(renamed.verify(Utc::now()).is_err());
+
+        let mut redirected = invite.clone();
+        redirected.address = Some("6.6.6.6:5000".to_string());
+        assert!(redirected.verify(Utc::now()).is_err());
+
+        // Another key signing the same name and address is caught by the fingerprint
+        let (mallory, _) = signed(&InviteOptions::default());
+        let mut swapped = mallory.clone();
+        swapped.fingerprint = invite.fingerprint.clone();
+        assert!(swapped.verify(Utc::now()).is_err());
+
+        let mut unsigned = invite;
+        unsigned.signature.clear();
+        assert!(unsigned.verify(Utc::now()).unwrap_err().to_string().contains("not signed"));
+    }
+
+    #[test]
+    fn test_expiry_and_single_use() {
+        let (invite, mut issued) = signed(&InviteOptions {
+            valid_for: Some(Duration::hours(1)),
+            single_use: true,
+            ..Default::default()
+        });
+        let now = Utc::now();
+        invite.verify(now).unwrap();
+        assert!(invite.verify(now + Duration::hours(2)).is_err());
+
+        // Moving the expiry breaks the signature
+        let mut extended = invite;
+        extended.expires_at = None;
+        assert!(extended.verify(now).is_err());
+
+        issued.check(now).unwrap();
+        assert!(issued.check(now + Duration::hours(2)).is_err());
+        issued.redeemed_at = Some(now);
+        assert!(issued.check(now).unwrap_err().to_string().contains("already used"));
+    }
+
+    #[test]
+    fn test_invite_proof_is_bound_to_token_and_session() {
+        let (invite, issued) = signed(&InviteOptions {
+            label: Some("Bob".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(issued.label.as_deref(), Some("Bob"));
+
+        let proof = invite_proof(&invite.token, &[1; 32]);
+        assert!(issued.matches_proof(&[1; 32], &proof));
+        assert!(!issued.matches_proof(&[2; 32], &proof), "another session");
+
+        let (other, _) = signed(&InviteOptions::default());
+        assert!(!issued.matches_proof(&[1; 32], &invite_proof(&other.token, &[1; 32])));
+        assert!(!issued.matches_proof(&[1; 32], &[]));
+    }
+}
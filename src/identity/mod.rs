@@ -2,41 +2,56 @@
 ///
 /// This module handles user identity, including:
 /// - Name and profile information
-/// - RSA key pair generation and storage
+/// - Key pair generation and storage (Ed25519; RSA identities from earlier
+///   versions keep working)
 /// - Fingerprint calculation
-/// - Invite link generation
+/// - Signed invite link generation
+/// - Key rotation with signed transition statements
 ///
 /// Identity is stored in a JSON file in the user's data directory.
 /// Keys are now encrypted with a password.
+pub mod backup;
+pub mod commands;
+pub mod invite;
+pub mod rotation;
+
+pub use invite::{invite_proof, Invite, InviteOptions, IssuedInvite};
+pub use rotation::KeyTransition;
+
 use anyhow::{anyhow, Result};
 use argon2::Argon2;
 use chacha20poly1305::{
     aead::{Aead, AeadCore, KeyInit},
     ChaCha20Poly1305, Nonce,
 };
-use rsa::{
-    pkcs8::{DecodePrivateKey, DecodePublicKey, EncodePrivateKey, EncodePublicKey, LineEnding},
-    RsaPrivateKey, RsaPublicKey,
-};
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
 use std::path::Path;
 use uuid::Uuid;
-use zeroize::Zeroizing;
-use base64::Engine;
+use zeroize::{Zeroize, Zeroizing};
 use rand::RngCore;
 
+use crate::core::{KeyAlgorithm, PrivateKey, PublicKey, fingerprint_public_key_pem};
+
 // Constants for encryption
 const KEY_SIZE: usize = 32; // 256-bit key
 
-/// User identity with RSA key pair
+/// Derive a ChaCha20-Poly1305 key from a password with Argon2
+pub(crate) fn derive_key(password: &str, salt: &[u8]) -> Result<Zeroizing<[u8; KEY_SIZE]>> {
+    let mut key_bytes = Zeroizing::new([0u8; KEY_SIZE]);
+    Argon2::default()
+        .hash_password_into(password.as_bytes(), salt, &mut key_bytes[..])
+        .map_err(|e| anyhow!("Failed to derive key with Argon2: {}", e))?;
+    Ok(key_bytes)
+}
+
+/// User identity with its signature key pair
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Identity {
     pub id: Uuid,
     pub name: String,
     pub created_at: chrono::DateTime<chrono::Utc>,
 
-    /// Encrypted RSA private key (ChaCha20-Poly1305)
+    /// Encrypted private key (ChaCha20-Poly1305)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub encrypted_private_key: Option<Vec<u8>>,
 
@@ -48,33 +63,33 @@ pub struct Identity {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub nonce: Option<Vec<u8>>,
 
-    /// RSA public key in PEM format (PKCS#8)
+    /// Public key in SPKI PEM format (Ed25519, or RSA for older identities)
     pub public_key_pem: String,
 
-    /// SHA-256 fingerprint of public key (hex format)
+    /// Fingerprint of the public key (hex), see `fingerprint_public_key_pem`
     pub fingerprint: String,
 
-    /// Plaintext private key, used temporarily after decryption.
-    /// This field is NOT serialized.
+    /// Signed statements for each past key rotation, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub key_transitions: Vec<KeyTransition>,
+
+    /// Plaintext private key, used temporarily after decryption and wiped
+    /// when dropped. This field is NOT serialized.
     #[serde(skip)]
-    private_key_pem_plaintext: Option<String>,
+    private_key_pem_plaintext: Option<Zeroizing<String>>,
 }
 
 impl Identity {
-    /// Create new identity with generated RSA key pair
+    /// Create new identity with a generated Ed25519 key pair
     pub fn new(name: String) -> Result<Self> {
-        use rand::rngs::OsRng;
-
-        tracing::info!("Generating new identity for: {}", name);
+        Self::generate(name, KeyAlgorithm::default())
+    }
 
-        // Generate 2048-bit RSA key pair
-        let mut rng = OsRng;
-        let private_key = RsaPrivateKey::new(&mut rng, 2048)?;
-        let public_key = RsaPublicKey::from(&private_key);
+    /// Create new identity with a key pair of the given algorithm
+    pub fn generate(name: String, algorithm: KeyAlgorithm) -> Result<Self> {
+        tracing::info!("Generating new {} identity for: {}", algorithm.label(), name);
 
-        // Encode to PEM
-        let private_key_pem = private_key.to_pkcs8_pem(LineEnding::LF)?.to_string();
-        let public_key_pem = public_key.to_public_key_pem(LineEnding::LF)?;
+        let (private_key_pem, public_key_pem) = Self::generate_key_pair(algorithm)?;
 
         // Calculate fingerprint
         let fingerprint = Self::calculate_fingerprint(&public_key_pem);
@@ -88,16 +103,27 @@ impl Identity {
             nonce: None,
             public_key_pem,
             fingerprint,
+            key_transitions: Vec::new(),
             private_key_pem_plaintext: Some(private_key_pem),
         })
     }
 
-    /// Calculate SHA-256 fingerprint of public key
-    fn calculate_fingerprint(public_key_pem: &str) -> String {
-        let mut hasher = Sha256::new();
-        hasher.update(public_key_pem.as_bytes());
-        let result = hasher.finalize();
-        hex::encode(result)
+    /// Generate a key pair, PEM encoded as (private, public)
+    fn generate_key_pair(algorithm: KeyAlgorithm) -> Result<(Zeroizing<String>, String)> {
+        let private_key = PrivateKey::generate(algorithm)?;
+        Ok((private_key.to_pem()?, private_key.public_key().to_pem()?))
+    }
+
+    /// Calculate the fingerprint of a public key
+    pub(crate) fn calculate_fingerprint(public_key_pem: &str) -> String {
+        fingerprint_public_key_pem(public_key_pem)
+    }
+
+    /// Algorithm of the identity key
+    pub fn algorithm(&self) -> KeyAlgorithm {
+        PublicKey::from_pem(&self.public_key_pem)
+            .map(|key| key.algorithm())
+            .unwrap_or(KeyAlgorithm::Rsa)
     }
 
     /// Encrypt the private key with a password.
@@ -110,11 +136,7 @@ impl Identity {
         // Derive key with Argon2 using random salt bytes
         let mut salt = [0u8; 16];
         rand::rngs::OsRng.fill_bytes(&mut salt);
-        let argon2 = Argon2::default();
-        let mut key_bytes = Zeroizing::new([0u8; KEY_SIZE]);
-        argon2
-            .hash_password_into(password.as_bytes(), &salt, &mut key_bytes[..])
-            .map_err(|e| anyhow!("Failed to derive key with Argon2: {}", e))?;
+        let key_bytes = derive_key(password, &salt)?;
 
         let cipher = ChaCha20Poly1305::new((&key_bytes[..]).into());
         let nonce = ChaCha20Poly1305::generate_nonce(&mut rand::rngs::OsRng);
@@ -147,68 +169,110 @@ impl Identity {
             .as_ref()
             .ok_or_else(|| anyhow!("Encrypted private key not found"))?;
 
-        let argon2 = Argon2::default();
-        let mut key_bytes = Zeroizing::new([0u8; KEY_SIZE]);
-        argon2
-            .hash_password_into(password.as_bytes(), salt_bytes, &mut key_bytes[..])
-            .map_err(|e| anyhow!("Failed to derive key with Argon2: {}", e))?;
+        let key_bytes = derive_key(password, salt_bytes)?;
 
         let cipher = ChaCha20Poly1305::new((&key_bytes[..]).into());
-        let nonce = Nonce::from_slice(nonce_bytes);
-        let plaintext = cipher
-            .decrypt(nonce, ciphertext.as_ref())
-            .map_err(|e| anyhow!("Decryption failed (likely wrong password): {}", e))?;
+        let nonce_arr: [u8; 12] = nonce_bytes
+            .as_slice()
+            .try_into()
+            .map_err(|_| anyhow!("Invalid nonce length"))?;
+        let nonce = Nonce::from(nonce_arr);
+        let plaintext = Zeroizing::new(
+            cipher
+                .decrypt(&nonce, ciphertext.as_ref())
+                .map_err(|e| anyhow!("Decryption failed (likely wrong password): {}", e))?,
+        );
 
-        self.private_key_pem_plaintext = Some(String::from_utf8(plaintext)?);
+        self.private_key_pem_plaintext = Some(Zeroizing::new(std::str::from_utf8(&plaintext)?.to_owned()));
 
         Ok(())
     }
 
+    /// Protect the private key with `new_password`, re-encrypting it under a fresh
+    /// salt and nonce. `current` must be the existing password when the key is
+    /// already encrypted; it is checked even if the identity is unlocked.
+    /// The identity stays unlocked afterwards.
+    pub fn change_password(&mut self, current: Option<&str>, new_password: &str) -> Result<()> {
+        if new_password.is_empty() {
+            return Err(anyhow!("The new password must not be empty"));
+        }
+        if self.is_encrypted() {
+            let current = current.ok_or_else(|| anyhow!("The current password is required"))?;
+            self.decrypt(current)?;
+        }
+        let plaintext = self.private_key_pem_plaintext.clone();
+        self.encrypt(new_password)?;
+        self.private_key_pem_plaintext = plaintext;
+        Ok(())
+    }
+
+    /// Replace the key pair with a new Ed25519 one, e.g. when the old key may be
+    /// compromised or to migrate an RSA identity. Returns the
+    /// transition statement signed by the old key, which is also kept in
+    /// `key_transitions` so peers can be told later. An encrypted key stays
+    /// encrypted under the same password, which must be given.
+    pub fn rotate(&mut self, password: Option<&str>) -> Result<KeyTransition> {
+        if self.is_encrypted() {
+            let password = password.ok_or_else(|| anyhow!("The identity password is required"))?;
+            self.decrypt(password)?;
+        }
+        let old_key = self.private_key()?;
+        let (private_key_pem, public_key_pem) = Self::generate_key_pair(KeyAlgorithm::Ed25519)?;
+        let transition = KeyTransition::sign(&old_key, &self.public_key_pem, &public_key_pem)?;
+
+        self.private_key_pem_plaintext = Some(private_key_pem);
+        if let Some(password) = password.filter(|_| self.is_encrypted()) {
+            let plaintext = self.private_key_pem_plaintext.clone();
+            self.encrypt(password)?;
+            self.private_key_pem_plaintext = plaintext;
+        }
+        self.fingerprint = Self::calculate_fingerprint(&public_key_pem);
+        self.public_key_pem = public_key_pem;
+        self.key_transitions.push(transition.clone());
+
+        tracing::info!("Rotated identity key, new fingerprint {}", self.fingerprint);
+        Ok(transition)
+    }
+
+    /// Whether the private key is stored encrypted with a password
+    pub fn is_encrypted(&self) -> bool {
+        self.encrypted_private_key.is_some()
+    }
+
+    /// Whether the plaintext private key is available (unencrypted or decrypted)
+    pub fn is_unlocked(&self) -> bool {
+        self.private_key_pem_plaintext.is_some()
+    }
+
     /// Get private key (if available)
-    pub fn private_key(&self) -> Result<RsaPrivateKey> {
+    pub fn private_key(&self) -> Result<PrivateKey> {
         let pem = self
             .private_key_pem_plaintext
             .as_ref()
             .ok_or_else(|| anyhow::anyhow!("Private key not available. Was the identity decrypted?"))?;
-        Ok(RsaPrivateKey::from_pkcs8_pem(pem)?)
+        PrivateKey::from_pem(pem)
     }
 
     /// Get public key
-    pub fn public_key(&self) -> Result<RsaPublicKey> {
-        Ok(RsaPublicKey::from_public_key_pem(&self.public_key_pem)?)
-    }
-
-    /// Generate invite link for this identity
-    pub fn generate_invite_link(&self, address: Option<String>) -> Result<String> {
-        use serde_json::json;
-
-        let payload = json!({
-            "name": self.name,
-            "address": address,
-            "fingerprint": self.fingerprint,
-            "public_key": self.public_key_pem,
-        });
-
-        let json = serde_json::to_string(&payload)?;
-        let encoded = base64::engine::general_purpose::STANDARD.encode(json);
-        Ok(format!("chat-p2p://invite/{}", encoded))
+    pub fn public_key(&self) -> Result<PublicKey> {
+        PublicKey::from_pem(&self.public_key_pem)
     }
 
     /// Load identity from file
     pub fn load(path: &Path) -> Result<Self> {
-        let content = std::fs::read_to_string(path)?;
+        let content = Zeroizing::new(std::fs::read_to_string(path)?);
         let mut identity: Identity = serde_json::from_str(&content)?;
         tracing::info!("Loaded identity: {} ({})", identity.name, identity.id);
 
         // For backward compatibility, if the old plaintext field exists, use it.
-        if let Ok(id_with_old_field) = serde_json::from_str::<serde_json::Value>(&content) {
-            if let Some(old_pem) = id_with_old_field
-                .get("private_key_pem")
-                .and_then(|v| v.as_str())
-            {
-                identity.private_key_pem_plaintext = Some(old_pem.to_string());
-                tracing::warn!("Loaded an unencrypted identity file. Please set a password to encrypt it.");
-            }
+        // The string is moved out of the parsed JSON rather than copied.
+        if let Ok(mut id_with_old_field) = serde_json::from_str::<serde_json::Value>(&content)
+            && let Some(serde_json::Value::String(old_pem)) = id_with_old_field
+                .get_mut("private_key_pem")
+                .map(serde_json::Value::take)
+        {
+            identity.private_key_pem_plaintext = Some(Zeroizing::new(old_pem));
+            tracing::warn!("Loaded an unencrypted identity file. Please set a password to encrypt it.");
         }
 
         Ok(identity)
@@ -221,12 +285,44 @@ impl Identity {
             std::fs::create_dir_all(parent)?;
         }
 
-        let content = serde_json::to_string_pretty(&self)?;
+        let mut value = serde_json::to_value(self)?;
+
+        // Identities without a password keep the key in the legacy plaintext field,
+        // otherwise it would be lost on the next load.
+        if self.encrypted_private_key.is_none()
+            && let Some(pem) = &self.private_key_pem_plaintext
+        {
+            value["private_key_pem"] = serde_json::Value::String(pem.to_string());
+        }
+
+        let content = Zeroizing::new(serde_json::to_string_pretty(&value)?);
+        if let Some(serde_json::Value::String(pem)) = value.get_mut("private_key_pem") {
+            pem.zeroize();
+        }
         std::fs::write(path, content)?;
         tracing::info!("Saved identity: {} to {}", self.name, path.display());
         Ok(())
     }
 
+    /// Whether an identity has been saved in the user data directory
+    pub fn exists(data_dir: &Path) -> bool {
+        data_dir.join("identity.json").exists()
+    }
+
+    /// Make this the identity of the user data directory (e.g. after restoring a
+    /// backup). An existing identity is only replaced when `overwrite` is set,
+    /// which callers should tie to an explicit confirmation from the user.
+    pub fn restore_to(&self, data_dir: &Path, overwrite: bool) -> Result<()> {
+        let identity_path = data_dir.join("identity.json");
+        if identity_path.exists() && !overwrite {
+            return Err(anyhow!(
+                "An identity already exists at {}; confirm to replace it",
+                identity_path.display()
+            ));
+        }
+        self.save(&identity_path)
+    }
+
     /// Get or create identity from user data directory
     pub fn get_or_create(data_dir: &Path, default_name: &str) -> Result<Self> {
         let identity_path = data_dir.join("identity.json");
@@ -265,7 +361,7 @@ mod tests {
         let identity = Identity::new("Test User".to_string()).unwrap();
 
         assert_eq!(identity.name, "Test User");
-        assert_eq!(identity.fingerprint.len(), 64); // SHA-256 in hex
+        assert_eq!(identity.fingerprint.len(), 40); // Truncated SHA-256 of the Ed25519 key, in hex
         assert!(identity.private_key_pem_plaintext.is_some());
         assert!(identity
             .public_key_pem
@@ -288,6 +384,30 @@ mod tests {
         assert!(loaded.private_key_pem_plaintext.is_some());
     }
 
+    #[test]
+    fn test_private_key_survives_save_load() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("identity.json");
+
+        // Without a password the key is kept as is
+        let mut identity = Identity::new("Test User".to_string()).unwrap();
+        identity.save(&path).unwrap();
+        let loaded = Identity::load(&path).unwrap();
+        assert_eq!(loaded.private_key_pem_plaintext, identity.private_key_pem_plaintext);
+
+        // With one, only the encrypted key reaches the disk
+        let original_pem = identity.private_key_pem_plaintext.clone().unwrap();
+        identity.encrypt("password123").unwrap();
+        identity.save(&path).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(!content.contains("\"private_key_pem\""));
+
+        let mut loaded = Identity::load(&path).unwrap();
+        assert!(loaded.private_key_pem_plaintext.is_none());
+        loaded.decrypt("password123").unwrap();
+        assert_eq!(loaded.private_key_pem_plaintext, Some(original_pem));
+    }
+
     #[test]
     fn test_encryption_decryption_roundtrip() {
         let mut identity = Identity::new("Test User".to_string()).unwrap();
@@ -300,9 +420,10 @@ mod tests {
         assert!(identity.salt.is_some());
         assert!(identity.nonce.is_some());
 
-        // Decrypt
+        // Decrypt; the plaintext PEM is wiped when dropped
         identity.decrypt("password123").unwrap();
-        assert!(identity.private_key_pem_plaintext.is_some());
+        fn assert_zeroize_on_drop<T: zeroize::ZeroizeOnDrop>(_: &T) {}
+        assert_zeroize_on_drop(identity.private_key_pem_plaintext.as_ref().unwrap());
         assert_eq!(
             identity.private_key_pem_plaintext.unwrap(),
             original_pem
@@ -337,10 +458,49 @@ mod tests {
         assert_eq!(loaded.private_key().unwrap(), original_pem);
     }
 
+    #[test]
+    fn test_change_password_uses_fresh_salt_and_nonce() {
+        let mut identity = Identity::new("Test User".to_string()).unwrap();
+        let original_key = identity.private_key().unwrap();
+
+        identity.change_password(None, "first").unwrap();
+        assert!(identity.is_unlocked());
+        let (salt, nonce) = (identity.salt.clone(), identity.nonce.clone());
+
+        // Unlocked, but the current password is still checked
+        assert!(identity.change_password(None, "second").is_err());
+        assert!(identity.change_password(Some("wrong"), "second").is_err());
+        identity.change_password(Some("first"), "second").unwrap();
+        assert_ne!(identity.salt, salt);
+        assert_ne!(identity.nonce, nonce);
+
+        let mut reloaded = identity.clone();
+        reloaded.private_key_pem_plaintext = None;
+        assert!(reloaded.decrypt("first").is_err());
+        reloaded.decrypt("second").unwrap();
+        assert_eq!(reloaded.private_key().unwrap(), original_key);
+    }
+
+    #[test]
+    fn test_rsa_identity_files_still_load() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("identity.json");
+
+        let identity = Identity::generate("Legacy".to_string(), KeyAlgorithm::Rsa).unwrap();
+        identity.save(&path).unwrap();
+
+        let loaded = Identity::load(&path).unwrap();
+        assert_eq!(loaded.algorithm(), KeyAlgorithm::Rsa);
+        assert_eq!(loaded.fingerprint, identity.fingerprint);
+        assert_eq!(loaded.fingerprint.len(), 64);
+        assert_eq!(loaded.private_key().unwrap(), identity.private_key().unwrap());
+        assert_eq!(Identity::new("New".to_string()).unwrap().algorithm(), KeyAlgorithm::Ed25519);
+    }
+
     #[test]
     fn test_invite_link_generation() {
         let identity = Identity::new("Test User".to_string()).unwrap();
-        let link = identity.generate_invite_link(None).unwrap();
+        let (link, _) = identity.generate_invite_link(&InviteOptions::default()).unwrap();
 
         assert!(link.starts_with("chat-p2p://invite/"));
         assert!(link.len() > 50); // Should be a substantial base64 string
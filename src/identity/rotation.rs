@@ -0,0 +1,142 @@
+//! Identity key rotation.
+//!
+//! Rotating generates a new key pair and a [`KeyTransition`]: a statement naming
+//! the new public key, signed by the old private key. RSA identities rotate to
+//! Ed25519, which is how they migrate without losing their contacts' trust. Peers
+//! who pinned the old fingerprint can check the statement and move the pin to the
+//! new key without a fresh out-of-band verification.
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+use super::Identity;
+use crate::core::{PrivateKey, PublicKey};
+
+/// Domain separation tag for the signed statement
+const STATEMENT_TAG: &str = "chat-p2p-key-transition|v1";
+
+/// "Key `old` is replaced by key `new`", signed by the old key
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct KeyTransition {
+    /// Public key being retired (PEM)
+    pub old_public_key_pem: String,
+    /// Replacement public key (PEM)
+    pub new_public_key_pem: String,
+    pub issued_at: chrono::DateTime<chrono::Utc>,
+    /// Signature by the old key over [`KeyTransition::signed_bytes`]
+    pub signature: Vec<u8>,
+}
+
+impl KeyTransition {
+    /// Sign a transition from `old_key` to `new_public_key_pem`
+    pub fn sign(
+        old_key: &PrivateKey,
+        old_public_key_pem: &str,
+        new_public_key_pem: &str,
+    ) -> Result<Self> {
+        let mut transition = Self {
+            old_public_key_pem: old_public_key_pem.to_string(),
+            new_public_key_pem: new_public_key_pem.to_string(),
+            issued_at: chrono::Utc::now(),
+            signature: Vec::new(),
+        };
+        transition.signature = old_key.sign(&transition.signed_bytes());
+        Ok(transition)
+    }
+
+    /// Bytes covered by the signature
+    fn signed_bytes(&self) -> Vec<u8> {
+        format!(
+            "{}|{}|{}|{}",
+            STATEMENT_TAG,
+            self.issued_at.to_rfc3339(),
+            self.old_public_key_pem,
+            self.new_public_key_pem
+        )
+        .into_bytes()
+    }
+
+    /// Fingerprint of the retired key
+    pub fn old_fingerprint(&self) -> String {
+        Identity::calculate_fingerprint(&self.old_public_key_pem)
+    }
+
+    /// Fingerprint of the replacement key
+    pub fn new_fingerprint(&self) -> String {
+        Identity::calculate_fingerprint(&self.new_public_key_pem)
+    }
+
+    /// Check that the old key signed this statement
+    pub fn verify(&self) -> Result<()> {
+        let old_key = PublicKey::from_pem(&self.old_public_key_pem)
+            .map_err(|e| anyhow!("Invalid old public key: {}", e))?;
+        PublicKey::from_pem(&self.new_public_key_pem)
+            .map_err(|e| anyhow!("Invalid new public key: {}", e))?;
+        if self.old_public_key_pem == self.new_public_key_pem {
+            return Err(anyhow!("Key transition does not change the key"));
+        }
+
+        old_key
+            .verify(&self.signed_bytes(), &self.signature)
+            .map_err(|e| anyhow!("Invalid key transition: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotation_produces_verifiable_transition() {
+        let mut identity = Identity::new("Alice".to_string()).unwrap();
+        let old_fingerprint = identity.fingerprint.clone();
+
+        let transition = identity.rotate(None).unwrap();
+        transition.verify().unwrap();
+        assert_eq!(transition.old_fingerprint(), old_fingerprint);
+        assert_eq!(transition.new_fingerprint(), identity.fingerprint);
+        assert_ne!(identity.fingerprint, old_fingerprint);
+        assert_eq!(identity.key_transitions, vec![transition.clone()]);
+
+        // Any change to the statement breaks the signature
+        let mut redirected = transition.clone();
+        redirected.new_public_key_pem = Identity::new("Mallory".to_string())
+            .unwrap()
+            .public_key_pem;
+        assert!(redirected.verify().is_err());
+
+        let mut redated = transition;
+        redated.issued_at += chrono::Duration::seconds(1);
+        assert!(redated.verify().is_err());
+    }
+
+    #[test]
+    fn test_rotation_keeps_password_protection() {
+        let mut identity = Identity::new("Alice".to_string()).unwrap();
+        identity.change_password(None, "secret").unwrap();
+
+        assert!(identity.rotate(None).is_err());
+        assert!(identity.rotate(Some("wrong")).is_err());
+        identity.rotate(Some("secret")).unwrap();
+        assert!(identity.is_encrypted());
+
+        let new_key = identity.private_key().unwrap();
+        let mut stored = identity.clone();
+        stored.private_key_pem_plaintext = None;
+        stored.decrypt("secret").unwrap();
+        assert_eq!(stored.private_key().unwrap(), new_key);
+        assert_eq!(new_key.public_key(), stored.public_key().unwrap());
+    }
+
+    #[test]
+    fn test_rsa_identity_migrates_to_ed25519() {
+        use crate::core::KeyAlgorithm;
+
+        let mut identity = Identity::generate("Alice".to_string(), KeyAlgorithm::Rsa).unwrap();
+        let transition = identity.rotate(None).unwrap();
+        transition.verify().unwrap();
+        assert_eq!(identity.algorithm(), KeyAlgorithm::Ed25519);
+        assert_eq!(identity.fingerprint.len(), 40);
+        assert_eq!(transition.old_fingerprint().len(), 64);
+    }
+}
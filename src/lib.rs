@@ -8,18 +8,25 @@
 //!
 //! Modules:
 //! - `app`: High-level orchestration (`ChatManager`) and state handling.
+//! - `cli`: Headless terminal chat mode.
 //! - `core`: Cryptography and wire protocol structures.
+//! - `daemon`: Background service with a JSON-RPC control socket (Unix only).
 //! - `network`: TCP sessions and handshake implementation.
 //! - `transfer`: Chunked file transfer utilities.
+//! - `tui`: Full-screen terminal frontend.
 //! - `identity`: Persistent identity (RSA keys, fingerprints).
 //! - `types`: Shared domain types used across layers.
 //! - `util`: Helpers and utilities.
 pub mod app;
+pub mod cli;
 pub mod core;
+#[cfg(unix)]
+pub mod daemon;
 pub mod gui;
 pub mod identity;
 pub mod network;
 pub mod transfer;
+pub mod tui;
 pub mod types;
 pub mod util;
 
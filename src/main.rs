@@ -21,9 +21,37 @@ struct Args {
     #[arg(short, long, default_value_t = PORT_DEFAULT)]
     port: u16,
 
-    /// Enable GUI mode (default)
-    #[arg(long, default_value_t = true)]
+    /// Run as a background daemon controlled over a JSON-RPC Unix socket
+    #[arg(long)]
+    daemon: bool,
+
+    /// Control socket path for --daemon (default: daemon.sock in the data directory)
+    #[arg(long)]
+    socket: Option<std::path::PathBuf>,
+
+    /// Run the full-screen terminal UI instead of the GUI
+    #[arg(long)]
+    tui: bool,
+
+    /// Force GUI mode (the default when neither --host nor --connect is given)
+    #[arg(long)]
     gui: bool,
+
+    /// chat-p2p:// link to open in the GUI (handed to the running window if there is one)
+    #[arg(value_name = "LINK")]
+    link: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum Command {
+    /// Manage the local identity (name, fingerprint, invites, password, backups)
+    Identity {
+        #[command(subcommand)]
+        action: identity::commands::IdentityCommand,
+    },
 }
 
 #[tokio::main]
@@ -42,7 +70,37 @@ async fn main() -> anyhow::Result<()> {
     let args = Args::parse();
     tracing::debug!(?args, "Parsed CLI arguments");
 
-    if args.gui || (!args.host && args.connect.is_none()) {
+    if let Some(Command::Identity { action }) = args.command {
+        let data_dir = app::default_data_dir()
+            .ok_or_else(|| anyhow::anyhow!("Could not determine user data directory"))?;
+        identity::commands::run(action, &data_dir)?;
+    } else if args.daemon {
+        run_daemon(args.socket).await?;
+    } else if args.tui {
+        tracing::info!("Starting terminal UI");
+        tui::run().await?;
+    } else if args.gui || args.link.is_some() || (!args.host && args.connect.is_none()) {
+        if let Some(link) = &args.link
+            && !app::instance::is_app_link(link)
+        {
+            anyhow::bail!("Not a {}:// link: {}", app::instance::URL_SCHEME, link);
+        }
+
+        // A window is already open: give it the link instead of starting another
+        let endpoint = app::default_data_dir().map(|dir| app::instance::instance_endpoint(&dir));
+        if let (Some(endpoint), Some(link)) = (&endpoint, &args.link)
+            && app::instance::forward_link(endpoint, link).await?
+        {
+            return Ok(());
+        }
+        let links = match &endpoint {
+            Some(endpoint) => app::instance::listen(endpoint).await.unwrap_or_else(|e| {
+                tracing::warn!("Links from other launches will not be received: {}", e);
+                None
+            }),
+            None => None,
+        };
+
         // Launch GUI
         tracing::info!("Starting GUI mode");
 
@@ -57,7 +115,14 @@ async fn main() -> anyhow::Result<()> {
         let run_result = eframe::run_native(
             "Encrypted P2P Messenger",
             native_options,
-            Box::new(|cc| Ok(Box::new(gui::App::new(cc, event_collector.clone())))),
+            Box::new(move |cc| {
+                let mut app = gui::App::new(cc, event_collector.clone());
+                app.incoming_links = links;
+                if let Some(link) = &args.link {
+                    app.open_link(link);
+                }
+                Ok(Box::new(app))
+            }),
         );
         if let Err(e) = run_result {
             tracing::error!(error = %e, "Failed to start GUI application");
@@ -67,11 +132,7 @@ async fn main() -> anyhow::Result<()> {
     } else if args.host {
         // CLI host mode
         tracing::info!("Starting host on port {}", args.port);
-        println!("Starting host on port {}...", args.port);
-        println!("Waiting for connections...");
-
-        // Keep running
-        tokio::signal::ctrl_c().await?;
+        cli::run(cli::CliMode::Host { port: args.port }).await?;
     } else if let Some(addr) = args.connect {
         // CLI client mode
         let (host, port) = if addr.contains(':') {
@@ -85,11 +146,25 @@ async fn main() -> anyhow::Result<()> {
         };
 
         tracing::info!("Connecting to {}:{}", host, port);
-        println!("Connecting to {}:{}...", host, port);
-
-        // Keep running
-        tokio::signal::ctrl_c().await?;
+        cli::run(cli::CliMode::Connect { host, port }).await?;
     }
 
     Ok(())
 }
+
+#[cfg(unix)]
+async fn run_daemon(socket: Option<std::path::PathBuf>) -> anyhow::Result<()> {
+    let data_dir = app::default_data_dir()
+        .ok_or_else(|| anyhow::anyhow!("Could not determine user data directory"))?;
+    let mut options = daemon::DaemonOptions::from_data_dir(&data_dir);
+    if let Some(socket) = socket {
+        options.socket_path = socket;
+    }
+    tracing::info!("Starting daemon mode");
+    daemon::run(options).await
+}
+
+#[cfg(not(unix))]
+async fn run_daemon(_socket: Option<std::path::PathBuf>) -> anyhow::Result<()> {
+    anyhow::bail!("Daemon mode needs Unix domain sockets and is not available on this platform")
+}
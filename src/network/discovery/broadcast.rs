@@ -0,0 +1,497 @@
+//! Signed UDP broadcast beacons, for networks that block multicast DNS.
+//!
+//! While hosting we broadcast a small JSON beacon every [`BEACON_INTERVAL`]
+//! with our name, identity public key and listen port, signed by the identity
+//! key. Receivers check the signature, that the fingerprint is the key's and
+//! that the beacon is recent, so nobody can make a beacon for a key they do
+//! not hold. The peer's address is the source of the datagram, which the
+//! signature does not cover: a recorded beacon could be resent from another
+//! machine. Receivers therefore only take beacons newer than the last one
+//! from the same fingerprint, so a copy never displaces the original, and
+//! connecting to a listed peer still fails without the advertised key.
+
+use super::{
+    is_valid_fingerprint, sanitize_peer_name, DiscoveredPeer, DiscoveryEvent, DiscoverySource,
+    LocalPeer,
+};
+use crate::core::{PrivateKey, PublicKey};
+use crate::identity::Identity;
+use anyhow::{anyhow, bail, Context, Result};
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+
+/// UDP port beacons are sent to and received on unless configured otherwise
+pub const DEFAULT_BEACON_PORT: u16 = 47474;
+
+/// Domain separation tag for the signed payload
+const BEACON_TAG: &str = "chat-p2p-beacon|v1";
+
+/// Time between two beacons from one host
+pub const BEACON_INTERVAL: Duration = Duration::from_secs(5);
+/// Shortest gap between beacons when the announced port changes
+const MIN_ANNOUNCE_GAP: Duration = Duration::from_secs(1);
+/// A peer is gone after this long without a beacon
+const PEER_TIMEOUT: Duration = Duration::from_secs(20);
+/// Oldest (or furthest in the future) beacon accepted, allowing for clock skew
+const MAX_BEACON_AGE_SECS: i64 = 60;
+/// Largest datagram read; an RSA key and signature fit comfortably
+const MAX_BEACON_LEN: usize = 2048;
+/// Beacons checked per second across all senders, so a flood costs little
+const MAX_BEACONS_PER_SEC: usize = 32;
+/// Shortest gap between two beacons checked from one source address
+const MIN_SOURCE_GAP: Duration = Duration::from_millis(1000);
+
+/// A signed "I am hosting here" announcement
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Beacon {
+    pub name: String,
+    pub fingerprint: String,
+    pub public_key: String,
+    /// TCP port the sender accepts connections on
+    pub port: u16,
+    /// When the beacon was made, in Unix seconds
+    pub sent_at: i64,
+    /// Base64 signature by `public_key` over [`Beacon::signed_bytes`]
+    pub signature: String,
+}
+
+impl Beacon {
+    /// Build and sign a beacon for `local` hosting on `port`
+    pub fn new(local: &LocalPeer, key: &PrivateKey, port: u16, now: DateTime<Utc>) -> Self {
+        let mut beacon = Self {
+            name: sanitize_peer_name(&local.name),
+            fingerprint: local.fingerprint.clone(),
+            public_key: local.public_key.clone(),
+            port,
+            sent_at: now.timestamp(),
+            signature: String::new(),
+        };
+        beacon.signature =
+            base64::engine::general_purpose::STANDARD.encode(key.sign(&beacon.signed_bytes()));
+        beacon
+    }
+
+    /// Bytes covered by the signature
+    fn signed_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(&(
+            BEACON_TAG,
+            &self.name,
+            &self.fingerprint,
+            &self.public_key,
+            self.port,
+            self.sent_at,
+        ))
+        .expect("serializing strings cannot fail")
+    }
+
+    /// Check the signature, that the fingerprint is the key's, and the age
+    pub fn verify(&self, now: DateTime<Utc>) -> Result<()> {
+        if (now.timestamp() - self.sent_at).abs() > MAX_BEACON_AGE_SECS {
+            bail!("Beacon is stale");
+        }
+        if self.port == 0 || !is_valid_fingerprint(&self.fingerprint) {
+            bail!("Malformed beacon");
+        }
+        let key = PublicKey::from_pem(&self.public_key)
+            .map_err(|e| anyhow!("Invalid public key in beacon: {}", e))?;
+        if Identity::calculate_fingerprint(&self.public_key) != self.fingerprint {
+            bail!("Beacon fingerprint does not match its public key");
+        }
+        let signature = base64::engine::general_purpose::STANDARD
+            .decode(&self.signature)
+            .map_err(|e| anyhow!("Invalid beacon signature: {}", e))?;
+        key.verify(&self.signed_bytes(), &signature)
+            .map_err(|e| anyhow!("Invalid beacon signature: {}", e))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("serializing a beacon cannot fail")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() > MAX_BEACON_LEN {
+            bail!("Beacon too large");
+        }
+        serde_json::from_slice(bytes).map_err(|e| anyhow!("Invalid beacon: {}", e))
+    }
+}
+
+/// Decides which received datagrams are worth checking
+struct BeaconLimiter {
+    window_start: Instant,
+    in_window: usize,
+    last_from: HashMap<SocketAddr, Instant>,
+}
+
+impl BeaconLimiter {
+    fn new(now: Instant) -> Self {
+        Self {
+            window_start: now,
+            in_window: 0,
+            last_from: HashMap::new(),
+        }
+    }
+
+    fn allow(&mut self, source: SocketAddr, now: Instant) -> bool {
+        if now.duration_since(self.window_start) >= Duration::from_secs(1) {
+            self.window_start = now;
+            self.in_window = 0;
+            self.last_from
+                .retain(|_, last| now.duration_since(*last) < MIN_SOURCE_GAP);
+        }
+        if self.in_window >= MAX_BEACONS_PER_SEC {
+            return false;
+        }
+        if self
+            .last_from
+            .get(&source)
+            .is_some_and(|last| now.duration_since(*last) < MIN_SOURCE_GAP)
+        {
+            return false;
+        }
+        self.in_window += 1;
+        self.last_from.insert(source, now);
+        true
+    }
+}
+
+pub struct BroadcastDiscovery {
+    port: watch::Sender<Option<u16>>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl BroadcastDiscovery {
+    /// Listen for beacons on `bind_port` and forward the peers they announce
+    /// to `events`. Once [`BroadcastDiscovery::announce`] is called, our own
+    /// beacons are sent to `target` if we have a key to sign them with.
+    pub fn start(
+        local: LocalPeer,
+        signer: Option<PrivateKey>,
+        bind_port: u16,
+        target: SocketAddr,
+        events: mpsc::UnboundedSender<DiscoveryEvent>,
+    ) -> Result<Self> {
+        let socket = Arc::new(bind(bind_port)?);
+        let (port_tx, port_rx) = watch::channel(None);
+
+        let mut tasks = vec![tokio::spawn(receive(
+            socket.clone(),
+            local.fingerprint.clone(),
+            events,
+        ))];
+        match signer {
+            Some(key) => tasks.push(tokio::spawn(send(socket, local, key, target, port_rx))),
+            None => tracing::info!("Identity is locked; listening for beacons without sending any"),
+        }
+        Ok(Self {
+            port: port_tx,
+            tasks,
+        })
+    }
+
+    /// Announce that we accept connections on `port`
+    pub fn announce(&self, port: u16) {
+        self.port.send_replace(Some(port));
+    }
+}
+
+impl Drop for BroadcastDiscovery {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// Bind a broadcast-capable socket that other instances on this machine can
+/// share
+fn bind(port: u16) -> Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.set_broadcast(true)?;
+    socket.set_nonblocking(true)?;
+    socket
+        .bind(&SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port).into())
+        .with_context(|| format!("Failed to bind the beacon port {}", port))?;
+    Ok(UdpSocket::from_std(socket.into())?)
+}
+
+async fn send(
+    socket: Arc<UdpSocket>,
+    local: LocalPeer,
+    key: PrivateKey,
+    target: SocketAddr,
+    mut port: watch::Receiver<Option<u16>>,
+) {
+    loop {
+        let current = *port.borrow_and_update();
+        if let Some(listen_port) = current {
+            let beacon = Beacon::new(&local, &key, listen_port, Utc::now());
+            if let Err(e) = socket.send_to(&beacon.to_bytes(), target).await {
+                tracing::debug!("Failed to send beacon: {}", e);
+            }
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(BEACON_INTERVAL) => {}
+            changed = port.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+                tokio::time::sleep(MIN_ANNOUNCE_GAP).await;
+            }
+        }
+    }
+}
+
+/// The newest beacon accepted from one fingerprint
+struct Sighting {
+    address: SocketAddr,
+    name: String,
+    sent_at: i64,
+    received: Instant,
+    /// Still reported as found; kept after it is lost until its beacons
+    /// would be stale, so they can't be replayed in the meantime
+    listed: bool,
+}
+
+/// Record a verified beacon from `source`. Returns the peer to report when it
+/// is new or has moved, and `None` for a repeat or for a copy of a beacon we
+/// already had, wherever it was sent from.
+fn record_beacon(
+    seen: &mut HashMap<String, Sighting>,
+    beacon: Beacon,
+    source: SocketAddr,
+    now: Instant,
+) -> Option<DiscoveredPeer> {
+    let address = SocketAddr::new(source.ip(), beacon.port);
+    let previous = seen.get(&beacon.fingerprint);
+    if previous.is_some_and(|s| beacon.sent_at <= s.sent_at) {
+        tracing::debug!(source = %source, "Dropping replayed beacon");
+        return None;
+    }
+    let unchanged =
+        previous.is_some_and(|s| s.listed && s.address == address && s.name == beacon.name);
+    seen.insert(
+        beacon.fingerprint.clone(),
+        Sighting {
+            address,
+            name: beacon.name.clone(),
+            sent_at: beacon.sent_at,
+            received: now,
+            listed: true,
+        },
+    );
+    (!unchanged).then(|| DiscoveredPeer {
+        name: beacon.name,
+        fingerprint: beacon.fingerprint,
+        address,
+        source: DiscoverySource::Broadcast,
+        last_seen: Utc::now(),
+    })
+}
+
+async fn receive(
+    socket: Arc<UdpSocket>,
+    own_fingerprint: String,
+    events: mpsc::UnboundedSender<DiscoveryEvent>,
+) {
+    let mut limiter = BeaconLimiter::new(Instant::now());
+    let mut seen: HashMap<String, Sighting> = HashMap::new();
+    let mut sweep = tokio::time::interval(BEACON_INTERVAL);
+    let mut buf = vec![0u8; MAX_BEACON_LEN + 1];
+
+    loop {
+        tokio::select! {
+            received = socket.recv_from(&mut buf) => {
+                let Ok((len, source)) = received else { continue };
+                if !limiter.allow(source, Instant::now()) {
+                    continue;
+                }
+                let beacon = match Beacon::from_bytes(&buf[..len])
+                    .and_then(|beacon| beacon.verify(Utc::now()).map(|_| beacon))
+                {
+                    Ok(beacon) => beacon,
+                    Err(e) => {
+                        tracing::debug!(source = %source, "Dropping beacon: {}", e);
+                        continue;
+                    }
+                };
+                if beacon.fingerprint == own_fingerprint {
+                    continue;
+                }
+
+                let Some(peer) = record_beacon(&mut seen, beacon, source, Instant::now()) else {
+                    continue;
+                };
+                if events.send(DiscoveryEvent::Found(peer)).is_err() {
+                    return;
+                }
+            }
+            _ = sweep.tick() => {
+                let stale = Duration::from_secs(2 * MAX_BEACON_AGE_SECS as u64);
+                seen.retain(|_, sighting| sighting.received.elapsed() <= stale);
+                for (fingerprint, sighting) in &mut seen {
+                    if !sighting.listed || sighting.received.elapsed() <= PEER_TIMEOUT {
+                        continue;
+                    }
+                    sighting.listed = false;
+                    let lost = DiscoveryEvent::Lost {
+                        fingerprint: fingerprint.clone(),
+                        source: DiscoverySource::Broadcast,
+                    };
+                    if events.send(lost).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(name: &str) -> (LocalPeer, PrivateKey) {
+        let identity = Identity::new(name.to_string()).unwrap();
+        (
+            LocalPeer::from_identity(&identity),
+            identity.private_key().unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_beacon_must_verify() {
+        let (alice, key) = peer("Alice");
+        let (mallory, mallory_key) = peer("Mallory");
+        let now = Utc::now();
+
+        let beacon = Beacon::new(&alice, &key, 5000, now);
+        let decoded = Beacon::from_bytes(&beacon.to_bytes()).unwrap();
+        decoded.verify(now).unwrap();
+
+        let mut redirected = beacon.clone();
+        redirected.port = 6666;
+        assert!(redirected.verify(now).is_err());
+
+        // Claiming Alice's fingerprint needs Alice's key
+        let mut spoofed = Beacon::new(&mallory, &mallory_key, 5000, now);
+        spoofed.fingerprint = alice.fingerprint.clone();
+        assert!(spoofed.verify(now).is_err());
+        let mut spoofed = Beacon::new(&mallory, &mallory_key, 5000, now);
+        spoofed.public_key = alice.public_key.clone();
+        spoofed.fingerprint = alice.fingerprint.clone();
+        assert!(spoofed.verify(now).is_err());
+
+        let replay_at = now + chrono::Duration::seconds(MAX_BEACON_AGE_SECS + 1);
+        assert!(beacon.verify(replay_at).is_err(), "old beacons are stale");
+        assert!(Beacon::from_bytes(b"not json").is_err());
+        assert!(Beacon::from_bytes(&[b' '; MAX_BEACON_LEN + 1]).is_err());
+    }
+
+    #[test]
+    fn test_beacons_are_rate_limited() {
+        let start = Instant::now();
+        let mut limiter = BeaconLimiter::new(start);
+        let source: SocketAddr = "192.168.1.20:47474".parse().unwrap();
+
+        assert!(limiter.allow(source, start));
+        assert!(!limiter.allow(source, start + Duration::from_millis(200)));
+        assert!(limiter.allow(source, start + MIN_SOURCE_GAP));
+
+        // A flood from many addresses is capped per second
+        let later = start + Duration::from_secs(5);
+        let allowed = (0..100u16)
+            .filter(|i| limiter.allow(SocketAddr::new(source.ip(), 1000 + i), later))
+            .count();
+        assert_eq!(allowed, MAX_BEACONS_PER_SEC);
+        assert!(limiter.allow(source, later + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_replayed_beacon_does_not_move_peer() {
+        let (alice, key) = peer("Alice");
+        let now = Utc::now();
+        let start = Instant::now();
+        let mut seen = HashMap::new();
+        let from_alice: SocketAddr = "192.168.1.20:47474".parse().unwrap();
+        let from_mallory: SocketAddr = "192.168.1.66:47474".parse().unwrap();
+
+        let beacon = Beacon::new(&alice, &key, 5000, now);
+        let found = record_beacon(&mut seen, beacon.clone(), from_alice, start).unwrap();
+        assert_eq!(found.address, "192.168.1.20:5000".parse().unwrap());
+
+        // Mallory resends the recorded beacon, and an older one, from her machine
+        assert!(record_beacon(&mut seen, beacon.clone(), from_mallory, start).is_none());
+        let older = Beacon::new(&alice, &key, 5000, now - chrono::Duration::seconds(5));
+        assert!(record_beacon(&mut seen, older, from_mallory, start).is_none());
+        assert_eq!(seen[&alice.fingerprint].address, found.address);
+
+        // Even once Alice is gone from the list
+        seen.get_mut(&alice.fingerprint).unwrap().listed = false;
+        assert!(record_beacon(&mut seen, beacon, from_mallory, start).is_none());
+
+        // Alice's next beacon lists her again, and a real move is reported
+        let next = Beacon::new(&alice, &key, 5000, now + chrono::Duration::seconds(5));
+        assert!(record_beacon(&mut seen, next, from_alice, start).is_some());
+        let moved = Beacon::new(&alice, &key, 5000, now + chrono::Duration::seconds(10));
+        let found = record_beacon(&mut seen, moved.clone(), "192.168.1.21:47474".parse().unwrap(), start);
+        assert_eq!(found.unwrap().address, "192.168.1.21:5000".parse().unwrap());
+        let again = Beacon::new(&alice, &key, 5000, now + chrono::Duration::seconds(15));
+        assert!(record_beacon(&mut seen, again, "192.168.1.21:47474".parse().unwrap(), start).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_signed_beacons_are_found_and_spoofs_dropped() {
+        let (alice, alice_key) = peer("Alice");
+        let (bob, _) = peer("Bob");
+        let (mallory, mallory_key) = peer("Mallory");
+        let (alice_port, bob_port) = (47581, 47582);
+        let loopback = |port| SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port);
+
+        let (bob_tx, mut bob_rx) = mpsc::unbounded_channel();
+        let _bob = BroadcastDiscovery::start(bob, None, bob_port, loopback(alice_port), bob_tx).unwrap();
+
+        // Mallory claims to be Alice
+        let mut forged = Beacon::new(&mallory, &mallory_key, 6666, Utc::now());
+        forged.fingerprint = alice.fingerprint.clone();
+        let attacker = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        attacker.send_to(&forged.to_bytes(), loopback(bob_port)).unwrap();
+
+        let (alice_tx, _alice_rx) = mpsc::unbounded_channel();
+        let host = BroadcastDiscovery::start(
+            alice.clone(),
+            Some(alice_key),
+            alice_port,
+            loopback(bob_port),
+            alice_tx,
+        )
+        .unwrap();
+        host.announce(4242);
+
+        let event = tokio::time::timeout(Duration::from_secs(10), bob_rx.recv())
+            .await
+            .expect("timed out waiting for a beacon")
+            .unwrap();
+        match event {
+            DiscoveryEvent::Found(peer) => {
+                assert_eq!(peer.name, "Alice");
+                assert_eq!(peer.fingerprint, alice.fingerprint);
+                assert_eq!(peer.address, loopback(4242), "the forged port is never listed");
+                assert_eq!(peer.source, DiscoverySource::Broadcast);
+            }
+            other => panic!("expected a peer, got {:?}", other),
+        }
+    }
+}
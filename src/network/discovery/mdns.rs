@@ -0,0 +1,231 @@
+//! mDNS/DNS-SD backend. Hosts register a `_chat-p2p._tcp` service whose TXT
+//! record carries their name and identity fingerprint; everyone browses for it.
+
+use super::{
+    is_valid_fingerprint, sanitize_peer_name, DiscoveredPeer, DiscoveryEvent, DiscoverySource,
+    LocalPeer,
+};
+use crate::core::protocol::PROTOCOL_VERSION;
+use anyhow::{Context, Result};
+use mdns_sd::{ResolvedService, ServiceDaemon, ServiceEvent, ServiceInfo};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+pub use mdns_sd::MDNS_PORT;
+
+/// DNS-SD service type we register and browse
+pub const SERVICE_TYPE: &str = "_chat-p2p._tcp.local.";
+
+const TXT_NAME: &str = "name";
+const TXT_FINGERPRINT: &str = "fp";
+const TXT_VERSION: &str = "v";
+
+/// How long to wait for the responder when withdrawing our service
+const GOODBYE_TIMEOUT: Duration = Duration::from_millis(500);
+
+pub struct MdnsDiscovery {
+    daemon: ServiceDaemon,
+    local: LocalPeer,
+    /// Full name and port of our service, once announced
+    registered: Mutex<Option<(String, u16)>>,
+}
+
+impl MdnsDiscovery {
+    /// Start a responder on `mdns_port` (normally [`MDNS_PORT`]) and forward
+    /// the peers it resolves to `events`. Our own service is left out.
+    pub fn start(
+        local: LocalPeer,
+        mdns_port: u16,
+        events: mpsc::UnboundedSender<DiscoveryEvent>,
+    ) -> Result<Self> {
+        let daemon =
+            ServiceDaemon::new_with_port(mdns_port).context("Failed to start the mDNS responder")?;
+        let browser = daemon
+            .browse(SERVICE_TYPE)
+            .context("Failed to browse for peers")?;
+
+        let own_fingerprint = local.fingerprint.clone();
+        std::thread::Builder::new()
+            .name("mdns-browse".to_string())
+            .spawn(move || {
+                // Removals only name the service, so remember whose it was
+                let mut seen: HashMap<String, String> = HashMap::new();
+                // Ends when the responder shuts down
+                while let Ok(event) = browser.recv() {
+                    let event = match event {
+                        ServiceEvent::ServiceResolved(service) => {
+                            let Some(peer) = peer_from_service(&service) else {
+                                tracing::debug!(service = %service.fullname, "Ignoring malformed mDNS service");
+                                continue;
+                            };
+                            if peer.fingerprint == own_fingerprint {
+                                continue;
+                            }
+                            seen.insert(service.fullname.clone(), peer.fingerprint.clone());
+                            DiscoveryEvent::Found(peer)
+                        }
+                        ServiceEvent::ServiceRemoved(_, fullname) => {
+                            let Some(fingerprint) = seen.remove(&fullname) else {
+                                continue;
+                            };
+                            DiscoveryEvent::Lost {
+                                fingerprint,
+                                source: DiscoverySource::Mdns,
+                            }
+                        }
+                        _ => continue,
+                    };
+                    if events.send(event).is_err() {
+                        break;
+                    }
+                }
+            })?;
+
+        Ok(Self {
+            daemon,
+            local,
+            registered: Mutex::new(None),
+        })
+    }
+
+    /// Register our service for `port`, replacing an earlier announcement
+    pub fn announce(&self, port: u16) -> Result<()> {
+        let mut registered = self.registered.lock().unwrap_or_else(|e| e.into_inner());
+        if registered.as_ref().is_some_and(|(_, p)| *p == port) {
+            return Ok(());
+        }
+        if let Some((fullname, _)) = registered.take() {
+            self.withdraw(&fullname);
+        }
+
+        // Instance and host labels must be unique and DNS-safe; the readable
+        // name travels in the TXT record
+        let short_id = &self.local.fingerprint[..self.local.fingerprint.len().min(16)];
+        let instance = format!("chat-p2p-{}", short_id);
+        let host_name = format!("{}.local.", instance);
+        let name = sanitize_peer_name(&self.local.name);
+        let version = PROTOCOL_VERSION.to_string();
+        let properties = [
+            (TXT_NAME, name.as_str()),
+            (TXT_FINGERPRINT, self.local.fingerprint.as_str()),
+            (TXT_VERSION, version.as_str()),
+        ];
+        let info = ServiceInfo::new(SERVICE_TYPE, &instance, &host_name, "", port, &properties[..])
+            .context("Invalid mDNS service")?
+            .enable_addr_auto();
+        let fullname = info.get_fullname().to_string();
+        self.daemon
+            .register(info)
+            .context("Failed to advertise on the local network")?;
+        tracing::info!(port = %port, "Advertising on the local network");
+        *registered = Some((fullname, port));
+        Ok(())
+    }
+
+    /// Unregister a service, waiting briefly so the goodbye goes out
+    fn withdraw(&self, fullname: &str) {
+        if let Ok(status) = self.daemon.unregister(fullname) {
+            let _ = status.recv_timeout(GOODBYE_TIMEOUT);
+        }
+    }
+}
+
+impl Drop for MdnsDiscovery {
+    fn drop(&mut self) {
+        let registered = self.registered.get_mut().ok().and_then(|r| r.take());
+        if let Some((fullname, _)) = registered {
+            self.withdraw(&fullname);
+        }
+        if let Ok(status) = self.daemon.shutdown() {
+            let _ = status.recv_timeout(GOODBYE_TIMEOUT);
+        }
+    }
+}
+
+/// Read a peer from a resolved service, if it carries what we need
+fn peer_from_service(service: &ResolvedService) -> Option<DiscoveredPeer> {
+    if !service.is_valid() || service.port == 0 {
+        return None;
+    }
+    let fingerprint = service.get_property_val_str(TXT_FINGERPRINT)?;
+    if !is_valid_fingerprint(fingerprint) {
+        return None;
+    }
+    let name = sanitize_peer_name(service.get_property_val_str(TXT_NAME).unwrap_or_default());
+
+    // Prefer IPv4: IPv6 link-local addresses would need their scope
+    let ip = match service.get_addresses_v4().into_iter().min() {
+        Some(v4) => IpAddr::V4(v4),
+        None => service.addresses.iter().map(|ip| ip.to_ip_addr()).min()?,
+    };
+
+    Some(DiscoveredPeer {
+        name,
+        fingerprint: fingerprint.to_ascii_lowercase(),
+        address: SocketAddr::new(ip, service.port),
+        source: DiscoverySource::Mdns,
+        last_seen: chrono::Utc::now(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn next_event(rx: &mut mpsc::UnboundedReceiver<DiscoveryEvent>) -> DiscoveryEvent {
+        tokio::time::timeout(Duration::from_secs(15), rx.recv())
+            .await
+            .expect("timed out waiting for mDNS")
+            .expect("browser stopped")
+    }
+
+    #[tokio::test]
+    async fn test_hosts_are_found_and_lost_in_process() {
+        // A private port keeps the test off the real mDNS responder
+        let mdns_port = 25353;
+        let alice = LocalPeer {
+            name: "Alice".to_string(),
+            fingerprint: "a1".repeat(16),
+            public_key: String::new(),
+        };
+        let bob = LocalPeer {
+            name: "Bob".to_string(),
+            fingerprint: "b2".repeat(16),
+            public_key: String::new(),
+        };
+
+        let (alice_tx, mut alice_rx) = mpsc::unbounded_channel();
+        let host = MdnsDiscovery::start(alice.clone(), mdns_port, alice_tx).unwrap();
+        let (bob_tx, mut bob_rx) = mpsc::unbounded_channel();
+        let _browser = MdnsDiscovery::start(bob, mdns_port, bob_tx).unwrap();
+
+        host.announce(4242).unwrap();
+        match next_event(&mut bob_rx).await {
+            DiscoveryEvent::Found(peer) => {
+                assert_eq!(peer.name, alice.name);
+                assert_eq!(peer.fingerprint, alice.fingerprint);
+                assert_eq!(peer.address.port(), 4242);
+                assert_eq!(peer.source, DiscoverySource::Mdns);
+            }
+            other => panic!("expected a peer, got {:?}", other),
+        }
+        assert!(alice_rx.try_recv().is_err(), "our own service is not listed");
+
+        drop(host);
+        // Updated records may resolve the service again before the goodbye
+        let mut event = next_event(&mut bob_rx).await;
+        while matches!(event, DiscoveryEvent::Found(_)) {
+            event = next_event(&mut bob_rx).await;
+        }
+        assert_eq!(
+            event,
+            DiscoveryEvent::Lost {
+                fingerprint: alice.fingerprint,
+                source: DiscoverySource::Mdns,
+            }
+        );
+    }
+}
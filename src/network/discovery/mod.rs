@@ -0,0 +1,158 @@
+//! Finding peers on the local network.
+//!
+//! Each backend reports what it sees as [`DiscoveryEvent`]s on one channel, so
+//! the UI shows a single list of nearby peers however they were found: mDNS
+//! (`mdns`), and signed UDP broadcast beacons (`broadcast`) for networks that
+//! block multicast. An advertised name and fingerprint only say who to expect:
+//! connecting to a listed peer refuses any identity key with another
+//! fingerprint, and the safety number is compared as for any connection.
+
+pub mod broadcast;
+pub mod mdns;
+
+use crate::core::PrivateKey;
+use crate::identity::Identity;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Mutex;
+use tokio::sync::mpsc;
+
+/// Longest advertised name kept, in characters
+pub const MAX_PEER_NAME_LEN: usize = 64;
+
+/// How a peer was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiscoverySource {
+    Mdns,
+    Broadcast,
+}
+
+/// What we advertise about ourselves
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalPeer {
+    pub name: String,
+    /// Identity fingerprint
+    pub fingerprint: String,
+    /// Identity public key (PEM), carried by signed beacons
+    pub public_key: String,
+}
+
+impl LocalPeer {
+    pub fn from_identity(identity: &Identity) -> Self {
+        Self {
+            name: identity.name.clone(),
+            fingerprint: identity.fingerprint.clone(),
+            public_key: identity.public_key_pem.clone(),
+        }
+    }
+}
+
+/// A peer seen on the local network
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveredPeer {
+    /// Name the peer advertised
+    pub name: String,
+    /// Identity fingerprint the peer advertised
+    pub fingerprint: String,
+    /// Where the peer accepts connections
+    pub address: SocketAddr,
+    pub source: DiscoverySource,
+    pub last_seen: DateTime<Utc>,
+}
+
+/// Change in the set of nearby peers
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiscoveryEvent {
+    Found(DiscoveredPeer),
+    Lost {
+        fingerprint: String,
+        source: DiscoverySource,
+    },
+}
+
+/// Whether `fingerprint` looks like an identity fingerprint
+pub fn is_valid_fingerprint(fingerprint: &str) -> bool {
+    !fingerprint.is_empty()
+        && fingerprint.len() <= 64
+        && fingerprint.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Advertised name, trimmed to something the sidebar can show
+pub fn sanitize_peer_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_PEER_NAME_LEN)
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Running discovery: browses for peers and, while hosting, advertises us
+pub struct LanDiscovery {
+    local: LocalPeer,
+    signing: bool,
+    beacon_port: u16,
+    mdns: mdns::MdnsDiscovery,
+    broadcast: broadcast::BroadcastDiscovery,
+    events: Mutex<mpsc::UnboundedReceiver<DiscoveryEvent>>,
+}
+
+impl LanDiscovery {
+    /// Start browsing with both backends. Nothing is advertised until
+    /// [`LanDiscovery::announce`]; beacons are only sent with a `signer`.
+    pub fn start(local: LocalPeer, signer: Option<PrivateKey>, beacon_port: u16) -> Result<Self> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let signing = signer.is_some();
+        let mdns = mdns::MdnsDiscovery::start(local.clone(), mdns::MDNS_PORT, tx.clone())?;
+        let target = SocketAddr::new(IpAddr::V4(Ipv4Addr::BROADCAST), beacon_port);
+        let broadcast =
+            broadcast::BroadcastDiscovery::start(local.clone(), signer, beacon_port, target, tx)?;
+        Ok(Self {
+            local,
+            signing,
+            beacon_port,
+            mdns,
+            broadcast,
+            events: Mutex::new(rx),
+        })
+    }
+
+    /// Whether this discovery was started for `local` with the same settings
+    pub fn matches(&self, local: &LocalPeer, signing: bool, beacon_port: u16) -> bool {
+        self.local == *local && self.signing == signing && self.beacon_port == beacon_port
+    }
+
+    /// Advertise that we accept connections on `port`
+    pub fn announce(&self, port: u16) -> Result<()> {
+        self.broadcast.announce(port);
+        self.mdns.announce(port)
+    }
+
+    /// Events received since the last call
+    pub fn drain_events(&self) -> Vec<DiscoveryEvent> {
+        let mut events = Vec::new();
+        if let Ok(mut rx) = self.events.lock() {
+            while let Ok(event) = rx.try_recv() {
+                events.push(event);
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_advertised_fields_are_checked() {
+        assert!(is_valid_fingerprint("0a1b2c3d"));
+        assert!(!is_valid_fingerprint(""));
+        assert!(!is_valid_fingerprint("not-hex"));
+        assert!(!is_valid_fingerprint(&"a".repeat(65)));
+
+        assert_eq!(sanitize_peer_name("  Alice\u{7}\n "), "Alice");
+        assert_eq!(sanitize_peer_name(&"x".repeat(100)).len(), MAX_PEER_NAME_LEN);
+    }
+}
@@ -1,3 +1,5 @@
+pub mod discovery;
+pub mod rekey;
 pub mod session;
 
-pub use session::*;
+pub use session::*;
\ No newline at end of file
@@ -0,0 +1,264 @@
+//! In-session rekeying.
+//!
+//! When a [`RekeyPolicy`] limit is reached, a peer sends `Rekey` with a fresh
+//! X25519 key, encrypted under the current key. The other side answers with its
+//! own `Rekey` unless it already sent one. Once a side knows both keys it sends
+//! `RekeyAck`, the last packet under its old send key, and switches. The peer
+//! switches the matching receive key when that `RekeyAck` arrives, so packets
+//! already in flight are still read with the key they were sent under.
+
+use anyhow::{anyhow, Result};
+use hkdf::Hkdf;
+use rand::rngs::OsRng;
+use sha2::Sha256;
+use tokio::time::{Duration, Instant};
+use x25519_dalek::{PublicKey as X25519PublicKey, StaticSecret};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::AES_KEY_SIZE;
+use crate::core::{parse_x25519_public, ProtocolMessage};
+use crate::types::RekeyPolicy;
+
+/// HKDF context string for a key replaced during the session
+const REKEY_HKDF_INFO: &[u8] = b"p2p-messenger-v4-rekey";
+
+/// Session key to switch to, and what to send under the old key first
+pub struct KeySwitch {
+    pub messages: Vec<ProtocolMessage>,
+    pub send_key: Zeroizing<[u8; AES_KEY_SIZE]>,
+}
+
+/// Tracks usage of the current key and drives the rekey exchange
+pub struct Rekeyer {
+    policy: RekeyPolicy,
+    /// Key of the current epoch, the salt for the next one
+    session_key: Zeroizing<[u8; AES_KEY_SIZE]>,
+    messages: u64,
+    bytes: u64,
+    established_at: Instant,
+    /// Our ephemeral secret, once our `Rekey` has been sent
+    pending: Option<StaticSecret>,
+    /// New key waiting for the peer's `RekeyAck`
+    next_receive_key: Option<Zeroizing<[u8; AES_KEY_SIZE]>>,
+    completed: u64,
+}
+
+impl Rekeyer {
+    pub fn new(session_key: &[u8; AES_KEY_SIZE], policy: RekeyPolicy) -> Self {
+        Self {
+            policy,
+            session_key: Zeroizing::new(*session_key),
+            messages: 0,
+            bytes: 0,
+            established_at: Instant::now(),
+            pending: None,
+            next_receive_key: None,
+            completed: 0,
+        }
+    }
+
+    /// Count a message sent under the current key
+    pub fn record_sent(&mut self, plaintext_len: usize) {
+        self.messages += 1;
+        self.bytes += plaintext_len as u64;
+    }
+
+    /// When the time limit expires, if there is one and no rekey is under way
+    pub fn deadline(&self) -> Option<Instant> {
+        if self.pending.is_some() || self.policy.after_minutes == 0 {
+            return None;
+        }
+        Some(self.established_at + Duration::from_secs(self.policy.after_minutes * 60))
+    }
+
+    /// Whether a limit is reached and we should send `Rekey`
+    pub fn is_due(&self) -> bool {
+        let limit_reached = |used: u64, limit: u64| limit > 0 && used >= limit;
+        self.pending.is_none()
+            && (limit_reached(self.messages, self.policy.after_messages)
+                || limit_reached(self.bytes, self.policy.after_bytes)
+                || self.deadline().is_some_and(|deadline| Instant::now() >= deadline))
+    }
+
+    /// Number of finished key switches
+    pub fn completed(&self) -> u64 {
+        self.completed
+    }
+
+    /// Start a rekey: returns the `Rekey` message to send
+    pub fn start(&mut self) -> ProtocolMessage {
+        let secret = StaticSecret::random_from_rng(OsRng);
+        let public_key = X25519PublicKey::from(&secret).as_bytes().to_vec();
+        self.pending = Some(secret);
+        ProtocolMessage::Rekey { public_key }
+    }
+
+    /// Handle the peer's `Rekey`. The returned messages go out under the old
+    /// key, then the send key is replaced.
+    pub fn handle_rekey(&mut self, peer_public: &[u8]) -> Result<KeySwitch> {
+        if self.next_receive_key.is_some() {
+            return Err(anyhow!("Rekey received before the previous RekeyAck"));
+        }
+        let peer_public = parse_x25519_public(peer_public)?;
+
+        let mut messages = Vec::new();
+        let secret = match self.pending.take() {
+            Some(secret) => secret,
+            // The peer started: answer with our own key first
+            None => {
+                let rekey = self.start();
+                messages.push(rekey);
+                self.pending.take().expect("start() sets a pending secret")
+            }
+        };
+        messages.push(ProtocolMessage::RekeyAck);
+
+        let shared = Zeroizing::new(secret.diffie_hellman(&peer_public).to_bytes());
+        let hkdf = Hkdf::<Sha256>::new(Some(self.session_key.as_ref()), shared.as_ref());
+        let mut next_key = Zeroizing::new([0u8; AES_KEY_SIZE]);
+        hkdf.expand(REKEY_HKDF_INFO, next_key.as_mut())
+            .expect("HKDF expand should not fail with valid length");
+
+        self.session_key = next_key.clone();
+        self.next_receive_key = Some(next_key.clone());
+        self.messages = 0;
+        self.bytes = 0;
+        self.established_at = Instant::now();
+
+        Ok(KeySwitch {
+            messages,
+            send_key: next_key,
+        })
+    }
+
+    /// Handle the peer's `RekeyAck`: returns the new receive key
+    pub fn handle_ack(&mut self) -> Result<Zeroizing<[u8; AES_KEY_SIZE]>> {
+        let key = self
+            .next_receive_key
+            .take()
+            .ok_or_else(|| anyhow!("Unexpected RekeyAck"))?;
+        self.completed += 1;
+        Ok(key)
+    }
+
+    /// Overwrite the current and next session keys with zeros and forget our
+    /// pending secret
+    fn wipe(&mut self) {
+        self.session_key.zeroize();
+        if let Some(key) = self.next_receive_key.as_mut() {
+            key.zeroize();
+        }
+        self.pending = None;
+    }
+}
+
+impl Drop for Rekeyer {
+    fn drop(&mut self) {
+        self.wipe();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(after_messages: u64) -> RekeyPolicy {
+        RekeyPolicy {
+            after_messages,
+            after_bytes: 0,
+            after_minutes: 0,
+        }
+    }
+
+    fn rekey_key(msg: &ProtocolMessage) -> Vec<u8> {
+        match msg {
+            ProtocolMessage::Rekey { public_key } => public_key.clone(),
+            other => panic!("expected Rekey, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut rekeyer = Rekeyer::new(&[1; AES_KEY_SIZE], policy(2));
+        rekeyer.record_sent(10);
+        assert!(!rekeyer.is_due());
+        rekeyer.record_sent(10);
+        assert!(rekeyer.is_due());
+        rekeyer.start();
+        assert!(!rekeyer.is_due(), "only one rekey at a time");
+
+        let mut by_bytes = Rekeyer::new(&[1; AES_KEY_SIZE], RekeyPolicy {
+            after_messages: 0,
+            after_bytes: 100,
+            after_minutes: 0,
+        });
+        by_bytes.record_sent(99);
+        assert!(!by_bytes.is_due());
+        by_bytes.record_sent(1);
+        assert!(by_bytes.is_due());
+        assert!(by_bytes.deadline().is_none());
+
+        let disabled = Rekeyer::new(&[1; AES_KEY_SIZE], policy(0));
+        assert!(!disabled.is_due());
+        let timed = Rekeyer::new(&[1; AES_KEY_SIZE], RekeyPolicy::default());
+        assert!(timed.deadline().is_some());
+    }
+
+    #[test]
+    fn test_one_side_starts() {
+        let key = [2; AES_KEY_SIZE];
+        let (mut alice, mut bob) = (Rekeyer::new(&key, policy(0)), Rekeyer::new(&key, policy(0)));
+
+        let offer = alice.start();
+        let bob_switch = bob.handle_rekey(&rekey_key(&offer)).unwrap();
+        assert_eq!(bob_switch.messages.len(), 2);
+        assert_eq!(bob_switch.messages[1], ProtocolMessage::RekeyAck);
+
+        let alice_switch = alice.handle_rekey(&rekey_key(&bob_switch.messages[0])).unwrap();
+        assert_eq!(alice_switch.messages, vec![ProtocolMessage::RekeyAck]);
+        assert_eq!(alice_switch.send_key, bob_switch.send_key);
+        assert_ne!(*alice_switch.send_key, key);
+
+        assert_eq!(alice.handle_ack().unwrap(), bob_switch.send_key);
+        assert_eq!(bob.handle_ack().unwrap(), alice_switch.send_key);
+        assert_eq!((alice.completed(), bob.completed()), (1, 1));
+        assert!(alice.handle_ack().is_err(), "RekeyAck without a Rekey");
+
+        // The next round is salted with the new key, not the first one
+        let offer = bob.start();
+        let alice_switch = alice.handle_rekey(&rekey_key(&offer)).unwrap();
+        let bob_switch = bob.handle_rekey(&rekey_key(&alice_switch.messages[0])).unwrap();
+        assert_eq!(alice_switch.send_key, bob_switch.send_key);
+    }
+
+    #[test]
+    fn test_both_sides_start_at_once() {
+        let key = [3; AES_KEY_SIZE];
+        let (mut alice, mut bob) = (Rekeyer::new(&key, policy(0)), Rekeyer::new(&key, policy(0)));
+        let (alice_offer, bob_offer) = (alice.start(), bob.start());
+
+        let alice_switch = alice.handle_rekey(&rekey_key(&bob_offer)).unwrap();
+        let bob_switch = bob.handle_rekey(&rekey_key(&alice_offer)).unwrap();
+        assert_eq!(alice_switch.messages, vec![ProtocolMessage::RekeyAck]);
+        assert_eq!(bob_switch.messages, vec![ProtocolMessage::RekeyAck]);
+        assert_eq!(alice_switch.send_key, bob_switch.send_key);
+
+        // A second Rekey before the peer's RekeyAck is a protocol error
+        assert!(alice.handle_rekey(&rekey_key(&bob.start())).is_err());
+        assert!(bob.handle_rekey(&[0; 5]).is_err());
+    }
+
+    #[test]
+    fn test_wipe_clears_every_key() {
+        let key = [4; AES_KEY_SIZE];
+        let (mut alice, mut bob) = (Rekeyer::new(&key, policy(0)), Rekeyer::new(&key, policy(0)));
+        bob.handle_rekey(&rekey_key(&alice.start())).unwrap();
+        alice.start();
+
+        bob.wipe();
+        assert_eq!(*bob.session_key, [0; AES_KEY_SIZE]);
+        assert_eq!(bob.next_receive_key.as_deref(), Some(&[0; AES_KEY_SIZE]));
+        alice.wipe();
+        assert!(alice.pending.is_none());
+    }
+}
@@ -1,27 +1,429 @@
 use anyhow::{anyhow, Result};
-use rsa::{RsaPrivateKey, RsaPublicKey};
 use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::mpsc;
 
 use crate::core::{
-    derive_session_key, fingerprint_pubkey, generate_ephemeral_keypair, parse_x25519_public,
-    pem_decode_public, pem_encode_public, recv_packet, send_packet, AesCipher, ProtocolMessage,
-    PROTOCOL_VERSION,
+    derive_hybrid_session_key, derive_session_key, fingerprint_public_key_pem,
+    generate_ephemeral_keypair, generate_kem_keypair, kem_decapsulate, kem_encapsulate,
+    parse_x25519_public, recv_packet, send_packet, AesCipher, PrivateKey, ProtocolMessage,
+    PublicKey, CAPABILITY_DOUBLE_RATCHET, CAPABILITY_HYBRID_KEM,
+    CAPABILITY_INVITE_PROOF, CAPABILITY_REKEY, CAPABILITY_SEQUENCED, PROTOCOL_VERSION,
+    SIGNED_HANDSHAKE_MIN_VERSION,
 };
-use crate::types::SessionEvent;
+use crate::core::channel::SecureChannel;
+use crate::core::ratchet::DoubleRatchet;
+use crate::identity::{invite_proof, Identity};
+use crate::network::rekey::Rekeyer;
+use crate::types::{Config, RekeyPolicy, SecurityEvent, SessionEvent};
+use crate::AES_KEY_SIZE;
+use hkdf::Hkdf;
+use rand::rngs::OsRng;
+use sha2::{Digest, Sha256};
+use x25519_dalek::{PublicKey as X25519PublicKey, StaticSecret};
+use zeroize::Zeroizing;
 
 /// HKDF context string for key derivation
 const HKDF_INFO: &[u8] = b"p2p-messenger-v2-forward-secrecy";
 
+/// HKDF context string for combining the X25519 and ML-KEM secrets
+const HYBRID_HKDF_INFO: &[u8] = b"p2p-messenger-v4-hybrid-mlkem768";
+
+/// HKDF context string for the value invite proofs are computed over
+const INVITE_BINDING_HKDF_INFO: &[u8] = b"p2p-messenger-v4-invite-binding";
+
+/// HKDF context string for binding the session key to the handshake transcript
+const TRANSCRIPT_HKDF_INFO: &[u8] = b"p2p-messenger-v5-transcript";
+
+/// Hash the handshake transcript starts from
+const TRANSCRIPT_LABEL: &[u8] = b"p2p-messenger-v5-handshake";
+
+/// Prefix of the transcript signature made by each side
+const HOST_SIGNATURE_LABEL: &[u8] = b"p2p-messenger-v5-host-signature";
+const CLIENT_SIGNATURE_LABEL: &[u8] = b"p2p-messenger-v5-client-signature";
+
+/// Our identity key pair, which signs our side of every handshake
+#[derive(Debug, Clone)]
+pub struct HandshakeIdentity {
+    pub public_key_pem: String,
+    pub private_key: PrivateKey,
+}
+
+impl HandshakeIdentity {
+    /// Key pair of an identity; fails while the identity is locked
+    pub fn from_identity(identity: &Identity) -> Result<Self> {
+        Ok(Self {
+            public_key_pem: identity.public_key_pem.clone(),
+            private_key: identity.private_key()?,
+        })
+    }
+
+    pub fn fingerprint(&self) -> String {
+        fingerprint_public_key_pem(&self.public_key_pem)
+    }
+}
+
+/// Settings a session takes from the app configuration
+#[derive(Debug, Clone)]
+pub struct SessionOptions {
+    /// Identity we sign the handshake with
+    pub identity: HandshakeIdentity,
+    /// Offer the hybrid X25519 + ML-KEM handshake to peers that support it
+    pub offer_post_quantum: bool,
+    /// When to replace the session key
+    pub rekey_policy: RekeyPolicy,
+    /// Token of the host's invite we were given (client only)
+    pub invite_token: Option<String>,
+    /// Identity fingerprint the host must have, e.g. the one in its invite
+    /// (client only)
+    pub expected_fingerprint: Option<String>,
+}
+
+impl SessionOptions {
+    pub fn from_config(config: &Config, identity: HandshakeIdentity) -> Self {
+        Self {
+            identity,
+            offer_post_quantum: config.enable_post_quantum,
+            rekey_policy: config.rekey_policy,
+            invite_token: None,
+            expected_fingerprint: None,
+        }
+    }
+}
+
+/// Hash of every plaintext handshake packet, in the order it went over the
+/// wire. Both peers sign it, so a packet that was changed, dropped or swapped
+/// for someone else's on the way makes the handshake fail.
+struct Transcript(Sha256);
+
+impl Transcript {
+    fn new() -> Self {
+        Self(Sha256::new_with_prefix(TRANSCRIPT_LABEL))
+    }
+
+    fn absorb(&mut self, packet: &[u8]) {
+        self.0.update((packet.len() as u32).to_be_bytes());
+        self.0.update(packet);
+    }
+
+    fn hash(&self) -> [u8; 32] {
+        self.0.clone().finalize().into()
+    }
+
+    /// What one side signs: its role label and the transcript so far
+    fn signed_bytes(&self, label: &[u8]) -> Vec<u8> {
+        [label, &self.hash()].concat()
+    }
+}
+
+/// Final session key: the exchanged key salted with the transcript hash, so
+/// peers that saw different versions, capabilities or keys end up with
+/// different keys even before the signatures are checked
+fn bind_to_transcript(
+    key: &[u8; AES_KEY_SIZE],
+    transcript: &Transcript,
+) -> Zeroizing<[u8; AES_KEY_SIZE]> {
+    let hkdf = Hkdf::<Sha256>::new(Some(&transcript.hash()), key);
+    let mut session_key = Zeroizing::new([0u8; AES_KEY_SIZE]);
+    hkdf.expand(TRANSCRIPT_HKDF_INFO, session_key.as_mut())
+        .expect("HKDF expand should not fail with valid length");
+    session_key
+}
+
+/// Send a handshake packet and add it to the transcript
+async fn send_recorded<S>(stream: &mut S, transcript: &mut Transcript, packet: &[u8]) -> Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    transcript.absorb(packet);
+    send_packet(stream, packet).await?;
+    Ok(())
+}
+
+/// Receive a handshake packet and add it to the transcript
+async fn recv_recorded<S>(stream: &mut S, transcript: &mut Transcript) -> Result<Vec<u8>>
+where
+    S: AsyncRead + Unpin,
+{
+    let packet = recv_packet(stream).await?;
+    transcript.absorb(&packet);
+    Ok(packet)
+}
+
+/// Sign the transcript so far with our identity key
+async fn send_transcript_signature<S>(
+    stream: &mut S,
+    transcript: &Transcript,
+    identity: &HandshakeIdentity,
+    label: &[u8],
+) -> Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    let msg = ProtocolMessage::HandshakeSignature {
+        signature: identity.private_key.sign(&transcript.signed_bytes(label)),
+    };
+    send_packet(stream, &msg.to_plain_bytes()).await?;
+    Ok(())
+}
+
+/// Check the peer's signature over the transcript so far
+async fn recv_transcript_signature<S>(
+    stream: &mut S,
+    transcript: &Transcript,
+    peer_key: &PublicKey,
+    label: &[u8],
+) -> Result<()>
+where
+    S: AsyncRead + Unpin,
+{
+    let packet = recv_packet(stream).await?;
+    match ProtocolMessage::from_plain_bytes(&packet) {
+        Some(ProtocolMessage::HandshakeSignature { signature }) => peer_key
+            .verify(&transcript.signed_bytes(label), &signature)
+            .map_err(|_| anyhow!("Handshake signature does not match the peer's identity key")),
+        _ => Err(anyhow!("Expected HandshakeSignature message")),
+    }
+}
+
+/// Optional features both peers agreed on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct SessionFeatures {
+    /// Session key also depends on an ML-KEM-768 exchange
+    hybrid_kem: bool,
+    /// Messages are encrypted with per-message keys from a Double Ratchet
+    double_ratchet: bool,
+    /// The session key is replaced in-session
+    rekey: bool,
+    /// The client opens the session with an `InviteProof`
+    invite_proof: bool,
+}
+
+/// Exchange `Capabilities` and return the features both sides offer. Every
+/// peer must offer `counter-nonces`. The host sends first.
+async fn negotiate_capabilities<S>(
+    stream: &mut S,
+    transcript: &mut Transcript,
+    offer_post_quantum: bool,
+    send_first: bool,
+) -> Result<SessionFeatures>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut features = vec![
+        CAPABILITY_DOUBLE_RATCHET.to_string(),
+        CAPABILITY_SEQUENCED.to_string(),
+        CAPABILITY_REKEY.to_string(),
+        CAPABILITY_INVITE_PROOF.to_string(),
+    ];
+    if offer_post_quantum {
+        features.push(CAPABILITY_HYBRID_KEM.to_string());
+    }
+    let ours = ProtocolMessage::Capabilities { features };
+    if send_first {
+        send_recorded(stream, transcript, &ours.to_plain_bytes()).await?;
+    }
+    let theirs = recv_recorded(stream, transcript).await?;
+    if !send_first {
+        send_recorded(stream, transcript, &ours.to_plain_bytes()).await?;
+    }
+
+    let peer_features = match ProtocolMessage::from_plain_bytes(&theirs) {
+        Some(ProtocolMessage::Capabilities { features }) => features,
+        other => return Err(anyhow!("Expected Capabilities message, got {:?}", other)),
+    };
+    tracing::debug!("Peer capabilities: {:?}", peer_features);
+
+    let peer_offers = |feature: &str| peer_features.iter().any(|f| f == feature);
+    if !peer_offers(CAPABILITY_SEQUENCED) {
+        return Err(anyhow!("Peer does not offer {}", CAPABILITY_SEQUENCED));
+    }
+    Ok(SessionFeatures {
+        hybrid_kem: offer_post_quantum && peer_offers(CAPABILITY_HYBRID_KEM),
+        double_ratchet: peer_offers(CAPABILITY_DOUBLE_RATCHET),
+        rekey: peer_offers(CAPABILITY_REKEY),
+        invite_proof: peer_offers(CAPABILITY_INVITE_PROOF),
+    })
+}
+
+/// Value the client's invite proof is computed over. It comes from the
+/// session key, so a proof is only valid on the connection it was sent on.
+fn invite_binding(session_key: &[u8; AES_KEY_SIZE]) -> Zeroizing<[u8; 32]> {
+    let hkdf = Hkdf::<Sha256>::new(None, session_key);
+    let mut binding = Zeroizing::new([0u8; 32]);
+    hkdf.expand(INVITE_BINDING_HKDF_INFO, binding.as_mut())
+        .expect("HKDF expand should not fail with valid length");
+    binding
+}
+
+/// Client: open the encrypted session with our invite proof, empty when we
+/// connect without an invite
+async fn send_invite_proof<S>(
+    stream: &mut S,
+    cipher: &mut ChannelCipher,
+    session_key: &[u8; AES_KEY_SIZE],
+    invite_token: Option<&str>,
+) -> Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    let proof = invite_token
+        .map(|token| invite_proof(token, invite_binding(session_key).as_ref()))
+        .unwrap_or_default();
+    if !proof.is_empty() {
+        tracing::info!("Proving our invite to the host");
+    }
+    let msg = ProtocolMessage::InviteProof { proof };
+    send_packet(stream, &cipher.encrypt(&msg.to_plain_bytes())).await?;
+    Ok(())
+}
+
+/// Host: read the client's invite proof. Returns the event for the app when
+/// the client presented one.
+async fn recv_invite_proof<S>(
+    stream: &mut S,
+    cipher: &mut ChannelCipher,
+    session_key: &[u8; AES_KEY_SIZE],
+) -> Result<Option<SessionEvent>>
+where
+    S: AsyncRead + Unpin,
+{
+    let packet = recv_packet(stream).await?;
+    let plaintext = cipher
+        .decrypt(&packet)
+        .map_err(|event| anyhow!("Invalid invite proof: {}", event))?
+        .0;
+    match ProtocolMessage::from_plain_bytes(&plaintext) {
+        Some(ProtocolMessage::InviteProof { proof }) if proof.is_empty() => Ok(None),
+        Some(ProtocolMessage::InviteProof { proof }) => Ok(Some(SessionEvent::InviteProof {
+            proof,
+            binding: invite_binding(session_key).to_vec(),
+        })),
+        _ => Err(anyhow!("Expected InviteProof message")),
+    }
+}
+
+/// Exchange initial ratchet keys under the handshake key and set up the
+/// Double Ratchet. The client is the initiator; the host sends its key first.
+async fn establish_ratchet<S>(
+    stream: &mut S,
+    session_key: &[u8; AES_KEY_SIZE],
+    initiator: bool,
+) -> Result<DoubleRatchet>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let handshake_cipher = AesCipher::new(session_key);
+    let secret = StaticSecret::random_from_rng(OsRng);
+    let ours = ProtocolMessage::RatchetKey {
+        public_key: X25519PublicKey::from(&secret).as_bytes().to_vec(),
+    };
+    let ours = handshake_cipher.encrypt(&ours.to_plain_bytes());
+
+    if !initiator {
+        send_packet(stream, &ours).await?;
+    }
+    let theirs = recv_packet(stream).await?;
+    if initiator {
+        send_packet(stream, &ours).await?;
+    }
+
+    let their_public = match handshake_cipher
+        .decrypt(&theirs)
+        .and_then(|plaintext| ProtocolMessage::from_plain_bytes(&plaintext))
+    {
+        Some(ProtocolMessage::RatchetKey { public_key }) => parse_x25519_public(&public_key)?,
+        _ => return Err(anyhow!("Expected RatchetKey message")),
+    };
+
+    Ok(if initiator {
+        DoubleRatchet::initiator(session_key, secret, their_public)
+    } else {
+        DoubleRatchet::responder(session_key, secret, their_public)
+    })
+}
+
+/// Encryption used by the message loop
+struct ChannelCipher {
+    /// Directional keys, counter nonces and a receive window
+    channel: Box<SecureChannel>,
+    /// Per-message keys inside the record layer, if negotiated
+    ratchet: Option<Box<DoubleRatchet>>,
+}
+
+impl ChannelCipher {
+    async fn establish<S>(
+        stream: &mut S,
+        session_key: &[u8; AES_KEY_SIZE],
+        features: SessionFeatures,
+        initiator: bool,
+    ) -> Result<Self>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let ratchet = if features.double_ratchet {
+            let ratchet = establish_ratchet(stream, session_key, initiator).await?;
+            tracing::info!("Double Ratchet established (per-message keys)");
+            Some(Box::new(ratchet))
+        } else {
+            None
+        };
+        let channel = Box::new(SecureChannel::new(session_key, initiator));
+        Ok(Self { channel, ratchet })
+    }
+
+    /// Switch the send direction to a key agreed during the session
+    fn rekey_send(&mut self, session_key: &[u8; AES_KEY_SIZE]) {
+        self.channel.rekey_send(session_key);
+    }
+
+    /// Switch the receive direction to a key agreed during the session
+    fn rekey_recv(&mut self, session_key: &[u8; AES_KEY_SIZE]) {
+        self.channel.rekey_recv(session_key);
+    }
+
+    fn encrypt(&mut self, plaintext: &[u8]) -> Vec<u8> {
+        let inner = Zeroizing::new(match &mut self.ratchet {
+            Some(ratchet) => ratchet.encrypt(plaintext),
+            None => plaintext.to_vec(),
+        });
+        self.channel.seal(&inner)
+    }
+
+    /// Decrypt a packet. An accepted packet may still carry a warning
+    /// (packets missing before it).
+    fn decrypt(
+        &mut self,
+        packet: &[u8],
+    ) -> std::result::Result<(Zeroizing<Vec<u8>>, Option<SecurityEvent>), SecurityEvent> {
+        let (inner, warning) = self.channel.open(packet)?;
+        let plaintext = match &mut self.ratchet {
+            Some(ratchet) => ratchet.decrypt(&inner).map_err(|e| {
+                tracing::debug!("Ratchet rejected packet: {}", e);
+                SecurityEvent::AuthenticationFailed
+            })?,
+            None => inner,
+        };
+        Ok((plaintext, warning))
+    }
+}
+
+/// Parse the peer's identity key (either algorithm) and return it with its fingerprint
+fn parse_peer_identity(pem_bytes: &[u8]) -> Result<(PublicKey, String)> {
+    let pem = std::str::from_utf8(pem_bytes)?;
+    let key = PublicKey::from_pem(pem)?;
+    tracing::debug!("Peer identity key is {}", key.algorithm().label());
+    Ok((key, fingerprint_public_key_pem(pem)))
+}
+
 /// Run host session: listen, accept, handshake, message loop
 pub async fn run_host_session(
     port: u16,
-    privkey: RsaPrivateKey,
     to_app_tx: mpsc::UnboundedSender<SessionEvent>,
     from_app_rx: mpsc::UnboundedReceiver<ProtocolMessage>,
     _confirm_rx: mpsc::UnboundedReceiver<bool>,
     chat_id: uuid::Uuid,
+    options: SessionOptions,
 ) -> Result<()> {
     // 1. Bind listener
     let listener = TcpListener::bind(("0.0.0.0", port)).await?;
@@ -41,15 +443,18 @@ pub async fn run_host_session(
         })
         .map_err(|e| anyhow!("Send error: {}", e))?;
 
+    let identity = &options.identity;
+    let mut transcript = Transcript::new();
+
     // 3. Send protocol version
     let version_msg = ProtocolMessage::Version {
         version: PROTOCOL_VERSION,
     };
-    send_packet(&mut stream, &version_msg.to_plain_bytes()).await?;
+    send_recorded(&mut stream, &mut transcript, &version_msg.to_plain_bytes()).await?;
     tracing::debug!("Sent protocol version: {}", PROTOCOL_VERSION);
 
     // 4. Receive client protocol version
-    let client_version_bytes = recv_packet(&mut stream).await?;
+    let client_version_bytes = recv_recorded(&mut stream, &mut transcript).await?;
     let client_version_msg = ProtocolMessage::from_plain_bytes(&client_version_bytes)
         .ok_or_else(|| anyhow!("Failed to parse client version"))?;
 
@@ -66,55 +471,63 @@ pub async fn run_host_session(
     tracing::info!("Client protocol version: {}", client_version);
 
     // Check version compatibility
-    if client_version < 2 {
+    if client_version < SIGNED_HANDSHAKE_MIN_VERSION {
         return Err(anyhow!(
-            "Client version {} not supported (need v2+)",
-            client_version
+            "Client version {} not supported (need v{}+)",
+            client_version,
+            SIGNED_HANDSHAKE_MIN_VERSION
         ));
     }
 
-    // 5. Send host public key (for identity/fingerprint)
-    let host_pub_pem = pem_encode_public(&RsaPublicKey::from(&privkey))?;
-    send_packet(&mut stream, host_pub_pem.as_bytes()).await?;
-    tracing::debug!("Sent host RSA public key");
-
-    // 6. Receive client public key
-    let client_pub_pem = recv_packet(&mut stream).await?;
-    let client_pub_pem_str = String::from_utf8(client_pub_pem)?;
-    let _client_pubkey = pem_decode_public(&client_pub_pem_str)?;
-    let client_fingerprint = fingerprint_pubkey(client_pub_pem_str.as_bytes());
+    let features = negotiate_capabilities(
+        &mut stream,
+        &mut transcript,
+        options.offer_post_quantum,
+        true,
+    )
+    .await?;
+
+    // 5. Send our identity key
+    send_recorded(&mut stream, &mut transcript, identity.public_key_pem.as_bytes()).await?;
+    tracing::debug!("Sent host {} identity key", identity.private_key.algorithm().label());
+
+    // 6. Receive client identity key
+    let client_pub_pem = recv_recorded(&mut stream, &mut transcript).await?;
+    let (client_key, client_fingerprint) = parse_peer_identity(&client_pub_pem)?;
     tracing::debug!(
-        "Received client RSA public key, fingerprint: {}",
+        "Received client identity key, fingerprint: {}",
         client_fingerprint
     );
 
     // 7. Receive chat_id from client (for logging/compat)
-    let client_chat_id_bytes = recv_packet(&mut stream).await?;
+    let client_chat_id_bytes = recv_recorded(&mut stream, &mut transcript).await?;
     let client_chat_id = uuid::Uuid::from_slice(&client_chat_id_bytes)?;
     tracing::debug!("Received client chat_id: {}", client_chat_id);
 
-    // 8. Display fingerprint and wait for user confirmation
-    to_app_tx
-        .send(SessionEvent::NewConnection {
-            peer_addr: peer_addr.to_string(),
-            fingerprint: client_fingerprint,
-            chat_id, // use host session's chat id to avoid creating a second chat
-        })
-        .map_err(|e| anyhow!("Send error: {}", e))?;
-
-    // 9. Generate ephemeral X25519 keypair for forward secrecy
+    // 8. Generate ephemeral X25519 keypair for forward secrecy
     let (host_ephemeral_secret, host_ephemeral_public) = generate_ephemeral_keypair();
     tracing::debug!("Generated host ephemeral X25519 keypair");
 
-    // 10. Send host ephemeral public key
+    // 9. Send host ephemeral public key
     let host_ephemeral_msg = ProtocolMessage::EphemeralKey {
         public_key: host_ephemeral_public.as_bytes().to_vec(),
     };
-    send_packet(&mut stream, &host_ephemeral_msg.to_plain_bytes()).await?;
+    send_recorded(&mut stream, &mut transcript, &host_ephemeral_msg.to_plain_bytes()).await?;
     tracing::debug!("Sent host ephemeral public key");
 
-    // 11. Receive client ephemeral public key
-    let client_ephemeral_bytes = recv_packet(&mut stream).await?;
+    // Hybrid handshake: also send an ephemeral ML-KEM-768 public key
+    let kem_key = if features.hybrid_kem {
+        let (decapsulation_key, public_key) = generate_kem_keypair();
+        let kem_msg = ProtocolMessage::KemPublicKey { public_key };
+        send_recorded(&mut stream, &mut transcript, &kem_msg.to_plain_bytes()).await?;
+        tracing::debug!("Sent host ML-KEM public key");
+        Some(decapsulation_key)
+    } else {
+        None
+    };
+
+    // 10. Receive client ephemeral public key
+    let client_ephemeral_bytes = recv_recorded(&mut stream, &mut transcript).await?;
     let client_ephemeral_msg = ProtocolMessage::from_plain_bytes(&client_ephemeral_bytes)
         .ok_or_else(|| anyhow!("Failed to parse client ephemeral key"))?;
 
@@ -124,29 +537,80 @@ pub async fn run_host_session(
     };
     tracing::debug!("Received client ephemeral public key");
 
-    // 12. Derive session key using ECDH + HKDF
-    let aes_key = derive_session_key(host_ephemeral_secret, &client_ephemeral_public, HKDF_INFO);
-    tracing::info!("Derived session key using X25519 ECDH + HKDF (forward secrecy enabled)");
+    let kem_shared = match &kem_key {
+        Some(decapsulation_key) => {
+            let kem_bytes = recv_recorded(&mut stream, &mut transcript).await?;
+            match ProtocolMessage::from_plain_bytes(&kem_bytes) {
+                Some(ProtocolMessage::KemCiphertext { ciphertext }) => {
+                    Some(kem_decapsulate(decapsulation_key, &ciphertext)?)
+                }
+                _ => return Err(anyhow!("Expected KemCiphertext message")),
+            }
+        }
+        None => None,
+    };
 
-    let cipher = AesCipher::new(&aes_key);
+    // 11. The client signs the transcript first; only a peer whose signature
+    // checks out is shown to the user
+    if let Err(e) =
+        recv_transcript_signature(&mut stream, &transcript, &client_key, CLIENT_SIGNATURE_LABEL).await
+    {
+        let _ = to_app_tx.send(SessionEvent::Error(format!("Handshake rejected: {}", e)));
+        return Err(e);
+    }
+    send_transcript_signature(&mut stream, &transcript, identity, HOST_SIGNATURE_LABEL).await?;
+    tracing::debug!("Handshake signatures exchanged");
 
-    // 13. Enter message loop
+    // 12. Display fingerprint and wait for user confirmation
+    to_app_tx
+        .send(SessionEvent::NewConnection {
+            peer_addr: peer_addr.to_string(),
+            fingerprint: client_fingerprint,
+            local_fingerprint: identity.fingerprint(),
+            chat_id, // use host session's chat id to avoid creating a second chat
+        })
+        .map_err(|e| anyhow!("Send error: {}", e))?;
+
+    // 13. Derive session key using ECDH + HKDF (and ML-KEM if negotiated)
+    let mut aes_key = derive_session_key(host_ephemeral_secret, &client_ephemeral_public, HKDF_INFO);
+    if let Some(kem_shared) = &kem_shared {
+        aes_key = derive_hybrid_session_key(&aes_key, kem_shared, HYBRID_HKDF_INFO);
+        tracing::info!("Derived session key using X25519 + ML-KEM-768 + HKDF (post-quantum hybrid)");
+    } else {
+        tracing::info!("Derived session key using X25519 ECDH + HKDF (forward secrecy enabled)");
+    }
+    let aes_key = bind_to_transcript(&aes_key, &transcript);
+
+    let mut cipher = ChannelCipher::establish(&mut stream, &aes_key, features, false).await?;
+    let rekeyer = features
+        .rekey
+        .then(|| Rekeyer::new(&aes_key, options.rekey_policy));
+
+    if features.invite_proof
+        && let Some(event) = recv_invite_proof(&mut stream, &mut cipher, &aes_key).await?
+    {
+        to_app_tx.send(event).map_err(|e| anyhow!("Send error: {}", e))?;
+    }
+
+    // 14. Enter message loop
     to_app_tx
-        .send(SessionEvent::Ready)
+        .send(SessionEvent::Ready {
+            post_quantum: features.hybrid_kem,
+        })
         .map_err(|e| anyhow!("Send error: {}", e))?;
 
-    run_message_loop(stream, cipher, to_app_tx, from_app_rx).await
+    run_message_loop(stream, cipher, rekeyer, to_app_tx, from_app_rx).await
 }
 
 /// Run client session: connect, handshake, message loop
 pub async fn run_client_session(
     host: &str,
     port: u16,
-    privkey: RsaPrivateKey,
     to_app_tx: mpsc::UnboundedSender<SessionEvent>,
     from_app_rx: mpsc::UnboundedReceiver<ProtocolMessage>,
     mut confirm_rx: mpsc::UnboundedReceiver<bool>,
     chat_id: uuid::Uuid,
+    options: SessionOptions,
 ) -> Result<()> {
     // 1. Connect to host
     let mut stream = TcpStream::connect((host, port)).await?;
@@ -158,8 +622,11 @@ pub async fn run_client_session(
         })
         .map_err(|e| anyhow!("Send error: {}", e))?;
 
+    let identity = &options.identity;
+    let mut transcript = Transcript::new();
+
     // 2. Receive host protocol version
-    let host_version_bytes = recv_packet(&mut stream).await?;
+    let host_version_bytes = recv_recorded(&mut stream, &mut transcript).await?;
     let host_version_msg = ProtocolMessage::from_plain_bytes(&host_version_bytes)
         .ok_or_else(|| anyhow!("Failed to parse host version"))?;
 
@@ -176,10 +643,11 @@ pub async fn run_client_session(
     tracing::info!("Host protocol version: {}", host_version);
 
     // Check version compatibility
-    if host_version < 2 {
+    if host_version < SIGNED_HANDSHAKE_MIN_VERSION {
         return Err(anyhow!(
-            "Host version {} not supported (need v2+)",
-            host_version
+            "Host version {} not supported (need v{}+)",
+            host_version,
+            SIGNED_HANDSHAKE_MIN_VERSION
         ));
     }
 
@@ -187,23 +655,106 @@ pub async fn run_client_session(
     let version_msg = ProtocolMessage::Version {
         version: PROTOCOL_VERSION,
     };
-    send_packet(&mut stream, &version_msg.to_plain_bytes()).await?;
+    send_recorded(&mut stream, &mut transcript, &version_msg.to_plain_bytes()).await?;
     tracing::debug!("Sent protocol version: {}", PROTOCOL_VERSION);
 
-    // 4. Receive host RSA public key (for identity/fingerprint)
-    let host_pub_pem = recv_packet(&mut stream).await?;
-    let host_pub_pem_str = String::from_utf8(host_pub_pem)?;
-    let _host_pubkey = pem_decode_public(&host_pub_pem_str)?;
-    let host_fingerprint = fingerprint_pubkey(host_pub_pem_str.as_bytes());
+    let features = negotiate_capabilities(
+        &mut stream,
+        &mut transcript,
+        options.offer_post_quantum,
+        false,
+    )
+    .await?;
+
+    // 4. Receive host identity key
+    let host_pub_pem = recv_recorded(&mut stream, &mut transcript).await?;
+    let (host_key, host_fingerprint) = parse_peer_identity(&host_pub_pem)?;
     tracing::debug!(
-        "Received host RSA public key, fingerprint: {}",
+        "Received host identity key, fingerprint: {}",
         host_fingerprint
     );
+    if let Some(expected) = &options.expected_fingerprint
+        && !expected.eq_ignore_ascii_case(&host_fingerprint)
+    {
+        let e = anyhow!(
+            "The host's identity key ({}) is not the one we expected ({})",
+            host_fingerprint,
+            expected
+        );
+        let _ = to_app_tx.send(SessionEvent::Error(format!("Handshake rejected: {}", e)));
+        return Err(e);
+    }
+
+    // 5. Send our identity key
+    send_recorded(&mut stream, &mut transcript, identity.public_key_pem.as_bytes()).await?;
+    tracing::debug!("Sent client {} identity key", identity.private_key.algorithm().label());
+
+    // 6. Send chat_id to host
+    send_recorded(&mut stream, &mut transcript, chat_id.as_bytes()).await?;
+    tracing::debug!("Sent chat_id to host: {}", chat_id);
+
+    // 7. Receive host ephemeral public key
+    let host_ephemeral_bytes = recv_recorded(&mut stream, &mut transcript).await?;
+    let host_ephemeral_msg = ProtocolMessage::from_plain_bytes(&host_ephemeral_bytes)
+        .ok_or_else(|| anyhow!("Failed to parse host ephemeral key"))?;
+
+    let host_ephemeral_public = match host_ephemeral_msg {
+        ProtocolMessage::EphemeralKey { public_key } => parse_x25519_public(&public_key)?,
+        _ => return Err(anyhow!("Expected EphemeralKey message")),
+    };
+    tracing::debug!("Received host ephemeral public key");
+
+    // Hybrid handshake: encapsulate a secret to the host's ML-KEM key
+    let kem_exchange = if features.hybrid_kem {
+        let kem_bytes = recv_recorded(&mut stream, &mut transcript).await?;
+        match ProtocolMessage::from_plain_bytes(&kem_bytes) {
+            Some(ProtocolMessage::KemPublicKey { public_key }) => Some(kem_encapsulate(&public_key)?),
+            _ => return Err(anyhow!("Expected KemPublicKey message")),
+        }
+    } else {
+        None
+    };
+
+    // 8. Generate ephemeral X25519 keypair for forward secrecy
+    let (client_ephemeral_secret, client_ephemeral_public) = generate_ephemeral_keypair();
+    tracing::debug!("Generated client ephemeral X25519 keypair");
 
-    // 5. Display fingerprint and wait for confirmation
+    // 9. Send client ephemeral public key
+    let client_ephemeral_msg = ProtocolMessage::EphemeralKey {
+        public_key: client_ephemeral_public.as_bytes().to_vec(),
+    };
+    send_recorded(&mut stream, &mut transcript, &client_ephemeral_msg.to_plain_bytes()).await?;
+    tracing::debug!("Sent client ephemeral public key");
+
+    // 10. Derive session key using ECDH + HKDF (and ML-KEM if negotiated)
+    let mut aes_key = derive_session_key(client_ephemeral_secret, &host_ephemeral_public, HKDF_INFO);
+    if let Some((ciphertext, kem_shared)) = kem_exchange {
+        let kem_msg = ProtocolMessage::KemCiphertext { ciphertext };
+        send_recorded(&mut stream, &mut transcript, &kem_msg.to_plain_bytes()).await?;
+        tracing::debug!("Sent client ML-KEM ciphertext");
+
+        aes_key = derive_hybrid_session_key(&aes_key, &kem_shared, HYBRID_HKDF_INFO);
+        tracing::info!("Derived session key using X25519 + ML-KEM-768 + HKDF (post-quantum hybrid)");
+    } else {
+        tracing::info!("Derived session key using X25519 ECDH + HKDF (forward secrecy enabled)");
+    }
+    let aes_key = bind_to_transcript(&aes_key, &transcript);
+
+    // 11. Sign the transcript, then check the host's signature
+    send_transcript_signature(&mut stream, &transcript, identity, CLIENT_SIGNATURE_LABEL).await?;
+    if let Err(e) =
+        recv_transcript_signature(&mut stream, &transcript, &host_key, HOST_SIGNATURE_LABEL).await
+    {
+        let _ = to_app_tx.send(SessionEvent::Error(format!("Handshake rejected: {}", e)));
+        return Err(e);
+    }
+    tracing::debug!("Handshake signatures exchanged");
+
+    // 12. Display the verified fingerprint and wait for confirmation
     to_app_tx
         .send(SessionEvent::ShowFingerprintVerification {
-            fingerprint: host_fingerprint.clone(),
+            fingerprint: host_fingerprint,
+            local_fingerprint: identity.fingerprint(),
             peer_name: host.to_string(),
             chat_id,
         })
@@ -236,86 +787,140 @@ pub async fn run_client_session(
         }
     }
 
-    // 6. Send client RSA public key
-    let client_pub_pem = pem_encode_public(&RsaPublicKey::from(&privkey))?;
-    send_packet(&mut stream, client_pub_pem.as_bytes()).await?;
-    tracing::debug!("Sent client RSA public key");
-
-    // 7. Send chat_id to host
-    send_packet(&mut stream, chat_id.as_bytes()).await?;
-    tracing::debug!("Sent chat_id to host: {}", chat_id);
+    let mut cipher = ChannelCipher::establish(&mut stream, &aes_key, features, true).await?;
+    let rekeyer = features
+        .rekey
+        .then(|| Rekeyer::new(&aes_key, options.rekey_policy));
 
-    // 8. Receive host ephemeral public key
-    let host_ephemeral_bytes = recv_packet(&mut stream).await?;
-    let host_ephemeral_msg = ProtocolMessage::from_plain_bytes(&host_ephemeral_bytes)
-        .ok_or_else(|| anyhow!("Failed to parse host ephemeral key"))?;
-
-    let host_ephemeral_public = match host_ephemeral_msg {
-        ProtocolMessage::EphemeralKey { public_key } => parse_x25519_public(&public_key)?,
-        _ => return Err(anyhow!("Expected EphemeralKey message")),
-    };
-    tracing::debug!("Received host ephemeral public key");
-
-    // 9. Generate ephemeral X25519 keypair for forward secrecy
-    let (client_ephemeral_secret, client_ephemeral_public) = generate_ephemeral_keypair();
-    tracing::debug!("Generated client ephemeral X25519 keypair");
-
-    // 10. Send client ephemeral public key
-    let client_ephemeral_msg = ProtocolMessage::EphemeralKey {
-        public_key: client_ephemeral_public.as_bytes().to_vec(),
-    };
-    send_packet(&mut stream, &client_ephemeral_msg.to_plain_bytes()).await?;
-    tracing::debug!("Sent client ephemeral public key");
-
-    // 11. Derive session key using ECDH + HKDF
-    let aes_key = derive_session_key(client_ephemeral_secret, &host_ephemeral_public, HKDF_INFO);
-    tracing::info!("Derived session key using X25519 ECDH + HKDF (forward secrecy enabled)");
-
-    let cipher = AesCipher::new(&aes_key);
+    if features.invite_proof {
+        send_invite_proof(&mut stream, &mut cipher, &aes_key, options.invite_token.as_deref()).await?;
+    } else if options.invite_token.is_some() {
+        tracing::info!("Host does not take invite proofs; it will verify us by hand");
+    }
 
-    // 12. Enter message loop
+    // 13. Enter message loop
     to_app_tx
-        .send(SessionEvent::Ready)
+        .send(SessionEvent::Ready {
+            post_quantum: features.hybrid_kem,
+        })
         .map_err(|e| anyhow!("Send error: {}", e))?;
 
-    run_message_loop(stream, cipher, to_app_tx, from_app_rx).await
+    run_message_loop(stream, cipher, rekeyer, to_app_tx, from_app_rx).await
 }
 
+/// Send our `Rekey` under the current key
+async fn start_rekey<S>(stream: &mut S, cipher: &mut ChannelCipher, rekeyer: &mut Rekeyer) -> Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    tracing::info!("Rekey limit reached, sending a new key");
+    let msg = rekeyer.start();
+    send_packet(stream, &cipher.encrypt(&msg.to_plain_bytes())).await?;
+    Ok(())
+}
+
+/// Apply the peer's `Rekey` or `RekeyAck`
+async fn handle_rekey_message<S>(
+    stream: &mut S,
+    cipher: &mut ChannelCipher,
+    rekeyer: &mut Rekeyer,
+    msg: ProtocolMessage,
+) -> Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    match msg {
+        ProtocolMessage::Rekey { public_key } => {
+            let switch = rekeyer.handle_rekey(&public_key)?;
+            // Our answer and RekeyAck still go out under the old key
+            for msg in &switch.messages {
+                send_packet(stream, &cipher.encrypt(&msg.to_plain_bytes())).await?;
+            }
+            cipher.rekey_send(&switch.send_key);
+            Ok(())
+        }
+        ProtocolMessage::RekeyAck => {
+            let key = rekeyer.handle_ack()?;
+            cipher.rekey_recv(&key);
+            tracing::info!("Session key replaced (rekey #{})", rekeyer.completed());
+            Ok(())
+        }
+        other => Err(anyhow!("Not a rekey message: {:?}", other)),
+    }
+}
+
+/// Received packets waiting for the message loop
+const PACKET_QUEUE_LEN: usize = 64;
+
 /// Main message loop: send and receive encrypted messages
 async fn run_message_loop<S>(
-    mut stream: S,
-    cipher: AesCipher,
+    stream: S,
+    mut cipher: ChannelCipher,
+    mut rekeyer: Option<Rekeyer>,
     to_app_tx: mpsc::UnboundedSender<SessionEvent>,
     mut from_app_rx: mpsc::UnboundedReceiver<ProtocolMessage>,
 ) -> Result<()>
 where
     S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
 {
+    // recv_packet is not cancel-safe: if the select below dropped it halfway
+    // through a packet, the stream would lose its framing. Read in a task of
+    // its own instead.
+    let (mut reader, mut stream) = tokio::io::split(stream);
+    let (packet_tx, mut packet_rx) = mpsc::channel(PACKET_QUEUE_LEN);
+    let reader_task = tokio::spawn(async move {
+        loop {
+            let result = recv_packet(&mut reader).await;
+            let failed = result.is_err();
+            if packet_tx.send(result).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+
     loop {
+        let rekey_deadline = rekeyer.as_ref().and_then(Rekeyer::deadline);
+
         tokio::select! {
             // Receive from network
-            result = recv_packet(&mut stream) => {
+            Some(result) = packet_rx.recv() => {
                 match result {
                     Ok(encrypted) => {
                         tracing::trace!("Received {} bytes encrypted", encrypted.len());
 
-                        if let Some(plaintext) = cipher.decrypt(&encrypted) {
-                            tracing::trace!("Decrypted {} bytes", plaintext.len());
+                        match cipher.decrypt(&encrypted) {
+                            Ok((plaintext, warning)) => {
+                                tracing::trace!("Decrypted {} bytes", plaintext.len());
 
-                            if let Some(msg) = ProtocolMessage::from_plain_bytes(&plaintext) {
-                                tracing::debug!("Received message: {:?}", msg);
+                                if let Some(event) = warning {
+                                    tracing::warn!("Security event: {}", event);
+                                    let _ = to_app_tx.send(SessionEvent::SecurityViolation(event));
+                                }
 
-                                if let Err(e) = to_app_tx.send(SessionEvent::MessageReceived(msg)) {
-                                    tracing::error!("Failed to send MessageReceived event: {}", e);
-                                    return Err(anyhow!("Event channel closed: {}", e));
+                                if let Some(msg) = ProtocolMessage::from_plain_bytes(&plaintext) {
+                                    tracing::debug!("Received message: {:?}", msg);
+
+                                    let is_rekey = matches!(msg, ProtocolMessage::Rekey { .. } | ProtocolMessage::RekeyAck);
+                                    if is_rekey && let Some(rekeyer) = rekeyer.as_mut() {
+                                        if let Err(e) = handle_rekey_message(&mut stream, &mut cipher, rekeyer, msg).await {
+                                            let err_msg = format!("Rekey failed: {}", e);
+                                            tracing::error!("{}", err_msg);
+                                            let _ = to_app_tx.send(SessionEvent::Error(err_msg));
+                                            break;
+                                        }
+                                    } else if let Err(e) = to_app_tx.send(SessionEvent::MessageReceived(msg)) {
+                                        tracing::error!("Failed to send MessageReceived event: {}", e);
+                                        return Err(anyhow!("Event channel closed: {}", e));
+                                    }
+                                } else {
+                                    tracing::warn!("Failed to parse message from {} bytes", plaintext.len());
+                                    tracing::debug!("Raw plaintext: {:?}", String::from_utf8_lossy(&plaintext));
                                 }
-                            } else {
-                                tracing::warn!("Failed to parse message from {} bytes", plaintext.len());
-                                tracing::debug!("Raw plaintext: {:?}", String::from_utf8_lossy(&plaintext));
                             }
-                        } else {
-                            tracing::error!("Decryption failed - possible tampering or key mismatch!");
-                            let _ = to_app_tx.send(SessionEvent::Error("Decryption failed!".to_string()));
+                            Err(event) => {
+                                tracing::error!("Rejected packet - {}!", event);
+                                let _ = to_app_tx.send(SessionEvent::SecurityViolation(event));
+                            }
                         }
                     }
                     Err(e) => {
@@ -331,7 +936,7 @@ where
             Some(msg) = from_app_rx.recv() => {
                 tracing::debug!("Sending message: {:?}", msg);
 
-                let plaintext = msg.to_plain_bytes();
+                let plaintext = Zeroizing::new(msg.to_plain_bytes());
                 tracing::trace!("Plaintext {} bytes", plaintext.len());
 
                 let encrypted = cipher.encrypt(&plaintext);
@@ -345,10 +950,33 @@ where
                 } else {
                     tracing::debug!("Message sent successfully");
                 }
+
+                if let Some(rekeyer) = rekeyer.as_mut() {
+                    rekeyer.record_sent(plaintext.len());
+                    if rekeyer.is_due() && let Err(e) = start_rekey(&mut stream, &mut cipher, rekeyer).await {
+                        let err_msg = format!("Network send error: {}", e);
+                        tracing::error!("{}", err_msg);
+                        let _ = to_app_tx.send(SessionEvent::Error(err_msg));
+                        break;
+                    }
+                }
+            }
+
+            // Time limit of the current key
+            _ = tokio::time::sleep_until(rekey_deadline.unwrap_or_else(tokio::time::Instant::now)), if rekey_deadline.is_some() => {
+                if let Some(rekeyer) = rekeyer.as_mut()
+                    && let Err(e) = start_rekey(&mut stream, &mut cipher, rekeyer).await
+                {
+                    let err_msg = format!("Network send error: {}", e);
+                    tracing::error!("{}", err_msg);
+                    let _ = to_app_tx.send(SessionEvent::Error(err_msg));
+                    break;
+                }
             }
         }
     }
 
+    reader_task.abort();
     to_app_tx
         .send(SessionEvent::Disconnected)
         .map_err(|e| anyhow!("Send error: {}", e))?;
@@ -359,9 +987,14 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::core::{generate_rsa_keypair, rsa_decrypt_oaep, rsa_encrypt_oaep};
+    use crate::core::{
+        generate_rsa_keypair, pem_decode_public, pem_encode_public, rsa_decrypt_oaep,
+        rsa_encrypt_oaep, KeyAlgorithm,
+    };
+    use crate::core::safety::SafetyNumber;
     use crate::RSA_KEY_BITS;
     use rand::RngCore;
+    use rsa::RsaPublicKey;
 
     #[tokio::test]
     async fn test_full_handshake() {
@@ -418,4 +1051,489 @@ mod tests {
         // Keys should match
         assert_eq!(host_aes, client_aes);
     }
+
+    #[tokio::test]
+    async fn test_capability_negotiation() {
+        // Both sides offer everything
+        let (mut host, mut client) = tokio::io::duplex(8192);
+        let (mut host_transcript, mut client_transcript) = (Transcript::new(), Transcript::new());
+        let (host_features, client_features) = tokio::join!(
+            negotiate_capabilities(&mut host, &mut host_transcript, true, true),
+            negotiate_capabilities(&mut client, &mut client_transcript, true, false),
+        );
+        // Both sides recorded the same packets
+        assert_eq!(host_transcript.hash(), client_transcript.hash());
+        let all = SessionFeatures {
+            hybrid_kem: true,
+            double_ratchet: true,
+            rekey: true,
+            invite_proof: true,
+        };
+        assert_eq!(host_features.unwrap(), all);
+        assert_eq!(client_features.unwrap(), all);
+
+        // One side has post-quantum turned off: both fall back to X25519
+        let (mut host, mut client) = tokio::io::duplex(8192);
+        let (mut host_transcript, mut client_transcript) = (Transcript::new(), Transcript::new());
+        let (host_features, client_features) = tokio::join!(
+            negotiate_capabilities(&mut host, &mut host_transcript, true, true),
+            negotiate_capabilities(&mut client, &mut client_transcript, false, false),
+        );
+        assert!(!host_features.unwrap().hybrid_kem);
+        assert!(!client_features.unwrap().hybrid_kem);
+
+        // A peer without counter nonces is refused
+        let (mut host, mut client) = tokio::io::duplex(8192);
+        let without_counters = ProtocolMessage::Capabilities {
+            features: vec![CAPABILITY_DOUBLE_RATCHET.to_string()],
+        };
+        let mut transcript = Transcript::new();
+        let (host_features, _) = tokio::join!(
+            negotiate_capabilities(&mut host, &mut transcript, true, true),
+            async {
+                recv_packet(&mut client).await.unwrap();
+                send_packet(&mut client, &without_counters.to_plain_bytes()).await.unwrap();
+            },
+        );
+        assert!(host_features.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_ratchet_channel_after_handshake() {
+        let session_key = [5u8; AES_KEY_SIZE];
+        let features = SessionFeatures {
+            hybrid_kem: false,
+            double_ratchet: true,
+            rekey: false,
+            invite_proof: false,
+        };
+        let (mut host_stream, mut client_stream) = tokio::io::duplex(8192);
+        let (host, client) = tokio::join!(
+            ChannelCipher::establish(&mut host_stream, &session_key, features, false),
+            ChannelCipher::establish(&mut client_stream, &session_key, features, true),
+        );
+        let (mut host, mut client) = (host.unwrap(), client.unwrap());
+        assert!(host.ratchet.is_some());
+
+        let first = host.encrypt(b"one");
+        let second = host.encrypt(b"one");
+        assert_ne!(first, second);
+        assert_eq!(client.decrypt(&first).unwrap().0.as_slice(), b"one");
+        assert_eq!(client.decrypt(&second).unwrap().0.as_slice(), b"one");
+        assert_eq!(host.decrypt(&client.encrypt(b"two")).unwrap().0.as_slice(), b"two");
+    }
+
+    #[tokio::test]
+    async fn test_sequenced_channel_rejects_replay_and_reordering() {
+        let session_key = [6u8; AES_KEY_SIZE];
+        let features = SessionFeatures {
+            hybrid_kem: false,
+            double_ratchet: true,
+            rekey: false,
+            invite_proof: false,
+        };
+        let (mut host_stream, mut client_stream) = tokio::io::duplex(8192);
+        let (host, client) = tokio::join!(
+            ChannelCipher::establish(&mut host_stream, &session_key, features, false),
+            ChannelCipher::establish(&mut client_stream, &session_key, features, true),
+        );
+        let (mut host, mut client) = (host.unwrap(), client.unwrap());
+
+        let packets: Vec<_> = (0..3u8).map(|i| client.encrypt(&[i])).collect();
+        let (plaintext, warning) = host.decrypt(&packets[0]).unwrap();
+        assert_eq!((plaintext.as_slice(), warning), ([0].as_slice(), None));
+        assert_eq!(
+            host.decrypt(&packets[0]),
+            Err(SecurityEvent::Replay { counter: 0 })
+        );
+        let (plaintext, warning) = host.decrypt(&packets[2]).unwrap();
+        assert_eq!(plaintext.as_slice(), [2]);
+        assert_eq!(
+            warning,
+            Some(SecurityEvent::MissingPackets {
+                expected: 1,
+                received: 2
+            })
+        );
+        assert_eq!(
+            host.decrypt(&packets[1]),
+            Err(SecurityEvent::OutOfOrder {
+                counter: 1,
+                expected: 3
+            })
+        );
+        assert_eq!(
+            host.decrypt(b"not a packet at all"),
+            Err(SecurityEvent::AuthenticationFailed)
+        );
+
+        // The channel keeps working after a rejected packet
+        assert_eq!(client.decrypt(&host.encrypt(b"ok")).unwrap().0.as_slice(), b"ok");
+    }
+
+    #[tokio::test]
+    async fn test_invite_proof_is_checked_against_the_session() {
+        let session_key = [8u8; AES_KEY_SIZE];
+        let features = SessionFeatures {
+            invite_proof: true,
+            ..Default::default()
+        };
+        let (mut host_stream, mut client_stream) = tokio::io::duplex(8192);
+        let (host, client) = tokio::join!(
+            ChannelCipher::establish(&mut host_stream, &session_key, features, false),
+            ChannelCipher::establish(&mut client_stream, &session_key, features, true),
+        );
+        let (mut host, mut client) = (host.unwrap(), client.unwrap());
+
+        let identity = crate::identity::Identity::new("Alice".to_string()).unwrap();
+        let (_, issued) = identity
+            .generate_invite_link(&crate::identity::InviteOptions::default())
+            .unwrap();
+
+        send_invite_proof(&mut client_stream, &mut client, &session_key, Some(&issued.token))
+            .await
+            .unwrap();
+        match recv_invite_proof(&mut host_stream, &mut host, &session_key).await.unwrap() {
+            Some(SessionEvent::InviteProof { proof, binding }) => {
+                assert!(issued.matches_proof(&binding, &proof));
+                // Someone relaying it into their own session with the host gets nowhere
+                let other = invite_binding(&[9u8; AES_KEY_SIZE]);
+                assert!(!issued.matches_proof(other.as_ref(), &proof));
+            }
+            other => panic!("expected InviteProof, got {:?}", other),
+        }
+
+        // Without an invite the client still sends the (empty) message
+        send_invite_proof(&mut client_stream, &mut client, &session_key, None)
+            .await
+            .unwrap();
+        assert!(recv_invite_proof(&mut host_stream, &mut host, &session_key).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_rekey_keeps_every_message() {
+        let session_key = [7u8; AES_KEY_SIZE];
+        let features = SessionFeatures {
+            hybrid_kem: false,
+            double_ratchet: true,
+            rekey: true,
+            invite_proof: false,
+        };
+        let (mut host_stream, mut client_stream) = tokio::io::duplex(1 << 16);
+        let (host, client) = tokio::join!(
+            ChannelCipher::establish(&mut host_stream, &session_key, features, false),
+            ChannelCipher::establish(&mut client_stream, &session_key, features, true),
+        );
+        let policy = RekeyPolicy {
+            after_messages: 3,
+            after_bytes: 0,
+            after_minutes: 0,
+        };
+
+        let mut sides = Vec::new();
+        for (stream, cipher) in [(host_stream, host.unwrap()), (client_stream, client.unwrap())] {
+            let (to_app_tx, to_app_rx) = mpsc::unbounded_channel();
+            let (from_app_tx, from_app_rx) = mpsc::unbounded_channel();
+            let rekeyer = Some(Rekeyer::new(&session_key, policy));
+            tokio::spawn(run_message_loop(stream, cipher, rekeyer, to_app_tx, from_app_rx));
+            sides.push((from_app_tx, to_app_rx));
+        }
+
+        // Both sides send at once, so rekeys overlap with messages in flight
+        let count = 40;
+        for i in 0..count {
+            for (from_app_tx, _) in &sides {
+                from_app_tx
+                    .send(ProtocolMessage::TypingStart)
+                    .unwrap();
+                from_app_tx
+                    .send(ProtocolMessage::Text {
+                        id: uuid::Uuid::new_v4(),
+                        text: i.to_string(),
+                        reply_to: None,
+                        timestamp: 0,
+                    })
+                    .unwrap();
+            }
+        }
+
+        for (_, to_app_rx) in &mut sides {
+            let mut texts = Vec::new();
+            while texts.len() < count {
+                match to_app_rx.recv().await.unwrap() {
+                    SessionEvent::MessageReceived(ProtocolMessage::Text { text, .. }) => texts.push(text),
+                    SessionEvent::MessageReceived(ProtocolMessage::TypingStart) => {}
+                    other => panic!("unexpected event {:?}", other),
+                }
+            }
+            let expected: Vec<_> = (0..count).map(|i| i.to_string()).collect();
+            assert_eq!(texts, expected);
+        }
+    }
+
+    fn test_identity() -> HandshakeIdentity {
+        let private_key = PrivateKey::generate(KeyAlgorithm::Ed25519).unwrap();
+        HandshakeIdentity {
+            public_key_pem: private_key.public_key().to_pem().unwrap(),
+            private_key,
+        }
+    }
+
+    type Session = (tokio::task::JoinHandle<Result<()>>, mpsc::UnboundedReceiver<SessionEvent>);
+
+    /// Host a session on `port` once it is listening
+    async fn spawn_host(port: u16, identity: HandshakeIdentity) -> Session {
+        let (to_app_tx, mut to_app_rx) = mpsc::unbounded_channel();
+        let (_from_app_tx, from_app_rx) = mpsc::unbounded_channel();
+        let (_confirm_tx, confirm_rx) = mpsc::unbounded_channel();
+        let options = SessionOptions::from_config(&Config::default(), identity);
+        let handle = tokio::spawn(run_host_session(
+            port,
+            to_app_tx,
+            from_app_rx,
+            confirm_rx,
+            uuid::Uuid::new_v4(),
+            options,
+        ));
+        assert!(matches!(to_app_rx.recv().await, Some(SessionEvent::Listening { .. })));
+        (handle, to_app_rx)
+    }
+
+    /// Connect to `port`, accepting the host's fingerprint
+    fn spawn_client(port: u16, identity: HandshakeIdentity) -> Session {
+        spawn_client_with(port, SessionOptions::from_config(&Config::default(), identity))
+    }
+
+    fn spawn_client_with(port: u16, options: SessionOptions) -> Session {
+        let (to_app_tx, to_app_rx) = mpsc::unbounded_channel();
+        let (_from_app_tx, from_app_rx) = mpsc::unbounded_channel();
+        let (confirm_tx, confirm_rx) = mpsc::unbounded_channel();
+        confirm_tx.send(true).unwrap();
+        let handle = tokio::spawn(async move {
+            run_client_session(
+                "127.0.0.1",
+                port,
+                to_app_tx,
+                from_app_rx,
+                confirm_rx,
+                uuid::Uuid::new_v4(),
+                options,
+            )
+            .await
+        });
+        (handle, to_app_rx)
+    }
+
+    /// Events up to and including `Ready`
+    async fn events_until_ready(rx: &mut mpsc::UnboundedReceiver<SessionEvent>) -> Vec<SessionEvent> {
+        let mut events = Vec::new();
+        loop {
+            let event = tokio::time::timeout(std::time::Duration::from_secs(10), rx.recv())
+                .await
+                .expect("timed out waiting for the handshake")
+                .expect("session ended before it was ready");
+            let ready = matches!(event, SessionEvent::Ready { .. });
+            events.push(event);
+            if ready {
+                return events;
+            }
+        }
+    }
+
+    /// Relay packets between a client on `listen_port` and the host on
+    /// `host_port`, passing each one through `tamper`
+    async fn tampering_proxy(listen_port: u16, host_port: u16, tamper: fn(Vec<u8>) -> Vec<u8>) {
+        let listener = TcpListener::bind(("127.0.0.1", listen_port)).await.unwrap();
+        let (client, _) = listener.accept().await.unwrap();
+        let host = TcpStream::connect(("127.0.0.1", host_port)).await.unwrap();
+        let (mut client_rx, mut client_tx) = client.into_split();
+        let (mut host_rx, mut host_tx) = host.into_split();
+        // Whichever side gives up first closes both connections
+        tokio::select! {
+            _ = async {
+                while let Ok(packet) = recv_packet(&mut client_rx).await {
+                    if send_packet(&mut host_tx, &tamper(packet)).await.is_err() {
+                        break;
+                    }
+                }
+            } => {}
+            _ = async {
+                while let Ok(packet) = recv_packet(&mut host_rx).await {
+                    if send_packet(&mut client_tx, &tamper(packet)).await.is_err() {
+                        break;
+                    }
+                }
+            } => {}
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handshake_is_signed_with_identity_keys() {
+        let (host_identity, client_identity) = (test_identity(), test_identity());
+        let port = 47611;
+        let (host, mut host_rx) = spawn_host(port, host_identity.clone()).await;
+        let (client, mut client_rx) = spawn_client(port, client_identity.clone());
+
+        // Each side sees the other's identity fingerprint, after the signatures
+        let host_events = events_until_ready(&mut host_rx).await;
+        assert!(host_events.iter().any(|event| matches!(
+            event,
+            SessionEvent::NewConnection { fingerprint, local_fingerprint, .. }
+                if *fingerprint == client_identity.fingerprint()
+                    && *local_fingerprint == host_identity.fingerprint()
+        )));
+        let client_events = events_until_ready(&mut client_rx).await;
+        assert!(client_events.iter().any(|event| matches!(
+            event,
+            SessionEvent::ShowFingerprintVerification { fingerprint, local_fingerprint, .. }
+                if *fingerprint == host_identity.fingerprint()
+                    && *local_fingerprint == client_identity.fingerprint()
+        )));
+
+        host.abort();
+        client.abort();
+    }
+
+    #[tokio::test]
+    async fn test_safety_number_is_stable_across_sessions() {
+        let (host_identity, client_identity) = (test_identity(), test_identity());
+        let mut numbers = Vec::new();
+        for port in [47641, 47642] {
+            let (host, mut host_rx) = spawn_host(port, host_identity.clone()).await;
+            let (client, mut client_rx) = spawn_client(port, client_identity.clone());
+            for event in events_until_ready(&mut host_rx).await {
+                if let SessionEvent::NewConnection { fingerprint, local_fingerprint, .. } = event {
+                    numbers.push(SafetyNumber::new(&local_fingerprint, &fingerprint));
+                }
+            }
+            for event in events_until_ready(&mut client_rx).await {
+                if let SessionEvent::ShowFingerprintVerification { fingerprint, local_fingerprint, .. } = event {
+                    numbers.push(SafetyNumber::new(&local_fingerprint, &fingerprint));
+                }
+            }
+            host.abort();
+            client.abort();
+        }
+
+        // Both sides, both sessions: the number of the two identities
+        let expected = SafetyNumber::new(&host_identity.fingerprint(), &client_identity.fingerprint());
+        assert_eq!(numbers.len(), 4);
+        assert!(numbers.iter().all(|number| *number == expected));
+    }
+
+    #[tokio::test]
+    async fn test_client_refuses_host_not_named_in_invite() {
+        let (host_identity, invited_by) = (test_identity(), test_identity());
+        let port = 47651;
+        let (host, _host_rx) = spawn_host(port, host_identity).await;
+        let options = SessionOptions {
+            expected_fingerprint: Some(invited_by.fingerprint()),
+            ..SessionOptions::from_config(&Config::default(), test_identity())
+        };
+        let (client, mut client_rx) = spawn_client_with(port, options);
+
+        let timeout = std::time::Duration::from_secs(10);
+        assert!(tokio::time::timeout(timeout, client).await.unwrap().unwrap().is_err());
+        let mut rejected = false;
+        while let Ok(event) = client_rx.try_recv() {
+            assert!(
+                !matches!(
+                    event,
+                    SessionEvent::ShowFingerprintVerification { .. } | SessionEvent::Ready { .. }
+                ),
+                "unexpected {:?}",
+                event
+            );
+            rejected |= matches!(event, SessionEvent::Error(ref e) if e.starts_with("Handshake rejected"));
+        }
+        assert!(rejected);
+        host.abort();
+    }
+
+    #[tokio::test]
+    async fn test_stripped_capability_fails_the_handshake() {
+        let (host_port, proxy_port) = (47631, 47632);
+        let (host, mut host_rx) = spawn_host(host_port, test_identity()).await;
+        let proxy = tokio::spawn(tampering_proxy(proxy_port, host_port, |packet| {
+            // Downgrade: hide the post-quantum offer from both sides so they
+            // settle for plain X25519
+            match ProtocolMessage::from_plain_bytes(&packet) {
+                Some(ProtocolMessage::Capabilities { features }) => ProtocolMessage::Capabilities {
+                    features: features
+                        .into_iter()
+                        .filter(|feature| feature != CAPABILITY_HYBRID_KEM)
+                        .collect(),
+                }
+                .to_plain_bytes(),
+                _ => packet,
+            }
+        }));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let (client, mut client_rx) = spawn_client(proxy_port, test_identity());
+
+        let timeout = std::time::Duration::from_secs(10);
+        assert!(tokio::time::timeout(timeout, host).await.unwrap().unwrap().is_err());
+        assert!(tokio::time::timeout(timeout, client).await.unwrap().unwrap().is_err());
+        proxy.abort();
+
+        for rx in [&mut host_rx, &mut client_rx] {
+            while let Ok(event) = rx.try_recv() {
+                assert!(!matches!(event, SessionEvent::Ready { .. }), "unexpected {:?}", event);
+            }
+        }
+    }
+
+    #[test]
+    fn test_session_key_depends_on_transcript() {
+        let key = [9u8; AES_KEY_SIZE];
+        let mut full = Transcript::new();
+        full.absorb(&ProtocolMessage::Capabilities {
+            features: vec![CAPABILITY_HYBRID_KEM.to_string()],
+        }
+        .to_plain_bytes());
+        let mut downgraded = Transcript::new();
+        downgraded.absorb(&ProtocolMessage::Capabilities { features: Vec::new() }.to_plain_bytes());
+        assert_ne!(*bind_to_transcript(&key, &full), *bind_to_transcript(&key, &downgraded));
+        assert_eq!(*bind_to_transcript(&key, &full), *bind_to_transcript(&key, &full));
+    }
+
+    #[tokio::test]
+    async fn test_substituted_ephemeral_fails_the_handshake() {
+        let (host_port, proxy_port) = (47621, 47622);
+        let (host, mut host_rx) = spawn_host(host_port, test_identity()).await;
+        let proxy = tokio::spawn(tampering_proxy(proxy_port, host_port, |packet| {
+            // Someone in the middle swaps in their own X25519 key each way,
+            // relaying everything else untouched
+            match ProtocolMessage::from_plain_bytes(&packet) {
+                Some(ProtocolMessage::EphemeralKey { .. }) => ProtocolMessage::EphemeralKey {
+                    public_key: generate_ephemeral_keypair().1.as_bytes().to_vec(),
+                }
+                .to_plain_bytes(),
+                _ => packet,
+            }
+        }));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let (client, mut client_rx) = spawn_client(proxy_port, test_identity());
+
+        let timeout = std::time::Duration::from_secs(10);
+        assert!(tokio::time::timeout(timeout, host).await.unwrap().unwrap().is_err());
+        assert!(tokio::time::timeout(timeout, client).await.unwrap().unwrap().is_err());
+        proxy.abort();
+
+        // Neither side got as far as showing the peer or opening the session
+        for rx in [&mut host_rx, &mut client_rx] {
+            while let Ok(event) = rx.try_recv() {
+                assert!(
+                    !matches!(
+                        event,
+                        SessionEvent::NewConnection { .. }
+                            | SessionEvent::ShowFingerprintVerification { .. }
+                            | SessionEvent::Ready { .. }
+                    ),
+                    "unexpected {:?}",
+                    event
+                );
+            }
+        }
+    }
 }
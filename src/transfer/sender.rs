@@ -102,7 +102,7 @@ mod tests {
 
         // Send file
         let path = temp_file.path().to_path_buf();
-        let send_cipher = cipher.clone();
+        let send_cipher = AesCipher::new(&aes_key);
         tokio::spawn(async move {
             send_file(&path, &mut client, &send_cipher, |_, _| {})
                 .await
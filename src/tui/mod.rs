@@ -0,0 +1,157 @@
+//! Full-screen terminal frontend.
+//!
+//! An alternative to `gui::App` for machines without a display: chat list,
+//! message pane, composer, fingerprint prompts, contacts and file transfers,
+//! all backed by the same `ChatManager` and history file as the egui app.
+
+pub mod state;
+pub mod ui;
+
+use anyhow::Result;
+use crossterm::event::{Event, KeyEventKind};
+use ratatui::DefaultTerminal;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+use crate::app::ChatManager;
+use crate::types::{Config, ToastLevel};
+pub use state::{Action, TuiApp};
+
+/// How often session events are polled and the screen redrawn
+const TICK_INTERVAL: Duration = Duration::from_millis(100);
+/// How often history is written to disk
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Run the terminal UI until the user quits
+pub async fn run() -> Result<()> {
+    let data_dir = crate::app::default_data_dir().unwrap_or_else(|| PathBuf::from("."));
+    let history_path = data_dir.join("history.json");
+    // Ask for the key password before the terminal switches to the UI
+    let identity = crate::identity::commands::load_for_session(&data_dir)?;
+
+    let mut manager = ChatManager::new(Config::default());
+    manager.sync_identity(&identity);
+    if history_path.exists()
+        && let Err(e) = manager.load_history(&history_path)
+    {
+        tracing::warn!("Failed to load history: {}", e);
+    }
+    if manager.config.auto_host_on_startup {
+        let port = manager.config.listen_port;
+        if let Err(e) = manager.start_host(port).await {
+            manager.add_toast(
+                ToastLevel::Error,
+                format!("Failed to auto-start host: {}", e),
+            );
+        }
+    }
+
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &mut manager, &history_path).await;
+    ratatui::restore();
+
+    if let Err(e) = manager.save_history(&history_path) {
+        tracing::warn!("Failed to save history: {}", e);
+    }
+    result
+}
+
+/// Forward terminal events from a blocking reader thread
+fn spawn_input_reader() -> mpsc::UnboundedReceiver<Event> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        while let Ok(event) = crossterm::event::read() {
+            if tx.send(event).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+async fn event_loop(
+    terminal: &mut DefaultTerminal,
+    manager: &mut ChatManager,
+    history_path: &Path,
+) -> Result<()> {
+    let mut app = TuiApp::new();
+    let mut input = spawn_input_reader();
+    let mut ticker = tokio::time::interval(TICK_INTERVAL);
+    let mut last_save = Instant::now();
+    let mut last_expiry_sweep = Instant::now();
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                manager.poll_session_events();
+                manager.cleanup_expired_toasts();
+                app.on_tick(manager);
+
+                // Purge expired disappearing messages about once a second
+                let mut purged = 0;
+                if last_expiry_sweep.elapsed() >= Duration::from_secs(1) {
+                    last_expiry_sweep = Instant::now();
+                    purged = manager.purge_expired_messages(chrono::Utc::now());
+                }
+                if purged > 0 || last_save.elapsed() >= SAVE_INTERVAL {
+                    if let Err(e) = manager.save_history(history_path) {
+                        tracing::warn!("Failed to auto-save history: {}", e);
+                    }
+                    last_save = Instant::now();
+                }
+            }
+
+            event = input.recv() => {
+                let Some(event) = event else {
+                    return Ok(());
+                };
+                if let Event::Key(key) = event
+                    && key.kind == KeyEventKind::Press
+                    && let Some(action) = app.handle_key(manager, key)
+                {
+                    if action == Action::Quit {
+                        return Ok(());
+                    }
+                    run_action(&mut app, manager, action).await;
+                }
+                app.on_tick(manager);
+            }
+        }
+
+        terminal.draw(|frame| ui::draw(frame, &app, manager))?;
+    }
+}
+
+/// Run an action that needs to await the manager; failures become toasts
+async fn run_action(app: &mut TuiApp, manager: &mut ChatManager, action: Action) {
+    let result = match action {
+        Action::Host(port) => manager.start_host(port).await.map(Some),
+        Action::Connect { host, port } => {
+            manager.connect_to_host(&host, port, None).await.map(Some)
+        }
+        Action::OpenContact(contact_id) => {
+            let has_address = manager
+                .get_contact(contact_id)
+                .and_then(|c| c.address.as_deref())
+                .is_some_and(|a| !a.trim().is_empty());
+            if has_address {
+                manager.connect_to_contact(contact_id, None).await.map(Some)
+            } else {
+                manager.add_toast(
+                    ToastLevel::Info,
+                    "This contact has no address. Press F6 to connect manually.".to_string(),
+                );
+                Ok(None)
+            }
+        }
+        Action::SendFile { chat_id, path } => manager.send_file(chat_id, path).await.map(|_| None),
+        Action::Quit => Ok(None),
+    };
+
+    match result {
+        Ok(Some(chat_id)) => app.open_chat(chat_id),
+        Ok(None) => {}
+        Err(e) => manager.add_toast(ToastLevel::Error, e.to_string()),
+    }
+}
@@ -0,0 +1,708 @@
+//! TUI state and key handling.
+//!
+//! Everything that changes the conversation goes through `ChatManager`, the same
+//! way the egui app does it. Calls that need to await (hosting, connecting,
+//! sending a file) are returned as an `Action` for the event loop to run.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::path::PathBuf;
+use uuid::Uuid;
+
+use crate::app::ChatManager;
+use crate::cli::{CliCommand, parse_command};
+use crate::core::safety::SafetyNumber;
+use crate::types::{Chat, Contact, MessageContent, ToastLevel, QUICK_REACTIONS};
+
+/// Top-level screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Screen {
+    Chats,
+    Contacts,
+    Transfers,
+}
+
+/// Pane receiving keys on the chats screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Focus {
+    ChatList,
+    Messages,
+    Composer,
+}
+
+/// Modal shown on top of the current screen
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Prompt {
+    Fingerprint {
+        chat_id: Uuid,
+        fingerprint: String,
+        peer_name: String,
+        safety_number: Option<SafetyNumber>,
+    },
+    Host {
+        input: String,
+    },
+    Connect {
+        input: String,
+    },
+    AddContact {
+        input: String,
+    },
+    Help,
+}
+
+/// Asynchronous work requested by a key press
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Host(u16),
+    Connect { host: String, port: u16 },
+    OpenContact(Uuid),
+    SendFile { chat_id: Uuid, path: PathBuf },
+    Quit,
+}
+
+pub struct TuiApp {
+    pub screen: Screen,
+    pub focus: Focus,
+    pub selected_chat: Option<Uuid>,
+    pub selected_message: Option<Uuid>,
+    pub selected_contact: usize,
+    pub input: String,
+    pub reply_to: Option<Uuid>,
+    pub editing: Option<Uuid>,
+    pub prompt: Option<Prompt>,
+    /// Latest toast, shown in the status bar
+    pub status: Option<(ToastLevel, String)>,
+}
+
+impl Default for TuiApp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Chats in sidebar order (newest first, like the egui sidebar)
+pub fn sorted_chats(manager: &ChatManager) -> Vec<&Chat> {
+    let mut chats: Vec<_> = manager.chats.values().collect();
+    chats.sort_by_key(|c| std::cmp::Reverse(c.created_at));
+    chats
+}
+
+/// Contacts in alphabetical order
+pub fn sorted_contacts(manager: &ChatManager) -> Vec<&Contact> {
+    let mut contacts: Vec<_> = manager.contacts.values().collect();
+    contacts.sort_by(|a, b| a.name.cmp(&b.name));
+    contacts
+}
+
+/// Parse `host:port` or `host` (using `default_port`)
+fn parse_address(input: &str, default_port: u16) -> Option<(String, u16)> {
+    let input = input.trim();
+    if input.is_empty() {
+        return None;
+    }
+    match input.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() => Some((host.to_string(), port.parse().ok()?)),
+        Some(_) => None,
+        None => Some((input.to_string(), default_port)),
+    }
+}
+
+impl TuiApp {
+    pub fn new() -> Self {
+        Self {
+            screen: Screen::Chats,
+            focus: Focus::ChatList,
+            selected_chat: None,
+            selected_message: None,
+            selected_contact: 0,
+            input: String::new(),
+            reply_to: None,
+            editing: None,
+            prompt: None,
+            status: None,
+        }
+    }
+
+    /// Pick up manager state after session events have been polled
+    pub fn on_tick(&mut self, manager: &mut ChatManager) {
+        if let Some((fingerprint, peer_name, chat_id)) =
+            manager.fingerprint_verification_request.take()
+        {
+            self.prompt = Some(Prompt::Fingerprint {
+                chat_id,
+                fingerprint,
+                peer_name,
+                safety_number: manager.safety_numbers.get(&chat_id).cloned(),
+            });
+        }
+        // The peer proved our invite while we were asking
+        if let Some(Prompt::Fingerprint { chat_id, .. }) = &self.prompt
+            && manager.joined_with_invite(*chat_id)
+        {
+            self.prompt = None;
+        }
+
+        self.status = manager
+            .toasts
+            .last()
+            .map(|toast| (toast.level, toast.message.clone()));
+
+        // Keep the selection valid when chats come and go
+        if self
+            .selected_chat
+            .is_none_or(|id| !manager.chats.contains_key(&id))
+        {
+            self.select_chat(sorted_chats(manager).first().map(|c| c.id));
+        }
+        let contact_count = manager.contacts.len();
+        if self.selected_contact >= contact_count {
+            self.selected_contact = contact_count.saturating_sub(1);
+        }
+    }
+
+    fn select_chat(&mut self, chat_id: Option<Uuid>) {
+        if self.selected_chat != chat_id {
+            self.selected_chat = chat_id;
+            self.selected_message = None;
+            self.reply_to = None;
+            self.editing = None;
+        }
+    }
+
+    /// Select a chat and move to the composer (after hosting or connecting)
+    pub fn open_chat(&mut self, chat_id: Uuid) {
+        self.screen = Screen::Chats;
+        self.select_chat(Some(chat_id));
+        self.focus = Focus::Composer;
+    }
+
+    /// Handle one key press
+    pub fn handle_key(&mut self, manager: &mut ChatManager, key: KeyEvent) -> Option<Action> {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        if ctrl && matches!(key.code, KeyCode::Char('c') | KeyCode::Char('q')) {
+            return Some(Action::Quit);
+        }
+
+        if self.prompt.is_some() {
+            return self.handle_prompt_key(manager, key);
+        }
+
+        match key.code {
+            KeyCode::F(1) => {
+                self.prompt = Some(Prompt::Help);
+                return None;
+            }
+            KeyCode::F(2) => {
+                self.screen = Screen::Chats;
+                return None;
+            }
+            KeyCode::F(3) => {
+                self.screen = Screen::Contacts;
+                return None;
+            }
+            KeyCode::F(4) => {
+                self.screen = Screen::Transfers;
+                return None;
+            }
+            KeyCode::F(5) => {
+                self.prompt = Some(Prompt::Host {
+                    input: manager.config.listen_port.to_string(),
+                });
+                return None;
+            }
+            KeyCode::F(6) => {
+                self.prompt = Some(Prompt::Connect {
+                    input: String::new(),
+                });
+                return None;
+            }
+            _ => {}
+        }
+
+        match self.screen {
+            Screen::Chats => self.handle_chats_key(manager, key),
+            Screen::Contacts => self.handle_contacts_key(manager, key),
+            Screen::Transfers => None,
+        }
+    }
+
+    fn handle_chats_key(&mut self, manager: &mut ChatManager, key: KeyEvent) -> Option<Action> {
+        match key.code {
+            KeyCode::Tab => {
+                self.focus = match self.focus {
+                    Focus::ChatList => Focus::Messages,
+                    Focus::Messages => Focus::Composer,
+                    Focus::Composer => Focus::ChatList,
+                };
+                return None;
+            }
+            KeyCode::BackTab => {
+                self.focus = match self.focus {
+                    Focus::ChatList => Focus::Composer,
+                    Focus::Messages => Focus::ChatList,
+                    Focus::Composer => Focus::Messages,
+                };
+                return None;
+            }
+            _ => {}
+        }
+
+        match self.focus {
+            Focus::ChatList => {
+                let ids: Vec<Uuid> = sorted_chats(manager).iter().map(|c| c.id).collect();
+                let index = self
+                    .selected_chat
+                    .and_then(|id| ids.iter().position(|&c| c == id));
+                match key.code {
+                    KeyCode::Up | KeyCode::Char('k') => {
+                        let index = index.map_or(0, |i| i.saturating_sub(1));
+                        self.select_chat(ids.get(index).copied());
+                    }
+                    KeyCode::Down | KeyCode::Char('j') => {
+                        let index = index.map_or(0, |i| (i + 1).min(ids.len().saturating_sub(1)));
+                        self.select_chat(ids.get(index).copied());
+                    }
+                    KeyCode::Enter => self.focus = Focus::Composer,
+                    _ => {}
+                }
+                None
+            }
+            Focus::Messages => {
+                self.handle_messages_key(manager, key);
+                None
+            }
+            Focus::Composer => self.handle_composer_key(manager, key),
+        }
+    }
+
+    fn handle_messages_key(&mut self, manager: &mut ChatManager, key: KeyEvent) {
+        let Some(chat_id) = self.selected_chat else {
+            return;
+        };
+        let Some(chat) = manager.get_chat(chat_id) else {
+            return;
+        };
+        let index = self
+            .selected_message
+            .and_then(|id| chat.messages.iter().position(|m| m.id == id));
+        let last = chat.messages.len().checked_sub(1);
+
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => {
+                let index = index.or(last).map(|i| i.saturating_sub(1));
+                self.selected_message = index.and_then(|i| chat.messages.get(i)).map(|m| m.id);
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                // Moving past the newest message clears the selection
+                self.selected_message = index.and_then(|i| chat.messages.get(i + 1)).map(|m| m.id);
+            }
+            KeyCode::Esc => self.selected_message = None,
+            _ => {}
+        }
+
+        let Some(message) = self
+            .selected_message
+            .and_then(|id| chat.messages.iter().find(|m| m.id == id))
+        else {
+            return;
+        };
+        let message_id = message.id;
+        let from_me = message.from_me;
+        let own_text = if message.from_me {
+            message.text().map(str::to_string)
+        } else {
+            None
+        };
+        let deleted = matches!(message.content, MessageContent::Deleted);
+
+        let result = match key.code {
+            KeyCode::Char('r') if !deleted => {
+                self.reply_to = Some(message_id);
+                self.editing = None;
+                self.focus = Focus::Composer;
+                Ok(())
+            }
+            KeyCode::Char('e') => {
+                if let Some(text) = own_text {
+                    self.editing = Some(message_id);
+                    self.reply_to = None;
+                    self.input = text;
+                    self.focus = Focus::Composer;
+                }
+                Ok(())
+            }
+            KeyCode::Char('d') if from_me && !deleted => {
+                manager.delete_message(chat_id, message_id)
+            }
+            KeyCode::Char(c) if !deleted && ('1'..='6').contains(&c) => {
+                let emoji = QUICK_REACTIONS[c as usize - '1' as usize];
+                manager.toggle_reaction(chat_id, message_id, emoji)
+            }
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            manager.add_toast(ToastLevel::Error, e.to_string());
+        }
+    }
+
+    fn handle_composer_key(&mut self, manager: &mut ChatManager, key: KeyEvent) -> Option<Action> {
+        match key.code {
+            KeyCode::Char(c) => self.input.push(c),
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Esc => {
+                if self.editing.take().is_some() {
+                    self.input.clear();
+                }
+                self.reply_to = None;
+            }
+            KeyCode::Enter => return self.submit(manager),
+            _ => {}
+        }
+        None
+    }
+
+    /// Send (or apply as an edit) the composer contents
+    fn submit(&mut self, manager: &mut ChatManager) -> Option<Action> {
+        let Some(chat_id) = self.selected_chat else {
+            manager.add_toast(ToastLevel::Warning, "No chat selected".to_string());
+            return None;
+        };
+
+        if let Some(message_id) = self.editing {
+            let text = self.input.trim().to_string();
+            if text.is_empty() {
+                return None;
+            }
+            match manager.edit_message(chat_id, message_id, text) {
+                Ok(()) => {
+                    self.editing = None;
+                    self.input.clear();
+                }
+                Err(e) => manager.add_toast(ToastLevel::Error, e.to_string()),
+            }
+            return None;
+        }
+
+        let command = parse_command(&self.input);
+        let action = match command {
+            Ok(CliCommand::Empty) => return None,
+            Ok(CliCommand::Message(text)) => {
+                match manager.send_message(chat_id, text, self.reply_to) {
+                    Ok(()) => self.reply_to = None,
+                    Err(e) => {
+                        manager.add_toast(ToastLevel::Error, format!("Failed to send: {}", e));
+                        return None;
+                    }
+                }
+                None
+            }
+            Ok(CliCommand::File(path)) => Some(Action::SendFile { chat_id, path }),
+            Ok(CliCommand::Help) => {
+                self.prompt = Some(Prompt::Help);
+                None
+            }
+            Ok(CliCommand::Quit) => Some(Action::Quit),
+            Err(usage) => {
+                manager.add_toast(ToastLevel::Warning, usage);
+                return None;
+            }
+        };
+        self.input.clear();
+        action
+    }
+
+    fn handle_contacts_key(&mut self, manager: &mut ChatManager, key: KeyEvent) -> Option<Action> {
+        let contact_id = sorted_contacts(manager)
+            .get(self.selected_contact)
+            .map(|c| c.id);
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.selected_contact = self.selected_contact.saturating_sub(1);
+            }
+            KeyCode::Down | KeyCode::Char('j')
+                if self.selected_contact + 1 < manager.contacts.len() =>
+            {
+                self.selected_contact += 1;
+            }
+            KeyCode::Char('a') => {
+                self.prompt = Some(Prompt::AddContact {
+                    input: String::new(),
+                });
+            }
+            KeyCode::Char('d') => {
+                if let Some(contact_id) = contact_id {
+                    manager.remove_contact(contact_id);
+                }
+            }
+            KeyCode::Enter => {
+                let contact_id = contact_id?;
+                // Reuse the contact's chat when one exists, like the egui contacts dialog
+                if let Some(chat_id) = manager.contact_to_chat.get(&contact_id).copied()
+                    && manager.chats.contains_key(&chat_id)
+                {
+                    self.open_chat(chat_id);
+                    return None;
+                }
+                return Some(Action::OpenContact(contact_id));
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn handle_prompt_key(&mut self, manager: &mut ChatManager, key: KeyEvent) -> Option<Action> {
+        match self.prompt.take()? {
+            Prompt::Help => None,
+            Prompt::Fingerprint {
+                chat_id,
+                fingerprint,
+                peer_name,
+                safety_number,
+            } => {
+                match key.code {
+                    KeyCode::Char('y') | KeyCode::Char('Y') => {
+                        let _ = manager.confirm_fingerprint(chat_id, true);
+                        if let Some(chat) = manager.chats.get_mut(&chat_id) {
+                            chat.peer_fingerprint = Some(fingerprint);
+                        }
+                        manager.mark_verified(chat_id);
+                        manager.add_toast(ToastLevel::Success, "Fingerprint accepted".to_string());
+                        self.open_chat(chat_id);
+                    }
+                    KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+                        let _ = manager.confirm_fingerprint(chat_id, false);
+                        manager.delete_chat(chat_id);
+                    }
+                    // Any other key leaves the question open
+                    _ => {
+                        self.prompt = Some(Prompt::Fingerprint {
+                            chat_id,
+                            fingerprint,
+                            peer_name,
+                            safety_number,
+                        });
+                    }
+                }
+                None
+            }
+            Prompt::Host { mut input } => {
+                if edit_line(&mut input, key) {
+                    return match input.trim().parse::<u16>() {
+                        Ok(port) => Some(Action::Host(port)),
+                        Err(_) => {
+                            manager.add_toast(ToastLevel::Warning, "Invalid port".to_string());
+                            self.prompt = Some(Prompt::Host { input });
+                            None
+                        }
+                    };
+                }
+                if key.code != KeyCode::Esc {
+                    self.prompt = Some(Prompt::Host { input });
+                }
+                None
+            }
+            Prompt::Connect { mut input } => {
+                if edit_line(&mut input, key) {
+                    return match parse_address(&input, crate::PORT_DEFAULT) {
+                        Some((host, port)) => Some(Action::Connect { host, port }),
+                        None => {
+                            manager.add_toast(
+                                ToastLevel::Warning,
+                                "Expected HOST or HOST:PORT".to_string(),
+                            );
+                            self.prompt = Some(Prompt::Connect { input });
+                            None
+                        }
+                    };
+                }
+                if key.code != KeyCode::Esc {
+                    self.prompt = Some(Prompt::Connect { input });
+                }
+                None
+            }
+            Prompt::AddContact { mut input } => {
+                if edit_line(&mut input, key) {
+                    match manager.parse_invite_link(input.trim()) {
+                        Ok(contact) => {
+                            let name = contact.name.clone();
+                            let id = manager.add_contact(
+                                contact.name,
+                                contact.address,
+                                contact.fingerprint,
+                                contact.public_key,
+                            );
+                            manager.set_invite_token(id, contact.invite_token);
+                            manager
+                                .add_toast(ToastLevel::Success, format!("Contact added: {}", name));
+                        }
+                        Err(e) => {
+                            manager.add_toast(
+                                ToastLevel::Error,
+                                format!("Invalid invite link: {}", e),
+                            );
+                            self.prompt = Some(Prompt::AddContact { input });
+                        }
+                    }
+                    return None;
+                }
+                if key.code != KeyCode::Esc {
+                    self.prompt = Some(Prompt::AddContact { input });
+                }
+                None
+            }
+        }
+    }
+}
+
+/// Apply a key to a one-line text field; returns true when Enter was pressed
+fn edit_line(input: &mut String, key: KeyEvent) -> bool {
+    match key.code {
+        KeyCode::Enter => return true,
+        KeyCode::Char(c) => input.push(c),
+        KeyCode::Backspace => {
+            input.pop();
+        }
+        _ => {}
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Config, Message};
+
+    fn press(app: &mut TuiApp, manager: &mut ChatManager, code: KeyCode) -> Option<Action> {
+        app.handle_key(manager, KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    fn type_text(app: &mut TuiApp, manager: &mut ChatManager, text: &str) {
+        for c in text.chars() {
+            press(app, manager, KeyCode::Char(c));
+        }
+    }
+
+    fn manager_with_chat() -> (ChatManager, Uuid) {
+        let mut manager = ChatManager::new(Config::default());
+        let chat = Chat {
+            id: Uuid::new_v4(),
+            title: "Alice".to_string(),
+            peer_fingerprint: None,
+            participants: Vec::new(),
+            messages: Vec::new(),
+            created_at: chrono::Utc::now(),
+            peer_typing: false,
+            typing_since: None,
+            disappearing_after_secs: None,
+        };
+        let chat_id = chat.id;
+        manager.chats.insert(chat_id, chat);
+        (manager, chat_id)
+    }
+
+    #[test]
+    fn test_parse_address() {
+        assert_eq!(
+            parse_address("10.0.0.2:4000", 12345),
+            Some(("10.0.0.2".to_string(), 4000))
+        );
+        assert_eq!(
+            parse_address(" example.org ", 12345),
+            Some(("example.org".to_string(), 12345))
+        );
+        assert_eq!(parse_address("host:nope", 12345), None);
+        assert_eq!(parse_address(":80", 12345), None);
+        assert_eq!(parse_address("", 12345), None);
+    }
+
+    #[test]
+    fn test_composer_commands() {
+        let (mut manager, chat_id) = manager_with_chat();
+        let mut app = TuiApp::new();
+        app.on_tick(&mut manager);
+        assert_eq!(app.selected_chat, Some(chat_id));
+
+        press(&mut app, &mut manager, KeyCode::Enter);
+        assert_eq!(app.focus, Focus::Composer);
+
+        type_text(&mut app, &mut manager, "/file /tmp/a.txt");
+        assert_eq!(
+            press(&mut app, &mut manager, KeyCode::Enter),
+            Some(Action::SendFile {
+                chat_id,
+                path: PathBuf::from("/tmp/a.txt")
+            })
+        );
+        assert!(app.input.is_empty());
+
+        type_text(&mut app, &mut manager, "/quit");
+        assert_eq!(
+            press(&mut app, &mut manager, KeyCode::Enter),
+            Some(Action::Quit)
+        );
+
+        // Letters typed in the composer never trigger shortcuts
+        type_text(&mut app, &mut manager, "d");
+        assert_eq!(app.input, "d");
+    }
+
+    #[test]
+    fn test_message_actions() {
+        let (mut manager, chat_id) = manager_with_chat();
+        let message = Message::new(
+            true,
+            MessageContent::Text {
+                text: "helo".to_string(),
+            },
+        );
+        let message_id = message.id;
+        manager.push_message(chat_id, message);
+
+        let mut app = TuiApp::new();
+        app.on_tick(&mut manager);
+        press(&mut app, &mut manager, KeyCode::Tab);
+        assert_eq!(app.focus, Focus::Messages);
+        press(&mut app, &mut manager, KeyCode::Up);
+        assert_eq!(app.selected_message, Some(message_id));
+
+        press(&mut app, &mut manager, KeyCode::Char('1'));
+        let chat = manager.get_chat(chat_id).unwrap();
+        assert!(chat.messages[0].has_my_reaction(QUICK_REACTIONS[0]));
+
+        press(&mut app, &mut manager, KeyCode::Char('e'));
+        assert_eq!(app.focus, Focus::Composer);
+        assert_eq!(app.input, "helo");
+        press(&mut app, &mut manager, KeyCode::Backspace);
+        type_text(&mut app, &mut manager, "lo");
+        press(&mut app, &mut manager, KeyCode::Enter);
+        let chat = manager.get_chat(chat_id).unwrap();
+        assert_eq!(chat.messages[0].text(), Some("hello"));
+        assert!(app.editing.is_none());
+
+        press(&mut app, &mut manager, KeyCode::BackTab);
+        press(&mut app, &mut manager, KeyCode::Char('d'));
+        let chat = manager.get_chat(chat_id).unwrap();
+        assert!(matches!(chat.messages[0].content, MessageContent::Deleted));
+    }
+
+    #[test]
+    fn test_fingerprint_prompt_reject_deletes_chat() {
+        let (mut manager, chat_id) = manager_with_chat();
+        manager.fingerprint_verification_request =
+            Some(("ab".repeat(32), "Alice".to_string(), chat_id));
+
+        let mut app = TuiApp::new();
+        app.on_tick(&mut manager);
+        assert!(matches!(app.prompt, Some(Prompt::Fingerprint { .. })));
+
+        // Unrelated keys keep the question open
+        press(&mut app, &mut manager, KeyCode::Char('x'));
+        assert!(app.prompt.is_some());
+
+        press(&mut app, &mut manager, KeyCode::Char('n'));
+        assert!(app.prompt.is_none());
+        assert!(manager.get_chat(chat_id).is_none());
+    }
+}
@@ -0,0 +1,601 @@
+//! TUI rendering.
+
+use ratatui::Frame;
+use ratatui::layout::{Constraint, Layout, Position, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{
+    Block, Borders, Clear, Gauge, List, ListItem, ListState, Paragraph, Tabs, Wrap,
+};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use super::state::{Focus, Prompt, Screen, TuiApp, sorted_chats, sorted_contacts};
+use crate::app::ChatManager;
+use crate::gui::chat_view::quote_snippet;
+use crate::types::{Chat, Contact, Message, MessageContent, Reactor, ToastLevel, TransferStatus};
+use crate::util::{format_duration_short, format_fingerprint_short, format_size};
+
+const SIDEBAR_WIDTH: u16 = 28;
+const DIM: Style = Style::new().fg(Color::DarkGray);
+
+/// Draw the whole screen
+pub fn draw(frame: &mut Frame, app: &TuiApp, manager: &ChatManager) {
+    let [header, body, status] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Min(0),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    draw_header(frame, app, header);
+    match app.screen {
+        Screen::Chats => draw_chats(frame, app, manager, body),
+        Screen::Contacts => draw_contacts(frame, app, manager, body),
+        Screen::Transfers => draw_transfers(frame, manager, body),
+    }
+    draw_status(frame, app, status);
+
+    if let Some(prompt) = &app.prompt {
+        draw_prompt(frame, prompt);
+    }
+}
+
+fn draw_header(frame: &mut Frame, app: &TuiApp, area: Rect) {
+    let selected = match app.screen {
+        Screen::Chats => 0,
+        Screen::Contacts => 1,
+        Screen::Transfers => 2,
+    };
+    let [tabs_area, keys_area] =
+        Layout::horizontal([Constraint::Min(0), Constraint::Length(44)]).areas(area);
+    let tabs = Tabs::new(["Chats [F2]", "Contacts [F3]", "Transfers [F4]"])
+        .select(selected)
+        .highlight_style(Style::new().bold().reversed());
+    frame.render_widget(tabs, tabs_area);
+    frame.render_widget(
+        Paragraph::new("F1 help · F5 host · F6 connect · ^Q quit")
+            .style(DIM)
+            .right_aligned(),
+        keys_area,
+    );
+}
+
+fn focused_block(title: impl Into<Line<'static>>, focused: bool) -> Block<'static> {
+    let block = Block::default().borders(Borders::ALL).title(title);
+    if focused {
+        block.border_style(Style::new().fg(Color::Cyan))
+    } else {
+        block
+    }
+}
+
+fn draw_chats(frame: &mut Frame, app: &TuiApp, manager: &ChatManager, area: Rect) {
+    let [sidebar, main] =
+        Layout::horizontal([Constraint::Length(SIDEBAR_WIDTH), Constraint::Min(0)]).areas(area);
+
+    // Chat list
+    let chats = sorted_chats(manager);
+    let items: Vec<ListItem> = chats
+        .iter()
+        .map(|chat| {
+            let marker = if manager.is_connected(chat.id) {
+                Span::styled("● ", Style::new().fg(Color::Green))
+            } else {
+                Span::styled("○ ", DIM)
+            };
+            ListItem::new(Line::from(vec![marker, Span::raw(chat.title.clone())]))
+        })
+        .collect();
+    let mut state = ListState::default().with_selected(
+        app.selected_chat
+            .and_then(|id| chats.iter().position(|c| c.id == id)),
+    );
+    let list = List::new(items)
+        .block(focused_block(" Chats ", app.focus == Focus::ChatList))
+        .highlight_style(Style::new().reversed());
+    frame.render_stateful_widget(list, sidebar, &mut state);
+
+    let [messages_area, composer_area] =
+        Layout::vertical([Constraint::Min(0), Constraint::Length(3)]).areas(main);
+
+    let chat = app.selected_chat.and_then(|id| manager.get_chat(id));
+    draw_messages(frame, app, chat, &manager.contacts, messages_area);
+    draw_composer(frame, app, chat, composer_area);
+}
+
+fn chat_title(chat: &Chat) -> Line<'static> {
+    let mut spans = vec![Span::raw(format!(" {} ", chat.title))];
+    if let Some(fp) = &chat.peer_fingerprint {
+        spans.push(Span::styled(
+            format!("🔒 {} ", format_fingerprint_short(fp)),
+            DIM,
+        ));
+    }
+    if let Some(secs) = chat.disappearing_after_secs {
+        spans.push(Span::styled(
+            format!("⏱ {} ", format_duration_short(secs)),
+            DIM,
+        ));
+    }
+    if chat.peer_typing {
+        spans.push(Span::styled("typing… ", Style::new().italic()));
+    }
+    Line::from(spans)
+}
+
+fn draw_messages(
+    frame: &mut Frame,
+    app: &TuiApp,
+    chat: Option<&Chat>,
+    contacts: &HashMap<Uuid, Contact>,
+    area: Rect,
+) {
+    let focused = app.focus == Focus::Messages;
+    let Some(chat) = chat else {
+        let hint = Paragraph::new("No chat selected. Press F5 to host or F6 to connect.")
+            .style(DIM)
+            .block(focused_block(" Messages ", focused));
+        frame.render_widget(hint, area);
+        return;
+    };
+
+    let block = focused_block(chat_title(chat), focused);
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let width = inner.width.saturating_sub(2).max(1) as usize;
+    let mut lines: Vec<Line> = Vec::new();
+    let mut selected_start = None;
+    for message in &chat.messages {
+        let selected = app.selected_message == Some(message.id);
+        if selected {
+            selected_start = Some(lines.len());
+        }
+        let quoted = message
+            .reply_to
+            .map(|id| chat.messages.iter().find(|m| m.id == id));
+        for line in message_lines(message, &chat.title, quoted, contacts, width) {
+            lines.push(if selected {
+                line.patch_style(Style::new().reversed())
+            } else {
+                line
+            });
+        }
+    }
+
+    // Follow the newest message unless a selected one would scroll out of view
+    let height = inner.height as usize;
+    let mut offset = lines.len().saturating_sub(height);
+    if let Some(start) = selected_start {
+        offset = offset.min(start);
+    }
+    let paragraph = Paragraph::new(lines).scroll((offset as u16, 0));
+    frame.render_widget(paragraph, inner);
+}
+
+/// Lines for one message: optional quote, wrapped body, reactions
+fn message_lines(
+    message: &Message,
+    peer_name: &str,
+    quoted: Option<Option<&Message>>,
+    contacts: &HashMap<Uuid, Contact>,
+    width: usize,
+) -> Vec<Line<'static>> {
+    let mut lines = Vec::new();
+    let time = message
+        .timestamp
+        .with_timezone(&chrono::Local)
+        .format("%H:%M")
+        .to_string();
+    let (sender, sender_style) = if message.from_me {
+        ("You".to_string(), Style::new().fg(Color::Cyan).bold())
+    } else {
+        (
+            peer_name.to_string(),
+            Style::new().fg(Color::Magenta).bold(),
+        )
+    };
+
+    if let Some(quoted) = quoted {
+        let snippet = match quoted {
+            Some(original) => quote_snippet(original),
+            None => "Original message not available".to_string(),
+        };
+        lines.push(Line::from(Span::styled(format!("  ┃ ↳ {}", snippet), DIM)));
+    }
+
+    let (body, body_style) = match &message.content {
+        MessageContent::Deleted => (
+            "🗑 This message was deleted".to_string(),
+            DIM.add_modifier(Modifier::ITALIC),
+        ),
+        MessageContent::File {
+            filename,
+            size,
+            path,
+        } => {
+            let mut body = format!("📄 {} ({})", filename, format_size(*size));
+            if !message.from_me
+                && let Some(path) = path
+            {
+                body.push_str(&format!(" → {}", path.display()));
+            }
+            (body, Style::new())
+        }
+        _ => {
+            let mut body = message.text().unwrap_or_default().to_string();
+            if !message.edit_history.is_empty() {
+                body.push_str(" (edited)");
+            }
+            (body, Style::new())
+        }
+    };
+
+    let prefix = format!("[{}] {}: ", time, sender);
+    let indent = " ".repeat(prefix.chars().count().min(width / 2));
+    let wrapped = wrap_text(&body, width.saturating_sub(indent.len()).max(1));
+    for (i, chunk) in wrapped.into_iter().enumerate() {
+        if i == 0 {
+            lines.push(Line::from(vec![
+                Span::styled(format!("[{}] ", time), DIM),
+                Span::styled(sender.clone(), sender_style),
+                Span::raw(": "),
+                Span::styled(chunk, body_style),
+            ]));
+        } else {
+            lines.push(Line::from(vec![
+                Span::raw(indent.clone()),
+                Span::styled(chunk, body_style),
+            ]));
+        }
+    }
+
+    if !message.reactions.is_empty() {
+        let chips: Vec<String> = message
+            .reactions
+            .iter()
+            .map(|(emoji, reactors)| {
+                let names: Vec<String> = reactors
+                    .iter()
+                    .map(|r| match r {
+                        Reactor::Me => "you".to_string(),
+                        Reactor::Peer => peer_name.to_string(),
+                        Reactor::Member(id) => contacts
+                            .get(id)
+                            .map(|c| c.name.clone())
+                            .unwrap_or_else(|| "unknown".to_string()),
+                    })
+                    .collect();
+                format!("{} {}", emoji, names.join(", "))
+            })
+            .collect();
+        lines.push(Line::from(Span::styled(
+            format!("{}{}", indent, chips.join("  ")),
+            DIM,
+        )));
+    }
+    lines
+}
+
+/// Break text into lines of at most `width` characters, keeping explicit newlines
+fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        let mut current = String::new();
+        for word in paragraph.split(' ') {
+            let needed = if current.is_empty() {
+                word.chars().count()
+            } else {
+                current.chars().count() + 1 + word.chars().count()
+            };
+            if needed > width && !current.is_empty() {
+                lines.push(std::mem::take(&mut current));
+            }
+            if !current.is_empty() {
+                current.push(' ');
+            }
+            current.push_str(word);
+            // Hard-split words longer than a whole line
+            while current.chars().count() > width {
+                let head: String = current.chars().take(width).collect();
+                current = current.chars().skip(width).collect();
+                lines.push(head);
+            }
+        }
+        lines.push(current);
+    }
+    lines
+}
+
+fn draw_composer(frame: &mut Frame, app: &TuiApp, chat: Option<&Chat>, area: Rect) {
+    let focused = app.focus == Focus::Composer;
+    let title = if app.editing.is_some() {
+        " Editing message (Esc to cancel) ".to_string()
+    } else if let Some(reply_to) = app.reply_to {
+        let snippet = chat
+            .and_then(|c| c.messages.iter().find(|m| m.id == reply_to))
+            .map(quote_snippet)
+            .unwrap_or_default();
+        format!(" Replying to: {} (Esc to cancel) ", snippet)
+    } else {
+        " Message — /file <path> sends a file ".to_string()
+    };
+    let block = focused_block(title, focused);
+    let inner = block.inner(area);
+
+    // Keep the end of long input visible
+    let visible = inner.width.saturating_sub(1) as usize;
+    let count = app.input.chars().count();
+    let shown: String = app
+        .input
+        .chars()
+        .skip(count.saturating_sub(visible))
+        .collect();
+    let cursor_x = inner.x + shown.chars().count() as u16;
+    frame.render_widget(Paragraph::new(shown).block(block), area);
+    if focused && app.prompt.is_none() {
+        frame.set_cursor_position(Position::new(cursor_x, inner.y));
+    }
+}
+
+fn draw_contacts(frame: &mut Frame, app: &TuiApp, manager: &ChatManager, area: Rect) {
+    let contacts = sorted_contacts(manager);
+    let items: Vec<ListItem> = contacts
+        .iter()
+        .map(|contact| {
+            let mut spans = vec![Span::raw(format!("{:<20} ", contact.name))];
+            spans.push(Span::styled(
+                format!(
+                    "{:<24} ",
+                    contact.address.as_deref().unwrap_or("no address")
+                ),
+                DIM,
+            ));
+            if let Some(fp) = &contact.fingerprint {
+                spans.push(Span::styled(format_fingerprint_short(fp), DIM));
+            }
+            if let Some(verified_at) = contact.verified_at {
+                spans.push(Span::styled(
+                    format!(" ✔ verified {}", verified_at.format("%Y-%m-%d")),
+                    Style::new().fg(Color::Green),
+                ));
+            }
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(" Contacts ")
+        .title_bottom(" Enter open chat · a add from invite link · d delete ");
+    if items.is_empty() {
+        frame.render_widget(
+            Paragraph::new("No contacts yet. Press 'a' to add one from an invite link.")
+                .style(DIM)
+                .block(block),
+            area,
+        );
+        return;
+    }
+    let mut state = ListState::default().with_selected(Some(app.selected_contact));
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(Style::new().reversed());
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+fn draw_transfers(frame: &mut Frame, manager: &ChatManager, area: Rect) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(" File transfers ");
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let mut transfers = manager.transfers();
+    if transfers.is_empty() {
+        frame.render_widget(Paragraph::new("No file transfers yet.").style(DIM), inner);
+        return;
+    }
+    transfers.sort_by(|a, b| a.filename.cmp(&b.filename));
+
+    let rows = Layout::vertical(vec![Constraint::Length(1); transfers.len()]).split(inner);
+    for (transfer, row) in transfers.iter().zip(rows.iter()) {
+        let ratio = if transfer.size == 0 {
+            1.0
+        } else {
+            (transfer.received as f64 / transfer.size as f64).clamp(0.0, 1.0)
+        };
+        let (status, color) = match &transfer.status {
+            TransferStatus::Pending => ("pending".to_string(), Color::DarkGray),
+            TransferStatus::InProgress => ("receiving".to_string(), Color::Cyan),
+            TransferStatus::Completed => ("done".to_string(), Color::Green),
+            TransferStatus::Failed(reason) => (format!("failed: {}", reason), Color::Red),
+            TransferStatus::Cancelled => ("cancelled".to_string(), Color::Yellow),
+        };
+        let label = format!(
+            "{}  {} / {}  {}",
+            transfer.filename,
+            format_size(transfer.received),
+            format_size(transfer.size),
+            status
+        );
+        let gauge = Gauge::default()
+            .gauge_style(Style::new().fg(color))
+            .ratio(ratio)
+            .label(label);
+        frame.render_widget(gauge, *row);
+    }
+}
+
+fn draw_status(frame: &mut Frame, app: &TuiApp, area: Rect) {
+    let line = match &app.status {
+        Some((level, message)) => {
+            let color = match level {
+                ToastLevel::Info => Color::Blue,
+                ToastLevel::Success => Color::Green,
+                ToastLevel::Warning => Color::Yellow,
+                ToastLevel::Error => Color::Red,
+            };
+            Line::from(Span::styled(message.clone(), Style::new().fg(color)))
+        }
+        None => Line::from(Span::styled(
+            match (app.screen, app.focus) {
+                (Screen::Chats, Focus::ChatList) => "↑/↓ select chat · Enter write · Tab next pane",
+                (Screen::Chats, Focus::Messages) => {
+                    "↑/↓ select message · r reply · e edit · d delete · 1-6 react · Tab next pane"
+                }
+                (Screen::Chats, Focus::Composer) => {
+                    "Enter send · Esc cancel reply/edit · Tab next pane"
+                }
+                _ => "",
+            },
+            DIM,
+        )),
+    };
+    frame.render_widget(Paragraph::new(line), area);
+}
+
+/// Rectangle of the given size centered in `area`
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+fn draw_prompt(frame: &mut Frame, prompt: &Prompt) {
+    let (title, lines, input): (&str, Vec<Line>, Option<&String>) = match prompt {
+        Prompt::Fingerprint {
+            fingerprint,
+            peer_name,
+            safety_number,
+            ..
+        } => {
+            let mut lines = vec![Line::from(format!("Peer: {}", peer_name)), Line::from("")];
+            match safety_number {
+                Some(safety_number) => {
+                    lines.extend(
+                        safety_number
+                            .groups()
+                            .chunks(4)
+                            .map(|row| Line::from(row.join(" ")).bold()),
+                    );
+                    lines.push(Line::from(safety_number.emoji().join(" ")));
+                    lines.push(Line::from(""));
+                    lines.push(Line::from("Does it match the safety number your peer sees?"));
+                }
+                None => {
+                    lines.push(Line::from(fingerprint.clone()).bold());
+                    lines.push(Line::from(""));
+                    lines.push(Line::from("Does it match the fingerprint your peer sees?"));
+                }
+            }
+            lines.push(Line::from("y accept · n reject"));
+            (" Verify safety number ", lines, None)
+        }
+        Prompt::Host { input } => (" Host on port ", Vec::new(), Some(input)),
+        Prompt::Connect { input } => (" Connect to HOST[:PORT] ", Vec::new(), Some(input)),
+        Prompt::AddContact { input } => (" Paste invite link ", Vec::new(), Some(input)),
+        Prompt::Help => (
+            " Keys ",
+            [
+                "F2 chats · F3 contacts · F4 transfers",
+                "F5 host · F6 connect · Ctrl-Q quit",
+                "",
+                "Chats: Tab cycles list / messages / composer",
+                "  messages: ↑/↓ select, r reply, e edit, d delete,",
+                "            1-6 react 👍 ❤️ 😂 😮 😢 🙏",
+                "  composer: Enter send, /file <path>, Esc cancel",
+                "Contacts: Enter open chat, a add, d delete",
+                "",
+                "Press any key to close",
+            ]
+            .into_iter()
+            .map(Line::from)
+            .collect(),
+            None,
+        ),
+    };
+
+    let height = match input {
+        Some(_) => 3,
+        None => lines.len() as u16 + 2,
+    };
+    let area = centered(frame.area(), 64, height);
+    frame.render_widget(Clear, area);
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(title)
+        .border_style(Style::new().fg(Color::Yellow));
+
+    match input {
+        Some(input) => {
+            let inner = block.inner(area);
+            let visible = inner.width.saturating_sub(1) as usize;
+            let count = input.chars().count();
+            let shown: String = input.chars().skip(count.saturating_sub(visible)).collect();
+            let cursor_x = inner.x + shown.chars().count() as u16;
+            frame.render_widget(Paragraph::new(shown).block(block), area);
+            frame.set_cursor_position(Position::new(cursor_x, inner.y));
+        }
+        None => {
+            frame.render_widget(
+                Paragraph::new(lines)
+                    .block(block)
+                    .wrap(Wrap { trim: false }),
+                area,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_text() {
+        assert_eq!(wrap_text("hello world", 20), vec!["hello world"]);
+        assert_eq!(wrap_text("hello world", 7), vec!["hello", "world"]);
+        assert_eq!(wrap_text("abcdefghij", 4), vec!["abcd", "efgh", "ij"]);
+        assert_eq!(wrap_text("one\ntwo", 20), vec!["one", "two"]);
+    }
+
+    #[test]
+    fn test_draw_chats_screen() {
+        use crate::types::Config;
+        use ratatui::Terminal;
+        use ratatui::backend::TestBackend;
+
+        let mut manager = ChatManager::new(Config::default());
+        let chat_id = manager.create_group_chat(Vec::new(), Some("Alice".to_string()));
+        manager.push_message(
+            chat_id,
+            Message::new(
+                false,
+                MessageContent::Text {
+                    text: "hello from alice".to_string(),
+                },
+            ),
+        );
+        let mut app = TuiApp::new();
+        app.on_tick(&mut manager);
+
+        let mut terminal = Terminal::new(TestBackend::new(100, 20)).unwrap();
+        terminal.draw(|frame| draw(frame, &app, &manager)).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(screen.contains("Alice"));
+        assert!(screen.contains("hello from alice"));
+    }
+}
@@ -1,5 +1,6 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::PathBuf;
 use uuid::Uuid;
 
@@ -17,6 +18,10 @@ pub struct Chat {
     pub peer_typing: bool,
     #[serde(skip)]
     pub typing_since: Option<std::time::Instant>,
+    /// Disappearing-messages timer: messages older than this many seconds are purged.
+    /// Agreed with the peer via `ProtocolMessage::SetExpiry`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disappearing_after_secs: Option<u64>,
 }
 
 /// A single message in a chat
@@ -26,6 +31,99 @@ pub struct Message {
     pub from_me: bool,
     pub content: MessageContent,
     pub timestamp: DateTime<Utc>,
+    /// Previous versions of the text, oldest first (empty if never edited)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub edit_history: Vec<MessageEdit>,
+    /// ID of the message this one replies to, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<Uuid>,
+    /// Emoji reactions: emoji -> who reacted with it
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub reactions: BTreeMap<String, BTreeSet<Reactor>>,
+}
+
+impl Message {
+    /// Create a message with a fresh ID, timestamped now
+    pub fn new(from_me: bool, content: MessageContent) -> Self {
+        Self::with_id(Uuid::new_v4(), from_me, content)
+    }
+
+    /// Create a message with a known ID (e.g. the ID chosen by the sending peer)
+    pub fn with_id(id: Uuid, from_me: bool, content: MessageContent) -> Self {
+        Self {
+            id,
+            from_me,
+            content,
+            timestamp: Utc::now(),
+            edit_history: Vec::new(),
+            reply_to: None,
+            reactions: BTreeMap::new(),
+        }
+    }
+
+    /// Add or remove a reaction. Returns true if the reactions changed.
+    pub fn set_reaction(&mut self, emoji: &str, reactor: Reactor, add: bool) -> bool {
+        if add {
+            self.reactions
+                .entry(emoji.to_string())
+                .or_default()
+                .insert(reactor)
+        } else {
+            let Some(reactors) = self.reactions.get_mut(emoji) else {
+                return false;
+            };
+            let removed = reactors.remove(&reactor);
+            if reactors.is_empty() {
+                self.reactions.remove(emoji);
+            }
+            removed
+        }
+    }
+
+    /// Whether we reacted to this message with `emoji`
+    pub fn has_my_reaction(&self, emoji: &str) -> bool {
+        self.reactions
+            .get(emoji)
+            .is_some_and(|reactors| reactors.contains(&Reactor::Me))
+    }
+
+    /// Mark this message as a reply to another message
+    pub fn replying_to(mut self, reply_to: Option<Uuid>) -> Self {
+        self.reply_to = reply_to;
+        self
+    }
+
+    /// Current text of a text message (original or edited)
+    pub fn text(&self) -> Option<&str> {
+        match &self.content {
+            MessageContent::Text { text } => Some(text),
+            MessageContent::Edited { new_text } => Some(new_text),
+            _ => None,
+        }
+    }
+}
+
+/// Reactions offered directly in a message's context menu (GUI) or on the
+/// number keys (TUI)
+pub const QUICK_REACTIONS: [&str; 6] = ["👍", "❤️", "😂", "😮", "😢", "🙏"];
+
+/// Who reacted to a message
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Reactor {
+    Me,
+    /// The peer of a one-to-one chat
+    Peer,
+    /// A group member, by contact ID
+    Member(Uuid),
+}
+
+/// A previous version of an edited message
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MessageEdit {
+    pub text: String,
+    /// When this version was replaced
+    pub replaced_at: DateTime<Utc>,
 }
 
 /// A contact (a known peer)
@@ -37,6 +135,12 @@ pub struct Contact {
     pub fingerprint: Option<String>,
     pub public_key: Option<String>,
     pub created_at: DateTime<Utc>,
+    /// When the safety number was last confirmed with this contact
+    #[serde(default)]
+    pub verified_at: Option<DateTime<Utc>>,
+    /// Secret of the invite this contact came from, proved to the host on connect
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invite_token: Option<String>,
 }
 
 /// Message content types
@@ -55,6 +159,8 @@ pub enum MessageContent {
     Edited {
         new_text: String,
     },
+    /// Tombstone left in place of a deleted message
+    Deleted,
 }
 
 /// Toast notification for UI
@@ -122,20 +228,62 @@ pub enum SessionEvent {
     NewConnection {
         peer_addr: String,
         fingerprint: String,
+        /// Fingerprint of our own identity key, for the safety number
+        local_fingerprint: String,
         chat_id: Uuid,
     },
     ShowFingerprintVerification {
         fingerprint: String,
+        local_fingerprint: String,
         peer_name: String,
         chat_id: Uuid,
     },
-    Ready,
+    /// Handshake finished; `post_quantum` is set when the session key also
+    /// depends on an ML-KEM exchange
+    Ready { post_quantum: bool },
+    /// The client proved it holds an invite secret (host only). `binding` is
+    /// what the proof must have been computed over for this session.
+    InviteProof { proof: Vec<u8>, binding: Vec<u8> },
     MessageReceived(crate::core::ProtocolMessage),
+    /// A received packet was rejected; the session keeps running
+    SecurityViolation(SecurityEvent),
     Disconnected,
     Error(String),
     Warning(String),
 }
 
+/// Why a received packet was rejected (or, for `MissingPackets`, flagged)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SecurityEvent {
+    /// A packet with this counter was already received
+    Replay { counter: u64 },
+    /// The packet is older than one already received
+    OutOfOrder { counter: u64, expected: u64 },
+    /// Packets were skipped: the sender's counter jumped ahead
+    MissingPackets { expected: u64, received: u64 },
+    /// The packet failed to decrypt (tampered, truncated or wrong key)
+    AuthenticationFailed,
+}
+
+impl std::fmt::Display for SecurityEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Replay { counter } => write!(f, "replayed packet #{}", counter),
+            Self::OutOfOrder { counter, expected } => {
+                write!(f, "out-of-order packet #{} (expected #{})", counter, expected)
+            }
+            Self::MissingPackets { expected, received } => write!(
+                f,
+                "{} packet(s) missing before #{}",
+                received - expected,
+                received
+            ),
+            Self::AuthenticationFailed => write!(f, "packet failed authentication"),
+        }
+    }
+}
+
 /// Application configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -155,6 +303,43 @@ pub struct Config {
     pub auto_host_on_startup: bool,
     #[serde(default = "default_listen_port")]
     pub listen_port: u16,
+    /// Store the message search index in the history file instead of rebuilding it on load
+    #[serde(default)]
+    pub persist_search_index: bool,
+    /// Offer the hybrid X25519 + ML-KEM handshake to peers that support it
+    #[serde(default = "default_true")]
+    pub enable_post_quantum: bool,
+    /// When to replace the session key during a long-lived session
+    #[serde(default)]
+    pub rekey_policy: RekeyPolicy,
+    /// Look for peers on the local network and advertise ourselves while hosting
+    #[serde(default)]
+    pub enable_lan_discovery: bool,
+    /// UDP port for signed discovery beacons, used where mDNS is blocked
+    #[serde(default = "default_beacon_port")]
+    pub discovery_beacon_port: u16,
+}
+
+/// Limits after which a session starts an in-session rekey. A limit of 0 is ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RekeyPolicy {
+    /// Messages sent under one key
+    pub after_messages: u64,
+    /// Plaintext bytes sent under one key
+    pub after_bytes: u64,
+    /// Minutes since the key was established
+    pub after_minutes: u64,
+}
+
+impl Default for RekeyPolicy {
+    fn default() -> Self {
+        Self {
+            after_messages: 10_000,
+            after_bytes: 256 * 1024 * 1024, // 256 MB
+            after_minutes: 60,
+        }
+    }
 }
 
 /// Theme options
@@ -188,8 +373,17 @@ impl Default for Config {
             notification_sound: NotificationSound::Default,
             auto_host_on_startup: false,
             listen_port: 5000,
+            persist_search_index: false,
+            enable_post_quantum: true,
+            rekey_policy: RekeyPolicy::default(),
+            enable_lan_discovery: false,
+            discovery_beacon_port: default_beacon_port(),
         }
     }
 }
 
 fn default_listen_port() -> u16 { 5000 }
+
+fn default_true() -> bool { true }
+
+fn default_beacon_port() -> u16 { crate::network::discovery::broadcast::DEFAULT_BEACON_PORT }
@@ -45,6 +45,17 @@ pub fn format_size(bytes: u64) -> String {
     format!("{:.2} {}", size, UNITS[unit_idx])
 }
 
+/// Format a duration in seconds as a short human-readable string (e.g. "5m", "1h", "7d")
+pub fn format_duration_short(secs: u64) -> String {
+    const UNITS: &[(u64, &str)] = &[(86_400, "d"), (3_600, "h"), (60, "m")];
+    for (unit_secs, suffix) in UNITS {
+        if secs >= *unit_secs && secs.is_multiple_of(*unit_secs) {
+            return format!("{}{}", secs / unit_secs, suffix);
+        }
+    }
+    format!("{}s", secs)
+}
+
 /// Format fingerprint for display (first 8 + last 8 chars)
 pub fn format_fingerprint_short(fp: &str) -> String {
     if fp.len() > 16 {
@@ -54,6 +65,116 @@ pub fn format_fingerprint_short(fp: &str) -> String {
     }
 }
 
+/// Render text (e.g. an invite link) as a QR code PNG
+pub fn render_qr_png(data: &str) -> anyhow::Result<Vec<u8>> {
+    use qrcode::QrCode;
+
+    let code = QrCode::new(data.as_bytes())
+        .map_err(|e| anyhow::anyhow!("Failed to generate QR code: {}", e))?;
+
+    let qr_image = code
+        .render::<image::Luma<u8>>()
+        .min_dimensions(200, 200)
+        .build();
+
+    let mut bytes = Vec::new();
+    let mut cursor = std::io::Cursor::new(&mut bytes);
+    image::DynamicImage::ImageLuma8(qr_image)
+        .write_to(&mut cursor, image::ImageFormat::Png)
+        .map_err(|e| anyhow::anyhow!("Failed to encode QR code: {}", e))?;
+
+    Ok(bytes)
+}
+
+/// Read the first QR code found in an image
+pub fn decode_qr(image: &image::DynamicImage) -> anyhow::Result<String> {
+    let mut prepared = rqrr::PreparedImage::prepare(image.to_luma8());
+    let grids = prepared.detect_grids();
+    if grids.is_empty() {
+        anyhow::bail!("No QR code found in the image");
+    }
+    grids
+        .iter()
+        .find_map(|grid| grid.decode().ok())
+        .map(|(_, content)| content)
+        .ok_or_else(|| anyhow::anyhow!("Found a QR code but could not decode it"))
+}
+
+/// Read a QR code from an image file (PNG, JPEG, ...)
+pub fn decode_qr_file(path: &std::path::Path) -> anyhow::Result<String> {
+    let image = image::open(path)
+        .map_err(|e| anyhow::anyhow!("Failed to open {}: {}", path.display(), e))?;
+    decode_qr(&image)
+}
+
+/// Read a QR code from an image on the clipboard (e.g. a screenshot)
+pub fn decode_qr_clipboard() -> anyhow::Result<String> {
+    let mut clipboard = arboard::Clipboard::new()?;
+    let data = clipboard
+        .get_image()
+        .map_err(|e| anyhow::anyhow!("No image on the clipboard: {}", e))?;
+    let rgba = image::RgbaImage::from_raw(
+        data.width as u32,
+        data.height as u32,
+        data.bytes.into_owned(),
+    )
+    .ok_or_else(|| anyhow::anyhow!("Clipboard image has an unexpected size"))?;
+    decode_qr(&image::DynamicImage::ImageRgba8(rgba))
+}
+
+/// Put text on the clipboard as a QR code image
+pub fn copy_qr_to_clipboard(data: &str) -> anyhow::Result<()> {
+    let rgba = image::load_from_memory(&render_qr_png(data)?)?.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    arboard::Clipboard::new()?.set_image(arboard::ImageData {
+        width: width as usize,
+        height: height as usize,
+        bytes: rgba.into_raw().into(),
+    })?;
+    Ok(())
+}
+
+/// Bind a Unix socket at `path` that only the owning user can connect to.
+/// It is bound inside a fresh 0700 directory next to `path`, restricted to
+/// 0600 and only then moved into place, so it is never reachable by others
+/// while its permissions are being set.
+#[cfg(unix)]
+pub fn bind_private_socket(path: &std::path::Path) -> anyhow::Result<tokio::net::UnixListener> {
+    use anyhow::Context;
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("{} is not a socket path", path.display()))?;
+    let parent = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(std::path::Path::new("."));
+    let staging = parent.join(format!(
+        ".{}.{}",
+        file_name.to_string_lossy(),
+        std::process::id()
+    ));
+    // Left over from a crash; fails harmlessly if it isn't ours
+    let _ = std::fs::remove_dir_all(&staging);
+    std::fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&staging)
+        .with_context(|| format!("Failed to create {}", staging.display()))?;
+
+    let staged = staging.join(file_name);
+    let bound = tokio::net::UnixListener::bind(&staged)
+        .with_context(|| format!("Failed to bind {}", path.display()))
+        .and_then(|listener| {
+            std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o600))?;
+            std::fs::rename(&staged, path)
+                .with_context(|| format!("Failed to move the socket to {}", path.display()))?;
+            Ok(listener)
+        });
+    let _ = std::fs::remove_dir_all(&staging);
+    bound
+}
+
 /// Generate a 4x4 color grid from a fingerprint
 pub fn generate_color_grid(fingerprint: &str) -> [[Color32; 4]; 4] {
     let mut grid = [[Color32::BLACK; 4]; 4];
@@ -117,6 +238,15 @@ mod tests {
         assert_eq!(format_size(1024 * 1024 * 1024), "1.00 GB");
     }
 
+    #[test]
+    fn test_format_duration_short() {
+        assert_eq!(format_duration_short(30), "30s");
+        assert_eq!(format_duration_short(300), "5m");
+        assert_eq!(format_duration_short(3_600), "1h");
+        assert_eq!(format_duration_short(604_800), "7d");
+        assert_eq!(format_duration_short(90), "90s");
+    }
+
     #[test]
     fn test_format_fingerprint_short() {
         let long_fp = "abcdefgh12345678901234567890ijklmnop";
@@ -125,6 +255,16 @@ mod tests {
         assert!(short.starts_with("abcdefgh"));
     }
 
+    #[test]
+    fn test_qr_roundtrip() {
+        let link = "chat-p2p://invite/eyJuYW1lIjoiQWxpY2UifQ==";
+        let image = image::load_from_memory(&render_qr_png(link).unwrap()).unwrap();
+        assert_eq!(decode_qr(&image).unwrap(), link);
+
+        let blank = image::DynamicImage::new_luma8(64, 64);
+        assert!(decode_qr(&blank).is_err());
+    }
+
     #[test]
     fn test_generate_color_grid() {
         let fp = "abcdefgh12345678901234567890ijklmnop";